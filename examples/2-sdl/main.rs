@@ -33,7 +33,7 @@ fn main() {
     };
     // Create the NES
     let mut nes = Nes::with_cartridge(Cartridge::from_ines(&ines_bytes, None).unwrap());
-    let settings = Settings::default();
+    let mut settings = Settings::default();
     // Initialize SDL2
     let sdl = sdl2::init().unwrap();
     // Setup video
@@ -52,7 +52,9 @@ fn main() {
             },
         )
         .unwrap();
-    let mut sample_queue = Vec::new();
+    // Have the NES generate its audio at the same rate as the SDL queue
+    settings.output_sample_rate = Some(queue.spec().freq as u32);
+    let mut sample_buffer = vec![0.0; queue.spec().freq as usize / 10];
     // Setup input
     let mut event_pump = sdl.event_pump().unwrap();
 
@@ -84,24 +86,16 @@ fn main() {
 
         // Add the NES's audio output to the SDL audio queue
         //
-        // The NES will generate one new same every CPU clock (~1,789,000 Hz)
-        // Our sample queue will consume samples at 44_800 Hz
-        // So we average the NES sound output
-        sample_queue.extend_from_slice(&nes.apu.sample_queue());
-        let chunks = sample_queue
-            .chunks_exact((CPU_CLOCK_SPEED as f64 / queue.spec().freq as f64).ceil() as usize);
-        let remainder = chunks.remainder();
-        let resampled_output: Vec<f32> = chunks
-            .map(|c| c.iter().sum::<f32>() / c.len() as f32)
-            .collect();
+        // Since we set the output sample rate above, the NES generates samples at the same rate
+        // SDL will consume them, so we can just copy them over
+        let num_samples = nes.apu.read_samples(&mut sample_buffer);
         // If we are over 1/2 a second ahead, clear the queue
         // This can sometimes happen because SDL takes a second to start up
         if queue.size() > queue.spec().freq as u32 {
             println!("Clearing queue");
             queue.clear();
         }
-        queue.queue_audio(&resampled_output).unwrap();
-        sample_queue = remainder.to_vec();
+        queue.queue_audio(&sample_buffer[..num_samples]).unwrap();
         queue.resume();
 
         // Update the NES's controllers
@@ -10,7 +10,7 @@ use std::{
 };
 use yane::{
     app::{Audio, Config, Input},
-    core::{Cartridge, Nes, Settings, CPU_CLOCK_SPEED},
+    core::{Cartridge, Nes, NtscFilter, Settings, CPU_CLOCK_SPEED, NTSC_OUTPUT_WIDTH},
 };
 
 mod open_gl;
@@ -70,6 +70,9 @@ fn main() {
         let tex = open_gl::load_and_create_texture(&gl, "examples/3-television/assets/texture.png");
         // Create an empty texture for the screen
         let screen_tex = open_gl::create_texture(&gl, (0, 0), &[]);
+        // Use an NTSC filter, since it's a television after all
+        let mut ntsc_filter = NtscFilter::default();
+        let mut screen_buffer = vec![[[0; 3]; NTSC_OUTPUT_WIDTH]; 240];
 
         gl.clear_color(0.0, 0.0, 0.0, 1.0);
         gl.enable(glow::MULTISAMPLE);
@@ -110,18 +113,19 @@ fn main() {
             audio.update(&mut nes, &Config::default());
             total_cycles += nes.advance_frame(&Settings::default()).unwrap();
             // Set screen texture
-            // Since the NES's output is already in RGB values, we can just pass it almost directly to open gl
+            // Filter the NES's output to get RGB values, then pass it almost directly to open gl
+            ntsc_filter.apply(&nes.ppu, screen_buffer.as_mut_slice().try_into().unwrap());
             gl.bind_texture(glow::TEXTURE_2D, Some(screen_tex));
             gl.tex_image_2d(
                 glow::TEXTURE_2D,
                 0,
                 glow::RGB as i32,
-                256,
+                NTSC_OUTPUT_WIDTH as i32,
                 240,
                 0,
                 glow::RGB,
                 glow::UNSIGNED_BYTE,
-                Some(nes.ppu.rgb_output().as_flattened().as_flattened()),
+                Some(screen_buffer.as_flattened().as_flattened()),
             );
             let dur = (Instant::now() - start_time).as_millis() as f32 / 5_000.0;
 
@@ -6,19 +6,25 @@ use sdl2::{
     AudioSubsystem,
 };
 
+/// The amount of audio to try to keep in the SDL queue, in seconds
+const TARGET_LATENCY: f64 = 0.05;
+/// The maximum amount dynamic rate control will change the resampling ratio by
+const MAX_RATE_DELTA: f64 = 0.005;
+
 /// The audio component of the emulator.
 ///
 /// Controls all the audio functionality of the app.
 /// Mostly just responsible for downsampling the NES audio's output and
 /// adding it to the SDL queue.
+///
+/// Uses dynamic rate control to avoid the queue running empty or growing too large:
+/// the resampling ratio is slightly raised when the queue is below [TARGET_LATENCY] and
+/// slightly lowered when it is above, which is far too small a change to hear.
 pub struct Audio {
     queue: AudioQueue<f32>,
     resampler: SincFixedIn<f32>,
     data_queue: Vec<f32>,
     last_speed: f32,
-    /// Vector of all samples ever taken.
-    /// Used for saving the sound if recording
-    pub all_samples: Vec<f32>,
 }
 
 impl Audio {
@@ -58,43 +64,52 @@ impl Audio {
             resampler,
             data_queue: Vec::<f32>::new(),
             last_speed: 1.0,
-            all_samples: Vec::new(),
         }
     }
     /// Add the NES's audio to the SDL audio queue
     pub fn update(&mut self, nes: &mut Nes, config: &Config) {
-        // Clear queue if it's too big
-        // Should only happen on startup when SDL is booting up
-        if self.queue.size() > CPU_CLOCK_SPEED / 60 {
-            debug!("Queue is too big, clearing (was {})", self.queue.size());
+        let samples = nes.apu.sample_queue();
+        self.queue_samples(&samples, config);
+    }
+    /// Add samples taken from [Apu::sample_queue][crate::core::Apu::sample_queue] to the SDL audio queue
+    ///
+    /// Use this instead of [Audio::update] if the samples are also needed elsewhere (i.e. for recording).
+    pub fn queue_samples(&mut self, samples: &[f32], config: &Config) {
+        // Clear queue if it's way too big
+        // Should only happen on startup when SDL is booting up, or if the emulator has stalled
+        if self.queued_samples() > 4 * self.target_queue_len() {
+            debug!("Queue is too big, clearing (was {})", self.queued_samples());
             self.queue.clear();
         } else if self.queue.size() == 0 && !config.paused {
             warn!("Queue is empty!");
         }
         // Get and transform data
-        let data = nes
-            .apu
-            .sample_queue()
+        let data = samples
             .iter()
             .map(|x| config.volume * x)
             .collect::<Vec<f32>>();
         self.data_queue.extend_from_slice(&data);
-        if config.record_audio {
-            self.all_samples.extend_from_slice(&data);
-        }
         // Downsample to audio output rate
-        let input_size = self.resampler.input_frames_next();
-        let mut out = vec![vec![0.0; self.resampler.output_frames_max()]; 1];
+        let ratio =
+            (self.queue.spec().freq as f64 / CPU_CLOCK_SPEED as f64) / config.speed.min(9.9) as f64;
         if config.speed != self.last_speed {
             self.last_speed = config.speed;
-            let ratio = (self.queue.spec().freq as f64 / CPU_CLOCK_SPEED as f64)
-                / config.speed.min(9.9999) as f64;
             self.resampler.reset();
             self.resampler
                 .set_resample_ratio(ratio, false)
                 .expect("Unable to change ratio");
             self.queue.clear();
+        } else {
+            // Dynamic rate control
+            // Produce slightly more samples if the queue is running low, and slightly fewer if it is filling up
+            let fill = self.queued_samples() as f64 / self.target_queue_len() as f64;
+            let adjustment = 1.0 + MAX_RATE_DELTA * (1.0 - fill).clamp(-1.0, 1.0);
+            self.resampler
+                .set_resample_ratio(ratio * adjustment, true)
+                .expect("Unable to change ratio");
         }
+        let input_size = self.resampler.input_frames_next();
+        let mut out = vec![vec![0.0; self.resampler.output_frames_max()]; 1];
         while self.data_queue.len() >= input_size {
             let input: Vec<f32> = self.data_queue.drain(0..input_size).collect();
             let (_nbr_in, nbr_out) = self
@@ -107,4 +122,16 @@ impl Audio {
                 .expect("Unable to queue audio");
         }
     }
+    /// The number of samples currently waiting in the SDL queue
+    pub fn queued_samples(&self) -> u32 {
+        self.queue.size() / size_of::<f32>() as u32
+    }
+    /// Whether the SDL queue has less audio than it should, and the emulator should be advanced
+    /// to generate more.
+    pub fn needs_samples(&self) -> bool {
+        self.queued_samples() < self.target_queue_len()
+    }
+    fn target_queue_len(&self) -> u32 {
+        (self.queue.spec().freq as f64 * TARGET_LATENCY) as u32
+    }
 }
@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-use crate::core::Settings as EmuSettings;
+use crate::core::{NtscSettings, Palette, Settings as EmuSettings};
 use std::path::PathBuf;
 
 use crate::app::KeyMap;
@@ -20,10 +20,15 @@ pub struct Config {
     pub volume: f32,
     // Set the speed multiplyer
     pub speed: f32,
-    // Whether to record audio
-    pub record_audio: bool,
-    // The file to record the audio samples to
-    pub record_audio_filename: String,
+    /// How to keep the emulator running in time with real time
+    #[serde(default)]
+    pub pacing: Pacing,
+    // Whether to record video and audio
+    #[serde(skip)]
+    pub recording: bool,
+    /// Folder to save recordings to
+    #[serde(default = "default_recording_dir")]
+    pub recording_dir: PathBuf,
     // Screen output size, only used by window
     pub screen_size: (u32, u32),
     // Whether to verbosely log a lot of things
@@ -44,9 +49,24 @@ pub struct Config {
     pub emu_settings: EmuSettings,
     /// Folder to save/load savestates from
     pub savestate_dir: PathBuf,
+    /// Folder to save screenshots to
+    #[serde(default = "default_screenshot_dir")]
+    pub screenshot_dir: PathBuf,
     /// Game name, if we managed to parse it from the file
     #[serde(skip)]
     pub game_name: Option<String>,
+    /// The .pal file to load the palette from, if not using the default palette
+    #[serde(default)]
+    pub palette_file: Option<PathBuf>,
+    /// The palette used to convert the NES's output to RGB values
+    #[serde(skip)]
+    pub palette: Palette,
+    /// Whether to use the NTSC filter instead of the palette to render the screen
+    #[serde(default)]
+    pub ntsc_filter: bool,
+    /// Settings for the NTSC filter
+    #[serde(default)]
+    pub ntsc_settings: NtscSettings,
 }
 
 impl Default for Config {
@@ -56,8 +76,9 @@ impl Default for Config {
             oam_debug: false,
             volume: 1.0,
             speed: 1.0,
-            record_audio: false,
-            record_audio_filename: "sample".to_string(),
+            pacing: Pacing::default(),
+            recording: false,
+            recording_dir: default_recording_dir(),
             screen_size: (256, 240),
             verbose_logging: false,
             restrict_controller_directions: true,
@@ -65,7 +86,32 @@ impl Default for Config {
             quickload_file: None,
             emu_settings: EmuSettings::default(),
             savestate_dir: PathBuf::from("./savestates"),
+            screenshot_dir: default_screenshot_dir(),
             game_name: None,
+            palette_file: None,
+            palette: Palette::default(),
+            ntsc_filter: false,
+            ntsc_settings: NtscSettings::default(),
         }
     }
 }
+
+fn default_recording_dir() -> PathBuf {
+    PathBuf::from("./recordings")
+}
+fn default_screenshot_dir() -> PathBuf {
+    PathBuf::from("./screenshots")
+}
+
+/// How the app keeps the emulator running at the right speed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Pacing {
+    /// Emulate frames whenever the audio queue needs more samples, so the audio device drives timing.
+    /// The window is rendered at 60 FPS.
+    #[default]
+    Audio,
+    /// Render the window on every vertical sync of the monitor, and emulate as many frames as
+    /// needed to keep up with real time.
+    /// Gives smoother video on monitors that do not refresh at 60 Hz.
+    Vsync,
+}
@@ -2,8 +2,11 @@ use copypasta::{ClipboardContext, ClipboardProvider};
 use log::*;
 
 use crate::{
-    app::Config,
-    core::{Cartridge, Nes, Ppu, DEBUG_PALETTE},
+    app::{Config, Pacing},
+    core::{
+        AudioChannel, Cartridge, ChannelMix, Nes, NtscSettings, Palette, Ppu, DEBUG_PALETTE,
+        DEFAULT_PALETTE,
+    },
     utils::*,
 };
 use glow::{HasContext, NativeTexture};
@@ -12,7 +15,10 @@ use imgui_glow_renderer::AutoRenderer;
 use imgui_sdl2_support::SdlPlatform;
 use sdl2::{event::Event, EventPump, VideoSubsystem};
 
-use super::utils::{quickload, quicksave};
+use super::utils::{load_palette, quickload, quicksave};
+
+// Texture number used for the OAM sprite texture
+const OAM_TEX_NUM: i32 = 3;
 
 /// Debug window for the emulator
 ///
@@ -39,9 +45,14 @@ pub struct DebugWindow {
     nametable_timer: u32,
     // Fonts
     small_font: FontId,
+    // Path of the palette file to load, as typed in by the user
+    palette_file_input: String,
+    // Index in palette RAM of the colour currently being edited
+    palette_ram_edit_index: usize,
 
     chr_tex: NativeTexture,
     nametable_tex: NativeTexture,
+    oam_tex: NativeTexture,
 }
 
 impl DebugWindow {
@@ -80,12 +91,11 @@ impl DebugWindow {
             }]);
 
         unsafe {
-            let palette_data: &[u8] = include_bytes!("../2C02G_wiki.pal");
-            let palette: [[u8; 3]; 64] =
-                core::array::from_fn(|i| core::array::from_fn(|j| palette_data[3 * i + j]));
+            let palette: [[u8; 3]; 64] = core::array::from_fn(|i| DEFAULT_PALETTE.colors[i]);
             check_error!(gl);
             let chr_tex = create_texture(&gl);
             let nametable_tex = create_texture(&gl);
+            let oam_tex = create_texture(&gl);
 
             let platform = SdlPlatform::new(&mut imgui);
             let renderer = AutoRenderer::new(gl, &mut imgui).unwrap();
@@ -104,8 +114,11 @@ impl DebugWindow {
                 chr_size: 4.0,
                 chr_tex,
                 nametable_tex,
+                oam_tex,
                 nametable_timer: 0,
                 small_font,
+                palette_file_input: String::new(),
+                palette_ram_edit_index: 0,
             }
         }
     }
@@ -156,6 +169,8 @@ impl DebugWindow {
     /// Render the debug window, and update the [Config] and [Nes] if any of the imgui buttons are pressed.
     pub fn render(&mut self, nes: &mut Nes, event_pump: &EventPump, config: &mut Config) {
         let chr_tex_num: i32 = 1;
+        // Use the same colours as the main window
+        self.palette = core::array::from_fn(|i| config.palette.colors[i]);
         unsafe {
             self.window.gl_make_current(&self.gl_context).unwrap();
             let gl = self.renderer.gl_context();
@@ -285,6 +300,36 @@ impl DebugWindow {
                 );
                 check_error!(gl);
             }
+            // Set up OAM texture
+            let (oam_tiles, oam_palettes) = DebugWindow::oam_tile_data(nes);
+            let oam_rows = oam_tiles.len() / (0x10 * 8);
+            let tex_data = self.transform_chr_data(
+                &oam_tiles,
+                8,
+                oam_rows,
+                if config.emu_settings.use_debug_palette {
+                    &DEBUG_PALETTE
+                } else {
+                    &nes.ppu.palette_ram
+                },
+                oam_palettes,
+            );
+            gl.active_texture(glow::TEXTURE0 + OAM_TEX_NUM as u32);
+            check_error!(gl);
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.oam_tex));
+            check_error!(gl);
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::RGB as i32,
+                8 * 8,
+                8 * oam_rows as i32,
+                0,
+                glow::RGB,
+                glow::UNSIGNED_BYTE,
+                Some(&tex_data),
+            );
+            check_error!(gl);
             gl.clear_color(0.0, 0.0, 0.0, 1.0);
             gl.clear(glow::COLOR_BUFFER_BIT);
         }
@@ -347,6 +392,11 @@ impl DebugWindow {
                 if ui.button("Reset to 1") {
                     config.speed = 1.0;
                 }
+                ui.text("Pacing");
+                ui.same_line();
+                ui.radio_button("Audio", &mut config.pacing, Pacing::Audio);
+                ui.same_line();
+                ui.radio_button("Vsync", &mut config.pacing, Pacing::Vsync);
                 ui.checkbox("Verbose Logging", &mut config.verbose_logging);
                 ui.checkbox(
                     "Restrict controller input",
@@ -383,6 +433,19 @@ impl DebugWindow {
                 }
                 if ui.collapsing_header("Graphics Debug", TreeNodeFlags::empty()) {
                     ui.checkbox("Debug palette", &mut config.emu_settings.use_debug_palette);
+                    if ui.collapsing_header("NTSC Filter", TreeNodeFlags::empty()) {
+                        ui.checkbox("Use NTSC filter", &mut config.ntsc_filter);
+                        if ui.button("Composite") {
+                            config.ntsc_settings = NtscSettings::composite();
+                        }
+                        ui.same_line();
+                        if ui.button("S-Video") {
+                            config.ntsc_settings = NtscSettings::svideo();
+                        }
+                        ui.slider("Sharpness", -1.0, 1.0, &mut config.ntsc_settings.sharpness);
+                        ui.slider("Artifacts", 0.0, 1.0, &mut config.ntsc_settings.artifacts);
+                        ui.slider("Fringing", 0.0, 1.0, &mut config.ntsc_settings.fringing);
+                    }
                     if ui.collapsing_header("CHR ROM/RAM", TreeNodeFlags::empty()) {
                         if let Some(c) =
                             ui.begin_combo("Palette", format!("Palette {}", self.palette_index))
@@ -430,14 +493,92 @@ impl DebugWindow {
                         f.pop();
                         imgui::Image::new(TextureId::new(2), [64.0 * 8.0, 60.0 * 8.0]).build(ui);
                     }
+                    if ui.collapsing_header("Palette", TreeNodeFlags::empty()) {
+                        DebugWindow::draw_palette_editor(
+                            ui,
+                            nes,
+                            config,
+                            &mut self.palette_file_input,
+                            &mut self.palette_ram_edit_index,
+                        );
+                    }
+                    if ui.collapsing_header("OAM", TreeNodeFlags::empty()) {
+                        let sprite_height = if nes.ppu.is_8x16_sprites() { 16 } else { 8 };
+                        ui.text(format!("Sprite size: 8x{}", sprite_height));
+                        // Sprites are drawn in an 8x8 grid, with each sprite scaled up 4 times
+                        imgui::Image::new(
+                            TextureId::new(OAM_TEX_NUM as usize),
+                            [8.0 * 8.0 * 4.0, 8.0 * sprite_height as f32 * 4.0],
+                        )
+                        .build(ui);
+                        DebugWindow::draw_scanline_histogram(ui, nes, config);
+                        ui.columns(8, "OAM entries", true);
+                        ["#", "X", "Y", "Tile", "Attr", "Palette", "Priority", "Flip"]
+                            .iter()
+                            .for_each(|h| {
+                                ui.text(h);
+                                ui.next_column();
+                            });
+                        ui.separator();
+                        nes.ppu.oam.chunks(4).enumerate().for_each(|(i, obj)| {
+                            [
+                                format!("{}", i),
+                                format!("{:02X}", obj[3]),
+                                format!("{:02X}", obj[0]),
+                                format!("{:02X}", obj[1]),
+                                format!("{:02X}", obj[2]),
+                                format!("{}", obj[2] & 0x03),
+                                if obj[2] & 0x20 == 0 {
+                                    "Front".to_string()
+                                } else {
+                                    "Back".to_string()
+                                },
+                                format!(
+                                    "{}{}",
+                                    if obj[2] & 0x40 != 0 { "H" } else { "" },
+                                    if obj[2] & 0x80 != 0 { "V" } else { "" }
+                                ),
+                            ]
+                            .iter()
+                            .for_each(|s| {
+                                ui.text(s);
+                                ui.next_column();
+                            });
+                        });
+                        ui.columns(1, "", false);
+                    }
                 }
                 if ui.collapsing_header("Audio", TreeNodeFlags::empty()) {
-                    ui.input_text(
-                        "Recording file name (.wav)",
-                        &mut config.record_audio_filename,
-                    )
-                    .build();
-                    ui.checkbox("Record?", &mut config.record_audio);
+                    ui.checkbox("Recording (video and audio)", &mut config.recording);
+                    ui.text(format!(
+                        "Recordings are saved in {:?}",
+                        config.recording_dir
+                    ));
+                    ui.text("Mixer");
+                    AudioChannel::ALL.iter().for_each(|c| {
+                        let mix = config.emu_settings.mix_mut(*c);
+                        ui.slider(format!("{}##volume", c.name()), 0.0, 2.0, &mut mix.volume);
+                        ui.same_line();
+                        ui.checkbox(format!("Mute##{}", c.name()), &mut mix.muted);
+                        ui.same_line();
+                        ui.checkbox(format!("Solo##{}", c.name()), &mut mix.solo);
+                    });
+                    if let Some(chip) = nes.cartridge.mapper.expansion_audio() {
+                        ui.slider(
+                            format!("{}##volume", chip.name()),
+                            0.0,
+                            2.0,
+                            config.emu_settings.expansion_volume_mut(chip),
+                        );
+                    }
+                    if ui.button("Reset mixer") {
+                        config.emu_settings.channel_mix = [ChannelMix::default(); 5];
+                        config.emu_settings.expansion_volumes = [1.0; 6];
+                    }
+                    ui.checkbox(
+                        "Hardware output filters",
+                        &mut config.emu_settings.audio_filters,
+                    );
                     ui.text(format!("Pulse 0 : {:?}", nes.apu.pulse_registers[0]));
                     ui.text(format!("Pulse 1 : {:?}", nes.apu.pulse_registers[1]));
                     ui.text(format!("Triangle: {:?}", nes.apu.triangle_register));
@@ -451,6 +592,227 @@ impl DebugWindow {
             .expect("Error rendering DearImGui");
         self.window.gl_swap_window();
     }
+    /// Draw the palette panel.
+    ///
+    /// Allows loading a different palette file, and shows the contents of palette RAM as swatches.
+    /// Clicking a swatch lets the user pick a different colour for that palette RAM entry.
+    fn draw_palette_editor(
+        ui: &imgui::Ui,
+        nes: &mut Nes,
+        config: &mut Config,
+        palette_file_input: &mut String,
+        palette_ram_edit_index: &mut usize,
+    ) {
+        ui.text(format!(
+            "Palette file: {}",
+            match &config.palette_file {
+                Some(f) => f.display().to_string(),
+                None => "Default".to_string(),
+            }
+        ));
+        ui.input_text("Palette file (.pal)", palette_file_input)
+            .build();
+        if ui.button("Load palette") {
+            let path = std::path::PathBuf::from(&*palette_file_input);
+            if let Some(p) = load_palette(&path) {
+                config.palette = p;
+                config.palette_file = Some(path);
+            }
+        }
+        ui.same_line();
+        if ui.button("Reset to default") {
+            config.palette = Palette::default();
+            config.palette_file = None;
+        }
+        let to_color = |rgb: [u8; 3]| {
+            [
+                rgb[0] as f32 / 255.0,
+                rgb[1] as f32 / 255.0,
+                rgb[2] as f32 / 255.0,
+                1.0,
+            ]
+        };
+        // Palette RAM, as 4 background and 4 sprite palettes
+        ui.text("Palette RAM (click to edit)");
+        (0..8).for_each(|palette| {
+            ui.text(format!(
+                "{} {}",
+                if palette < 4 { "BG " } else { "SPR" },
+                palette % 4
+            ));
+            (0..4).for_each(|color| {
+                // The first colour of every sprite palette mirrors the background palette
+                let index = if color == 0 {
+                    (4 * palette) % 0x10
+                } else {
+                    4 * palette + color
+                };
+                let hv = nes.ppu.palette_ram[index] as usize & 0x3F;
+                ui.same_line();
+                if ui
+                    .color_button_config(
+                        format!("{:02X}##palette_ram_{}", hv, 4 * palette + color),
+                        to_color(config.palette.rgb(hv, 0)),
+                    )
+                    .size([24.0, 24.0])
+                    .build()
+                {
+                    *palette_ram_edit_index = index;
+                    ui.open_popup("palette_ram_edit");
+                }
+            });
+        });
+        let edit_index = *palette_ram_edit_index;
+        ui.popup("palette_ram_edit", || {
+            ui.text(format!(
+                "Palette RAM {:#04X}: {:02X}",
+                edit_index, nes.ppu.palette_ram[edit_index]
+            ));
+            (0..64).for_each(|hv| {
+                if hv % 16 != 0 {
+                    ui.same_line();
+                }
+                if ui
+                    .color_button_config(
+                        format!("{:02X}##master_palette_{}", hv, hv),
+                        to_color(config.palette.rgb(hv, 0)),
+                    )
+                    .size([16.0, 16.0])
+                    .build()
+                {
+                    nes.ppu.palette_ram[edit_index] = hv as u8;
+                    ui.close_current_popup();
+                }
+            });
+        });
+    }
+    /// Get the tile data of every sprite in OAM, flipped the way the PPU would draw them,
+    /// along with the palette index of every tile.
+    ///
+    /// Sprites are arranged in rows of 8, with each sprite taking up 1 tile row in 8x8 mode
+    /// and 2 tile rows (top and bottom tiles) in 8x16 mode.
+    fn oam_tile_data(nes: &Nes) -> (Vec<u8>, Vec<usize>) {
+        let is_8x16 = nes.ppu.is_8x16_sprites();
+        let read_tile = |addr: usize| -> [u8; 16] {
+            core::array::from_fn(|i| {
+                nes.cartridge
+                    .mapper
+                    .read_ppu_debug(addr + i, &nes.cartridge.memory)
+            })
+        };
+        // Get the (top, bottom) tiles of every sprite
+        let sprites: Vec<([u8; 16], [u8; 16], usize)> = nes
+            .ppu
+            .oam
+            .chunks(4)
+            .map(|obj| {
+                let (mut top, mut bottom) = if is_8x16 {
+                    let addr = 0x1000 * (obj[1] & 0x01) as usize + 16 * (obj[1] & 0xFE) as usize;
+                    (read_tile(addr), read_tile(addr + 16))
+                } else {
+                    (
+                        read_tile(nes.ppu.spr_pattern_table_addr() + 16 * obj[1] as usize),
+                        [0; 16],
+                    )
+                };
+                if obj[2] & 0x40 != 0 {
+                    top.iter_mut().for_each(|b| *b = b.reverse_bits());
+                    bottom.iter_mut().for_each(|b| *b = b.reverse_bits());
+                }
+                if obj[2] & 0x80 != 0 {
+                    [&mut top, &mut bottom].into_iter().for_each(|t| {
+                        t[0..8].reverse();
+                        t[8..16].reverse();
+                    });
+                    if is_8x16 {
+                        std::mem::swap(&mut top, &mut bottom);
+                    }
+                }
+                // Sprites use the last 4 palettes
+                (top, bottom, 4 + (obj[2] & 0x03) as usize)
+            })
+            .collect();
+        sprites
+            .chunks(8)
+            .flat_map(|row| {
+                let tops = row.iter().map(|(t, _, p)| (*t, *p));
+                let bottoms = row.iter().map(|(_, b, p)| (*b, *p));
+                if is_8x16 {
+                    tops.chain(bottoms).collect::<Vec<([u8; 16], usize)>>()
+                } else {
+                    tops.collect()
+                }
+            })
+            .fold((Vec::new(), Vec::new()), |mut a, (tile, palette)| {
+                a.0.extend_from_slice(&tile);
+                a.1.push(palette);
+                a
+            })
+    }
+    /// Draw a histogram of how many sprites are on each scanline.
+    ///
+    /// Scanlines with more than 8 sprites are drawn in red.
+    fn draw_scanline_histogram(ui: &imgui::Ui, nes: &Nes, config: &Config) {
+        const BAR_WIDTH: f32 = 2.0;
+        const BAR_SCALE: f32 = 4.0;
+        let counts = nes.ppu.sprite_count_per_scanline();
+        let max_count = counts.iter().max().copied().unwrap_or(0).max(8);
+        ui.text("Sprites per scanline");
+        let origin = ui.cursor_screen_pos();
+        let height = BAR_SCALE * max_count as f32;
+        let draw_list = ui.get_window_draw_list();
+        // Line showing the 8 sprite limit
+        let limit_y = origin[1] + height - BAR_SCALE * 8.0;
+        draw_list
+            .add_line(
+                [origin[0], limit_y],
+                [origin[0] + BAR_WIDTH * counts.len() as f32, limit_y],
+                [1.0, 1.0, 0.0, 1.0],
+            )
+            .build();
+        counts.iter().enumerate().for_each(|(i, c)| {
+            let x = origin[0] + BAR_WIDTH * i as f32;
+            let color = if *c > 8 {
+                [1.0, 0.0, 0.0, 1.0]
+            } else {
+                [0.0, 1.0, 0.0, 1.0]
+            };
+            draw_list
+                .add_rect(
+                    [x, origin[1] + height - BAR_SCALE * *c as f32],
+                    [x + BAR_WIDTH, origin[1] + height],
+                    color,
+                )
+                .filled(true)
+                .build();
+        });
+        ui.dummy([BAR_WIDTH * counts.len() as f32, height]);
+        if ui.is_item_hovered() {
+            let scanline = ((ui.io().mouse_pos[0] - origin[0]) / BAR_WIDTH) as usize;
+            if let Some(c) = counts.get(scanline) {
+                ui.tooltip_text(format!("Scanline {}: {} sprites", scanline, c));
+            }
+        }
+        let overflowing: Vec<String> = counts
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| **c > 8)
+            .map(|(i, _)| i.to_string())
+            .collect();
+        if overflowing.is_empty() {
+            ui.text("No scanlines have more than 8 sprites");
+        } else {
+            ui.text_wrapped(format!(
+                "Scanlines with more than 8 sprites{}: {}",
+                if config.emu_settings.scanline_sprite_limit {
+                    " (sprites past the 8th are not drawn)"
+                } else {
+                    ""
+                },
+                overflowing.join(", ")
+            ));
+        }
+    }
     fn format_nametable_text(ppu: &Ppu, cartridge: &Cartridge) -> String {
         [
             [
@@ -6,7 +6,7 @@ use log::*;
 
 use super::{
     key_map::Key,
-    utils::{quickload, quicksave},
+    utils::{quickload, quicksave, screenshot},
 };
 use sdl2::{keyboard::Keycode, EventPump};
 
@@ -54,28 +54,46 @@ impl Input {
         }
         nes.set_controller_state(index, controller);
     }
-    pub fn update(&mut self, nes: &mut Nes, event_pump: &EventPump, config: &mut Config) {
-        // Get keyboard state
-        let keys: Vec<Keycode> = event_pump
+    // Get the keys currently pressed
+    fn pressed_keys(event_pump: &EventPump) -> Vec<Keycode> {
+        event_pump
             .keyboard_state()
             .pressed_scancodes()
             .filter_map(Keycode::from_scancode)
-            .collect();
-        // Update inputs
-        self.update_controller(nes, 0, &keys, config);
-        self.update_controller(nes, 1, &keys, config);
+            .collect()
+    }
+    // Update pausing and volume
+    fn update_playback(&self, keys: &[Keycode], config: &mut Config) {
         let km = &config.key_map;
-        if self.key_pressed(&km.pause, &keys) {
+        if self.key_pressed(&km.pause, keys) {
             config.paused = !config.paused;
         }
-        let diff = if self.key_pressed(&km.volume_up, &keys) {
+        let diff = if self.key_pressed(&km.volume_up, keys) {
             0.1
-        } else if self.key_pressed(&km.volume_down, &keys) {
+        } else if self.key_pressed(&km.volume_down, keys) {
             -0.1
         } else {
             0.0
         };
         config.volume = (config.volume + diff).clamp(0.0, 3.0);
+    }
+    pub fn update(&mut self, nes: &mut Nes, event_pump: &EventPump, config: &mut Config) {
+        // Get keyboard state
+        let keys = Input::pressed_keys(event_pump);
+        // Update inputs
+        self.update_controller(nes, 0, &keys, config);
+        self.update_controller(nes, 1, &keys, config);
+        self.update_playback(&keys, config);
+        let km = &config.key_map;
+        if self.key_pressed(&km.screenshot, &keys) {
+            screenshot(nes, config);
+        }
+        if self.key_pressed(&km.record, &keys) {
+            config.recording = !config.recording;
+        }
+        if self.key_pressed(&km.switch_disk_side, &keys) {
+            nes.cartridge.switch_disk_side();
+        }
 
         // Check for quickload
         if self.key_pressed(&km.quicksave, &keys) {
@@ -89,6 +107,23 @@ impl Input {
 
         self.last_keys = keys;
     }
+    /// Update the input while playing an NSF.
+    ///
+    /// Handles pausing and volume, and returns the change in track requested (`-1`, `0` or `1`).
+    pub fn update_nsf(&mut self, event_pump: &EventPump, config: &mut Config) -> i32 {
+        let keys = Input::pressed_keys(event_pump);
+        self.update_playback(&keys, config);
+        let km = &config.key_map;
+        let change = if self.key_pressed(&km.next_track, &keys) {
+            1
+        } else if self.key_pressed(&km.previous_track, &keys) {
+            -1
+        } else {
+            0
+        };
+        self.last_keys = keys;
+        change
+    }
 }
 
 impl Default for Input {
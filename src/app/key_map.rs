@@ -89,15 +89,26 @@ pub struct KeyMap {
     pub volume_down: Key,
     pub quicksave: Key,
     pub quickload: Key,
+    #[serde(default = "default_screenshot_key")]
+    pub screenshot: Key,
+    #[serde(default = "default_record_key")]
+    pub record: Key,
+    #[serde(default = "default_next_track_key")]
+    pub next_track: Key,
+    #[serde(default = "default_previous_track_key")]
+    pub previous_track: Key,
+    #[serde(default = "default_switch_disk_side_key")]
+    pub switch_disk_side: Key,
+}
+
+macro_rules! sdl_key {
+    ($key: ident) => {
+        Keycode::$key.into()
+    };
 }
 
 impl Default for KeyMap {
     fn default() -> Self {
-        macro_rules! sdl_key {
-            ($key: ident) => {
-                Keycode::$key.into()
-            };
-        }
         KeyMap {
             controllers: [
                 Controller {
@@ -126,6 +137,27 @@ impl Default for KeyMap {
             volume_down: sdl_key!(DOWN),
             quicksave: sdl_key!(F1),
             quickload: sdl_key!(F2),
+            screenshot: default_screenshot_key(),
+            record: default_record_key(),
+            next_track: default_next_track_key(),
+            previous_track: default_previous_track_key(),
+            switch_disk_side: default_switch_disk_side_key(),
         }
     }
 }
+
+fn default_screenshot_key() -> Key {
+    sdl_key!(F12)
+}
+fn default_record_key() -> Key {
+    sdl_key!(F10)
+}
+fn default_next_track_key() -> Key {
+    sdl_key!(RIGHT)
+}
+fn default_previous_track_key() -> Key {
+    sdl_key!(LEFT)
+}
+fn default_switch_disk_side_key() -> Key {
+    sdl_key!(F5)
+}
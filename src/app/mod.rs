@@ -14,6 +14,8 @@ mod key_map;
 pub use key_map::KeyMap;
 mod config;
 pub mod utils;
-pub use config::Config;
+pub use config::{Config, Pacing};
 mod input;
 pub use input::Input;
+mod recorder;
+pub use recorder::{Recorder, WavWriter, Y4mWriter, RECORDING_SAMPLE_RATE};
@@ -0,0 +1,242 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, SeekFrom, Write},
+    path::PathBuf,
+};
+
+use log::*;
+
+use crate::{
+    app::Config,
+    core::{Nes, Palette, CPU_CLOCK_SPEED},
+};
+
+/// The sample rate recorded audio is saved at
+pub const RECORDING_SAMPLE_RATE: u32 = 44_100;
+/// The frame rate of the NES, as a fraction (numerator, denominator).
+/// The NES renders a frame every 29780.5 CPU cycles.
+const FRAME_RATE: (u32, u32) = (2 * CPU_CLOCK_SPEED, 59561);
+
+/// Streams WAV audio to a writer.
+///
+/// Writes mono 32 bit float samples.
+/// The header is written with an empty size, which is filled in by [WavWriter::finish].
+/// Since the sizes are 32 bit, the file can't be bigger than 4GB, which is about 6.7 hours of audio at 44.1kHz.
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    num_samples: u64,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    const HEADER_SIZE: u32 = 44;
+    // The RIFF chunk size, which includes the rest of the header, has to fit in 32 bits
+    const MAX_DATA_SIZE: u64 = u32::MAX as u64 - (WavWriter::<W>::HEADER_SIZE as u64 - 8);
+    /// Create a new [WavWriter] and write the WAV header
+    pub fn new(mut writer: W, sample_rate: u32) -> Result<WavWriter<W>, String> {
+        let mut header = Vec::with_capacity(WavWriter::<W>::HEADER_SIZE as usize);
+        header.extend_from_slice(b"RIFF");
+        // File size, filled in later
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        // IEEE float format
+        header.extend_from_slice(&3u16.to_le_bytes());
+        // Mono
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&sample_rate.to_le_bytes());
+        // Byte rate, block align and bits per sample
+        header.extend_from_slice(&(4 * sample_rate).to_le_bytes());
+        header.extend_from_slice(&4u16.to_le_bytes());
+        header.extend_from_slice(&32u16.to_le_bytes());
+        header.extend_from_slice(b"data");
+        // Data size, filled in later
+        header.extend_from_slice(&0u32.to_le_bytes());
+        writer
+            .write_all(&header)
+            .map_err(|e| format!("Unable to write WAV header: {}", e))?;
+        Ok(WavWriter {
+            writer,
+            num_samples: 0,
+        })
+    }
+    /// Write samples to the WAV file.
+    ///
+    /// Returns an error without writing anything if the samples would make the file too big.
+    pub fn write_samples(&mut self, samples: &[f32]) -> Result<(), String> {
+        let num_samples = self.num_samples + samples.len() as u64;
+        if 4 * num_samples > WavWriter::<W>::MAX_DATA_SIZE {
+            return Err(format!(
+                "WAV file is full after {} samples, since it can't be bigger than 4GB",
+                self.num_samples
+            ));
+        }
+        let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        self.writer
+            .write_all(&bytes)
+            .map_err(|e| format!("Unable to write WAV samples: {}", e))?;
+        self.num_samples = num_samples;
+        Ok(())
+    }
+    /// Fill in the sizes in the header and flush the writer
+    pub fn finish(mut self) -> Result<W, String> {
+        let data_size = (4 * self.num_samples) as u32;
+        let to_err = |e: std::io::Error| format!("Unable to finish WAV file: {}", e);
+        self.writer.seek(SeekFrom::Start(4)).map_err(to_err)?;
+        self.writer
+            .write_all(&(WavWriter::<W>::HEADER_SIZE - 8 + data_size).to_le_bytes())
+            .map_err(to_err)?;
+        self.writer.seek(SeekFrom::Start(40)).map_err(to_err)?;
+        self.writer
+            .write_all(&data_size.to_le_bytes())
+            .map_err(to_err)?;
+        self.writer.seek(SeekFrom::End(0)).map_err(to_err)?;
+        self.writer.flush().map_err(to_err)?;
+        Ok(self.writer)
+    }
+}
+
+/// Streams uncompressed YUV4MPEG2 (.y4m) video to a writer.
+///
+/// Frames are written using 4:4:4 chroma, so no colour information is lost converting from RGB.
+pub struct Y4mWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    /// Create a new [Y4mWriter] and write the stream header
+    pub fn new(mut writer: W, frame_rate: (u32, u32)) -> Result<Y4mWriter<W>, String> {
+        // NES pixels are slightly wider than they are tall
+        writer
+            .write_all(
+                format!(
+                    "YUV4MPEG2 W256 H240 F{}:{} Ip A8:7 C444\n",
+                    frame_rate.0, frame_rate.1
+                )
+                .as_bytes(),
+            )
+            .map_err(|e| format!("Unable to write Y4M header: {}", e))?;
+        Ok(Y4mWriter { writer })
+    }
+    /// Write a single frame of RGB values
+    pub fn write_frame(&mut self, rgb: &[[[u8; 3]; 256]; 240]) -> Result<(), String> {
+        let pixels = rgb.as_flattened();
+        let mut data = Vec::with_capacity(6 + 3 * pixels.len());
+        data.extend_from_slice(b"FRAME\n");
+        // BT.601 conversion, written as separate Y, U and V planes
+        data.extend(pixels.iter().map(|[r, g, b]| {
+            (((66 * *r as i32 + 129 * *g as i32 + 25 * *b as i32 + 128) >> 8) + 16) as u8
+        }));
+        data.extend(pixels.iter().map(|[r, g, b]| {
+            (((-38 * *r as i32 - 74 * *g as i32 + 112 * *b as i32 + 128) >> 8) + 128) as u8
+        }));
+        data.extend(pixels.iter().map(|[r, g, b]| {
+            (((112 * *r as i32 - 94 * *g as i32 - 18 * *b as i32 + 128) >> 8) + 128) as u8
+        }));
+        self.writer
+            .write_all(&data)
+            .map_err(|e| format!("Unable to write Y4M frame: {}", e))
+    }
+    /// Flush the writer
+    pub fn finish(mut self) -> Result<W, String> {
+        self.writer
+            .flush()
+            .map_err(|e| format!("Unable to finish Y4M file: {}", e))?;
+        Ok(self.writer)
+    }
+}
+
+/// Records the emulator's video and audio output to disk.
+///
+/// Streams the video to a .y4m file and the audio to a .wav file as the emulator runs, so
+/// recordings are only limited by the size of the .wav file, which is about 6.7 hours.
+/// Video is recorded once per emulated frame and audio is resampled from the emulator's output,
+/// so both streams are in emulated time and stay in sync regardless of [Config::speed].
+pub struct Recorder {
+    video: Y4mWriter<BufWriter<File>>,
+    audio: WavWriter<BufWriter<File>>,
+    // Buffer used to convert the PPU output to RGB
+    screen_buffer: Box<[[[u8; 3]; 256]; 240]>,
+    // Sum and number of the NES samples that will be averaged into the next output sample
+    sample_sum: f32,
+    sample_count: u32,
+    // Position (in NES samples) of the next output sample
+    next_sample_pos: f64,
+    // Path of the recording files, without the extension
+    path: PathBuf,
+}
+
+impl Recorder {
+    /// Start a new recording.
+    ///
+    /// Creates a .y4m and a .wav file in [Config::recording_dir] containing [Config::game_name]
+    /// and the time the recording was started.
+    pub fn start(config: &Config) -> Result<Recorder, String> {
+        let game = match &config.game_name {
+            Some(n) => format!("{}_", n),
+            None => String::new(),
+        };
+        let time = chrono::Local::now().format("%Y_%m_%d__%H_%M_%S");
+        let name = format!("recording_{}{}", game, time);
+        let create = |ext: &str| {
+            let mut p = config.recording_dir.clone();
+            p.push(format!("{}.{}", name, ext));
+            File::create(&p)
+                .map(BufWriter::new)
+                .map_err(|e| format!("Unable to create {:?}: {}", p, e))
+        };
+        let video = Y4mWriter::new(create("y4m")?, FRAME_RATE)?;
+        let audio = WavWriter::new(create("wav")?, RECORDING_SAMPLE_RATE)?;
+        let mut path = config.recording_dir.clone();
+        path.push(name);
+        info!("Started recording to {:?}", path);
+        Ok(Recorder {
+            video,
+            audio,
+            screen_buffer: Box::new([[[0; 3]; 256]; 240]),
+            sample_sum: 0.0,
+            sample_count: 0,
+            next_sample_pos: 0.0,
+            path,
+        })
+    }
+    /// Record the current frame of the [Nes], using the palette given.
+    ///
+    /// Should be called once after every frame the emulator advances.
+    pub fn add_frame(&mut self, nes: &Nes, palette: &Palette) -> Result<(), String> {
+        nes.ppu
+            .rgb_output_buf_with_palette(&mut self.screen_buffer, palette);
+        self.video.write_frame(&self.screen_buffer)
+    }
+    /// Record audio samples, as output by [Apu::sample_queue][crate::core::Apu::sample_queue].
+    ///
+    /// `sample_rate` is the rate the samples were generated at, which is
+    /// [Settings::output_sample_rate][crate::core::Settings::output_sample_rate] if set or [CPU_CLOCK_SPEED] otherwise.
+    pub fn add_samples(&mut self, samples: &[f32], sample_rate: u32) -> Result<(), String> {
+        let step = sample_rate as f64 / RECORDING_SAMPLE_RATE as f64;
+        let mut output = Vec::with_capacity((samples.len() as f64 / step).ceil() as usize + 1);
+        samples.iter().for_each(|s| {
+            self.sample_sum += s;
+            self.sample_count += 1;
+            self.next_sample_pos -= 1.0;
+            // Average all the samples since the last output sample
+            if self.next_sample_pos <= 0.0 {
+                let sample = self.sample_sum / self.sample_count as f32;
+                // Repeat it if the samples are at a lower rate than the recording
+                while self.next_sample_pos <= 0.0 {
+                    output.push(sample);
+                    self.next_sample_pos += step;
+                }
+                self.sample_sum = 0.0;
+                self.sample_count = 0;
+            }
+        });
+        self.audio.write_samples(&output)
+    }
+    /// Stop recording, and finish writing the files
+    pub fn finish(self) -> Result<(), String> {
+        self.video.finish()?;
+        self.audio.finish()?;
+        info!("Saved recording to {:?}", self.path);
+        Ok(())
+    }
+}
@@ -1,5 +1,9 @@
-use crate::{app::Config, core::Nes};
+use crate::{
+    app::Config,
+    core::{Nes, Palette},
+};
 use log::*;
+use std::path::{Path, PathBuf};
 
 /// Perform a quick save
 ///
@@ -27,6 +31,33 @@ pub fn quicksave(nes: &Nes, config: &mut Config) {
         }
     }
 }
+/// Take a screenshot
+///
+/// Encode the [Nes]'s current screen as a PNG using [Config::palette], and save it to a file in [Config::screenshot_dir]
+/// containing [Config::game_name] and the time the screenshot was taken.
+/// Returns the path of the screenshot if successful.
+pub fn screenshot(nes: &Nes, config: &Config) -> Option<PathBuf> {
+    match nes.ppu.rgb_png(&config.palette) {
+        Err(e) => error!("Unable to create screenshot: {}", e),
+        Ok(data) => {
+            let mut path = config.screenshot_dir.clone();
+            let game = match &config.game_name {
+                Some(n) => format!("{}_", n),
+                None => String::new(),
+            };
+            let time = chrono::Local::now().format("%Y_%m_%d__%H_%M_%S_%3f");
+            path.push(format!("screenshot_{}{}.png", game, time));
+            match std::fs::write(&path, data) {
+                Ok(_) => {
+                    info!("Saved screenshot to {:?}", &path);
+                    return Some(path);
+                }
+                Err(e) => error!("Unable to save screenshot: {}", e),
+            }
+        }
+    }
+    None
+}
 /// Perform a quick load
 ///
 /// Load the savestate at [Config::quickload_file], parse the [Nes] from the bytes, and return the [Nes].
@@ -46,3 +77,20 @@ pub fn quickload(config: &mut Config) -> Option<Nes> {
     }
     None
 }
+/// Load a palette from a .pal file
+///
+/// Read the file at `path` and parse it as either a 64 or 512 colour palette.
+/// Logs any error and returns [None] if the palette could not be loaded.
+pub fn load_palette(path: &Path) -> Option<Palette> {
+    match std::fs::read(path) {
+        Ok(data) => match Palette::from_pal_bytes(&data) {
+            Ok(p) => {
+                info!("Loaded palette at {:?}", path);
+                return Some(p);
+            }
+            Err(e) => error!("Unable to parse palette {:?}: {}", path, e),
+        },
+        Err(e) => error!("Unable to read palette {:?}: {}", path, e),
+    }
+    None
+}
@@ -1,12 +1,16 @@
 use crate::{
     app::Config,
-    core::Nes,
+    core::{Nes, NtscFilter, NTSC_OUTPUT_WIDTH},
     utils::{
         self, check_error, create_f32_slice_vao, create_program, create_screen_texture, set_uniform,
     },
 };
 use glow::{Context, HasContext, NativeProgram, NativeTexture, NativeVertexArray};
-use sdl2::{video::GLContext, VideoSubsystem};
+use log::*;
+use sdl2::{
+    video::{GLContext, SwapInterval},
+    VideoSubsystem,
+};
 
 /// The window of the emulator app.
 ///
@@ -29,6 +33,9 @@ pub struct Window {
     wireframe_vao: NativeVertexArray,
     // Screen data buffer
     screen_buffer: [[[u8; 3]; 256]; 240],
+    // NTSC filter and its (wider) output buffer
+    ntsc_filter: NtscFilter,
+    ntsc_buffer: Box<[[[u8; 3]; NTSC_OUTPUT_WIDTH]; 240]>,
 }
 impl Window {
     /// Create a new [Window] from an SDL video subsystem
@@ -67,6 +74,11 @@ impl Window {
                 wireframe_program,
                 wireframe_vao,
                 screen_buffer: [[[0; 3]; 256]; 240],
+                ntsc_filter: NtscFilter::default(),
+                ntsc_buffer: vec![[[0; 3]; NTSC_OUTPUT_WIDTH]; 240]
+                    .into_boxed_slice()
+                    .try_into()
+                    .unwrap(),
             }
         }
     }
@@ -87,15 +99,24 @@ impl Window {
             self.gl
                 .bind_texture(glow::TEXTURE_2D, Some(self.screen_texture));
             // Copy output
-            nes.ppu.rgb_output_buf(&mut self.screen_buffer);
-            // Flatten to a single slice
-            let texture_data: &[u8] = self.screen_buffer.as_flattened().as_flattened();
+            let (texture_data, width): (&[u8], usize) = if config.ntsc_filter {
+                self.ntsc_filter.settings = config.ntsc_settings;
+                self.ntsc_filter.apply(&nes.ppu, &mut self.ntsc_buffer);
+                (
+                    self.ntsc_buffer.as_flattened().as_flattened(),
+                    NTSC_OUTPUT_WIDTH,
+                )
+            } else {
+                nes.ppu
+                    .rgb_output_buf_with_palette(&mut self.screen_buffer, &config.palette);
+                (self.screen_buffer.as_flattened().as_flattened(), 256)
+            };
             // Pipe to texture
             self.gl.tex_image_2d(
                 glow::TEXTURE_2D,
                 0,
                 glow::RGB as i32,
-                256,
+                width as i32,
                 240,
                 0,
                 glow::RGB,
@@ -179,6 +200,18 @@ impl Window {
         }
         self.window.gl_swap_window();
     }
+    /// Set whether [Window::render] should wait for the monitor's vertical sync
+    pub fn set_vsync(&self, video: &VideoSubsystem, vsync: bool) {
+        self.window.gl_make_current(&self.gl_context).unwrap();
+        let interval = if vsync {
+            SwapInterval::VSync
+        } else {
+            SwapInterval::Immediate
+        };
+        if let Err(e) = video.gl_set_swap_interval(interval) {
+            error!("Unable to set swap interval: {}", e);
+        }
+    }
     /// Get a mutable reference to the SDL [Window][sdl2::video::Window]
     ///
     /// Allows configuration of the underlying SDL window such as changing title, icon, etc
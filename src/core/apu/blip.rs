@@ -0,0 +1,134 @@
+use std::f64::consts::PI;
+
+/// Number of output samples each step is spread over
+const TAPS: usize = 16;
+/// Number of fractional positions a step can be placed at between two output samples
+const PHASES: usize = 64;
+/// Cutoff of the low-pass filter, relative to the Nyquist frequency of the output
+const CUTOFF: f64 = 0.9;
+
+/// A band-limited synthesis buffer, in the style of [blip_buf](https://code.google.com/archive/p/blip-buf/).
+///
+/// Rather than generating a sample every clock cycle and resampling them, the changes (deltas) in the
+/// signal are added at the clock cycle they happen.
+/// Each delta is added as a band-limited step, so the output can be read directly at the output
+/// sample rate without any aliasing.
+/// ```
+/// use yane::core::BlipBuf;
+/// // Clocked at the CPU's speed, output at 44.1 kHz
+/// let mut blip = BlipBuf::new(1_789_000, 44_100);
+/// // A square wave
+/// (0..100).for_each(|i| blip.add_delta(i * 1000, if i % 2 == 0 { 1.0 } else { -1.0 }));
+/// blip.end_frame(100_000);
+/// let mut samples = Vec::new();
+/// blip.read_samples(&mut samples);
+/// assert_eq!(samples.len(), blip.samples_read() as usize);
+/// ```
+#[derive(Debug, Clone)]
+pub struct BlipBuf {
+    clock_rate: u32,
+    sample_rate: u32,
+    // Output samples per clock
+    factor: f64,
+    // Position of the start of the current frame, in output samples relative to the start of `buffer`
+    offset: f64,
+    // Deltas of each output sample, integrated when read
+    buffer: Vec<f32>,
+    // Current value of the integrated output
+    integrator: f32,
+    // Step kernel for each phase
+    kernel: Vec<[f32; TAPS]>,
+    // Total number of samples read
+    samples_read: u64,
+}
+
+impl BlipBuf {
+    /// Create a new buffer.
+    ///
+    /// * `clock_rate` The rate, in hertz, of the clock the deltas are timed with
+    /// * `sample_rate` The sample rate of the output
+    pub fn new(clock_rate: u32, sample_rate: u32) -> BlipBuf {
+        // Windowed sinc, with each phase normalised so that a step always reaches its full height
+        let kernel = (0..PHASES)
+            .map(|phase| {
+                let frac = phase as f64 / PHASES as f64;
+                let mut taps: [f64; TAPS] = core::array::from_fn(|k| {
+                    let t = k as f64 - (TAPS / 2) as f64 + 1.0 - frac;
+                    let x = PI * CUTOFF * t;
+                    let sinc = if x == 0.0 { 1.0 } else { x.sin() / x };
+                    // Blackman window
+                    let w = (t + TAPS as f64 / 2.0) / TAPS as f64;
+                    let window = 0.42 - 0.5 * (2.0 * PI * w).cos() + 0.08 * (4.0 * PI * w).cos();
+                    sinc * window.max(0.0)
+                });
+                let sum: f64 = taps.iter().sum();
+                taps.iter_mut().for_each(|t| *t /= sum);
+                taps.map(|t| t as f32)
+            })
+            .collect();
+        BlipBuf {
+            clock_rate,
+            sample_rate,
+            factor: sample_rate as f64 / clock_rate as f64,
+            offset: 0.0,
+            buffer: Vec::new(),
+            integrator: 0.0,
+            kernel,
+            samples_read: 0,
+        }
+    }
+    /// The rate of the clock the deltas are timed with
+    pub fn clock_rate(&self) -> u32 {
+        self.clock_rate
+    }
+    /// The sample rate of the output
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+    /// Add a change in the signal at a given time.
+    ///
+    /// * `time` The time of the change, in clocks since the start of the current frame
+    /// * `delta` The change in the signal's value
+    pub fn add_delta(&mut self, time: u32, delta: f32) {
+        let pos = self.offset + time as f64 * self.factor;
+        let index = pos.floor() as usize;
+        let phase = ((pos - pos.floor()) * PHASES as f64) as usize % PHASES;
+        if self.buffer.len() < index + TAPS {
+            self.buffer.resize(index + TAPS, 0.0);
+        }
+        self.buffer[index..index + TAPS]
+            .iter_mut()
+            .zip(self.kernel[phase].iter())
+            .for_each(|(b, k)| *b += delta * k);
+    }
+    /// End the current frame, and start a new frame a given number of clocks later.
+    ///
+    /// Samples before the end of the frame are then available to read.
+    pub fn end_frame(&mut self, clocks: u32) {
+        self.offset += clocks as f64 * self.factor;
+    }
+    /// The number of samples that are available to read
+    pub fn samples_available(&self) -> usize {
+        self.offset.floor() as usize
+    }
+    /// The total number of samples read from the buffer
+    pub fn samples_read(&self) -> u64 {
+        self.samples_read
+    }
+    /// Read all the available samples, appending them to `out`.
+    ///
+    /// Returns the number of samples read.
+    pub fn read_samples(&mut self, out: &mut Vec<f32>) -> usize {
+        let available = self.samples_available();
+        if self.buffer.len() < available {
+            self.buffer.resize(available, 0.0);
+        }
+        out.extend(self.buffer.drain(0..available).map(|d| {
+            self.integrator += d;
+            self.integrator
+        }));
+        self.offset -= available as f64;
+        self.samples_read += available as u64;
+        available
+    }
+}
@@ -0,0 +1,73 @@
+use std::f32::consts::PI;
+
+/// A first order high-pass or low-pass filter.
+#[derive(Debug, Clone, Copy)]
+struct OnePoleFilter {
+    high_pass: bool,
+    // Filter coefficient
+    alpha: f32,
+    last_input: f32,
+    last_output: f32,
+}
+
+impl OnePoleFilter {
+    fn new(high_pass: bool, cutoff: f32, sample_rate: f32) -> OnePoleFilter {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate;
+        OnePoleFilter {
+            high_pass,
+            alpha: if high_pass {
+                rc / (rc + dt)
+            } else {
+                dt / (rc + dt)
+            },
+            last_input: 0.0,
+            last_output: 0.0,
+        }
+    }
+    fn process(&mut self, input: f32) -> f32 {
+        let output = if self.high_pass {
+            self.alpha * (self.last_output + input - self.last_input)
+        } else {
+            self.last_output + self.alpha * (input - self.last_output)
+        };
+        self.last_input = input;
+        self.last_output = output;
+        output
+    }
+}
+
+/// The filters the NES applies to its audio output.
+///
+/// Consists of a high-pass filter at 90 Hz, a high-pass filter at 440 Hz and a low-pass filter at 14 kHz.
+/// See [the NESDEV wiki](https://www.nesdev.org/wiki/APU_Mixer).
+#[derive(Debug, Clone)]
+pub struct FilterChain {
+    sample_rate: u32,
+    filters: [OnePoleFilter; 3],
+}
+
+impl FilterChain {
+    /// Create a new filter chain for audio with the sample rate given
+    pub fn new(sample_rate: u32) -> FilterChain {
+        let rate = sample_rate as f32;
+        FilterChain {
+            sample_rate,
+            filters: [
+                OnePoleFilter::new(true, 90.0, rate),
+                OnePoleFilter::new(true, 440.0, rate),
+                OnePoleFilter::new(false, 14_000.0, rate),
+            ],
+        }
+    }
+    /// The sample rate the filter chain was created for
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+    /// Filter a single sample
+    pub fn process(&mut self, sample: f32) -> f32 {
+        self.filters
+            .iter_mut()
+            .fold(sample, |s, filter| filter.process(s))
+    }
+}
@@ -8,6 +8,10 @@ mod noise;
 pub use noise::NoiseRegister;
 mod dmc;
 pub use dmc::{DmcRegister, DMC_RATES};
+mod filter;
+pub use filter::FilterChain;
+mod blip;
+pub use blip::BlipBuf;
 
 use std::cmp::max;
 use std::fmt::Debug;
@@ -15,9 +19,9 @@ use std::fmt::Debug;
 use log::*;
 use serde::{Deserialize, Serialize};
 
-use crate::core::Cartridge;
+use crate::core::{AudioOverflow, Cartridge, Settings, CPU_CLOCK_SPEED};
 
-const LENGTH_TABLE: [usize; 0x20] = [
+pub(crate) const LENGTH_TABLE: [usize; 0x20] = [
     0x0A, 0xFE, 0x14, 0x02, 0x28, 0x04, 0x50, 0x06, 0xA0, 0x08, 0x3C, 0x0A, 0x0E, 0x0C, 0x1A, 0x0E,
     0x0C, 0x10, 0x18, 0x12, 0x30, 0x14, 0x60, 0x16, 0xC0, 0x18, 0x48, 0x1A, 0x10, 0x1C, 0x20, 0x1E,
 ];
@@ -28,11 +32,85 @@ const NOISE_TIMER_PERIODS: [u32; 16] = [
 
 const STEPS: [i32; 5] = [7457, 14912, 22371, 29828, 37281];
 
+/// The audio channels of the APU.
+///
+/// Used to index the per-channel settings in [Settings::channel_mix] and the queues returned
+/// by [Apu::channel_sample_queues].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioChannel {
+    Pulse1 = 0,
+    Pulse2 = 1,
+    Triangle = 2,
+    Noise = 3,
+    Dmc = 4,
+}
+
+impl AudioChannel {
+    /// Every audio channel, in order
+    pub const ALL: [AudioChannel; 5] = [
+        AudioChannel::Pulse1,
+        AudioChannel::Pulse2,
+        AudioChannel::Triangle,
+        AudioChannel::Noise,
+        AudioChannel::Dmc,
+    ];
+    /// Get the name of the channel
+    pub fn name(&self) -> &'static str {
+        match self {
+            AudioChannel::Pulse1 => "Pulse 1",
+            AudioChannel::Pulse2 => "Pulse 2",
+            AudioChannel::Triangle => "Triangle",
+            AudioChannel::Noise => "Noise",
+            AudioChannel::Dmc => "DMC",
+        }
+    }
+}
+
+/// The expansion audio chips some cartridges contain, which are mixed with the APU's output.
+///
+/// Used to index the per-chip volumes in [Settings::expansion_volumes].
+/// See [Mapper::expansion_audio][crate::core::Mapper::expansion_audio].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpansionAudio {
+    Fds = 0,
+    Mmc5 = 1,
+    Vrc6 = 2,
+    Vrc7 = 3,
+    Namco163 = 4,
+    Sunsoft5B = 5,
+}
+
+impl ExpansionAudio {
+    /// Every expansion audio chip, in order
+    pub const ALL: [ExpansionAudio; 6] = [
+        ExpansionAudio::Fds,
+        ExpansionAudio::Mmc5,
+        ExpansionAudio::Vrc6,
+        ExpansionAudio::Vrc7,
+        ExpansionAudio::Namco163,
+        ExpansionAudio::Sunsoft5B,
+    ];
+    /// Get the name of the chip
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExpansionAudio::Fds => "FDS",
+            ExpansionAudio::Mmc5 => "MMC5",
+            ExpansionAudio::Vrc6 => "VRC6",
+            ExpansionAudio::Vrc7 => "VRC7",
+            ExpansionAudio::Namco163 => "Namco 163",
+            ExpansionAudio::Sunsoft5B => "Sunsoft 5B",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 /// The APU (Audio Processing Unit) of the NES.
 ///
 /// Handles all audio processing and generates a new audio sample every clock cycle.
 /// These samples are available via [Apu::sample_queue].
+/// If [Settings::output_sample_rate] is set, the samples are instead synthesized directly at that
+/// sample rate using a band-limited buffer ([BlipBuf]).
+/// The cartridge's expansion audio (if it has any) is clocked and mixed in every clock cycle as well.
 pub struct Apu {
     /// The pulse (variable length square) registers
     pub pulse_registers: [PulseRegister; 2],
@@ -49,6 +127,21 @@ pub struct Apu {
     cycles: i32,
     // Queue of audio samples
     queue: Vec<f32>,
+    // Queues of the audio samples of each channel, only filled if [Settings::channel_sample_queues] is set
+    #[serde(skip)]
+    channel_queues: [Vec<f32>; 5],
+    // Band-limited synthesis buffer, used if [Settings::output_sample_rate] is set
+    #[serde(skip)]
+    blip: Option<BlipBuf>,
+    // Last sample added to the band-limited synthesis buffer
+    #[serde(skip)]
+    last_sample: f32,
+    // Output filters, used if [Settings::audio_filters] is set
+    #[serde(skip)]
+    filters: Option<FilterChain>,
+    // Number of samples discarded because the queue was full
+    #[serde(skip)]
+    dropped_samples: u64,
 }
 
 impl Default for Apu {
@@ -70,6 +163,11 @@ impl Apu {
             mode: 0,
             cycles: 0,
             queue: Vec::new(),
+            channel_queues: Default::default(),
+            blip: None,
+            last_sample: 0.0,
+            filters: None,
+            dropped_samples: 0,
         }
     }
     /// Write a single byte of data to the APU given its address in CPU memory space.
@@ -220,13 +318,45 @@ impl Apu {
     /// Advance the APU by a given number of CPU cycles.
     /// * `cpu_cycles`: The number of cycles to advance
     /// * `cartridge`: The cartridge currently inserted in the NES
-    pub fn advance_cpu_cycles(&mut self, cpu_cycles: u32, cartridge: &mut Cartridge) {
-        const MAX_QUEUE_LEN: usize = 2usize.pow(16);
-        (0..cpu_cycles).for_each(|_| {
-            if self.queue.len() < MAX_QUEUE_LEN {
-                self.queue.push(self.mixer_output());
-            } else {
-                warn!("Max queue size hit");
+    /// * `settings`: The emulator settings, used for the per-channel mixer settings
+    pub fn advance_cpu_cycles(
+        &mut self,
+        cpu_cycles: u32,
+        cartridge: &mut Cartridge,
+        settings: &Settings,
+    ) {
+        let gains = settings.channel_gains();
+        let expansion_gain = cartridge
+            .mapper
+            .expansion_audio()
+            .map_or(0.0, |chip| settings.expansion_gain(chip));
+        self.update_output_settings(settings);
+        (0..cpu_cycles).for_each(|i| {
+            cartridge.mapper.clock_expansion_audio();
+            let sample =
+                self.mix(&gains) + cartridge.mapper.expansion_audio_output() * expansion_gain;
+            match self.blip.as_mut() {
+                // Add the change in the output to the band-limited buffer
+                Some(blip) => {
+                    if sample != self.last_sample {
+                        blip.add_delta(i, sample - self.last_sample);
+                        self.last_sample = sample;
+                    }
+                }
+                None => {
+                    let sample = match self.filters.as_mut() {
+                        Some(f) => f.process(sample),
+                        None => sample,
+                    };
+                    self.queue.push(sample);
+                }
+            }
+            if settings.channel_sample_queues {
+                let outputs = self.channel_outputs(&gains);
+                self.channel_queues
+                    .iter_mut()
+                    .zip(outputs)
+                    .for_each(|(q, o)| q.push(o));
             }
             self.cycles += 1;
             if self.mode == 0 {
@@ -344,6 +474,62 @@ impl Apu {
                 }
             }
         });
+        // Move the samples from the band-limited buffer to the queue
+        if let Some(blip) = self.blip.as_mut() {
+            blip.end_frame(cpu_cycles);
+            let start = self.queue.len();
+            blip.read_samples(&mut self.queue);
+            if let Some(f) = self.filters.as_mut() {
+                self.queue[start..]
+                    .iter_mut()
+                    .for_each(|s| *s = f.process(*s));
+            }
+        }
+        let dropped = Apu::limit_queue(&mut self.queue, settings);
+        if dropped > 0 {
+            warn!("Audio sample queue is full, dropped {} samples", dropped);
+            self.dropped_samples += dropped as u64;
+        }
+        self.channel_queues.iter_mut().for_each(|q| {
+            Apu::limit_queue(q, settings);
+        });
+    }
+    /// Remove samples from a queue according to [Settings::audio_overflow] if it is over capacity.
+    /// Returns the number of samples removed.
+    fn limit_queue(queue: &mut Vec<f32>, settings: &Settings) -> usize {
+        let excess = queue.len().saturating_sub(settings.audio_queue_capacity);
+        match settings.audio_overflow {
+            AudioOverflow::DropNewest => queue.truncate(settings.audio_queue_capacity),
+            AudioOverflow::DropOldest => {
+                queue.drain(0..excess);
+            }
+            AudioOverflow::Unbounded => return 0,
+        }
+        excess
+    }
+    /// Create or remove the band-limited buffer and output filters if the settings have changed
+    fn update_output_settings(&mut self, settings: &Settings) {
+        let rate = settings.output_sample_rate.unwrap_or(CPU_CLOCK_SPEED);
+        match settings.output_sample_rate {
+            Some(r) => {
+                if self.blip.as_ref().is_none_or(|b| b.sample_rate() != r) {
+                    self.blip = Some(BlipBuf::new(CPU_CLOCK_SPEED, r));
+                    self.last_sample = 0.0;
+                }
+            }
+            None => self.blip = None,
+        }
+        if settings.audio_filters {
+            if self
+                .filters
+                .as_ref()
+                .is_none_or(|f| f.sample_rate() != rate)
+            {
+                self.filters = Some(FilterChain::new(rate));
+            }
+        } else {
+            self.filters = None;
+        }
     }
     fn on_quater_frame(&mut self) {
         self.pulse_registers.iter_mut().for_each(|reg| {
@@ -386,34 +572,60 @@ impl Apu {
     }
     /// Get the current output of the mixer unit of the APU.
     /// Should always be a value between 0 and 1.
+    /// Uses the default volume for every channel, use [Apu::mix] to change the volume of each channel.
+    pub fn mixer_output(&self) -> f32 {
+        self.mix(&[1.0; 5])
+    }
+    /// Get the current output of the mixer unit of the APU, with a gain applied to each channel.
+    ///
+    /// `gains` is indexed by [AudioChannel] and is applied to each channel before the nonlinear mix.
     /// Each CPU cycles, the APU will call this function and store the result in its output queue,
     /// which can be accessed via [Apu::sample_queue]
-    pub fn mixer_output(&self) -> f32 {
+    pub fn mix(&self, gains: &[f32; 5]) -> f32 {
         // Add up the pulse registers
-        let pulse: u32 = self.pulse_registers.iter().map(|p| p.value()).sum();
-        if pulse > 30 {
-            warn!("Pulse is higher than 30 ({})", pulse);
-        }
-        let pulse_out = if pulse == 0 {
-            0.0
-        } else {
-            95.88 / ((8128.0 / pulse as f32) + 100.0)
-        };
-        let t = self.triangle_register.value();
-        let n = self.noise_register.value();
-        let d = self.dmc_register.output;
-        let tnd_out = if t + n + d == 0 {
+        let pulse = self.pulse_registers[0].value() as f32 * gains[AudioChannel::Pulse1 as usize]
+            + self.pulse_registers[1].value() as f32 * gains[AudioChannel::Pulse2 as usize];
+        let t = self.triangle_register.value() as f32 * gains[AudioChannel::Triangle as usize];
+        let n = self.noise_register.value() as f32 * gains[AudioChannel::Noise as usize];
+        let d = self.dmc_register.output as f32 * gains[AudioChannel::Dmc as usize];
+        // Gains above 1 can push the output past its usual range, so clip it like an overdriven amplifier
+        (Apu::pulse_out(pulse) + Apu::tnd_out(t, n, d)).clamp(0.0, 1.0)
+    }
+    /// Get the output of each channel on its own, indexed by [AudioChannel]
+    fn channel_outputs(&self, gains: &[f32; 5]) -> [f32; 5] {
+        let pulse = |i: usize| self.pulse_registers[i].value() as f32 * gains[i];
+        [
+            Apu::pulse_out(pulse(0)),
+            Apu::pulse_out(pulse(1)),
+            Apu::tnd_out(
+                self.triangle_register.value() as f32 * gains[AudioChannel::Triangle as usize],
+                0.0,
+                0.0,
+            ),
+            Apu::tnd_out(
+                0.0,
+                self.noise_register.value() as f32 * gains[AudioChannel::Noise as usize],
+                0.0,
+            ),
+            Apu::tnd_out(
+                0.0,
+                0.0,
+                self.dmc_register.output as f32 * gains[AudioChannel::Dmc as usize],
+            ),
+        ]
+    }
+    fn pulse_out(pulse: f32) -> f32 {
+        if pulse == 0.0 {
             0.0
         } else {
-            159.79 / (1.0 / (t as f32 / 8227.0 + n as f32 / 12241.0 + d as f32 / 22638.0) + 100.0)
-        };
-        let v = tnd_out + pulse_out;
-        if !(0.0..=1.0).contains(&v) {
-            error!("Invalid mixer output: {}", v);
-            // Mute this so I don't destroy my ears
+            95.88 / ((8128.0 / pulse) + 100.0)
+        }
+    }
+    fn tnd_out(t: f32, n: f32, d: f32) -> f32 {
+        if t + n + d == 0.0 {
             0.0
         } else {
-            v
+            159.79 / (1.0 / (t / 8227.0 + n / 12241.0 + d / 22638.0) + 100.0)
         }
     }
     /// Get and clear the sample queue of the APU.
@@ -426,4 +638,48 @@ impl Apu {
         std::mem::swap(&mut self.queue, &mut v);
         v
     }
+    /// Read queued samples into a buffer, removing them from the queue.
+    ///
+    /// Reads as many samples as are available, up to the length of `out`, and returns the number of samples read.
+    /// Set [Settings::output_sample_rate] to have the samples generated at the rate of the audio device,
+    /// and [Settings::audio_overflow] to choose what happens if samples are not read fast enough.
+    /// ```
+    /// use yane::core::{Nes, Settings};
+    /// let mut nes = Nes::new();
+    /// let settings = Settings {
+    ///     output_sample_rate: Some(48_000),
+    ///     ..Settings::default()
+    /// };
+    /// nes.advance_frame(&settings).unwrap();
+    /// let mut buffer = [0.0; 1024];
+    /// let read = nes.apu.read_samples(&mut buffer);
+    /// // About 800 samples are generated each frame at 48 kHz
+    /// assert!(read > 700 && read < 900);
+    /// assert_eq!(nes.apu.samples_available(), 0);
+    /// ```
+    pub fn read_samples(&mut self, out: &mut [f32]) -> usize {
+        let len = out.len().min(self.queue.len());
+        out[..len].copy_from_slice(&self.queue[..len]);
+        self.queue.drain(..len);
+        len
+    }
+    /// The number of samples waiting in the queue
+    pub fn samples_available(&self) -> usize {
+        self.queue.len()
+    }
+    /// The total number of samples discarded because the queue was full.
+    ///
+    /// See [Settings::audio_overflow].
+    pub fn dropped_samples(&self) -> u64 {
+        self.dropped_samples
+    }
+    /// Get and clear the sample queues of each channel.
+    ///
+    /// Returns each channel's samples (indexed by [AudioChannel]) since the last call to [Apu::channel_sample_queues],
+    /// and clears the internal queues.
+    /// Each sample is the output of the mixer if only that channel was playing.
+    /// Samples are only stored if [Settings::channel_sample_queues] is set.
+    pub fn channel_sample_queues(&mut self) -> [Vec<f32>; 5] {
+        std::mem::take(&mut self.channel_queues)
+    }
 }
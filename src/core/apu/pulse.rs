@@ -47,12 +47,16 @@ impl PulseRegister {
             || self.timer_reload < 8
     }
     pub fn value(&self) -> u32 {
-        if self.muted() || DUTY_CYCLES[self.duty as usize][self.sequencer] == 0 {
+        if self.muted() || self.sequencer_output() == 0 {
             0
         } else {
             self.envelope.value()
         }
     }
+    /// Get the current output of the sequencer, ignoring the volume, either 0 or 1
+    pub fn sequencer_output(&self) -> u32 {
+        DUTY_CYCLES[self.duty as usize][self.sequencer]
+    }
     pub fn set_enabled(&mut self, enabled: bool) {
         self.enabled = enabled;
         if !self.enabled {
@@ -1,10 +1,33 @@
-use crate::core::mappers::AxRom;
+use crate::core::mappers::{
+    AxRom, BnRom, Camerica, ColorDreams, ExRom, Fme7, FxRom, GxRom, JalecoJf05, JalecoJf11,
+    Namco163, Nina0306, UnRom512, Vrc4, Vrc6, Vrc7,
+};
 
 use super::{
-    mappers::{CnRom, NRom, PxRom, SxRom, TxRom, UxRom},
+    mappers::{CnRom, NRom, NsfMapper, PxRom, SxRom, TxRom, UxRom},
     CartridgeMemory, NametableArrangement,
 };
+use crate::core::ExpansionAudio;
+use serde::{Deserialize, Serialize};
 use std::fmt::{Debug, Display};
+
+/// What the PPU is currently reading from or writing to the cartridge.
+///
+/// Given to the mapper with [Mapper::set_ppu_fetch] before the PPU accesses the cartridge,
+/// for mappers that behave differently depending on what is being fetched.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PpuFetch {
+    /// Fetching the nametable, attribute and pattern data of a background tile
+    Background,
+    /// Fetching the pattern data of sprites
+    Sprites,
+    /// The 2 unused nametable fetches at the end of each scanline
+    Dummy,
+    /// Reading or writing through `PPUDATA`
+    #[default]
+    Cpu,
+}
+
 /// Interface for the various cartridge mappers.
 ///
 /// Reading and writing bytes will go through these functions,
@@ -44,23 +67,119 @@ pub trait Mapper: Debug + Display {
     fn irq(&mut self) -> bool {
         false
     }
+    /// Set what the PPU is about to fetch from the cartridge
+    fn set_ppu_fetch(&mut self, _fetch: PpuFetch) {}
+    /// Called whenever the CPU writes to one of the PPU's registers (`0x2000..0x2008`).
+    ///
+    /// Some cartridges listen to these writes to keep track of the PPU's state.
+    fn write_ppu_register(&mut self, _addr: usize, _value: u8) {}
+    /// Read a byte of nametable data given an address in PPU memory space (`0x2000..0x3000`).
+    ///
+    /// Return [None] to read from the PPU's VRAM (using [Mapper::transform_nametable_addr] if the nametable
+    /// arrangement is [NametableArrangement::Custom]), or the byte if the cartridge provides the nametable itself.
+    fn read_nametable(&mut self, _ppu_addr: usize, _mem: &CartridgeMemory) -> Option<u8> {
+        None
+    }
+    /// Write a byte of nametable data given an address in PPU memory space (`0x2000..0x3000`).
+    ///
+    /// Return [false] to write to the PPU's VRAM, or [true] if the cartridge handled the write itself.
+    fn write_nametable(
+        &mut self,
+        _ppu_addr: usize,
+        _mem: &mut CartridgeMemory,
+        _value: u8,
+    ) -> bool {
+        false
+    }
     /// Get the iNes mapper number of this mapper
     fn mapper_num(&self) -> u32;
     /// Use a custom methodto transform VRAM memory address
     fn transform_nametable_addr(&self, _addr: usize) -> usize {
         0
     }
+    /// Get the expansion audio chip the cartridge contains, if any
+    fn expansion_audio(&self) -> Option<ExpansionAudio> {
+        None
+    }
+    /// Advance the cartridge's expansion audio by one CPU cycle.
+    ///
+    /// Called by [Apu][crate::core::Apu] every CPU cycle, before reading [Mapper::expansion_audio_output].
+    fn clock_expansion_audio(&mut self) {}
+    /// Get the current output of the cartridge's expansion audio.
+    ///
+    /// The output is relative to the APU's, at the level the chip is mixed at on real hardware,
+    /// so that a value of 1.0 is as loud as the APU's maximum output.
+    /// Mixed with the APU's channels by [Apu][crate::core::Apu], after applying the volume in
+    /// [Settings::expansion_volumes][crate::core::Settings::expansion_volumes].
+    fn expansion_audio_output(&self) -> f32 {
+        0.0
+    }
+    /// Get any battery backed memory other than PRG RAM, such as RAM inside the mapper chip or flash PRG ROM.
+    ///
+    /// Saved after PRG RAM in the savedata if the cartridge has battery backed RAM, see [Nes::savedata][crate::core::Nes::savedata].
+    fn battery_ram<'a>(&'a self, _mem: &'a CartridgeMemory) -> &'a [u8] {
+        &[]
+    }
+    /// Load the mapper's battery backed memory from the savedata.
+    ///
+    /// `data` is the part of the savedata after PRG RAM, and is the same length as [Mapper::battery_ram].
+    fn load_battery_ram(&mut self, _mem: &mut CartridgeMemory, _data: &[u8]) {}
+    /// Get the number of disk sides the cartridge has.
+    ///
+    /// Only used by the Famicom Disk System, for all other cartridges this is 0.
+    fn disk_sides(&self) -> usize {
+        0
+    }
+    /// Get the disk side currently inserted, if there is one
+    fn disk_side(&self) -> Option<usize> {
+        None
+    }
+    /// Eject the disk currently inserted, and then insert the disk side given (if any)
+    fn insert_disk(&mut self, _side: Option<usize>) {}
+    /// Get an IPS patch of all the changes that have been written to the disk, if the cartridge has one.
+    ///
+    /// The patch applies to the disk image the cartridge was created from.
+    fn disk_patch(&self) -> Option<Vec<u8>> {
+        None
+    }
 }
-/// Get an implementation of `Mapper` given a certain mapper number
-pub fn get_mapper(mapper_id: usize) -> Option<Box<dyn Mapper>> {
+/// Get an implementation of `Mapper` given a certain mapper number and NES 2.0 submapper number.
+///
+/// `flags` is byte 6 of the iNES header, which some mappers use to choose between variants.
+/// `mem` is the cartridge's memory, which some mappers use to detect the board when there is no submapper.
+pub fn get_mapper(
+    mapper_id: usize,
+    submapper: u8,
+    flags: u8,
+    mem: &CartridgeMemory,
+) -> Option<Box<dyn Mapper>> {
     Some(match mapper_id {
         0 => Box::new(NRom::default()),
-        1 => Box::new(SxRom::default()),
-        2 => Box::new(UxRom::default()),
-        3 => Box::new(CnRom::default()),
-        4 => Box::new(TxRom::default()),
-        7 => Box::new(AxRom::default()),
+        1 => Box::new(SxRom::new(submapper == 3, submapper, mem)),
+        2 => Box::new(UxRom::new(submapper)),
+        3 => Box::new(CnRom::new(submapper)),
+        4 | 118 | 119 => Box::new(TxRom::new(mapper_id as u32, submapper)),
+        5 => Box::new(ExRom::default()),
+        7 => Box::new(AxRom::new(submapper)),
         9 => Box::new(PxRom::default()),
+        10 => Box::new(FxRom::default()),
+        11 => Box::new(ColorDreams::default()),
+        19 => Box::new(Namco163::default()),
+        21 | 22 | 23 | 25 => Box::new(Vrc4::new(mapper_id as u32, submapper)),
+        24 => Box::new(Vrc6::new(false)),
+        26 => Box::new(Vrc6::new(true)),
+        30 => Box::new(UnRom512::new(flags)),
+        31 => Box::new(NsfMapper::default()),
+        34 => Box::new(BnRom::new(submapper)),
+        66 => Box::new(GxRom::default()),
+        69 => Box::new(Fme7::new(submapper)),
+        71 => Box::new(Camerica::new(submapper)),
+        79 => Box::new(Nina0306::new(false)),
+        85 => Box::new(Vrc7::new(submapper)),
+        87 => Box::new(JalecoJf05::default()),
+        113 => Box::new(Nina0306::new(true)),
+        140 => Box::new(JalecoJf11::default()),
+        155 => Box::new(SxRom::new(true, submapper, mem)),
         _ => return None,
     })
 }
@@ -72,3 +191,28 @@ pub fn bank_addr(bank_size: usize, bank_num: usize, offset: usize) -> usize {
 pub fn num_banks(bank_size: usize, mem: &[u8]) -> usize {
     (mem.len() - 1) / bank_size + 1
 }
+/// Get the value a register in PRG ROM space (`0x8000-0xFFFF`) receives when written to.
+///
+/// On boards with bus conflicts the PRG ROM outputs the byte at the address while the CPU is writing to it,
+/// so the register receives the written value ANDed with the ROM's byte.
+/// Writes outside of PRG ROM space, or on boards without bus conflicts, are unchanged.
+pub fn bus_conflict<M: Mapper + ?Sized>(
+    mapper: &M,
+    bus_conflicts: bool,
+    cpu_addr: usize,
+    mem: &CartridgeMemory,
+    value: u8,
+) -> u8 {
+    if bus_conflicts && cpu_addr >= 0x8000 {
+        value & mapper.read_cpu(cpu_addr, mem)
+    } else {
+        value
+    }
+}
+/// Whether a discrete logic board has bus conflicts given its NES 2.0 submapper.
+///
+/// Submapper 2 means the board has bus conflicts, and 1 means it doesn't.
+/// Boards without a submapper are assumed not to have bus conflicts, since games for them usually avoid the conflicts.
+pub fn has_bus_conflicts(submapper: u8) -> bool {
+    submapper == 2
+}
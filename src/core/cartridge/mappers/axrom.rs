@@ -2,12 +2,31 @@ use std::fmt::{Debug, Display};
 
 use serde::{Deserialize, Serialize};
 
-use crate::core::{cartridge::mapper::bank_addr, CartridgeMemory, Mapper, NametableArrangement};
+use crate::core::{
+    cartridge::mapper::{bank_addr, bus_conflict, has_bus_conflicts},
+    CartridgeMemory, Mapper, NametableArrangement,
+};
 
 #[derive(Default, Serialize, Deserialize)]
+/// AxROM cartridge mapper (mapper 7)
+///
+/// Has bus conflicts if the NES 2.0 submapper is 2.
 pub struct AxRom {
     prg_bank: usize,
     vram_select: usize,
+    #[serde(default)]
+    bus_conflicts: bool,
+}
+
+impl AxRom {
+    /// Create a new AxROM mapper given the NES 2.0 submapper
+    pub fn new(submapper: u8) -> AxRom {
+        AxRom {
+            prg_bank: 0,
+            vram_select: 0,
+            bus_conflicts: has_bus_conflicts(submapper),
+        }
+    }
 }
 
 impl Display for AxRom {
@@ -28,6 +47,7 @@ impl Mapper for AxRom {
         mem.read_prg_rom(bank_addr(0x8000, self.prg_bank, cpu_addr))
     }
     fn write_cpu(&mut self, cpu_addr: usize, mem: &mut crate::core::CartridgeMemory, value: u8) {
+        let value = bus_conflict(self, self.bus_conflicts, cpu_addr, mem, value);
         self.prg_bank = (value & 0x07) as usize;
         self.vram_select = ((value & 0x10) >> 4) as usize
     }
@@ -0,0 +1,116 @@
+use std::fmt::{Debug, Display};
+
+use crate::core::{
+    cartridge::mapper::{bank_addr, bus_conflict},
+    CartridgeMemory, Mapper,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+/// BNROM and NINA-001 cartridge mappers (mapper 34)
+///
+/// The NES 2.0 submapper chooses between them, 1 for the NINA-001 and 2 for BNROM.
+/// Without a submapper, cartridges with more than 8KB of CHR ROM are assumed to be the NINA-001.
+///
+/// BNROM switches 32KB PRG banks through `0x8000-0xFFFF`, with bus conflicts.
+/// The NINA-001 has PRG RAM and switches 32KB PRG banks and two 4KB CHR banks through `0x7FFD-0x7FFF`.
+/// See [the NESDEV wiki](https://www.nesdev.org/wiki/INES_Mapper_034).
+pub struct BnRom {
+    // None if the board should be guessed from the CHR ROM size
+    nina001: Option<bool>,
+    prg_bank: usize,
+    chr_banks: [usize; 2],
+}
+
+impl BnRom {
+    /// Create a new BNROM/NINA-001 mapper given the NES 2.0 submapper
+    pub fn new(submapper: u8) -> BnRom {
+        BnRom {
+            nina001: match submapper {
+                1 => Some(true),
+                2 => Some(false),
+                _ => None,
+            },
+            prg_bank: 0,
+            chr_banks: [0, 1],
+        }
+    }
+    fn is_nina001(&self, mem: &CartridgeMemory) -> bool {
+        self.nina001.unwrap_or(mem.chr_rom.len() > 0x2000)
+    }
+}
+
+#[typetag::serde]
+impl Mapper for BnRom {
+    fn mapper_num(&self) -> u32 {
+        34
+    }
+    fn read_cpu(&self, cpu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        match cpu_addr {
+            0x6000..0x8000 => mem.read_prg_ram(cpu_addr - 0x6000),
+            0x8000..0x10000 => mem.read_prg_rom(bank_addr(0x8000, self.prg_bank, cpu_addr)),
+            _ => 0,
+        }
+    }
+    fn write_cpu(&mut self, cpu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        let v = value as usize;
+        if self.is_nina001(mem) {
+            // The registers are also written to PRG RAM
+            if (0x6000..0x8000).contains(&cpu_addr) {
+                mem.write_prg_ram(cpu_addr - 0x6000, value);
+            }
+            match cpu_addr {
+                0x7FFD => self.prg_bank = v & 0x01,
+                0x7FFE => self.chr_banks[0] = v & 0x0F,
+                0x7FFF => self.chr_banks[1] = v & 0x0F,
+                _ => {}
+            }
+        } else if cpu_addr >= 0x8000 {
+            self.prg_bank = bus_conflict(self, true, cpu_addr, mem, value) as usize;
+        }
+    }
+    fn read_ppu_debug(&self, ppu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        if self.is_nina001(mem) {
+            mem.read_chr(bank_addr(
+                0x1000,
+                self.chr_banks[ppu_addr / 0x1000],
+                ppu_addr,
+            ))
+        } else {
+            mem.read_chr(ppu_addr)
+        }
+    }
+    fn write_ppu(&mut self, ppu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        if self.is_nina001(mem) {
+            mem.write_chr(
+                bank_addr(0x1000, self.chr_banks[ppu_addr / 0x1000], ppu_addr),
+                value,
+            )
+        } else {
+            mem.write_chr(ppu_addr, value)
+        }
+    }
+}
+
+impl Display for BnRom {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self.nina001 {
+                Some(true) => "NINA-001",
+                Some(false) => "BNROM",
+                None => "BNROM/NINA-001",
+            }
+        )
+    }
+}
+impl Debug for BnRom {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} prg_bank={} chr_banks={:?}",
+            self, self.prg_bank, self.chr_banks
+        )
+    }
+}
@@ -0,0 +1,88 @@
+use std::fmt::{Debug, Display};
+
+use crate::core::{
+    cartridge::mapper::{bank_addr, num_banks},
+    CartridgeMemory, Mapper, NametableArrangement,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+/// Camerica BF9093/BF9097 cartridge mapper (mapper 71)
+///
+/// Switches a 16KB PRG bank at `0x8000`, with the last bank fixed at `0xC000`.
+/// The BF9097 (submapper 1, used by Fire Hawk) also selects one screen mirroring through `0x8000-0x9FFF`.
+/// Without a submapper only `0x9000-0x9FFF` selects the mirroring, since other games don't write there.
+/// See [the NESDEV wiki](https://www.nesdev.org/wiki/INES_Mapper_071).
+pub struct Camerica {
+    bf9097: bool,
+    prg_bank: usize,
+    // The screen used for one screen mirroring, or None to use the cartridge's mirroring
+    one_screen: Option<usize>,
+}
+
+impl Camerica {
+    /// Create a new Camerica mapper given the NES 2.0 submapper
+    pub fn new(submapper: u8) -> Camerica {
+        Camerica {
+            bf9097: submapper == 1,
+            prg_bank: 0,
+            one_screen: None,
+        }
+    }
+}
+
+#[typetag::serde]
+impl Mapper for Camerica {
+    fn mapper_num(&self) -> u32 {
+        71
+    }
+    fn read_cpu(&self, cpu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        match cpu_addr {
+            0x8000..0xC000 => mem.read_prg_rom(bank_addr(0x4000, self.prg_bank, cpu_addr)),
+            0xC000..0x10000 => mem.read_prg_rom(bank_addr(
+                0x4000,
+                num_banks(0x4000, &mem.prg_rom) - 1,
+                cpu_addr,
+            )),
+            _ => 0,
+        }
+    }
+    fn write_cpu(&mut self, cpu_addr: usize, _mem: &mut CartridgeMemory, value: u8) {
+        match cpu_addr {
+            0x8000..0x9000 if self.bf9097 => self.one_screen = Some(((value >> 4) & 0x01) as usize),
+            0x9000..0xA000 => self.one_screen = Some(((value >> 4) & 0x01) as usize),
+            0xC000..0x10000 => self.prg_bank = (value & 0x0F) as usize,
+            _ => {}
+        }
+    }
+    fn read_ppu_debug(&self, ppu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        mem.read_chr(ppu_addr)
+    }
+    fn write_ppu(&mut self, ppu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        mem.write_chr(ppu_addr, value)
+    }
+    fn nametable_arrangement(&self, mem: &CartridgeMemory) -> NametableArrangement {
+        match self.one_screen {
+            Some(_) => NametableArrangement::Custom,
+            None => mem.nametable_arrangement,
+        }
+    }
+    fn transform_nametable_addr(&self, addr: usize) -> usize {
+        self.one_screen.unwrap_or(0) * 0x400 + addr % 0x400
+    }
+}
+
+impl Display for Camerica {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", if self.bf9097 { "BF9097" } else { "BF909x" })
+    }
+}
+impl Debug for Camerica {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} prg_bank={} one_screen={:?}",
+            self, self.prg_bank, self.one_screen
+        )
+    }
+}
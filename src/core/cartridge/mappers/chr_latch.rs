@@ -0,0 +1,36 @@
+//! The tile triggered CHR latches shared by the MMC2 and MMC4.
+//!
+//! Each pattern table has two 4KB CHR banks, and a latch selecting between them which is set to `0xFD` or `0xFE` when
+//! the PPU fetches that tile from the pattern table.
+//! The latch is only changed after the fetch, so the tile itself still uses the old bank.
+//! The mappers store the banks and latches themselves, so that their serialized fields stay the same.
+//! See [the NESDEV wiki](https://www.nesdev.org/wiki/MMC2).
+use crate::core::cartridge::mapper::bank_addr;
+
+/// Get the address in CHR memory of a PPU address.
+///
+/// `chr_banks` is indexed first by pattern table and then by latch (0 = `0xFD`, 1 = `0xFE`).
+pub fn chr_addr(chr_banks: &[[usize; 2]; 2], latches: &[usize; 2], ppu_addr: usize) -> usize {
+    let table = (ppu_addr / 0x1000) % 2;
+    let bank = if latches[table] == 0xFE {
+        chr_banks[table][1]
+    } else {
+        chr_banks[table][0]
+    };
+    bank_addr(0x1000, bank, ppu_addr)
+}
+
+/// Update the latches after the PPU has fetched from an address.
+///
+/// `any_row` is whether the first pattern table's latch is set by any row of the tile like the second one (MMC4),
+/// or only by the first row (MMC2).
+pub fn update_latches(latches: &mut [usize; 2], ppu_addr: usize, any_row: bool) {
+    let table = (ppu_addr / 0x1000) % 2;
+    let tile_addr = ppu_addr % 0x1000;
+    let row_mask = if table == 0 && !any_row { 0xFFF } else { 0xFF8 };
+    match tile_addr & row_mask {
+        0xFD8 => latches[table] = 0xFD,
+        0xFE8 => latches[table] = 0xFE,
+        _ => {}
+    }
+}
@@ -1,13 +1,30 @@
 use std::fmt::{Debug, Display};
 
-use crate::core::{cartridge::mapper::bank_addr, CartridgeMemory, Mapper};
+use crate::core::{
+    cartridge::mapper::{bank_addr, bus_conflict, has_bus_conflicts},
+    CartridgeMemory, Mapper,
+};
 use log::*;
 use serde::{Deserialize, Serialize};
 
 #[derive(Default, Serialize, Deserialize)]
 /// CnROM cartridge mapper (mapper 3)
+///
+/// Has bus conflicts if the NES 2.0 submapper is 2.
 pub struct CnRom {
     chr_bank_select: usize,
+    #[serde(default)]
+    bus_conflicts: bool,
+}
+
+impl CnRom {
+    /// Create a new CnROM mapper given the NES 2.0 submapper
+    pub fn new(submapper: u8) -> CnRom {
+        CnRom {
+            chr_bank_select: 0,
+            bus_conflicts: has_bus_conflicts(submapper),
+        }
+    }
 }
 
 #[typetag::serde]
@@ -23,8 +40,9 @@ impl Mapper for CnRom {
             mem.read_prg_rom(cpu_addr)
         }
     }
-    fn write_cpu(&mut self, cpu_addr: usize, _mem: &mut CartridgeMemory, value: u8) {
+    fn write_cpu(&mut self, cpu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
         if cpu_addr >= 0x8000 {
+            let value = bus_conflict(self, self.bus_conflicts, cpu_addr, mem, value);
             self.chr_bank_select = (value & 0x03) as usize;
         }
     }
@@ -0,0 +1,472 @@
+use std::{
+    cell::Cell,
+    fmt::{Debug, Display},
+};
+
+use crate::core::{
+    cartridge::{
+        mapper::{bank_addr, PpuFetch},
+        mappers::exrom_audio::ExRomAudio,
+    },
+    CartridgeMemory, ExpansionAudio, Mapper, NametableArrangement,
+};
+use serde::{Deserialize, Serialize};
+
+/// Number of CPU cycles without the PPU reading from the cartridge before the MMC5 decides the PPU has stopped
+/// rendering.
+/// The real MMC5 waits 3 cycles, but the PPU here fetches in bursts so we wait a full scanline.
+const IDLE_CYCLES: u32 = 114;
+
+/// What a nametable is mapped to, set by `0x5105`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum NametableSource {
+    /// One of the two pages of the PPU's VRAM
+    Vram(usize),
+    /// The MMC5's internal expansion RAM
+    ExRam,
+    /// The fill tile and attribute set by `0x5106` and `0x5107`
+    Fill,
+}
+
+/// ExROM cartridge mapper, i.e. the MMC5 (mapper 5)
+///
+/// Supports every PRG and CHR banking mode, expansion RAM in all four modes (including extended attributes),
+/// fill mode, the vertical split, the scanline IRQ, the multiplier and the expansion audio.
+/// See [the NESDEV wiki](https://www.nesdev.org/wiki/MMC5).
+#[derive(Serialize, Deserialize)]
+pub struct ExRom {
+    prg_mode: usize,
+    chr_mode: usize,
+    prg_ram_protect: [u8; 2],
+    exram_mode: u8,
+    nametables: [NametableSource; 4],
+    fill_tile: u8,
+    fill_attribute: u8,
+    prg_ram_bank: usize,
+    // 0x5114-0x5117
+    prg_banks: [usize; 4],
+    // 0x5120-0x5127, used for sprites
+    chr_banks_a: [usize; 8],
+    // 0x5128-0x512B, used for the background when using 8x16 sprites
+    chr_banks_b: [usize; 4],
+    chr_upper_bits: usize,
+    // Whether the last CHR bank register written was in the B set
+    last_chr_b: bool,
+    exram: Vec<u8>,
+    // Vertical split
+    split_enabled: bool,
+    split_right: bool,
+    split_tile: usize,
+    split_scroll: usize,
+    split_bank: usize,
+    // Scanline IRQ
+    irq_scanline: usize,
+    irq_enabled: bool,
+    irq_pending: Cell<bool>,
+    in_frame: bool,
+    scanline: usize,
+    // Cycles since the PPU last read from the cartridge
+    idle_cycles: u32,
+    // Used to detect the start of a scanline, which is when the PPU reads the same nametable address 3 times in a row
+    last_nametable_addr: Option<usize>,
+    nametable_reads: u32,
+    // Multiplier
+    multiplicand: u8,
+    multiplier: u8,
+    // PPU state
+    large_sprites: bool,
+    fetch: PpuFetch,
+    // Index of the background tile being fetched on the current scanline
+    tile_num: usize,
+    // The ExRAM byte of the current background tile, used in extended attribute mode
+    tile_exram: u8,
+    // Whether the current background tile is part of the vertical split, and if so its position in the split
+    tile_in_split: bool,
+    split_x: usize,
+    split_y: usize,
+    audio: ExRomAudio,
+}
+
+impl Default for ExRom {
+    fn default() -> Self {
+        ExRom {
+            prg_mode: 3,
+            chr_mode: 3,
+            prg_ram_protect: [0; 2],
+            exram_mode: 0,
+            nametables: [NametableSource::Vram(0); 4],
+            fill_tile: 0,
+            fill_attribute: 0,
+            prg_ram_bank: 0,
+            prg_banks: [0xFF; 4],
+            chr_banks_a: [0; 8],
+            chr_banks_b: [0; 4],
+            chr_upper_bits: 0,
+            last_chr_b: false,
+            exram: vec![0; 0x400],
+            split_enabled: false,
+            split_right: false,
+            split_tile: 0,
+            split_scroll: 0,
+            split_bank: 0,
+            irq_scanline: 0,
+            irq_enabled: false,
+            irq_pending: Cell::new(false),
+            in_frame: false,
+            scanline: 0,
+            idle_cycles: 0,
+            last_nametable_addr: None,
+            nametable_reads: 0,
+            multiplicand: 0xFF,
+            multiplier: 0xFF,
+            large_sprites: false,
+            fetch: PpuFetch::Cpu,
+            tile_num: 0,
+            tile_exram: 0,
+            tile_in_split: false,
+            split_x: 0,
+            split_y: 0,
+            audio: ExRomAudio::default(),
+        }
+    }
+}
+
+impl ExRom {
+    // Get the 8KB bank and whether it is in ROM for an address in 0x6000..0x10000
+    fn prg_bank(&self, cpu_addr: usize) -> (usize, bool) {
+        if cpu_addr < 0x8000 {
+            return (self.prg_ram_bank, false);
+        }
+        // Index of the 8KB slot in 0x8000..0x10000
+        let slot = (cpu_addr - 0x8000) / 0x2000;
+        let (reg, bank) = match (self.prg_mode, slot) {
+            // 32KB
+            (0, _) => (3, (self.prg_banks[3] & !0x03) + slot),
+            // 16KB
+            (1, 0..2) | (2, 0..2) => (1, (self.prg_banks[1] & !0x01) + slot),
+            (1, _) => (3, (self.prg_banks[3] & !0x01) + slot - 2),
+            // 8KB
+            (2, 2) => (2, self.prg_banks[2]),
+            (2, _) => (3, self.prg_banks[3]),
+            (_, s) => (s, self.prg_banks[s]),
+        };
+        // The last register always maps ROM
+        let rom = reg == 3 || (self.prg_banks[reg] & 0x80) != 0;
+        (bank & 0x7F, rom)
+    }
+    fn prg_ram_writable(&self) -> bool {
+        self.prg_ram_protect == [0x02, 0x01]
+    }
+    // Whether to use the B set of CHR banks (0x5128-0x512B)
+    fn use_chr_b(&self) -> bool {
+        if !self.large_sprites {
+            return false;
+        }
+        match self.fetch {
+            PpuFetch::Background | PpuFetch::Dummy => true,
+            PpuFetch::Sprites => false,
+            PpuFetch::Cpu => self.last_chr_b,
+        }
+    }
+    fn chr_addr(&self, ppu_addr: usize) -> usize {
+        if self.fetch == PpuFetch::Background {
+            if self.tile_in_split {
+                // Replace the fine Y scroll with the split's
+                let addr = (ppu_addr & 0xFF8) | (self.split_y & 0x07);
+                return bank_addr(0x1000, self.split_bank, addr);
+            }
+            if self.exram_mode == 1 {
+                let bank = (self.tile_exram & 0x3F) as usize | (self.chr_upper_bits << 6);
+                return bank_addr(0x1000, bank, ppu_addr);
+            }
+        }
+        let (size, reg) = match self.chr_mode {
+            0 => (0x2000, 7),
+            1 => (0x1000, 3 + 4 * (ppu_addr / 0x1000)),
+            2 => (0x800, 1 + 2 * (ppu_addr / 0x800)),
+            _ => (0x400, ppu_addr / 0x400),
+        };
+        let bank = if self.use_chr_b() {
+            self.chr_banks_b[reg % 4]
+        } else {
+            self.chr_banks_a[reg]
+        };
+        bank_addr(size, bank, ppu_addr)
+    }
+    // Y scroll of the tile being fetched in the vertical split
+    fn current_split_y(&self) -> usize {
+        // The first 2 tiles are fetched on the previous scanline
+        let scanline = if self.tile_num < 2 {
+            if self.in_frame {
+                self.scanline + 1
+            } else {
+                0
+            }
+        } else {
+            self.scanline
+        };
+        (self.split_scroll + scanline) % 240
+    }
+    fn exram_readable_as_nametable(&self) -> bool {
+        self.exram_mode < 2
+    }
+    // Called whenever the PPU reads from the cartridge while rendering
+    fn on_ppu_read(&mut self) {
+        self.idle_cycles = 0;
+    }
+    // Update the scanline detection with a nametable read
+    fn detect_scanline(&mut self, ppu_addr: usize) {
+        if self.last_nametable_addr == Some(ppu_addr) {
+            self.nametable_reads += 1;
+            if self.nametable_reads == 2 {
+                if self.in_frame {
+                    self.scanline += 1;
+                    if self.scanline == self.irq_scanline {
+                        self.irq_pending.set(true);
+                    }
+                } else {
+                    self.in_frame = true;
+                    self.scanline = 0;
+                }
+            }
+        } else {
+            self.nametable_reads = 0;
+        }
+        self.last_nametable_addr = Some(ppu_addr);
+    }
+    fn end_frame(&mut self) {
+        self.in_frame = false;
+        self.last_nametable_addr = None;
+    }
+}
+
+#[typetag::serde]
+impl Mapper for ExRom {
+    fn mapper_num(&self) -> u32 {
+        5
+    }
+    fn read_cpu(&self, cpu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        match cpu_addr {
+            0x5000..0x5016 => self.audio.read(cpu_addr),
+            0x5204 => {
+                let value = if self.irq_pending.get() { 0x80 } else { 0 }
+                    | if self.in_frame { 0x40 } else { 0 };
+                self.irq_pending.set(false);
+                value
+            }
+            0x5205 => (self.multiplicand as u16 * self.multiplier as u16) as u8,
+            0x5206 => ((self.multiplicand as u16 * self.multiplier as u16) >> 8) as u8,
+            0x5C00..0x6000 if !self.exram_readable_as_nametable() => self.exram[cpu_addr - 0x5C00],
+            0x6000..0x10000 => {
+                let (bank, rom) = self.prg_bank(cpu_addr);
+                let value = if rom {
+                    mem.read_prg_rom(bank_addr(0x2000, bank, cpu_addr))
+                } else {
+                    mem.read_prg_ram(bank_addr(0x2000, bank & 0x07, cpu_addr))
+                };
+                if cpu_addr < 0xC000 {
+                    self.audio.on_prg_read(value);
+                }
+                value
+            }
+            _ => 0,
+        }
+    }
+    fn write_cpu(&mut self, cpu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        let v = value as usize;
+        match cpu_addr {
+            0x5000..0x5016 => self.audio.write(cpu_addr, value),
+            0x5100 => self.prg_mode = v & 0x03,
+            0x5101 => self.chr_mode = v & 0x03,
+            0x5102 => self.prg_ram_protect[0] = value & 0x03,
+            0x5103 => self.prg_ram_protect[1] = value & 0x03,
+            0x5104 => self.exram_mode = value & 0x03,
+            0x5105 => {
+                self.nametables = core::array::from_fn(|i| match (v >> (2 * i)) & 0x03 {
+                    0 => NametableSource::Vram(0),
+                    1 => NametableSource::Vram(1),
+                    2 => NametableSource::ExRam,
+                    _ => NametableSource::Fill,
+                })
+            }
+            0x5106 => self.fill_tile = value,
+            0x5107 => self.fill_attribute = value & 0x03,
+            0x5113 => self.prg_ram_bank = v & 0x07,
+            0x5114..0x5118 => self.prg_banks[cpu_addr - 0x5114] = v,
+            0x5120..0x5128 => {
+                self.chr_banks_a[cpu_addr - 0x5120] = v | (self.chr_upper_bits << 8);
+                self.last_chr_b = false;
+            }
+            0x5128..0x512C => {
+                self.chr_banks_b[cpu_addr - 0x5128] = v | (self.chr_upper_bits << 8);
+                self.last_chr_b = true;
+            }
+            0x5130 => self.chr_upper_bits = v & 0x03,
+            0x5200 => {
+                self.split_enabled = (value & 0x80) != 0;
+                self.split_right = (value & 0x40) != 0;
+                self.split_tile = v & 0x1F;
+            }
+            0x5201 => self.split_scroll = v,
+            0x5202 => self.split_bank = v,
+            0x5203 => self.irq_scanline = v,
+            0x5204 => self.irq_enabled = (value & 0x80) != 0,
+            0x5205 => self.multiplicand = value,
+            0x5206 => self.multiplier = value,
+            0x5C00..0x6000 => match self.exram_mode {
+                // Can only be written while rendering when used as a nametable
+                0 | 1 => self.exram[cpu_addr - 0x5C00] = if self.in_frame { value } else { 0 },
+                2 => self.exram[cpu_addr - 0x5C00] = value,
+                _ => {}
+            },
+            0x6000..0x10000 => {
+                let (bank, rom) = self.prg_bank(cpu_addr);
+                if !rom && self.prg_ram_writable() {
+                    mem.write_prg_ram(bank_addr(0x2000, bank & 0x07, cpu_addr), value);
+                }
+            }
+            _ => {}
+        }
+    }
+    fn read_ppu(&mut self, ppu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        if self.fetch != PpuFetch::Cpu {
+            self.on_ppu_read();
+            self.last_nametable_addr = None;
+        }
+        self.read_ppu_debug(ppu_addr, mem)
+    }
+    fn read_ppu_debug(&self, ppu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        mem.read_chr(self.chr_addr(ppu_addr))
+    }
+    fn write_ppu(&mut self, ppu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        let addr = self.chr_addr(ppu_addr);
+        mem.write_chr(addr, value);
+    }
+    fn nametable_arrangement(&self, _mem: &CartridgeMemory) -> NametableArrangement {
+        NametableArrangement::Custom
+    }
+    fn transform_nametable_addr(&self, addr: usize) -> usize {
+        match self.nametables[(addr / 0x400) % 4] {
+            NametableSource::Vram(page) => page * 0x400 + addr % 0x400,
+            // Not in VRAM, but something valid has to be returned
+            _ => addr % 0x400,
+        }
+    }
+    fn read_nametable(&mut self, ppu_addr: usize, _mem: &CartridgeMemory) -> Option<u8> {
+        if self.fetch != PpuFetch::Cpu {
+            self.on_ppu_read();
+            self.detect_scanline(ppu_addr);
+        }
+        let offset = ppu_addr % 0x400;
+        let is_attribute = offset >= 0x3C0;
+        if self.fetch == PpuFetch::Background {
+            if !is_attribute {
+                // Start of a new tile
+                self.tile_in_split = self.split_enabled
+                    && self.exram_readable_as_nametable()
+                    && if self.split_right {
+                        self.tile_num >= self.split_tile
+                    } else {
+                        self.tile_num < self.split_tile
+                    };
+                if self.tile_in_split {
+                    self.split_x = self.tile_num % 32;
+                    self.split_y = self.current_split_y();
+                    self.tile_num += 1;
+                    return Some(self.exram[(self.split_y / 8) * 32 + self.split_x]);
+                }
+                self.tile_exram = self.exram[offset];
+                self.tile_num += 1;
+            } else if self.tile_in_split {
+                let x = self.split_x;
+                let y = self.split_y / 8;
+                let attribute = self.exram[0x3C0 + (y / 4) * 8 + x / 4];
+                let palette = (attribute >> (((y & 0x02) << 1) | (x & 0x02))) & 0x03;
+                return Some(palette * 0x55);
+            } else if self.exram_mode == 1 {
+                return Some((self.tile_exram >> 6) * 0x55);
+            }
+        }
+        match self.nametables[(ppu_addr / 0x400) % 4] {
+            NametableSource::Vram(_) => None,
+            NametableSource::ExRam => Some(if self.exram_readable_as_nametable() {
+                self.exram[offset]
+            } else {
+                0
+            }),
+            NametableSource::Fill => Some(if is_attribute {
+                self.fill_attribute * 0x55
+            } else {
+                self.fill_tile
+            }),
+        }
+    }
+    fn write_nametable(&mut self, ppu_addr: usize, _mem: &mut CartridgeMemory, value: u8) -> bool {
+        match self.nametables[(ppu_addr / 0x400) % 4] {
+            NametableSource::Vram(_) => false,
+            NametableSource::ExRam => {
+                if self.exram_readable_as_nametable() {
+                    self.exram[ppu_addr % 0x400] = value;
+                }
+                true
+            }
+            NametableSource::Fill => true,
+        }
+    }
+    fn set_ppu_fetch(&mut self, fetch: PpuFetch) {
+        // Sprites are fetched between the tiles of one scanline and the first tiles of the next
+        if fetch == PpuFetch::Sprites {
+            self.tile_num = 0;
+        }
+        self.fetch = fetch;
+    }
+    fn write_ppu_register(&mut self, addr: usize, value: u8) {
+        match addr {
+            0x2000 => self.large_sprites = (value & 0x20) != 0,
+            // Rendering disabled
+            0x2001 if (value & 0x18) == 0 => self.end_frame(),
+            _ => {}
+        }
+    }
+    fn advance_cpu_cycles(&mut self, cycles: u32) {
+        if self.in_frame {
+            self.idle_cycles += cycles;
+            if self.idle_cycles > IDLE_CYCLES {
+                self.end_frame();
+            }
+        }
+    }
+    fn irq(&mut self) -> bool {
+        (self.irq_enabled && self.irq_pending.get()) || self.audio.irq()
+    }
+    fn expansion_audio(&self) -> Option<ExpansionAudio> {
+        Some(ExpansionAudio::Mmc5)
+    }
+    fn clock_expansion_audio(&mut self) {
+        self.audio.clock();
+    }
+    fn expansion_audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+}
+
+impl Debug for ExRom {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "ExROM prg_mode={} chr_mode={} prg_banks={:X?} exram_mode={} in_frame={} scanline={} irq_scanline={}",
+            self.prg_mode,
+            self.chr_mode,
+            self.prg_banks,
+            self.exram_mode,
+            self.in_frame,
+            self.scanline,
+            self.irq_scanline
+        )
+    }
+}
+impl Display for ExRom {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ExROM")
+    }
+}
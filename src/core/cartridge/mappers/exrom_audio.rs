@@ -0,0 +1,144 @@
+use std::cell::Cell;
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::apu::{PulseRegister, LENGTH_TABLE};
+
+/// Number of CPU cycles between each clock of the envelopes and length counters (about 240Hz)
+const FRAME_PERIOD: u32 = 7457;
+/// Output of a pulse channel for each step of volume, relative to the APU's output
+const PULSE_VOLUME: f32 = 0.00752;
+/// Output of the PCM channel for each step, relative to the APU's output
+const PCM_VOLUME: f32 = 0.0017;
+
+/// The expansion audio of the MMC5.
+///
+/// Two pulse channels that are identical to the APU's except that they have no sweep unit,
+/// and a raw 8 bit PCM channel.
+/// See [the NESDEV wiki](https://www.nesdev.org/wiki/MMC5_audio).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExRomAudio {
+    pulses: [PulseRegister; 2],
+    frame_timer: u32,
+    odd_cycle: bool,
+    // PCM
+    pcm_read_mode: bool,
+    pcm_irq_enabled: bool,
+    pcm_irq: Cell<bool>,
+    pcm_output: Cell<u8>,
+}
+
+impl ExRomAudio {
+    /// Read one of the audio registers (`0x5000..0x5016`)
+    pub fn read(&self, addr: usize) -> u8 {
+        match addr {
+            0x5010 => {
+                let value = if self.pcm_irq.get() && self.pcm_irq_enabled {
+                    0x80
+                } else {
+                    0x00
+                };
+                self.pcm_irq.set(false);
+                value
+            }
+            0x5015 => self.pulses.iter().enumerate().fold(0, |acc, (i, p)| {
+                if p.length_counter.load > 0 {
+                    acc | (1 << i)
+                } else {
+                    acc
+                }
+            }),
+            _ => 0,
+        }
+    }
+    /// Write to one of the audio registers (`0x5000..0x5016`)
+    pub fn write(&mut self, addr: usize, value: u8) {
+        match addr {
+            0x5000..0x5008 => {
+                let p = &mut self.pulses[(addr - 0x5000) / 4];
+                match addr % 4 {
+                    0 => {
+                        p.duty = ((value & 0xC0) >> 6) as u32;
+                        p.length_counter.halt = (value & 0x20) != 0;
+                        p.envelope.constant = (value & 0x10) != 0;
+                        p.envelope.volume = (value & 0x0F) as usize;
+                    }
+                    // No sweep unit
+                    1 => {}
+                    2 => p.timer_reload = (p.timer_reload & 0x0700) | value as usize,
+                    _ => {
+                        p.timer_reload = (p.timer_reload & 0x00FF) | ((value as usize & 0x07) << 8);
+                        if p.enabled {
+                            p.length_counter.load = LENGTH_TABLE[(value as usize & 0xF8) >> 3];
+                        }
+                        p.envelope.decay = 0xF;
+                        p.envelope.divider = p.envelope.volume;
+                        p.sequencer = 0;
+                    }
+                }
+            }
+            0x5010 => {
+                self.pcm_read_mode = (value & 0x01) != 0;
+                self.pcm_irq_enabled = (value & 0x80) != 0;
+            }
+            // Writing 0 has no effect in write mode
+            0x5011 if !self.pcm_read_mode && value != 0 => self.pcm_output.set(value),
+            0x5015 => {
+                self.pulses[0].set_enabled((value & 0x01) != 0);
+                self.pulses[1].set_enabled((value & 0x02) != 0);
+            }
+            _ => {}
+        }
+    }
+    /// Called whenever the CPU reads from `0x8000..0xC000`, which the PCM channel can play in read mode
+    pub fn on_prg_read(&self, value: u8) {
+        if self.pcm_read_mode {
+            if value == 0 {
+                self.pcm_irq.set(true);
+            } else {
+                self.pcm_output.set(value);
+            }
+        }
+    }
+    /// [true] if the PCM channel is triggering an IRQ
+    pub fn irq(&self) -> bool {
+        self.pcm_irq.get() && self.pcm_irq_enabled
+    }
+    /// Advance the audio by one CPU cycle
+    pub fn clock(&mut self) {
+        self.frame_timer += 1;
+        if self.frame_timer >= FRAME_PERIOD {
+            self.frame_timer = 0;
+            self.pulses.iter_mut().for_each(|p| {
+                p.envelope.clock(p.length_counter.halt);
+                p.length_counter.clock();
+            });
+        }
+        // Pulse timers are clocked every other CPU cycle, like the APU's
+        self.odd_cycle = !self.odd_cycle;
+        if !self.odd_cycle {
+            self.pulses.iter_mut().for_each(|p| {
+                p.timer = (p.timer + 1) % p.timer_reload.max(1);
+                if p.timer == 0 {
+                    p.sequencer = (p.sequencer + 1) % 8;
+                }
+            });
+        }
+    }
+    /// The current output, relative to the APU's output
+    pub fn output(&self) -> f32 {
+        // Unlike the APU, short periods do not mute the pulse channels
+        let pulses: u32 = self
+            .pulses
+            .iter()
+            .map(|p| {
+                if !p.enabled || p.length_counter.muted() {
+                    0
+                } else {
+                    p.sequencer_output() * p.envelope.value()
+                }
+            })
+            .sum();
+        pulses as f32 * PULSE_VOLUME + self.pcm_output.get() as f32 * PCM_VOLUME
+    }
+}
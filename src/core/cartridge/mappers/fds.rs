@@ -0,0 +1,490 @@
+use std::{
+    cell::Cell,
+    fmt::{Debug, Display},
+};
+
+use crate::core::{
+    cartridge::{mappers::fds_audio::FdsAudio, CartridgeMemory},
+    ips, ExpansionAudio, Mapper, NametableArrangement, CPU_CLOCK_SPEED,
+};
+use log::*;
+use serde::{Deserialize, Serialize};
+
+/// Size of a disk side in a .fds file
+pub const FDS_SIDE_SIZE: usize = 65500;
+/// Size of a disk side once the gaps and CRCs have been added
+const RAW_SIDE_SIZE: usize = 0x14000;
+/// Length of the gap at the start of a side, in bytes
+const LEADING_GAP: usize = 28300 / 8;
+/// Length of the gap after each block, in bytes
+const BLOCK_GAP: usize = 976 / 8;
+/// Number of CPU cycles it takes the drive to read or write a byte
+const CYCLES_PER_BYTE: u32 = 150;
+/// Number of CPU cycles between the motor being turned on and the drive starting to read
+const MOTOR_DELAY: u32 = 50_000;
+/// Number of CPU cycles a disk is ejected for when switching sides
+const SWITCH_DELAY: u32 = CPU_CLOCK_SPEED;
+
+/// Get the length of a block in a disk side, given its type and the size given by the last file header
+fn block_len(block_type: u8, file_size: usize) -> Option<usize> {
+    match block_type {
+        // Disk info
+        1 => Some(56),
+        // File amount
+        2 => Some(2),
+        // File header
+        3 => Some(16),
+        // File data
+        4 => Some(1 + file_size),
+        _ => None,
+    }
+}
+
+/// Convert a side from a .fds file to the data actually on the disk.
+///
+/// Adds the gaps between blocks, the bit marking the start of each block, and each block's CRC.
+fn add_gaps(side: &[u8]) -> Vec<u8> {
+    let mut raw = vec![0; LEADING_GAP];
+    let mut i = 0;
+    let mut file_size = 0;
+    while let Some(len) = side.get(i).and_then(|t| block_len(*t, file_size)) {
+        let Some(block) = side.get(i..i + len) else {
+            break;
+        };
+        if block[0] == 3 {
+            file_size = u16::from_le_bytes([block[13], block[14]]) as usize;
+        }
+        raw.push(0x80);
+        raw.extend_from_slice(block);
+        // CRC, which is never checked
+        raw.extend_from_slice(&[0x4D, 0x62]);
+        raw.extend(std::iter::repeat_n(0, BLOCK_GAP));
+        i += len;
+    }
+    raw.resize(raw.len().max(RAW_SIDE_SIZE), 0);
+    raw
+}
+
+/// Convert the data on a disk back to a side of a .fds file.
+fn remove_gaps(raw: &[u8]) -> Vec<u8> {
+    let mut side = Vec::with_capacity(FDS_SIDE_SIZE);
+    let mut i = 0;
+    let mut file_size = 0;
+    loop {
+        // Skip the gap
+        while raw.get(i) == Some(&0) {
+            i += 1;
+        }
+        // Skip the start of block mark
+        i += 1;
+        let Some(len) = raw.get(i).and_then(|t| block_len(*t, file_size)) else {
+            break;
+        };
+        let Some(block) = raw.get(i..i + len) else {
+            break;
+        };
+        if block[0] == 3 {
+            file_size = u16::from_le_bytes([block[13], block[14]]) as usize;
+        }
+        side.extend_from_slice(block);
+        // Skip the CRC
+        i += len + 2;
+    }
+    side.resize(FDS_SIDE_SIZE, 0);
+    side
+}
+
+/// Famicom Disk System RAM adapter and disk drive.
+///
+/// Acts as a cartridge containing 32KB of PRG RAM at `0x6000..0xE000`, the disk BIOS at `0xE000..0x10000`,
+/// 8KB of CHR RAM, and the registers used to control the disk drive, timer IRQ and expansion audio.
+/// The disk is emulated at the level of the bytes passing under the drive's head.
+/// See [the NESDEV wiki](https://www.nesdev.org/wiki/Family_Computer_Disk_System).
+#[derive(Serialize, Deserialize)]
+pub struct Fds {
+    // The contents of each side of the disk(s), including gaps
+    sides: Vec<Vec<u8>>,
+    // The fwNES header of the .fds file, if it has one
+    header: Vec<u8>,
+    // The original .fds file without any patch applied, used to create patches
+    original: Vec<u8>,
+    // Currently inserted side
+    side: Option<usize>,
+    // Side to insert once the switch delay is over
+    next_side: Option<usize>,
+    switch_delay: u32,
+    // Registers
+    disk_enabled: bool,
+    sound_enabled: bool,
+    irq_reload: u16,
+    irq_counter: u16,
+    irq_enabled: bool,
+    irq_repeat: bool,
+    timer_irq: Cell<bool>,
+    disk_irq: Cell<bool>,
+    // Drive state
+    motor_on: bool,
+    reset_transfer: bool,
+    read_mode: bool,
+    crc_control: bool,
+    last_crc_control: bool,
+    disk_ready: bool,
+    disk_irq_enabled: bool,
+    transfer_complete: Cell<bool>,
+    read_data: u8,
+    write_data: u8,
+    position: usize,
+    delay: u32,
+    end_of_head: bool,
+    scanning: bool,
+    gap_ended: bool,
+    crc: u16,
+    audio: FdsAudio,
+}
+
+impl Fds {
+    /// Create a new RAM adapter with the contents of a .fds file inserted.
+    ///
+    /// The file may or may not have a fwNES header.
+    /// If `patch` is given, it is an IPS patch of the changes previously made to the disk, and is applied to it.
+    pub fn new(original: &[u8], patch: Option<&[u8]>) -> Result<Fds, String> {
+        let patched = patch.and_then(|p| match ips::apply(original, p) {
+            Ok(d) => Some(d),
+            Err(e) => {
+                error!("Unable to apply disk changes: {}", e);
+                None
+            }
+        });
+        let disk = patched.as_deref().unwrap_or(original);
+        let header_len = if disk.starts_with(b"FDS\x1A") {
+            16.min(disk.len())
+        } else {
+            0
+        };
+        let data = &disk[header_len..];
+        if data.len() < FDS_SIDE_SIZE {
+            return Err(format!(
+                "FDS image is too small to contain a disk side ({} bytes)",
+                data.len()
+            ));
+        }
+        if !data.len().is_multiple_of(FDS_SIDE_SIZE) {
+            warn!(
+                "FDS image is not a multiple of the side size ({} bytes)",
+                data.len()
+            );
+        }
+        let sides: Vec<Vec<u8>> = data.chunks_exact(FDS_SIDE_SIZE).map(add_gaps).collect();
+        info!("Loaded FDS image with {} sides", sides.len());
+        Ok(Fds {
+            header: disk[..header_len].to_vec(),
+            original: original.to_vec(),
+            sides,
+            side: Some(0),
+            next_side: None,
+            switch_delay: 0,
+            disk_enabled: false,
+            sound_enabled: false,
+            irq_reload: 0,
+            irq_counter: 0,
+            irq_enabled: false,
+            irq_repeat: false,
+            timer_irq: Cell::new(false),
+            disk_irq: Cell::new(false),
+            motor_on: false,
+            reset_transfer: false,
+            read_mode: true,
+            crc_control: false,
+            last_crc_control: false,
+            disk_ready: false,
+            disk_irq_enabled: false,
+            transfer_complete: Cell::new(false),
+            read_data: 0,
+            write_data: 0,
+            position: 0,
+            delay: 0,
+            end_of_head: true,
+            scanning: false,
+            gap_ended: false,
+            crc: 0,
+            audio: FdsAudio::default(),
+        })
+    }
+    /// Get the current contents of the disk(s) as a .fds file, with the same header as the original file
+    pub fn disk_image(&self) -> Vec<u8> {
+        let mut image = self.header.clone();
+        self.sides
+            .iter()
+            .for_each(|s| image.extend_from_slice(&remove_gaps(s)));
+        image
+    }
+    fn update_crc(&mut self, value: u8) {
+        // CRC-16/KERMIT, processed one bit at a time
+        (0..8).for_each(|i| {
+            let carry = (self.crc & 1) != 0;
+            self.crc = (self.crc >> 1) | ((((value >> i) & 1) as u16) << 15);
+            if carry {
+                self.crc ^= 0x8408;
+            }
+        });
+    }
+    fn clock_timer(&mut self) {
+        if self.irq_enabled && self.disk_enabled {
+            if self.irq_counter == 0 {
+                self.timer_irq.set(true);
+                self.irq_counter = self.irq_reload;
+                if !self.irq_repeat {
+                    self.irq_enabled = false;
+                }
+            } else {
+                self.irq_counter -= 1;
+            }
+        }
+    }
+    fn clock_drive(&mut self) {
+        // Insert the next side once the switch is over
+        if self.switch_delay > 0 {
+            self.switch_delay -= 1;
+            if self.switch_delay == 0 {
+                self.side = self.next_side.take();
+                info!("Inserted disk side {:?}", self.side);
+            }
+        }
+        let Some(side) = self.side else {
+            self.end_of_head = true;
+            self.scanning = false;
+            return;
+        };
+        if !self.motor_on {
+            self.end_of_head = true;
+            self.scanning = false;
+            return;
+        }
+        if self.reset_transfer && !self.scanning {
+            return;
+        }
+        // Move the head back to the start of the disk
+        if self.end_of_head {
+            self.delay = MOTOR_DELAY;
+            self.end_of_head = false;
+            self.position = 0;
+            self.gap_ended = false;
+            return;
+        }
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+        self.scanning = true;
+        let mut irq = self.disk_irq_enabled;
+        if self.read_mode {
+            let value = self.sides[side][self.position];
+            if !self.last_crc_control {
+                self.update_crc(value);
+            }
+            if !self.disk_ready {
+                self.gap_ended = false;
+                self.crc = 0;
+            } else if value != 0 && !self.gap_ended {
+                // This is the start of block mark, the next byte is the first byte of the block
+                self.gap_ended = true;
+                irq = false;
+            }
+            if self.gap_ended {
+                self.transfer_complete.set(true);
+                self.read_data = value;
+                if irq {
+                    self.disk_irq.set(true);
+                }
+            }
+        } else {
+            let mut value = 0;
+            if !self.crc_control {
+                self.transfer_complete.set(true);
+                value = self.write_data;
+                if irq {
+                    self.disk_irq.set(true);
+                }
+            }
+            if !self.disk_ready {
+                value = 0;
+                self.crc = 0;
+            }
+            if !self.crc_control {
+                self.update_crc(value);
+            } else {
+                if !self.last_crc_control {
+                    self.update_crc(0);
+                    self.update_crc(0);
+                }
+                value = (self.crc & 0xFF) as u8;
+                self.crc >>= 8;
+            }
+            self.sides[side][self.position] = value;
+            self.gap_ended = false;
+        }
+        self.last_crc_control = self.crc_control;
+        self.position += 1;
+        if self.position >= self.sides[side].len() {
+            self.motor_on = false;
+            self.end_of_head = true;
+        } else {
+            self.delay = CYCLES_PER_BYTE;
+        }
+    }
+}
+
+#[typetag::serde]
+impl Mapper for Fds {
+    fn mapper_num(&self) -> u32 {
+        20
+    }
+    fn read_cpu(&self, cpu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        match cpu_addr {
+            0x4030 => {
+                let mut value = 0;
+                if self.timer_irq.get() {
+                    value |= 0x01;
+                }
+                if self.transfer_complete.get() {
+                    value |= 0x02;
+                }
+                if self.end_of_head {
+                    value |= 0x40;
+                }
+                self.transfer_complete.set(false);
+                self.timer_irq.set(false);
+                self.disk_irq.set(false);
+                value
+            }
+            0x4031 => {
+                self.transfer_complete.set(false);
+                self.disk_irq.set(false);
+                self.read_data
+            }
+            0x4032 => {
+                let mut value = 0x40;
+                if self.side.is_none() {
+                    // Disk not inserted, not ready, and not writable
+                    value |= 0x07;
+                } else if !self.scanning {
+                    value |= 0x02;
+                }
+                value
+            }
+            // Battery is good
+            0x4033 => 0x80,
+            0x4040..0x4098 if self.sound_enabled => self.audio.read(cpu_addr),
+            0x6000..0xE000 => mem.read_prg_ram(cpu_addr - 0x6000),
+            0xE000..0x10000 => mem.read_prg_rom(cpu_addr - 0xE000),
+            _ => 0,
+        }
+    }
+    fn write_cpu(&mut self, cpu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        match cpu_addr {
+            0x4020 => {
+                self.irq_reload = (self.irq_reload & 0xFF00) | value as u16;
+                self.timer_irq.set(false);
+            }
+            0x4021 => {
+                self.irq_reload = (self.irq_reload & 0x00FF) | ((value as u16) << 8);
+                self.timer_irq.set(false);
+            }
+            0x4022 => {
+                self.irq_repeat = (value & 0x01) != 0;
+                self.irq_enabled = (value & 0x02) != 0 && self.disk_enabled;
+                if self.irq_enabled {
+                    self.irq_counter = self.irq_reload;
+                } else {
+                    self.timer_irq.set(false);
+                }
+            }
+            0x4023 => {
+                self.disk_enabled = (value & 0x01) != 0;
+                self.sound_enabled = (value & 0x02) != 0;
+                if !self.disk_enabled {
+                    self.irq_enabled = false;
+                    self.timer_irq.set(false);
+                    self.disk_irq.set(false);
+                }
+            }
+            0x4024 if self.disk_enabled => {
+                self.write_data = value;
+                self.transfer_complete.set(false);
+                self.disk_irq.set(false);
+            }
+            0x4025 if self.disk_enabled => {
+                self.motor_on = (value & 0x01) != 0;
+                self.reset_transfer = (value & 0x02) != 0;
+                self.read_mode = (value & 0x04) != 0;
+                mem.nametable_arrangement = if (value & 0x08) != 0 {
+                    NametableArrangement::Vertical
+                } else {
+                    NametableArrangement::Horizontal
+                };
+                self.crc_control = (value & 0x10) != 0;
+                self.disk_ready = (value & 0x40) != 0;
+                self.disk_irq_enabled = (value & 0x80) != 0;
+                self.disk_irq.set(false);
+            }
+            0x4040..0x4098 if self.sound_enabled => self.audio.write(cpu_addr, value),
+            0x6000..0xE000 => mem.write_prg_ram(cpu_addr - 0x6000, value),
+            _ => {}
+        }
+    }
+    fn read_ppu_debug(&self, ppu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        mem.read_chr(ppu_addr)
+    }
+    fn write_ppu(&mut self, ppu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        mem.write_chr(ppu_addr, value);
+    }
+    fn advance_cpu_cycles(&mut self, cycles: u32) {
+        (0..cycles).for_each(|_| {
+            self.clock_timer();
+            self.clock_drive();
+        });
+    }
+    fn irq(&mut self) -> bool {
+        self.timer_irq.get() || self.disk_irq.get()
+    }
+    fn expansion_audio(&self) -> Option<ExpansionAudio> {
+        Some(ExpansionAudio::Fds)
+    }
+    fn clock_expansion_audio(&mut self) {
+        self.audio.clock();
+    }
+    fn expansion_audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+    fn disk_sides(&self) -> usize {
+        self.sides.len()
+    }
+    fn disk_side(&self) -> Option<usize> {
+        self.side
+    }
+    fn insert_disk(&mut self, side: Option<usize>) {
+        // Eject the disk, and give the BIOS time to notice before inserting the new side
+        self.side = None;
+        self.next_side = side.filter(|s| *s < self.sides.len());
+        self.switch_delay = SWITCH_DELAY;
+        info!("Ejected disk, inserting side {:?}", self.next_side);
+    }
+    fn disk_patch(&self) -> Option<Vec<u8>> {
+        Some(ips::create(&self.original, &self.disk_image()))
+    }
+}
+
+impl Display for Fds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "FDS")
+    }
+}
+impl Debug for Fds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "FDS side={:?} motor={} position={:X} irq_counter={:X}",
+            self.side, self.motor_on, self.position, self.irq_counter
+        )
+    }
+}
@@ -0,0 +1,253 @@
+use serde::{Deserialize, Serialize};
+use serde_big_array::BigArray;
+
+/// Volume of the wave for each master volume setting, out of 36
+const MASTER_VOLUMES: [u32; 4] = [36, 24, 17, 14];
+/// Value in the modulation table that resets the modulation counter
+const MOD_RESET: i32 = 0x100;
+/// Change of the modulation counter for each value of the modulation table
+const MOD_STEPS: [i32; 8] = [0, 1, 2, 4, MOD_RESET, -4, -2, -1];
+/// The output at full volume, relative to the APU's output.
+/// At full volume the FDS is about 2.4 times louder than a pulse channel at full volume.
+const FULL_VOLUME: f32 = 0.36;
+
+/// An envelope, and the frequency of the unit it controls
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct Envelope {
+    speed: u8,
+    gain: u8,
+    increase: bool,
+    disabled: bool,
+    frequency: u16,
+    timer: u32,
+}
+
+impl Envelope {
+    fn write_control(&mut self, value: u8, master_speed: u8) {
+        self.speed = value & 0x3F;
+        self.increase = (value & 0x40) != 0;
+        self.disabled = (value & 0x80) != 0;
+        self.reset_timer(master_speed);
+        if self.disabled {
+            self.gain = self.speed;
+        }
+    }
+    fn write_frequency_low(&mut self, value: u8) {
+        self.frequency = (self.frequency & 0x0F00) | value as u16;
+    }
+    fn write_frequency_high(&mut self, value: u8) {
+        self.frequency = (self.frequency & 0x00FF) | (((value & 0x0F) as u16) << 8);
+    }
+    fn reset_timer(&mut self, master_speed: u8) {
+        self.timer = 8 * (self.speed as u32 + 1) * master_speed as u32;
+    }
+    // Clock the envelope, returns true if the gain could have changed
+    fn clock(&mut self, master_speed: u8) -> bool {
+        if self.disabled || master_speed == 0 {
+            return false;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.reset_timer(master_speed);
+            if self.increase && self.gain < 32 {
+                self.gain += 1;
+            } else if !self.increase && self.gain > 0 {
+                self.gain -= 1;
+            }
+        }
+        true
+    }
+}
+
+/// The expansion audio of the Famicom Disk System.
+///
+/// A single wavetable channel with a 64 step waveform, a volume envelope and a frequency modulator.
+/// See [the NESDEV wiki](https://www.nesdev.org/wiki/FDS_audio).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FdsAudio {
+    #[serde(with = "BigArray")]
+    wave_table: [u8; 64],
+    wave_write_enabled: bool,
+    wave_halted: bool,
+    wave_position: usize,
+    wave_accumulator: u16,
+    envelopes_disabled: bool,
+    master_volume: usize,
+    master_envelope_speed: u8,
+    volume: Envelope,
+    modulation: Envelope,
+    #[serde(with = "BigArray")]
+    mod_table: [u8; 64],
+    mod_position: usize,
+    mod_accumulator: u16,
+    mod_disabled: bool,
+    mod_counter: i32,
+    // Pitch change caused by the modulator
+    mod_output: i32,
+    output: u8,
+}
+
+impl Default for FdsAudio {
+    fn default() -> Self {
+        FdsAudio {
+            wave_table: [0; 64],
+            wave_write_enabled: false,
+            wave_halted: true,
+            wave_position: 0,
+            wave_accumulator: 0,
+            envelopes_disabled: false,
+            master_volume: 0,
+            master_envelope_speed: 0xE8,
+            volume: Envelope::default(),
+            modulation: Envelope::default(),
+            mod_table: [0; 64],
+            mod_position: 0,
+            mod_accumulator: 0,
+            mod_disabled: true,
+            mod_counter: 0,
+            mod_output: 0,
+            output: 0,
+        }
+    }
+}
+
+impl FdsAudio {
+    /// Read one of the audio registers (`0x4040..0x4098`)
+    pub fn read(&self, addr: usize) -> u8 {
+        match addr {
+            0x4040..0x4080 => self.wave_table[addr - 0x4040] | 0x40,
+            0x4090 => self.volume.gain | 0x40,
+            0x4092 => self.modulation.gain | 0x40,
+            _ => 0x40,
+        }
+    }
+    /// Write to one of the audio registers (`0x4040..0x4098`)
+    pub fn write(&mut self, addr: usize, value: u8) {
+        let speed = self.master_envelope_speed;
+        match addr {
+            0x4040..0x4080 if self.wave_write_enabled => {
+                self.wave_table[addr - 0x4040] = value & 0x3F;
+            }
+            0x4080 => self.volume.write_control(value, speed),
+            0x4082 => self.volume.write_frequency_low(value),
+            0x4083 => {
+                self.volume.write_frequency_high(value);
+                self.envelopes_disabled = (value & 0x40) != 0;
+                self.wave_halted = (value & 0x80) != 0;
+                if self.wave_halted {
+                    self.wave_position = 0;
+                    self.wave_accumulator = 0;
+                }
+                if self.envelopes_disabled {
+                    self.volume.reset_timer(speed);
+                    self.modulation.reset_timer(speed);
+                }
+            }
+            0x4084 => self.modulation.write_control(value, speed),
+            0x4085 => self.set_mod_counter((value & 0x7F) as i32),
+            0x4086 => self.modulation.write_frequency_low(value),
+            0x4087 => {
+                self.modulation.write_frequency_high(value);
+                self.mod_disabled = (value & 0x80) != 0;
+                if self.mod_disabled {
+                    self.mod_accumulator = 0;
+                }
+            }
+            // The table can only be written while the modulator is disabled, and each write fills 2 entries
+            0x4088 if self.mod_disabled => {
+                self.mod_table[self.mod_position] = value & 0x07;
+                self.mod_table[(self.mod_position + 1) % 64] = value & 0x07;
+                self.mod_position = (self.mod_position + 2) % 64;
+            }
+            0x4089 => {
+                self.master_volume = (value & 0x03) as usize;
+                self.wave_write_enabled = (value & 0x80) != 0;
+            }
+            0x408A => self.master_envelope_speed = value,
+            _ => {}
+        }
+    }
+    // Set the modulation counter, a 7 bit signed value
+    fn set_mod_counter(&mut self, value: i32) {
+        self.mod_counter = (value + 64).rem_euclid(128) - 64;
+    }
+    fn mod_enabled(&self) -> bool {
+        !self.mod_disabled && self.modulation.frequency > 0
+    }
+    // Compute the pitch change caused by the modulator
+    fn update_mod_output(&mut self) {
+        let pitch = self.volume.frequency as i32;
+        let mut temp = self.mod_counter * self.modulation.gain as i32;
+        let remainder = temp & 0x0F;
+        temp >>= 4;
+        if remainder > 0 && (temp & 0x80) == 0 {
+            temp += if self.mod_counter < 0 { -1 } else { 2 };
+        }
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+        temp *= pitch;
+        let remainder = temp & 0x3F;
+        temp >>= 6;
+        if remainder >= 32 {
+            temp += 1;
+        }
+        self.mod_output = temp;
+    }
+    /// Advance the audio by one CPU cycle
+    pub fn clock(&mut self) {
+        let speed = self.master_envelope_speed;
+        if !self.wave_halted && !self.envelopes_disabled {
+            self.volume.clock(speed);
+            if self.modulation.clock(speed) {
+                self.update_mod_output();
+            }
+        }
+        // Clock the modulator
+        if self.mod_enabled() {
+            let (acc, overflow) = self
+                .mod_accumulator
+                .overflowing_add(self.modulation.frequency);
+            self.mod_accumulator = acc;
+            if overflow {
+                let step = MOD_STEPS[self.mod_table[self.mod_position] as usize];
+                if step == MOD_RESET {
+                    self.set_mod_counter(0);
+                } else {
+                    self.set_mod_counter(self.mod_counter + step);
+                }
+                self.mod_position = (self.mod_position + 1) % 64;
+                self.update_mod_output();
+            }
+        }
+        // Clock the wave
+        self.update_output();
+        let mod_output = if self.mod_enabled() {
+            self.mod_output
+        } else {
+            0
+        };
+        let pitch = self.volume.frequency as i32 + mod_output;
+        if !self.wave_halted && !self.wave_write_enabled && pitch > 0 {
+            let (acc, overflow) = self.wave_accumulator.overflowing_add(pitch as u16);
+            self.wave_accumulator = acc;
+            if overflow {
+                self.wave_position = (self.wave_position + 1) % 64;
+            }
+        }
+    }
+    fn update_output(&mut self) {
+        // The wave table can't be read while it is being written
+        if self.wave_write_enabled {
+            return;
+        }
+        let level = (self.volume.gain.min(32) as u32) * MASTER_VOLUMES[self.master_volume];
+        self.output = (self.wave_table[self.wave_position] as u32 * level / 1152) as u8;
+    }
+    /// The current output, relative to the APU's output
+    pub fn output(&self) -> f32 {
+        self.output as f32 / 63.0 * FULL_VOLUME
+    }
+}
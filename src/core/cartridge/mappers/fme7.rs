@@ -0,0 +1,207 @@
+use std::fmt::{Debug, Display};
+
+use crate::core::{
+    cartridge::{
+        mapper::{bank_addr, num_banks},
+        mappers::sunsoft5b_audio::Sunsoft5bAudio,
+    },
+    CartridgeMemory, ExpansionAudio, Mapper, NametableArrangement,
+};
+use serde::{Deserialize, Serialize};
+
+/// The chips using the FME-7's interface
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum Fme7Variant {
+    /// The FME-7, without audio
+    Fme7,
+    /// The Sunsoft 5A, which is the same as the FME-7
+    Sunsoft5A,
+    /// The Sunsoft 5B, which adds expansion audio
+    Sunsoft5B,
+}
+
+/// Sunsoft FME-7 cartridge mapper, also used for the Sunsoft 5A and 5B (mapper 69)
+///
+/// The variant is given by the NES 2.0 submapper: 1 for the FME-7, 2 for the 5A and 3 for the 5B.
+/// Without a submapper the 5B is used, since it is the same as the others except for its audio.
+/// See [the NESDEV wiki](https://www.nesdev.org/wiki/Sunsoft_FME-7).
+#[derive(Serialize, Deserialize)]
+pub struct Fme7 {
+    variant: Fme7Variant,
+    command: u8,
+    chr_banks: [usize; 8],
+    // 8KB banks at 0x6000, 0x8000, 0xA000 and 0xC000
+    prg_banks: [usize; 4],
+    // Whether 0x6000 is RAM or ROM, and if RAM whether it is enabled
+    prg_ram_selected: bool,
+    prg_ram_enabled: bool,
+    mirroring: u8,
+    irq_enabled: bool,
+    irq_counter_enabled: bool,
+    irq_counter: u16,
+    irq_pending: bool,
+    audio: Sunsoft5bAudio,
+}
+
+impl Fme7 {
+    /// Create a new FME-7 given the NES 2.0 submapper
+    pub fn new(submapper: u8) -> Fme7 {
+        Fme7 {
+            variant: match submapper {
+                1 => Fme7Variant::Fme7,
+                2 => Fme7Variant::Sunsoft5A,
+                _ => Fme7Variant::Sunsoft5B,
+            },
+            command: 0,
+            chr_banks: [0; 8],
+            prg_banks: [0; 4],
+            prg_ram_selected: false,
+            prg_ram_enabled: false,
+            mirroring: 0,
+            irq_enabled: false,
+            irq_counter_enabled: false,
+            irq_counter: 0,
+            irq_pending: false,
+            audio: Sunsoft5bAudio::default(),
+        }
+    }
+    fn write_parameter(&mut self, value: u8) {
+        let v = value as usize;
+        match self.command {
+            c @ 0x0..0x8 => self.chr_banks[c as usize] = v,
+            0x8 => {
+                self.prg_banks[0] = v & 0x3F;
+                self.prg_ram_selected = (value & 0x40) != 0;
+                self.prg_ram_enabled = (value & 0x80) != 0;
+            }
+            c @ 0x9..0xC => self.prg_banks[c as usize - 0x8] = v & 0x3F,
+            0xC => self.mirroring = value & 0x03,
+            0xD => {
+                self.irq_enabled = (value & 0x01) != 0;
+                self.irq_counter_enabled = (value & 0x80) != 0;
+                self.irq_pending = false;
+            }
+            0xE => self.irq_counter = (self.irq_counter & 0xFF00) | value as u16,
+            _ => self.irq_counter = (self.irq_counter & 0x00FF) | ((value as u16) << 8),
+        }
+    }
+    fn chr_addr(&self, ppu_addr: usize) -> usize {
+        bank_addr(0x400, self.chr_banks[(ppu_addr / 0x400) % 8], ppu_addr)
+    }
+}
+
+#[typetag::serde]
+impl Mapper for Fme7 {
+    fn mapper_num(&self) -> u32 {
+        69
+    }
+    fn read_cpu(&self, cpu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        match cpu_addr {
+            0x6000..0x8000 if self.prg_ram_selected && self.prg_ram_enabled => {
+                mem.read_prg_ram(bank_addr(0x2000, self.prg_banks[0], cpu_addr))
+            }
+            // Disabled RAM
+            0x6000..0x8000 if self.prg_ram_selected => 0,
+            0x6000..0xE000 => {
+                let bank = self.prg_banks[(cpu_addr - 0x6000) / 0x2000];
+                mem.read_prg_rom(bank_addr(0x2000, bank, cpu_addr))
+            }
+            0xE000..0x10000 => mem.read_prg_rom(bank_addr(
+                0x2000,
+                num_banks(0x2000, &mem.prg_rom) - 1,
+                cpu_addr,
+            )),
+            _ => 0,
+        }
+    }
+    fn write_cpu(&mut self, cpu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        match cpu_addr {
+            0x6000..0x8000 if self.prg_ram_selected && self.prg_ram_enabled => {
+                mem.write_prg_ram(bank_addr(0x2000, self.prg_banks[0], cpu_addr), value)
+            }
+            0x8000..0xA000 => self.command = value & 0x0F,
+            0xA000..0xC000 => self.write_parameter(value),
+            0xC000..0xE000 => self.audio.select_register(value),
+            0xE000..0x10000 => self.audio.write(value),
+            _ => {}
+        }
+    }
+    fn read_ppu_debug(&self, ppu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        mem.read_chr(self.chr_addr(ppu_addr))
+    }
+    fn write_ppu(&mut self, ppu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        mem.write_chr(self.chr_addr(ppu_addr), value);
+    }
+    fn nametable_arrangement(&self, _mem: &CartridgeMemory) -> NametableArrangement {
+        NametableArrangement::Custom
+    }
+    fn transform_nametable_addr(&self, addr: usize) -> usize {
+        let nametable = ((addr - 0x2000) / 0x400) % 4;
+        let page = match self.mirroring {
+            // Vertical mirroring
+            0 => nametable % 2,
+            // Horizontal mirroring
+            1 => nametable / 2,
+            // One screen
+            2 => 0,
+            _ => 1,
+        };
+        page * 0x400 + addr % 0x400
+    }
+    fn advance_cpu_cycles(&mut self, cycles: u32) {
+        if !self.irq_counter_enabled {
+            return;
+        }
+        (0..cycles).for_each(|_| {
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+            if self.irq_counter == 0xFFFF && self.irq_enabled {
+                self.irq_pending = true;
+            }
+        });
+    }
+    fn irq(&mut self) -> bool {
+        self.irq_pending
+    }
+    fn expansion_audio(&self) -> Option<ExpansionAudio> {
+        if self.variant == Fme7Variant::Sunsoft5B {
+            Some(ExpansionAudio::Sunsoft5B)
+        } else {
+            None
+        }
+    }
+    fn clock_expansion_audio(&mut self) {
+        if self.variant == Fme7Variant::Sunsoft5B {
+            self.audio.clock();
+        }
+    }
+    fn expansion_audio_output(&self) -> f32 {
+        if self.variant == Fme7Variant::Sunsoft5B {
+            self.audio.output()
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Debug for Fme7 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} command={:X} prg_banks={:X?} chr_banks={:X?} irq_counter={:04X}",
+            self, self.command, self.prg_banks, self.chr_banks, self.irq_counter
+        )
+    }
+}
+impl Display for Fme7 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self.variant {
+                Fme7Variant::Fme7 => "FME-7",
+                Fme7Variant::Sunsoft5A => "Sunsoft 5A",
+                Fme7Variant::Sunsoft5B => "Sunsoft 5B",
+            }
+        )
+    }
+}
@@ -0,0 +1,106 @@
+use std::fmt::{Debug, Display};
+
+use crate::core::{
+    cartridge::{
+        mapper::{bank_addr, num_banks},
+        mappers::chr_latch,
+    },
+    CartridgeMemory, Mapper, NametableArrangement,
+};
+use log::*;
+use serde::{Deserialize, Serialize};
+
+/// FxROM cartridge mapper, using the MMC4 (mapper 10)
+///
+/// The same as [PxRom][super::PxRom], with tile triggered CHR latches, but with a switchable 16KB PRG bank and the
+/// last 16KB bank fixed.
+/// See [the NESDEV wiki](https://www.nesdev.org/wiki/MMC4).
+#[derive(Serialize, Deserialize)]
+pub struct FxRom {
+    prg_bank: usize,
+    // The CHR banks, indexed first by address (0 = 0x0000-0x0FFF, 1 = 0x1000-0x1FFF)
+    // and then by mode (0 = latch is FD, 1 = latch is FE)
+    chr_banks: [[usize; 2]; 2],
+    // latches
+    latches: [usize; 2],
+    nametable_arrangement: NametableArrangement,
+}
+impl Default for FxRom {
+    fn default() -> Self {
+        FxRom {
+            prg_bank: 0,
+            chr_banks: [[0; 2]; 2],
+            latches: [0xFD; 2],
+            nametable_arrangement: NametableArrangement::Horizontal,
+        }
+    }
+}
+#[typetag::serde]
+impl Mapper for FxRom {
+    fn mapper_num(&self) -> u32 {
+        10
+    }
+    fn read_cpu(&self, cpu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        if cpu_addr < 0x6000 {
+            warn!("Invalid CPU addr {:X}", cpu_addr);
+            0
+        } else if cpu_addr < 0x8000 {
+            mem.read_prg_ram(cpu_addr - 0x6000)
+        } else if cpu_addr < 0xC000 {
+            mem.read_prg_rom(bank_addr(0x4000, self.prg_bank, cpu_addr))
+        } else {
+            let last_bank = num_banks(0x4000, &mem.prg_rom) - 1;
+            mem.read_prg_rom(bank_addr(0x4000, last_bank, cpu_addr))
+        }
+    }
+    fn read_ppu_debug(&self, ppu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        mem.read_chr(chr_latch::chr_addr(
+            &self.chr_banks,
+            &self.latches,
+            ppu_addr,
+        ))
+    }
+    fn read_ppu(&mut self, ppu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        let v = self.read_ppu_debug(ppu_addr, mem);
+        chr_latch::update_latches(&mut self.latches, ppu_addr, true);
+        v
+    }
+    fn write_cpu(&mut self, cpu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        let bank = (value & 0x1F) as usize;
+        match cpu_addr {
+            0x6000..0x8000 => mem.write_prg_ram(cpu_addr - 0x6000, value),
+            0xA000..0xB000 => self.prg_bank = bank & 0x0F,
+            0xB000..0xC000 => self.chr_banks[0][0] = bank,
+            0xC000..0xD000 => self.chr_banks[0][1] = bank,
+            0xD000..0xE000 => self.chr_banks[1][0] = bank,
+            0xE000..0xF000 => self.chr_banks[1][1] = bank,
+            0xF000..0x10000 => {
+                self.nametable_arrangement = if (value & 0x01) == 0 {
+                    NametableArrangement::Horizontal
+                } else {
+                    NametableArrangement::Vertical
+                }
+            }
+            _ => {}
+        }
+    }
+    fn write_ppu(&mut self, _ppu_addr: usize, _mem: &mut CartridgeMemory, _value: u8) {}
+    fn nametable_arrangement(&self, _: &CartridgeMemory) -> NametableArrangement {
+        self.nametable_arrangement
+    }
+}
+
+impl Display for FxRom {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "FxROM")
+    }
+}
+impl Debug for FxRom {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "FxROM, prg_bank={} chr_banks={:?} latches={:X?} NT arrangement={:?}",
+            self.prg_bank, self.chr_banks, self.latches, self.nametable_arrangement
+        )
+    }
+}
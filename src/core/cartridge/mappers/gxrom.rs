@@ -0,0 +1,58 @@
+use std::fmt::{Debug, Display};
+
+use crate::core::{
+    cartridge::mapper::{bank_addr, bus_conflict},
+    CartridgeMemory, Mapper,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Default, Serialize, Deserialize)]
+/// GxROM cartridge mapper (mapper 66)
+///
+/// Switches 32KB PRG and 8KB CHR banks, with bus conflicts.
+/// See [the NESDEV wiki](https://www.nesdev.org/wiki/GxROM).
+pub struct GxRom {
+    prg_bank: usize,
+    chr_bank: usize,
+}
+
+#[typetag::serde]
+impl Mapper for GxRom {
+    fn mapper_num(&self) -> u32 {
+        66
+    }
+    fn read_cpu(&self, cpu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        if cpu_addr < 0x8000 {
+            return 0;
+        }
+        mem.read_prg_rom(bank_addr(0x8000, self.prg_bank, cpu_addr))
+    }
+    fn write_cpu(&mut self, cpu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        if cpu_addr >= 0x8000 {
+            let value = bus_conflict(self, true, cpu_addr, mem, value);
+            self.prg_bank = ((value >> 4) & 0x03) as usize;
+            self.chr_bank = (value & 0x03) as usize;
+        }
+    }
+    fn read_ppu_debug(&self, ppu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        mem.read_chr(bank_addr(0x2000, self.chr_bank, ppu_addr))
+    }
+    fn write_ppu(&mut self, ppu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        mem.write_chr(bank_addr(0x2000, self.chr_bank, ppu_addr), value)
+    }
+}
+
+impl Display for GxRom {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "GxROM")
+    }
+}
+impl Debug for GxRom {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} prg_bank={} chr_bank={}",
+            self, self.prg_bank, self.chr_bank
+        )
+    }
+}
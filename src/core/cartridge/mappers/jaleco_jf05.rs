@@ -0,0 +1,49 @@
+use std::fmt::{Debug, Display};
+
+use crate::core::{cartridge::mapper::bank_addr, CartridgeMemory, Mapper};
+use serde::{Deserialize, Serialize};
+
+#[derive(Default, Serialize, Deserialize)]
+/// Jaleco JF-05 to JF-10 cartridge mapper, also used by some Konami and Taito boards (mapper 87)
+///
+/// Switches 8KB CHR banks through a register at `0x6000-0x7FFF`, so there are no bus conflicts.
+/// The 2 bits of the bank number are swapped.
+/// See [the NESDEV wiki](https://www.nesdev.org/wiki/INES_Mapper_087).
+pub struct JalecoJf05 {
+    chr_bank: usize,
+}
+
+#[typetag::serde]
+impl Mapper for JalecoJf05 {
+    fn mapper_num(&self) -> u32 {
+        87
+    }
+    fn read_cpu(&self, cpu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        if cpu_addr < 0x8000 {
+            return 0;
+        }
+        mem.read_prg_rom(cpu_addr - 0x8000)
+    }
+    fn write_cpu(&mut self, cpu_addr: usize, _mem: &mut CartridgeMemory, value: u8) {
+        if (0x6000..0x8000).contains(&cpu_addr) {
+            self.chr_bank = (((value & 0x01) << 1) | ((value >> 1) & 0x01)) as usize;
+        }
+    }
+    fn read_ppu_debug(&self, ppu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        mem.read_chr(bank_addr(0x2000, self.chr_bank, ppu_addr))
+    }
+    fn write_ppu(&mut self, ppu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        mem.write_chr(bank_addr(0x2000, self.chr_bank, ppu_addr), value)
+    }
+}
+
+impl Display for JalecoJf05 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Jaleco JF-05")
+    }
+}
+impl Debug for JalecoJf05 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} chr_bank={}", self, self.chr_bank)
+    }
+}
@@ -0,0 +1,54 @@
+use std::fmt::{Debug, Display};
+
+use crate::core::{cartridge::mapper::bank_addr, CartridgeMemory, Mapper};
+use serde::{Deserialize, Serialize};
+
+#[derive(Default, Serialize, Deserialize)]
+/// Jaleco JF-11/JF-14 cartridge mapper (mapper 140)
+///
+/// Switches 32KB PRG and 8KB CHR banks through a register at `0x6000-0x7FFF`, so there are no bus conflicts.
+/// See [the NESDEV wiki](https://www.nesdev.org/wiki/INES_Mapper_140).
+pub struct JalecoJf11 {
+    prg_bank: usize,
+    chr_bank: usize,
+}
+
+#[typetag::serde]
+impl Mapper for JalecoJf11 {
+    fn mapper_num(&self) -> u32 {
+        140
+    }
+    fn read_cpu(&self, cpu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        if cpu_addr < 0x8000 {
+            return 0;
+        }
+        mem.read_prg_rom(bank_addr(0x8000, self.prg_bank, cpu_addr))
+    }
+    fn write_cpu(&mut self, cpu_addr: usize, _mem: &mut CartridgeMemory, value: u8) {
+        if (0x6000..0x8000).contains(&cpu_addr) {
+            self.prg_bank = ((value >> 4) & 0x03) as usize;
+            self.chr_bank = (value & 0x0F) as usize;
+        }
+    }
+    fn read_ppu_debug(&self, ppu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        mem.read_chr(bank_addr(0x2000, self.chr_bank, ppu_addr))
+    }
+    fn write_ppu(&mut self, ppu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        mem.write_chr(bank_addr(0x2000, self.chr_bank, ppu_addr), value)
+    }
+}
+
+impl Display for JalecoJf11 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Jaleco JF-11")
+    }
+}
+impl Debug for JalecoJf11 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} prg_bank={} chr_bank={}",
+            self, self.prg_bank, self.chr_bank
+        )
+    }
+}
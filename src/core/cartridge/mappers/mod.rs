@@ -10,7 +10,49 @@ mod cnrom;
 pub use cnrom::CnRom;
 mod txrom;
 pub use txrom::TxRom;
+mod chr_latch;
 mod pxrom;
 pub use pxrom::PxRom;
+mod fxrom;
+pub use fxrom::FxRom;
 mod axrom;
 pub use axrom::AxRom;
+mod nsf;
+pub use nsf::NsfMapper;
+mod fds;
+mod fds_audio;
+pub use fds::{Fds, FDS_SIDE_SIZE};
+mod exrom;
+mod exrom_audio;
+pub use exrom::ExRom;
+mod vrc6;
+mod vrc6_audio;
+mod vrc_irq;
+pub use vrc6::Vrc6;
+mod vrc4;
+pub use vrc4::Vrc4;
+mod vrc7;
+mod vrc7_audio;
+pub use vrc7::Vrc7;
+mod fme7;
+mod sunsoft5b_audio;
+pub use fme7::Fme7;
+mod namco163;
+mod namco163_audio;
+pub use namco163::Namco163;
+mod gxrom;
+pub use gxrom::GxRom;
+mod color_dreams;
+pub use color_dreams::ColorDreams;
+mod bnrom;
+pub use bnrom::BnRom;
+mod camerica;
+pub use camerica::Camerica;
+mod nina0306;
+pub use nina0306::Nina0306;
+mod jaleco_jf11;
+pub use jaleco_jf11::JalecoJf11;
+mod jaleco_jf05;
+pub use jaleco_jf05::JalecoJf05;
+mod unrom512;
+pub use unrom512::UnRom512;
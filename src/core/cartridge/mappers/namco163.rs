@@ -0,0 +1,200 @@
+use std::fmt::{Debug, Display};
+
+use crate::core::{
+    cartridge::{
+        mapper::{bank_addr, num_banks},
+        mappers::namco163_audio::Namco163Audio,
+    },
+    CartridgeMemory, ExpansionAudio, Mapper, NametableArrangement,
+};
+use serde::{Deserialize, Serialize};
+
+/// Namco 129/163 cartridge mapper (mapper 19)
+///
+/// Supports PRG/CHR banking, using nametable RAM as CHR and CHR ROM as nametables, the IRQ counter and the
+/// wavetable audio.
+/// The mapper holds the nametable RAM itself, since it can be mapped into the pattern tables.
+/// The audio's internal RAM is battery backed along with PRG RAM if the cartridge has a battery.
+/// See [the NESDEV wiki](https://www.nesdev.org/wiki/INES_Mapper_019).
+#[derive(Serialize, Deserialize)]
+pub struct Namco163 {
+    // 8KB banks at 0x8000, 0xA000 and 0xC000
+    prg_banks: [usize; 3],
+    // 1KB banks in the pattern tables, followed by the 4 nametables
+    chr_banks: [usize; 12],
+    // Whether nametable RAM can be used in the lower and upper pattern tables
+    ciram_chr_disabled: [bool; 2],
+    ciram: Vec<u8>,
+    // 0xF800, used to write protect PRG RAM
+    write_protect: u8,
+    irq_counter: u16,
+    irq_enabled: bool,
+    audio: Namco163Audio,
+}
+
+impl Default for Namco163 {
+    fn default() -> Self {
+        Namco163 {
+            prg_banks: [0; 3],
+            chr_banks: [0; 12],
+            ciram_chr_disabled: [false; 2],
+            ciram: vec![0; 0x800],
+            write_protect: 0,
+            irq_counter: 0,
+            irq_enabled: false,
+            audio: Namco163Audio::default(),
+        }
+    }
+}
+
+impl Namco163 {
+    // Get the address in nametable RAM if a 1KB slot of PPU memory (0x0000-0x2FFF) is mapped to it
+    fn ciram_addr(&self, ppu_addr: usize) -> Option<usize> {
+        let slot = (ppu_addr / 0x400) % 12;
+        let bank = self.chr_banks[slot];
+        // Banks 0xE0 and up select nametable RAM, unless disabled for the pattern table
+        if bank >= 0xE0 && (slot >= 8 || !self.ciram_chr_disabled[slot / 4]) {
+            Some((bank % 2) * 0x400 + ppu_addr % 0x400)
+        } else {
+            None
+        }
+    }
+    fn read_chr(&self, ppu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        match self.ciram_addr(ppu_addr) {
+            Some(addr) => self.ciram[addr],
+            None => mem.read_chr(bank_addr(
+                0x400,
+                self.chr_banks[(ppu_addr / 0x400) % 12],
+                ppu_addr,
+            )),
+        }
+    }
+    fn write_chr(&mut self, ppu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        match self.ciram_addr(ppu_addr) {
+            Some(addr) => self.ciram[addr] = value,
+            None => mem.write_chr(
+                bank_addr(0x400, self.chr_banks[(ppu_addr / 0x400) % 12], ppu_addr),
+                value,
+            ),
+        }
+    }
+    fn prg_ram_writable(&self, cpu_addr: usize) -> bool {
+        // The upper nibble must be 0x4, and each of the lower bits protects 2KB
+        (self.write_protect & 0xF0) == 0x40
+            && (self.write_protect & (1 << ((cpu_addr - 0x6000) / 0x800))) == 0
+    }
+}
+
+#[typetag::serde]
+impl Mapper for Namco163 {
+    fn mapper_num(&self) -> u32 {
+        19
+    }
+    fn read_cpu(&self, cpu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        match cpu_addr {
+            0x4800..0x5000 => self.audio.read(),
+            0x5000..0x5800 => self.irq_counter as u8,
+            0x5800..0x6000 => ((self.irq_counter >> 8) as u8) | ((self.irq_enabled as u8) << 7),
+            0x6000..0x8000 => mem.read_prg_ram(cpu_addr - 0x6000),
+            0x8000..0xE000 => {
+                let bank = self.prg_banks[(cpu_addr - 0x8000) / 0x2000];
+                mem.read_prg_rom(bank_addr(0x2000, bank, cpu_addr))
+            }
+            0xE000..0x10000 => mem.read_prg_rom(bank_addr(
+                0x2000,
+                num_banks(0x2000, &mem.prg_rom) - 1,
+                cpu_addr,
+            )),
+            _ => 0,
+        }
+    }
+    fn write_cpu(&mut self, cpu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        let v = value as usize;
+        match cpu_addr {
+            0x4800..0x5000 => self.audio.write(value),
+            // Writing either half of the counter acknowledges the IRQ
+            0x5000..0x5800 => self.irq_counter = (self.irq_counter & 0x7F00) | value as u16,
+            0x5800..0x6000 => {
+                self.irq_counter = (self.irq_counter & 0x00FF) | ((value as u16 & 0x7F) << 8);
+                self.irq_enabled = (value & 0x80) != 0;
+            }
+            0x6000..0x8000 if self.prg_ram_writable(cpu_addr) => {
+                mem.write_prg_ram(cpu_addr - 0x6000, value)
+            }
+            0x8000..0xE000 => self.chr_banks[(cpu_addr - 0x8000) / 0x800] = v,
+            0xE000..0xE800 => {
+                self.prg_banks[0] = v & 0x3F;
+                self.audio.set_muted((value & 0x40) != 0);
+            }
+            0xE800..0xF000 => {
+                self.prg_banks[1] = v & 0x3F;
+                self.ciram_chr_disabled = [(value & 0x40) != 0, (value & 0x80) != 0];
+            }
+            0xF000..0xF800 => self.prg_banks[2] = v & 0x3F,
+            0xF800..0x10000 => {
+                self.write_protect = value;
+                self.audio.set_addr(value);
+            }
+            _ => {}
+        }
+    }
+    fn read_ppu_debug(&self, ppu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        self.read_chr(ppu_addr, mem)
+    }
+    fn write_ppu(&mut self, ppu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        self.write_chr(ppu_addr, mem, value);
+    }
+    fn nametable_arrangement(&self, _mem: &CartridgeMemory) -> NametableArrangement {
+        NametableArrangement::Custom
+    }
+    fn transform_nametable_addr(&self, addr: usize) -> usize {
+        // Not used since the mapper holds the nametable RAM, but something valid has to be returned
+        addr % 0x800
+    }
+    fn read_nametable(&mut self, ppu_addr: usize, mem: &CartridgeMemory) -> Option<u8> {
+        Some(self.read_chr(0x2000 + ppu_addr % 0x1000, mem))
+    }
+    fn write_nametable(&mut self, ppu_addr: usize, mem: &mut CartridgeMemory, value: u8) -> bool {
+        self.write_chr(0x2000 + ppu_addr % 0x1000, mem, value);
+        true
+    }
+    fn advance_cpu_cycles(&mut self, cycles: u32) {
+        if self.irq_enabled {
+            // Counts up and stops at 0x7FFF
+            self.irq_counter = (self.irq_counter + cycles.min(0x7FFF) as u16).min(0x7FFF);
+        }
+    }
+    fn irq(&mut self) -> bool {
+        self.irq_enabled && self.irq_counter == 0x7FFF
+    }
+    fn expansion_audio(&self) -> Option<ExpansionAudio> {
+        Some(ExpansionAudio::Namco163)
+    }
+    fn clock_expansion_audio(&mut self) {
+        self.audio.clock();
+    }
+    fn expansion_audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+    fn battery_ram<'a>(&'a self, _mem: &'a CartridgeMemory) -> &'a [u8] {
+        self.audio.ram()
+    }
+    fn load_battery_ram(&mut self, _mem: &mut CartridgeMemory, data: &[u8]) {
+        self.audio.load_ram(data);
+    }
+}
+
+impl Debug for Namco163 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Namco163 prg_banks={:X?} chr_banks={:X?} irq_counter={:04X}",
+            self.prg_banks, self.chr_banks, self.irq_counter
+        )
+    }
+}
+impl Display for Namco163 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Namco 163")
+    }
+}
@@ -0,0 +1,129 @@
+use std::cell::Cell;
+
+use serde::{Deserialize, Serialize};
+
+/// Output of a channel for each step of its sample multiplied by its volume, relative to the APU's output
+const CHANNEL_VOLUME: f32 = 0.0025;
+/// Number of CPU cycles taken to update a single channel
+const CHANNEL_CYCLES: u32 = 15;
+/// Size of the internal sound RAM
+const SOUND_RAM_SIZE: usize = 0x80;
+
+/// The expansion audio of the Namco 163.
+///
+/// Up to 8 wavetable channels, each playing 4 bit samples stored in the chip's 128 bytes of internal RAM.
+/// The channels' registers are also stored in the RAM, from `0x40` onward.
+/// Only one channel is updated and output at a time, so the channels are averaged together.
+/// See [the NESDEV wiki](https://www.nesdev.org/wiki/Namco_163_audio).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Namco163Audio {
+    ram: Vec<u8>,
+    // Address of the data port, which is incremented after every access if auto_increment is set
+    addr: Cell<u8>,
+    auto_increment: bool,
+    muted: bool,
+    divider: u32,
+    // The channel being updated
+    channel: usize,
+    // The last output of each channel
+    outputs: [i32; 8],
+}
+
+impl Default for Namco163Audio {
+    fn default() -> Self {
+        Namco163Audio {
+            ram: vec![0; SOUND_RAM_SIZE],
+            addr: Cell::new(0),
+            auto_increment: false,
+            muted: false,
+            divider: 0,
+            channel: 7,
+            outputs: [0; 8],
+        }
+    }
+}
+
+impl Namco163Audio {
+    /// Set the address of the data port (`0xF800`)
+    pub fn set_addr(&mut self, value: u8) {
+        self.addr.set(value & 0x7F);
+        self.auto_increment = (value & 0x80) != 0;
+    }
+    fn next_addr(&self) -> usize {
+        let addr = self.addr.get();
+        if self.auto_increment {
+            self.addr.set((addr + 1) & 0x7F);
+        }
+        addr as usize
+    }
+    /// Read the data port (`0x4800`)
+    pub fn read(&self) -> u8 {
+        self.ram[self.next_addr()]
+    }
+    /// Write to the data port (`0x4800`)
+    pub fn write(&mut self, value: u8) {
+        let addr = self.next_addr();
+        self.ram[addr] = value;
+    }
+    /// Silence the audio
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+    /// The internal RAM, used for both the waveforms and the channels' registers
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+    /// Load the internal RAM
+    pub fn load_ram(&mut self, data: &[u8]) {
+        self.ram.copy_from_slice(data);
+    }
+    // Number of channels enabled, which are always the last channels
+    fn num_channels(&self) -> usize {
+        ((self.ram[0x7F] >> 4) & 0x07) as usize + 1
+    }
+    fn clock_channel(&mut self, channel: usize) {
+        let base = 0x40 + 8 * channel;
+        let r = &mut self.ram[base..base + 8];
+        let freq = r[0] as u32 | ((r[2] as u32) << 8) | ((r[4] as u32 & 0x03) << 16);
+        let length = 256 - (r[4] as u32 & 0xFC);
+        let phase = r[1] as u32 | ((r[3] as u32) << 8) | ((r[5] as u32) << 16);
+        let phase = (phase + freq) % (length << 16);
+        r[1] = phase as u8;
+        r[3] = (phase >> 8) as u8;
+        r[5] = (phase >> 16) as u8;
+        let sample_addr = (((phase >> 16) + r[6] as u32) & 0xFF) as usize;
+        let volume = (r[7] & 0x0F) as i32;
+        // Samples are stored low nibble first
+        let sample = (self.ram[sample_addr / 2] >> (4 * (sample_addr % 2))) & 0x0F;
+        self.outputs[channel] = (sample as i32 - 8) * volume;
+    }
+    /// Advance the audio by one CPU cycle
+    pub fn clock(&mut self) {
+        self.divider += 1;
+        if self.divider < CHANNEL_CYCLES {
+            return;
+        }
+        self.divider = 0;
+        self.clock_channel(self.channel);
+        // Go through the enabled channels from 7 downwards
+        self.channel = if self.channel <= 8 - self.num_channels() {
+            7
+        } else {
+            self.channel - 1
+        };
+    }
+    /// The current output, relative to the APU's output
+    pub fn output(&self) -> f32 {
+        if self.muted {
+            return 0.0;
+        }
+        let num_channels = self.num_channels();
+        // Each channel is only output for part of the time
+        self.outputs[(8 - num_channels)..]
+            .iter()
+            .map(|o| *o as f32)
+            .sum::<f32>()
+            * CHANNEL_VOLUME
+            / num_channels as f32
+    }
+}
@@ -0,0 +1,98 @@
+use std::fmt::{Debug, Display};
+
+use crate::core::{cartridge::mapper::bank_addr, CartridgeMemory, Mapper, NametableArrangement};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+/// NINA-03/NINA-06 cartridge mapper (mapper 79), and the multicart variant (mapper 113)
+///
+/// Switches 32KB PRG and 8KB CHR banks through a register at `0x4100-0x5FFF`, so there are no bus conflicts.
+/// The multicart variant has more PRG and CHR banks, and also controls the mirroring.
+/// See [the NESDEV wiki](https://www.nesdev.org/wiki/NINA-003-006).
+pub struct Nina0306 {
+    multicart: bool,
+    prg_bank: usize,
+    chr_bank: usize,
+    vertical_mirroring: bool,
+}
+
+impl Nina0306 {
+    /// Create a new NINA-03/06 mapper, using the multicart variant (mapper 113) if `multicart` is set
+    pub fn new(multicart: bool) -> Nina0306 {
+        Nina0306 {
+            multicart,
+            prg_bank: 0,
+            chr_bank: 0,
+            vertical_mirroring: false,
+        }
+    }
+}
+
+#[typetag::serde]
+impl Mapper for Nina0306 {
+    fn mapper_num(&self) -> u32 {
+        if self.multicart {
+            113
+        } else {
+            79
+        }
+    }
+    fn read_cpu(&self, cpu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        if cpu_addr < 0x8000 {
+            return 0;
+        }
+        mem.read_prg_rom(bank_addr(0x8000, self.prg_bank, cpu_addr))
+    }
+    fn write_cpu(&mut self, cpu_addr: usize, _mem: &mut CartridgeMemory, value: u8) {
+        // Register is at 0x4100 and mirrored every 0x200 bytes up to 0x5FFF
+        if (0x4100..0x6000).contains(&cpu_addr) && (cpu_addr & 0x0100) != 0 {
+            let v = value as usize;
+            if self.multicart {
+                self.prg_bank = (v >> 3) & 0x07;
+                self.chr_bank = (v & 0x07) | ((v >> 3) & 0x08);
+                self.vertical_mirroring = (value & 0x80) != 0;
+            } else {
+                self.prg_bank = (v >> 3) & 0x01;
+                self.chr_bank = v & 0x07;
+            }
+        }
+    }
+    fn read_ppu_debug(&self, ppu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        mem.read_chr(bank_addr(0x2000, self.chr_bank, ppu_addr))
+    }
+    fn write_ppu(&mut self, ppu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        mem.write_chr(bank_addr(0x2000, self.chr_bank, ppu_addr), value)
+    }
+    fn nametable_arrangement(&self, mem: &CartridgeMemory) -> NametableArrangement {
+        if !self.multicart {
+            mem.nametable_arrangement
+        } else if self.vertical_mirroring {
+            NametableArrangement::Horizontal
+        } else {
+            NametableArrangement::Vertical
+        }
+    }
+}
+
+impl Display for Nina0306 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            if self.multicart {
+                "NINA-03/06 multicart"
+            } else {
+                "NINA-03/06"
+            }
+        )
+    }
+}
+impl Debug for Nina0306 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} prg_bank={} chr_bank={}",
+            self, self.prg_bank, self.chr_bank
+        )
+    }
+}
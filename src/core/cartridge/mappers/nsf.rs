@@ -0,0 +1,92 @@
+use std::fmt::{Debug, Display};
+
+use crate::core::{
+    cartridge::{
+        mapper::{bank_addr, num_banks},
+        CartridgeMemory,
+    },
+    Mapper,
+};
+use log::*;
+use serde::{Deserialize, Serialize};
+
+const BANK_SIZE: usize = 0x1000;
+
+#[derive(Default, Serialize, Deserialize)]
+/// NSF bankswitching mapper (mapper 31).
+///
+/// Used to play NSF files, as well as by some homebrew games.
+/// Divides `0x8000..0x10000` into 8 banks of 4KB, selected by writing to `0x5FF8..0x6000`.
+/// Also contains 8KB of PRG RAM at `0x6000..0x8000`.
+pub struct NsfMapper {
+    banks: [usize; 8],
+    // Whether the bank at 0xF000 has been set, since it is the last bank of PRG ROM on power up
+    #[serde(default)]
+    last_bank_set: bool,
+}
+
+impl NsfMapper {
+    /// Create a new mapper with the banks given
+    pub fn new(banks: [u8; 8]) -> NsfMapper {
+        NsfMapper {
+            banks: banks.map(|b| b as usize),
+            last_bank_set: true,
+        }
+    }
+}
+
+#[typetag::serde]
+impl Mapper for NsfMapper {
+    fn mapper_num(&self) -> u32 {
+        31
+    }
+    fn read_cpu(&self, cpu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        match cpu_addr {
+            0x6000..0x8000 => mem.read_prg_ram(cpu_addr - 0x6000),
+            0x8000..0x10000 => {
+                let n = num_banks(BANK_SIZE, &mem.prg_rom);
+                let bank = if cpu_addr >= 0xF000 && !self.last_bank_set {
+                    n - 1
+                } else {
+                    self.banks[(cpu_addr - 0x8000) / BANK_SIZE] % n
+                };
+                mem.read_prg_rom(bank_addr(BANK_SIZE, bank, cpu_addr))
+            }
+            _ => 0,
+        }
+    }
+    fn write_cpu(&mut self, cpu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        match cpu_addr {
+            0x5FF8..0x6000 => {
+                self.banks[cpu_addr - 0x5FF8] = value as usize;
+                self.last_bank_set |= cpu_addr == 0x5FFF;
+            }
+            0x6000..0x8000 => mem.write_prg_ram(cpu_addr - 0x6000, value),
+            _ => {}
+        }
+    }
+    fn read_ppu_debug(&self, ppu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        mem.read_chr(ppu_addr)
+    }
+    fn write_ppu(&mut self, ppu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        if mem.chr_ram.is_empty() {
+            warn!(
+                "Tring to write to CHR RAM when there is none (Address = {:X})",
+                ppu_addr
+            );
+        } else {
+            mem.write_chr(ppu_addr, value);
+        }
+    }
+}
+
+impl Display for NsfMapper {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "NSF")
+    }
+}
+impl Debug for NsfMapper {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "NSF banks={:X?}", self.banks)
+    }
+}
@@ -1,7 +1,10 @@
 use std::fmt::{Debug, Display};
 
 use crate::core::{
-    cartridge::mapper::{bank_addr, num_banks},
+    cartridge::{
+        mapper::{bank_addr, num_banks},
+        mappers::chr_latch,
+    },
     CartridgeMemory, Mapper, NametableArrangement,
 };
 use log::*;
@@ -44,7 +47,7 @@ impl Mapper for PxRom {
             // Switchable bank
             mem.read_prg_rom(bank_addr(0x2000, self.prg_bank, cpu_addr))
         } else {
-            let n = num_banks(0x2000, &mem.chr_rom);
+            let n = num_banks(0x2000, &mem.prg_rom);
             let bank_num = if cpu_addr < 0xC000 {
                 // Fixed to third last bank
                 n - 3
@@ -59,37 +62,15 @@ impl Mapper for PxRom {
         }
     }
     fn read_ppu_debug(&self, ppu_addr: usize, mem: &CartridgeMemory) -> u8 {
-        let bank_num = if ppu_addr < 0x1000 {
-            if self.latches[0] == 0xFD {
-                self.chr_banks[0][0]
-            } else if self.latches[0] == 0xFE {
-                self.chr_banks[0][1]
-            } else {
-                error!("Invalid latches value {:X?}", self.latches);
-                self.chr_banks[0][0]
-            }
-        // ppu_addr >= 0x1000
-        } else if self.latches[1] == 0xFD {
-            self.chr_banks[1][0]
-        } else if self.latches[1] == 0xFE {
-            self.chr_banks[1][1]
-        } else {
-            error!("Invalid latches value {:X?}", self.latches);
-            self.chr_banks[1][0]
-        };
-        mem.read_chr(bank_addr(0x1000, bank_num, ppu_addr))
+        mem.read_chr(chr_latch::chr_addr(
+            &self.chr_banks,
+            &self.latches,
+            ppu_addr,
+        ))
     }
     fn read_ppu(&mut self, ppu_addr: usize, mem: &CartridgeMemory) -> u8 {
         let v = self.read_ppu_debug(ppu_addr, mem);
-        if ppu_addr == 0x0FD8 {
-            self.latches[0] = 0xFD;
-        } else if ppu_addr == 0x0FE8 {
-            self.latches[0] = 0xFE;
-        } else if (0x1FD8..=0x1FDF).contains(&ppu_addr) {
-            self.latches[1] = 0xFD
-        } else if (0x1FE8..=0x1FEF).contains(&ppu_addr) {
-            self.latches[1] = 0xFE;
-        }
+        chr_latch::update_latches(&mut self.latches, ppu_addr, false);
         v
     }
     fn write_cpu(&mut self, cpu_addr: usize, _mem: &mut CartridgeMemory, value: u8) {
@@ -130,7 +111,7 @@ impl Debug for PxRom {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "PxROM, prg_bank={} chr_banks={:?} latches={:?} NT arrangement={:?}",
+            "PxROM, prg_bank={} chr_banks={:?} latches={:X?} NT arrangement={:?}",
             self.prg_bank, self.chr_banks, self.latches, self.nametable_arrangement
         )
     }
@@ -0,0 +1,208 @@
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+
+/// Output of a channel at its maximum volume, relative to the APU's output
+const CHANNEL_VOLUME: f32 = 0.15;
+/// Number of CPU cycles between each clock of the tone, noise and envelope timers
+const CLOCK_DIVIDER: u32 = 16;
+
+/// Amplitude of each of the 32 volume levels, where each level is 1.5dB louder than the last and level 0 is silent
+fn level_amplitude(level: u32) -> f32 {
+    static LEVELS: OnceLock<[f32; 32]> = OnceLock::new();
+    LEVELS.get_or_init(|| {
+        core::array::from_fn(|i| {
+            if i == 0 {
+                0.0
+            } else {
+                10f32.powf((i as f32 - 31.0) * 1.5 / 20.0)
+            }
+        })
+    })[level as usize]
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ToneChannel {
+    period: u32,
+    timer: u32,
+    output: bool,
+    tone_disabled: bool,
+    noise_disabled: bool,
+    volume: u32,
+    use_envelope: bool,
+}
+
+/// The expansion audio of the Sunsoft 5B.
+///
+/// A variant of the AY-3-8910 with 3 square wave channels, a noise generator and an envelope generator,
+/// all using a logarithmic volume curve.
+/// See [the NESDEV wiki](https://www.nesdev.org/wiki/Sunsoft_5B_audio).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sunsoft5bAudio {
+    selected_register: u8,
+    channels: [ToneChannel; 3],
+    divider: u32,
+    // Noise
+    noise_period: u32,
+    noise_timer: u32,
+    // The noise timer is clocked at half the rate of the tone timers
+    noise_half: bool,
+    lfsr: u32,
+    // Envelope
+    envelope_period: u32,
+    envelope_timer: u32,
+    envelope_shape: u8,
+    // 5 bit level, and whether the envelope is counting down (attack clear) or up
+    envelope_level: u32,
+    envelope_attack: bool,
+    envelope_holding: bool,
+}
+
+impl Default for Sunsoft5bAudio {
+    fn default() -> Self {
+        Sunsoft5bAudio {
+            selected_register: 0,
+            channels: Default::default(),
+            divider: 0,
+            noise_period: 0,
+            noise_timer: 0,
+            noise_half: false,
+            lfsr: 1,
+            envelope_period: 0,
+            envelope_timer: 0,
+            envelope_shape: 0,
+            envelope_level: 0,
+            envelope_attack: false,
+            envelope_holding: true,
+        }
+    }
+}
+
+impl Sunsoft5bAudio {
+    /// Select the register to write to (`0xC000-0xDFFF`)
+    pub fn select_register(&mut self, value: u8) {
+        self.selected_register = value;
+    }
+    /// Write to the selected register (`0xE000-0xFFFF`)
+    pub fn write(&mut self, value: u8) {
+        let v = value as u32;
+        match self.selected_register {
+            reg @ 0x00..0x06 => {
+                let c = &mut self.channels[reg as usize / 2];
+                c.period = if reg % 2 == 0 {
+                    (c.period & 0xF00) | v
+                } else {
+                    (c.period & 0x0FF) | ((v & 0x0F) << 8)
+                };
+            }
+            0x06 => self.noise_period = v & 0x1F,
+            0x07 => self.channels.iter_mut().enumerate().for_each(|(i, c)| {
+                c.tone_disabled = (value & (0x01 << i)) != 0;
+                c.noise_disabled = (value & (0x08 << i)) != 0;
+            }),
+            reg @ 0x08..0x0B => {
+                let c = &mut self.channels[reg as usize - 8];
+                c.volume = v & 0x0F;
+                c.use_envelope = (value & 0x10) != 0;
+            }
+            0x0B => self.envelope_period = (self.envelope_period & 0xFF00) | v,
+            0x0C => self.envelope_period = (self.envelope_period & 0x00FF) | (v << 8),
+            0x0D => {
+                self.envelope_shape = value & 0x0F;
+                self.envelope_attack = (value & 0x04) != 0;
+                self.envelope_level = if self.envelope_attack { 0 } else { 31 };
+                self.envelope_holding = false;
+                self.envelope_timer = 0;
+            }
+            _ => {}
+        }
+    }
+    fn clock_envelope(&mut self) {
+        if self.envelope_holding {
+            return;
+        }
+        let at_end = if self.envelope_attack {
+            self.envelope_level == 31
+        } else {
+            self.envelope_level == 0
+        };
+        if !at_end {
+            if self.envelope_attack {
+                self.envelope_level += 1;
+            } else {
+                self.envelope_level -= 1;
+            }
+            return;
+        }
+        // End of a cycle
+        let repeat = (self.envelope_shape & 0x08) != 0;
+        let alternate = (self.envelope_shape & 0x02) != 0;
+        let hold = (self.envelope_shape & 0x01) != 0;
+        if !repeat {
+            self.envelope_level = 0;
+            self.envelope_holding = true;
+        } else if hold {
+            if alternate {
+                self.envelope_level = 31 - self.envelope_level;
+            }
+            self.envelope_holding = true;
+        } else if alternate {
+            self.envelope_attack = !self.envelope_attack;
+        } else {
+            self.envelope_level = if self.envelope_attack { 0 } else { 31 };
+        }
+    }
+    /// Advance the audio by one CPU cycle
+    pub fn clock(&mut self) {
+        self.divider += 1;
+        if self.divider < CLOCK_DIVIDER {
+            return;
+        }
+        self.divider = 0;
+        self.channels.iter_mut().for_each(|c| {
+            c.timer += 1;
+            if c.timer >= c.period.max(1) {
+                c.timer = 0;
+                c.output = !c.output;
+            }
+        });
+        self.noise_half = !self.noise_half;
+        if self.noise_half {
+            self.noise_timer += 1;
+            if self.noise_timer >= self.noise_period.max(1) {
+                self.noise_timer = 0;
+                // 17 bit LFSR with taps at bits 0 and 3
+                let bit = (self.lfsr ^ (self.lfsr >> 3)) & 0x01;
+                self.lfsr = (self.lfsr >> 1) | (bit << 16);
+            }
+        }
+        // The envelope has 32 steps, each taking half of its period
+        self.envelope_timer += 2;
+        if self.envelope_timer >= self.envelope_period.max(1) {
+            self.envelope_timer = 0;
+            self.clock_envelope();
+        }
+    }
+    /// The current output, relative to the APU's output
+    pub fn output(&self) -> f32 {
+        let noise = (self.lfsr & 0x01) != 0;
+        self.channels
+            .iter()
+            .map(|c| {
+                if (c.output || c.tone_disabled) && (noise || c.noise_disabled) {
+                    let level = if c.use_envelope {
+                        self.envelope_level
+                    } else if c.volume == 0 {
+                        0
+                    } else {
+                        c.volume * 2 + 1
+                    };
+                    level_amplitude(level)
+                } else {
+                    0.0
+                }
+            })
+            .sum::<f32>()
+            * CHANNEL_VOLUME
+    }
+}
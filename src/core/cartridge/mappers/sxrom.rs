@@ -1,23 +1,68 @@
 use std::fmt::{Debug, Display};
 
-use crate::core::{cartridge::mapper::bank_addr, CartridgeMemory, Mapper, NametableArrangement};
+use crate::core::{
+    cartridge::mapper::{bank_addr, num_banks},
+    CartridgeMemory, Mapper, NametableArrangement,
+};
 use log::*;
 use serde::{Deserialize, Serialize};
 
+/// The board the MMC1 is on, which changes what the upper CHR bank bits do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+enum Board {
+    // CHR bank bits are only used for CHR
+    #[default]
+    Standard,
+    // CHR bit 4 disables PRG RAM
+    Snrom,
+    // CHR bit 3 selects the 8KB PRG RAM bank
+    Sorom,
+    // CHR bit 4 selects the 256KB PRG ROM half
+    Surom,
+    // CHR bit 4 selects the 256KB PRG ROM half and bits 2-3 select the 8KB PRG RAM bank
+    Sxrom,
+    // 32KB of PRG ROM which is never switched
+    Serom,
+}
+
 #[derive(Serialize, Deserialize)]
-/// SxROM cartridge mapper and variants (mapper 1)
+/// SxROM cartridge mapper and variants (mapper 1 and mapper 155 or mapper 1 submapper 3 for the MMC1A)
+///
+/// The board is detected from the NES 2.0 submapper if present, otherwise from the size of the PRG ROM and PRG RAM.
+/// For iNES 1.0 files, this means 512KB of PRG ROM is SUROM, and the PRG RAM size in byte 8 is used to detect SOROM and SXROM.
+/// Since CHR bank 0 and 1 are usually the same when used for PRG banking, CHR bank 0 is always used.
+/// See [the NESDEV wiki](https://www.nesdev.org/wiki/MMC1).
 pub struct SxRom {
     shift: usize,
     chr_bank_0: usize,
     chr_bank_1: usize,
     prg_bank: usize,
     control: usize,
-    // Whether something has been written this CPU cycle, and thus further writes should be blocked
+    // Whether something has been written this CPU instruction, and thus further writes should be ignored.
+    // The MMC1 ignores writes on consecutive cycles, which only happen during read-modify-write instructions.
     has_written: bool,
+    // The MMC1A doesn't have the PRG RAM disable bit
+    #[serde(default)]
+    mmc1a: bool,
+    #[serde(default)]
+    submapper: u8,
+    #[serde(default)]
+    board: Board,
 }
 
 impl Default for SxRom {
     fn default() -> SxRom {
+        SxRom::with_board(false, 0, Board::Standard)
+    }
+}
+
+impl SxRom {
+    /// Create a new MMC1 mapper, given whether it is an MMC1A (mapper 155 or submapper 3), the NES 2.0 submapper, and
+    /// the cartridge memory, which is used to detect the board if there is no submapper
+    pub fn new(mmc1a: bool, submapper: u8, mem: &CartridgeMemory) -> SxRom {
+        SxRom::with_board(mmc1a, submapper, SxRom::detect_board(submapper, mem))
+    }
+    fn with_board(mmc1a: bool, submapper: u8, board: Board) -> SxRom {
         SxRom {
             shift: 0x10,
             chr_bank_0: 0,
@@ -25,13 +70,116 @@ impl Default for SxRom {
             prg_bank: 0,
             control: 0,
             has_written: false,
+            mmc1a,
+            submapper,
+            board,
+        }
+    }
+    fn detect_board(submapper: u8, mem: &CartridgeMemory) -> Board {
+        match submapper {
+            1 => Board::Surom,
+            2 => Board::Sorom,
+            4 => Board::Sxrom,
+            5 => Board::Serom,
+            _ => {
+                let board = if mem.prg_rom.len() > 0x40000 {
+                    if mem.prg_ram.len() > 0x4000 {
+                        Board::Sxrom
+                    } else {
+                        Board::Surom
+                    }
+                } else if mem.prg_ram.len() > 0x4000 {
+                    Board::Sxrom
+                } else if mem.prg_ram.len() > 0x2000 {
+                    Board::Sorom
+                } else if mem.chr_rom.is_empty() && mem.chr_ram.len() == 0x2000 {
+                    Board::Snrom
+                } else {
+                    Board::Standard
+                };
+                // The PRG RAM size in iNES 1.0 headers is often wrong, so this guess might be as well
+                if matches!(board, Board::Sorom | Board::Sxrom) {
+                    warn!(
+                        "No submapper given, guessing the MMC1 board is {} from {}KB of PRG RAM",
+                        if board == Board::Sorom {
+                            "SOROM"
+                        } else {
+                            "SXROM"
+                        },
+                        mem.prg_ram.len() / 0x400
+                    );
+                }
+                board
+            }
+        }
+    }
+    fn prg_rom_addr(&self, cpu_addr: usize, mem: &CartridgeMemory) -> usize {
+        if self.board == Board::Serom {
+            return bank_addr(0x8000, 0, cpu_addr);
+        }
+        // The 256KB half of PRG ROM
+        let outer_bank = match self.board {
+            Board::Surom | Board::Sxrom => (self.chr_bank_0 & 0x10) >> 4,
+            _ => 0,
+        };
+        let mode = (self.control & 0x0C) >> 2;
+        let bank_num = self.prg_bank & 0x0F;
+        let bank_num = match mode {
+            // Switch 32 KiB mode
+            0 | 1 => (bank_num & 0x0E) | ((cpu_addr - 0x8000) / 0x4000),
+            // First 16 KiB bank fixed, switch 16 KiB bank at 0xC000
+            2 => {
+                if cpu_addr < 0xC000 {
+                    0
+                } else {
+                    bank_num
+                }
+            }
+            // Switch 16 KiB bank at 0x8000, last 16 KiB bank fixed
+            3 => {
+                if cpu_addr < 0xC000 {
+                    bank_num
+                } else {
+                    num_banks(0x4000, &mem.prg_rom).min(16) - 1
+                }
+            }
+            _ => panic!("Should never happen"),
+        };
+        bank_addr(0x4000, outer_bank * 16 + bank_num, cpu_addr)
+    }
+    // The address in PRG RAM, or None if PRG RAM is disabled
+    fn prg_ram_addr(&self, cpu_addr: usize) -> Option<usize> {
+        if !self.mmc1a && (self.prg_bank & 0x10) != 0 {
+            return None;
+        }
+        let bank = match self.board {
+            Board::Snrom if (self.chr_bank_0 & 0x10) != 0 => return None,
+            Board::Sorom => (self.chr_bank_0 & 0x08) >> 3,
+            Board::Sxrom => (self.chr_bank_0 & 0x0C) >> 2,
+            _ => 0,
+        };
+        Some(bank_addr(0x2000, bank, cpu_addr))
+    }
+    fn chr_addr(&self, ppu_addr: usize) -> usize {
+        let mode = (self.control & 0x10) >> 4;
+        if mode == 0 {
+            bank_addr(0x2000, (self.chr_bank_0 & 0x1E) >> 1, ppu_addr)
+        } else if ppu_addr < 0x1000 {
+            bank_addr(0x1000, self.chr_bank_0, ppu_addr)
+        } else {
+            bank_addr(0x1000, self.chr_bank_1, ppu_addr)
         }
     }
 }
+
 #[typetag::serde]
 impl Mapper for SxRom {
     fn mapper_num(&self) -> u32 {
-        1
+        if self.mmc1a && self.submapper != 3 {
+            155
+        } else {
+            1
+        }
     }
     fn read_cpu(&self, cpu_addr: usize, mem: &CartridgeMemory) -> u8 {
         if cpu_addr < 0x8000 {
@@ -39,87 +187,49 @@ impl Mapper for SxRom {
                 warn!("Reading to {:X}", cpu_addr);
                 return 0;
             }
-            mem.read_prg_ram(cpu_addr - 0x6000)
+            match self.prg_ram_addr(cpu_addr) {
+                Some(addr) => mem.read_prg_ram(addr),
+                None => 0,
+            }
         } else {
-            let mode = (self.control & 0x0C) >> 2;
-            let addr = match mode {
-                0 | 1 => {
-                    let bank_num = (self.prg_bank & 0x0E) >> 1;
-                    // Switch 32 KiB mode
-                    bank_addr(0x8000, bank_num, cpu_addr)
-                }
-                2 => {
-                    let bank_num = self.prg_bank & 0x0F;
-                    if cpu_addr < 0xC000 {
-                        // First 16 KiB bank only
-                        // cpu_addr % 0x4000
-                        bank_addr(0x4000, 0, cpu_addr)
-                    } else {
-                        // Switchable 16 KiB bank
-                        bank_addr(0x4000, bank_num, cpu_addr)
-                    }
-                }
-                3 => {
-                    let bank_num = self.prg_bank & 0x0F;
-                    if cpu_addr < 0xC000 {
-                        // Switch 16 KiB bank
-                        bank_addr(0x4000, bank_num, cpu_addr)
-                    } else {
-                        // Last 16 KiB bank
-                        let last_bank_num = (mem.prg_rom.len() - 1) / 0x4000;
-                        bank_addr(0x4000, last_bank_num, cpu_addr)
-                    }
-                }
-                _ => panic!("Should never happen"),
-            };
-            mem.read_prg_rom(addr)
+            mem.read_prg_rom(self.prg_rom_addr(cpu_addr, mem))
         }
     }
     fn read_ppu_debug(&self, ppu_addr: usize, mem: &CartridgeMemory) -> u8 {
-        let mode = (self.control & 0x10) >> 4;
-        let addr = if mode == 0 {
-            bank_addr(0x2000, (self.chr_bank_0 & 0x1E) >> 1, ppu_addr)
-        } else if ppu_addr < 0x1000 {
-            bank_addr(0x1000, self.chr_bank_0, ppu_addr)
-        } else {
-            bank_addr(0x1000, self.chr_bank_1, ppu_addr)
-        };
-        mem.read_chr(addr)
+        mem.read_chr(self.chr_addr(ppu_addr))
     }
     fn write_cpu(&mut self, cpu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
         if cpu_addr < 0x8000 {
             if cpu_addr < 0x6000 {
                 warn!("Writing to {:X}", cpu_addr);
-            } else {
-                mem.write_prg_ram(cpu_addr - 0x6000, value);
+            } else if let Some(addr) = self.prg_ram_addr(cpu_addr) {
+                mem.write_prg_ram(addr, value);
             }
-        } else {
+        } else if !self.has_written {
+            self.has_written = true;
             // If value high bit is not set
             if value & 0x80 == 0 {
-                if !self.has_written {
-                    self.has_written = true;
-                    // Check if shift register is full
-                    let new_shift = (self.shift >> 1) | ((value as usize & 0x01) << 4);
-                    if (self.shift & 0x01) != 0 {
-                        // Set register based on address
-                        if cpu_addr < 0xA000 {
-                            // Set control
-                            self.control = new_shift;
-                        } else if cpu_addr < 0xC000 {
-                            // Set first character bank
-                            self.chr_bank_0 = new_shift;
-                        } else if cpu_addr < 0xE000 {
-                            // Set second character bank
-                            self.chr_bank_1 = new_shift;
-                        } else {
-                            // Set program bank
-                            self.prg_bank = new_shift;
-                        }
-                        self.shift = 0x10;
+                // Check if shift register is full
+                let new_shift = (self.shift >> 1) | ((value as usize & 0x01) << 4);
+                if (self.shift & 0x01) != 0 {
+                    // Set register based on address
+                    if cpu_addr < 0xA000 {
+                        // Set control
+                        self.control = new_shift;
+                    } else if cpu_addr < 0xC000 {
+                        // Set first character bank
+                        self.chr_bank_0 = new_shift;
+                    } else if cpu_addr < 0xE000 {
+                        // Set second character bank
+                        self.chr_bank_1 = new_shift;
                     } else {
-                        // Add bit to shift register
-                        self.shift = new_shift;
+                        // Set program bank
+                        self.prg_bank = new_shift;
                     }
+                    self.shift = 0x10;
+                } else {
+                    // Add bit to shift register
+                    self.shift = new_shift;
                 }
             } else {
                 // Reset shift and set control
@@ -128,17 +238,8 @@ impl Mapper for SxRom {
             }
         }
     }
-    // Todo: dedup
     fn write_ppu(&mut self, ppu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
-        let mode = (self.control & 0x10) >> 4;
-        let addr = if mode == 0 {
-            bank_addr(0x2000, (self.chr_bank_0 & 0x1E) >> 1, ppu_addr)
-        } else if ppu_addr < 0x1000 {
-            bank_addr(0x1000, self.chr_bank_0, ppu_addr)
-        } else {
-            bank_addr(0x1000, self.chr_bank_1, ppu_addr)
-        };
-        mem.write_chr(addr, value);
+        mem.write_chr(self.chr_addr(ppu_addr), value);
     }
     fn nametable_arrangement(&self, _: &CartridgeMemory) -> NametableArrangement {
         match self.control & 0x03 {
@@ -157,7 +258,8 @@ impl Debug for SxRom {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "SxROM, Mode: {:X}, Control: {:X}, shift: {:X}",
+            "{}, Mode: {:X}, Control: {:X}, shift: {:X}",
+            self,
             (self.control & 0x0C) >> 2,
             self.control,
             self.shift
@@ -166,6 +268,6 @@ impl Debug for SxRom {
 }
 impl Display for SxRom {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "SxROM")
+        write!(f, "{}", if self.mmc1a { "SxROM (MMC1A)" } else { "SxROM" })
     }
 }
@@ -7,8 +7,26 @@ use crate::core::{
 use log::*;
 use serde::{Deserialize, Serialize};
 
+/// The boards and chips using the MMC3's interface
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+enum Mmc3Variant {
+    /// The MMC3 on any of the TxROM boards
+    #[default]
+    Mmc3,
+    /// The MMC6, with 1KB of internal PRG RAM at `0x7000`
+    Mmc6,
+    /// TQROM, where bit 6 of the CHR banks selects 8KB of CHR RAM instead of CHR ROM
+    Tqrom,
+    /// TxSROM, where bit 7 of the CHR banks selects the nametable page instead of using `0xA000`
+    Txsrom,
+}
+
 #[derive(Serialize, Deserialize)]
-/// TxROM cartridge mapper and variants (mapper 4)
+/// TxROM cartridge mapper and variants (mappers 4, 118 and 119)
+///
+/// Mapper 4 uses the NES 2.0 submapper to select the MMC6 (1) or the MMC3A's IRQ (4), and uses the MMC3C otherwise.
+/// Mapper 118 is TxSROM and mapper 119 is TQROM.
+/// See [the NESDEV wiki](https://www.nesdev.org/wiki/MMC3).
 pub struct TxRom {
     prg_banks: [u32; 2],
     chr_banks: [u32; 6],
@@ -21,15 +39,33 @@ pub struct TxRom {
     irq_latch: u32,
     last_ppu_addr: u32,
     generate_irq: bool,
+    // The MMC3A and MMC3B only generate an IRQ when the counter is decremented or reloaded to 0 after a write to 0xC001,
+    // and not when it is reloaded to 0 because it was already 0
+    #[serde(default)]
+    old_irq: bool,
     // Bank select, i.e. which bank we are setting
     // 0-5: Editing CHR bank
     // 6-7: Editing PRG bank seelct
     bank_select: u32,
     nametable: NametableArrangement,
+    #[serde(default)]
+    variant: Mmc3Variant,
+    // The MMC6's PRG RAM enable bit in 0x8000 and protection bits in 0xA001
+    #[serde(default)]
+    mmc6_ram_enabled: bool,
+    #[serde(default)]
+    mmc6_ram_protect: u8,
 }
 
 impl Default for TxRom {
     fn default() -> Self {
+        TxRom::new(4, 0)
+    }
+}
+
+impl TxRom {
+    /// Create a new MMC3 given the iNES mapper number and the NES 2.0 submapper
+    pub fn new(mapper: u32, submapper: u8) -> TxRom {
         TxRom {
             prg_banks: [0; 2],
             chr_banks: [0; 6],
@@ -40,24 +76,82 @@ impl Default for TxRom {
             irq_counter: 0,
             irq_latch: 0,
             generate_irq: false,
+            old_irq: mapper == 4 && submapper == 4,
             last_ppu_addr: 0,
             bank_select: 0,
             nametable: NametableArrangement::Horizontal,
+            variant: match (mapper, submapper) {
+                (4, 1) => Mmc3Variant::Mmc6,
+                (118, _) => Mmc3Variant::Txsrom,
+                (119, _) => Mmc3Variant::Tqrom,
+                _ => Mmc3Variant::Mmc3,
+            },
+            mmc6_ram_enabled: false,
+            mmc6_ram_protect: 0,
         }
     }
+    // Get the 1KB CHR bank at a PPU address, including any bits that aren't used for CHR
+    fn chr_bank(&self, ppu_addr: usize) -> u32 {
+        // Swap the pattern tables in CHR mode 1
+        let addr = if self.chr_mode == 0 {
+            ppu_addr
+        } else {
+            ppu_addr ^ 0x1000
+        };
+        if addr < 0x1000 {
+            // 2KB banks, which ignore the lowest bit
+            (self.chr_banks[addr / 0x800] & !0x01) | ((addr / 0x400) % 2) as u32
+        } else {
+            self.chr_banks[(addr - 0x1000) / 0x400 + 2]
+        }
+    }
+    // Get the address in CHR RAM if the bank at the PPU address uses it
+    fn tqrom_chr_ram_addr(&self, ppu_addr: usize) -> Option<usize> {
+        let bank = self.chr_bank(ppu_addr);
+        if self.variant == Mmc3Variant::Tqrom && (bank & 0x40) != 0 {
+            Some(bank_addr(0x400, (bank & 0x07) as usize, ppu_addr))
+        } else {
+            None
+        }
+    }
+    // Get whether the MMC6 allows reading and writing to the 512 byte half of its PRG RAM at the address.
+    // 0xA001 is laid out as HhLl, where H/L allow reading and h/l allow writing the upper/lower half.
+    fn mmc6_ram_access(&self, cpu_addr: usize) -> (bool, bool) {
+        let half_shift = if (cpu_addr % 0x400) < 0x200 { 4 } else { 6 };
+        let readable = (self.mmc6_ram_protect >> (half_shift + 1)) & 0x01 != 0;
+        let writable = (self.mmc6_ram_protect >> half_shift) & 0x01 != 0;
+        (
+            self.mmc6_ram_enabled && readable,
+            self.mmc6_ram_enabled && readable && writable,
+        )
+    }
 }
 
 #[typetag::serde]
 impl Mapper for TxRom {
     fn mapper_num(&self) -> u32 {
-        4
+        match self.variant {
+            Mmc3Variant::Txsrom => 118,
+            Mmc3Variant::Tqrom => 119,
+            _ => 4,
+        }
     }
     fn read_cpu(&self, cpu_addr: usize, mem: &CartridgeMemory) -> u8 {
         if cpu_addr < 0x6000 {
             warn!("Trying to read PRG RAM where there is none: {:X}", cpu_addr);
             0
         } else if cpu_addr < 0x8000 {
-            mem.read_prg_ram(cpu_addr - 0x6000)
+            if self.variant != Mmc3Variant::Mmc6 {
+                mem.read_prg_ram(cpu_addr - 0x6000)
+            } else if cpu_addr >= 0x7000 && self.mmc6_ram_access(cpu_addr).0 {
+                mem.read_prg_ram(cpu_addr % 0x400)
+            } else if cpu_addr >= 0x7000 && self.mmc6_ram_access(cpu_addr ^ 0x200).0 {
+                // Reading from a disabled half gives 0 if the other half can be read
+                0
+            } else {
+                // Open bus, which is usually the high byte of the address
+                (cpu_addr >> 8) as u8
+            }
         } else {
             let prg_addr = if cpu_addr < 0xA000 {
                 if self.prg_mode == 0 {
@@ -94,7 +188,11 @@ impl Mapper for TxRom {
     }
     fn write_cpu(&mut self, cpu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
         if (0x6000..0x8000).contains(&cpu_addr) {
-            mem.write_prg_ram(cpu_addr - 0x6000, value);
+            if self.variant != Mmc3Variant::Mmc6 {
+                mem.write_prg_ram(cpu_addr - 0x6000, value);
+            } else if cpu_addr >= 0x7000 && self.mmc6_ram_access(cpu_addr).1 {
+                mem.write_prg_ram(cpu_addr % 0x400, value);
+            }
         } else if cpu_addr < 0xA000 {
             if cpu_addr % 2 == 0 {
                 // Choose bank select
@@ -104,6 +202,8 @@ impl Mapper for TxRom {
                 self.prg_mode = ((value & 0x40) >> 6) as u32;
                 // Set CHR bank mode (CHR inversion)
                 self.chr_mode = ((value & 0x80) >> 7) as u32;
+                // Enable the MMC6's PRG RAM
+                self.mmc6_ram_enabled = (value & 0x20) != 0;
             } else {
                 // Bank data
                 if self.bank_select < 6 {
@@ -122,6 +222,11 @@ impl Mapper for TxRom {
                 } else {
                     NametableArrangement::Vertical
                 }
+            } else if self.variant == Mmc3Variant::Mmc6 {
+                // MMC6 PRG RAM protection, which can only be changed while the PRG RAM is enabled
+                if self.mmc6_ram_enabled {
+                    self.mmc6_ram_protect = value & 0xF0;
+                }
             } else {
                 // PRG RAM protection (todo)
             }
@@ -144,23 +249,33 @@ impl Mapper for TxRom {
         self.read_ppu_debug(ppu_addr, mem)
     }
     fn read_ppu_debug(&self, ppu_addr: usize, mem: &CartridgeMemory) -> u8 {
-        let (bank_size, bank_num) = if self.chr_mode == 0 {
-            if ppu_addr < 0x1000 {
-                (0x800, self.chr_banks[ppu_addr / 0x800] / 2)
-            } else {
-                (0x400, self.chr_banks[(ppu_addr - 0x1000) / 0x400 + 2])
-            }
-        } else if ppu_addr < 0x1000 {
-            (0x400, self.chr_banks[ppu_addr / 0x400 + 2])
-        } else {
-            (0x800, self.chr_banks[(ppu_addr - 0x1000) / 0x800] / 2)
+        if let Some(addr) = self.tqrom_chr_ram_addr(ppu_addr) {
+            return mem.chr_ram[addr % mem.chr_ram.len()];
+        }
+        let bank = match self.variant {
+            // TQROM only has 64KB of CHR ROM, and TxSROM only has 128KB
+            Mmc3Variant::Tqrom => self.chr_bank(ppu_addr) & 0x3F,
+            Mmc3Variant::Txsrom => self.chr_bank(ppu_addr) & 0x7F,
+            _ => self.chr_bank(ppu_addr),
         };
-        mem.read_chr(bank_addr(bank_size, bank_num as usize, ppu_addr))
+        mem.read_chr(bank_addr(0x400, bank as usize, ppu_addr))
+    }
+    fn write_ppu(&mut self, ppu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        if let Some(addr) = self.tqrom_chr_ram_addr(ppu_addr) {
+            let i = addr % mem.chr_ram.len();
+            mem.chr_ram[i] = value;
+        } else if mem.chr_rom.is_empty() {
+            mem.write_chr(
+                bank_addr(0x400, self.chr_bank(ppu_addr) as usize, ppu_addr),
+                value,
+            );
+        }
     }
-    fn write_ppu(&mut self, _ppu_addr: usize, _mem: &mut CartridgeMemory, _value: u8) {}
     fn set_addr_value(&mut self, ppu_addr: u32) {
         // Update IRQ
         if self.last_ppu_addr == 0 && ppu_addr & 0x1000 != 0 {
+            let was_reloaded = self.irq_reload;
+            let old_counter = self.irq_counter;
             // Check for reload or decrement
             if self.irq_counter == 0 || self.irq_reload {
                 self.irq_counter = self.irq_latch;
@@ -169,14 +284,27 @@ impl Mapper for TxRom {
                 self.irq_counter -= 1;
             }
             // Check for interrupt
-            if self.irq_counter == 0 && self.irq_enable {
+            if self.irq_counter == 0
+                && self.irq_enable
+                && (!self.old_irq || old_counter != 0 || was_reloaded)
+            {
                 self.generate_irq = true;
             }
         }
         self.last_ppu_addr = ppu_addr & 0x1000;
     }
     fn nametable_arrangement(&self, _: &CartridgeMemory) -> NametableArrangement {
-        self.nametable
+        if self.variant == Mmc3Variant::Txsrom {
+            NametableArrangement::Custom
+        } else {
+            self.nametable
+        }
+    }
+    fn transform_nametable_addr(&self, addr: usize) -> usize {
+        // Each nametable uses the page in the CHR bank at the same position in the pattern table at 0x0000
+        let nametable = ((addr - 0x2000) / 0x400) % 4;
+        let page = (self.chr_bank(0x400 * nametable) >> 7) as usize;
+        0x400 * page + addr % 0x400
     }
     fn irq(&mut self) -> bool {
         if self.generate_irq {
@@ -191,13 +319,22 @@ impl Debug for TxRom {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "TxROM irq_enable = {:} irq_reload = {:}",
-            self.irq_enable, self.irq_reload
+            "{} irq_enable = {:} irq_reload = {:}",
+            self, self.irq_enable, self.irq_reload
         )
     }
 }
 impl Display for TxRom {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "TxROM")
+        write!(
+            f,
+            "{}",
+            match self.variant {
+                Mmc3Variant::Mmc3 => "TxROM",
+                Mmc3Variant::Mmc6 => "HKROM (MMC6)",
+                Mmc3Variant::Tqrom => "TQROM",
+                Mmc3Variant::Txsrom => "TxSROM",
+            }
+        )
     }
 }
@@ -0,0 +1,213 @@
+use std::fmt::{Debug, Display};
+
+use crate::core::{
+    cartridge::mapper::{bank_addr, bus_conflict, num_banks},
+    CartridgeMemory, Mapper, NametableArrangement,
+};
+use serde::{Deserialize, Serialize};
+
+/// Manufacturer and device ID of the SST39SF040, returned in software ID mode
+const FLASH_ID: [u8; 2] = [0xBF, 0xB7];
+/// Size of the sectors erased by the sector erase command
+const SECTOR_SIZE: usize = 0x1000;
+
+/// The state of the flash chip's command sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum FlashState {
+    Ready,
+    // Received 0xAA at 0x5555
+    Unlocked,
+    // Received 0x55 at 0x2AAA
+    Command,
+    // Received the byte program command, the next write is programmed
+    Program,
+    // Received the erase command, which is followed by another unlock sequence
+    Erase,
+    EraseUnlocked,
+    EraseCommand,
+}
+
+/// The mirroring selected by the iNES header
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum Mirroring {
+    Horizontal,
+    Vertical,
+    // One screen, selected by the bank register
+    OneScreen,
+    // Four screen, using the last 8KB of CHR RAM
+    FourScreen,
+}
+
+/// UNROM 512 cartridge mapper (mapper 30)
+///
+/// Switches a 16KB PRG bank at `0x8000` and an 8KB CHR RAM bank, with the last PRG bank fixed at `0xC000`.
+/// If the battery flag is set in the iNES header, the PRG ROM is an SST39SF040 flash chip which the game can
+/// write to through `0x8000-0xBFFF`, and the whole PRG ROM is saved along with the savedata.
+/// Otherwise the board has bus conflicts.
+/// See [the NESDEV wiki](https://www.nesdev.org/wiki/UNROM_512).
+#[derive(Serialize, Deserialize)]
+pub struct UnRom512 {
+    flashable: bool,
+    mirroring: Mirroring,
+    prg_bank: usize,
+    chr_bank: usize,
+    one_screen: usize,
+    flash_state: FlashState,
+    software_id: bool,
+}
+
+impl UnRom512 {
+    /// Create a new UNROM 512 mapper given byte 6 of the iNES header, which contains the mirroring and battery flags
+    pub fn new(flags: u8) -> UnRom512 {
+        UnRom512 {
+            flashable: (flags & 0x02) != 0,
+            mirroring: match flags & 0x09 {
+                0x00 => Mirroring::Horizontal,
+                0x01 => Mirroring::Vertical,
+                0x08 => Mirroring::OneScreen,
+                _ => Mirroring::FourScreen,
+            },
+            prg_bank: 0,
+            chr_bank: 0,
+            one_screen: 0,
+            flash_state: FlashState::Ready,
+            software_id: false,
+        }
+    }
+    fn prg_addr(&self, cpu_addr: usize, mem: &CartridgeMemory) -> usize {
+        let bank = if cpu_addr < 0xC000 {
+            self.prg_bank
+        } else {
+            num_banks(0x4000, &mem.prg_rom) - 1
+        };
+        bank_addr(0x4000, bank, cpu_addr) % mem.prg_rom.len()
+    }
+    fn write_flash(&mut self, cpu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        let addr = self.prg_addr(cpu_addr, mem);
+        // Commands only use the lower 15 bits of the address
+        let command_addr = addr & 0x7FFF;
+        self.flash_state = match (self.flash_state, command_addr, value) {
+            (FlashState::Ready, 0x5555, 0xAA) => FlashState::Unlocked,
+            (FlashState::Unlocked, 0x2AAA, 0x55) => FlashState::Command,
+            (FlashState::Command, 0x5555, 0xA0) => FlashState::Program,
+            (FlashState::Command, 0x5555, 0x80) => FlashState::Erase,
+            (FlashState::Command, 0x5555, 0x90) => {
+                self.software_id = true;
+                FlashState::Ready
+            }
+            (FlashState::Program, _, _) => {
+                // Programming can only clear bits
+                mem.prg_rom[addr] &= value;
+                FlashState::Ready
+            }
+            (FlashState::Erase, 0x5555, 0xAA) => FlashState::EraseUnlocked,
+            (FlashState::EraseUnlocked, 0x2AAA, 0x55) => FlashState::EraseCommand,
+            (FlashState::EraseCommand, _, 0x30) => {
+                let start = addr - addr % SECTOR_SIZE;
+                mem.prg_rom[start..(start + SECTOR_SIZE)].fill(0xFF);
+                FlashState::Ready
+            }
+            (FlashState::EraseCommand, 0x5555, 0x10) => {
+                mem.prg_rom.fill(0xFF);
+                FlashState::Ready
+            }
+            // Exits software ID mode, either on its own or at the end of an unlock sequence
+            (_, _, 0xF0) => {
+                self.software_id = false;
+                FlashState::Ready
+            }
+            _ => FlashState::Ready,
+        };
+    }
+}
+
+#[typetag::serde]
+impl Mapper for UnRom512 {
+    fn mapper_num(&self) -> u32 {
+        30
+    }
+    fn read_cpu(&self, cpu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        if cpu_addr < 0x8000 {
+            return 0;
+        }
+        let addr = self.prg_addr(cpu_addr, mem);
+        if self.software_id {
+            FLASH_ID[addr % 2]
+        } else {
+            mem.read_prg_rom(addr)
+        }
+    }
+    fn write_cpu(&mut self, cpu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        match cpu_addr {
+            // Flashable boards only have the register at 0xC000-0xFFFF
+            0x8000..0xC000 if self.flashable => self.write_flash(cpu_addr, mem, value),
+            0x8000..0x10000 => {
+                let value = bus_conflict(self, !self.flashable, cpu_addr, mem, value);
+                self.prg_bank = (value & 0x1F) as usize;
+                self.chr_bank = ((value >> 5) & 0x03) as usize;
+                self.one_screen = (value >> 7) as usize;
+            }
+            _ => {}
+        }
+    }
+    fn read_ppu_debug(&self, ppu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        mem.read_chr(bank_addr(0x2000, self.chr_bank, ppu_addr))
+    }
+    fn write_ppu(&mut self, ppu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        mem.write_chr(bank_addr(0x2000, self.chr_bank, ppu_addr), value)
+    }
+    fn nametable_arrangement(&self, _mem: &CartridgeMemory) -> NametableArrangement {
+        NametableArrangement::Custom
+    }
+    fn transform_nametable_addr(&self, addr: usize) -> usize {
+        let nametable = ((addr - 0x2000) / 0x400) % 4;
+        let page = match self.mirroring {
+            Mirroring::Horizontal => nametable / 2,
+            Mirroring::Vertical => nametable % 2,
+            Mirroring::OneScreen => self.one_screen,
+            // Not in VRAM, but something valid has to be returned
+            Mirroring::FourScreen => 0,
+        };
+        page * 0x400 + addr % 0x400
+    }
+    fn read_nametable(&mut self, ppu_addr: usize, mem: &CartridgeMemory) -> Option<u8> {
+        if self.mirroring == Mirroring::FourScreen {
+            Some(mem.read_chr(0x6000 + ppu_addr % 0x2000))
+        } else {
+            None
+        }
+    }
+    fn write_nametable(&mut self, ppu_addr: usize, mem: &mut CartridgeMemory, value: u8) -> bool {
+        if self.mirroring == Mirroring::FourScreen {
+            mem.write_chr(0x6000 + ppu_addr % 0x2000, value);
+            true
+        } else {
+            false
+        }
+    }
+    fn battery_ram<'a>(&'a self, mem: &'a CartridgeMemory) -> &'a [u8] {
+        if self.flashable {
+            &mem.prg_rom
+        } else {
+            &[]
+        }
+    }
+    fn load_battery_ram(&mut self, mem: &mut CartridgeMemory, data: &[u8]) {
+        mem.prg_rom.copy_from_slice(data);
+    }
+}
+
+impl Display for UnRom512 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "UNROM 512")
+    }
+}
+impl Debug for UnRom512 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} prg_bank={} chr_bank={} flash_state={:?}",
+            self, self.prg_bank, self.chr_bank, self.flash_state
+        )
+    }
+}
@@ -2,7 +2,7 @@ use std::fmt::{Debug, Display};
 
 use crate::core::{
     cartridge::{
-        mapper::{bank_addr, num_banks},
+        mapper::{bank_addr, bus_conflict, has_bus_conflicts, num_banks},
         CartridgeMemory,
     },
     Mapper,
@@ -12,8 +12,22 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Default, Serialize, Deserialize)]
 /// UxROM cartridge mapper and variants (mapper 2)
+///
+/// Has bus conflicts if the NES 2.0 submapper is 2.
 pub struct UxRom {
     bank: usize,
+    #[serde(default)]
+    bus_conflicts: bool,
+}
+
+impl UxRom {
+    /// Create a new UxROM mapper given the NES 2.0 submapper
+    pub fn new(submapper: u8) -> UxRom {
+        UxRom {
+            bank: 0,
+            bus_conflicts: has_bus_conflicts(submapper),
+        }
+    }
 }
 
 const BANK_SIZE: usize = 0x4000;
@@ -37,8 +51,8 @@ impl Mapper for UxRom {
             mem.prg_rom[bank_addr(BANK_SIZE, self.bank, cpu_addr)]
         }
     }
-    fn write_cpu(&mut self, _cpu_addr: usize, _mem: &mut CartridgeMemory, value: u8) {
-        self.bank = value as usize;
+    fn write_cpu(&mut self, cpu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        self.bank = bus_conflict(self, self.bus_conflicts, cpu_addr, mem, value) as usize;
     }
     fn read_ppu_debug(&self, ppu_addr: usize, mem: &CartridgeMemory) -> u8 {
         // No switching
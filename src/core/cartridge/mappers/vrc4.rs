@@ -0,0 +1,205 @@
+use std::fmt::{Debug, Display};
+
+use crate::core::{
+    cartridge::{
+        mapper::{bank_addr, num_banks},
+        mappers::vrc_irq::VrcIrq,
+    },
+    CartridgeMemory, Mapper, NametableArrangement,
+};
+use serde::{Deserialize, Serialize};
+
+// Mask for a single CPU address line
+const fn line(n: usize) -> usize {
+    1 << n
+}
+
+/// VRC2 and VRC4 cartridge mapper (mappers 21, 22, 23 and 25)
+///
+/// The boards connect the chip's `A0` and `A1` pins to different CPU address lines, which is given by the
+/// NES 2.0 submapper.
+/// When there is no submapper, both wirings possible for the mapper number are decoded at once, and the VRC4 is
+/// used for everything but mapper 22 since it is a superset of the VRC2.
+/// See [the NESDEV wiki](https://www.nesdev.org/wiki/VRC2_and_VRC4).
+#[derive(Serialize, Deserialize)]
+pub struct Vrc4 {
+    mapper: u32,
+    vrc2: bool,
+    // CPU address lines connected to the chip's A0 and A1
+    a0_lines: usize,
+    a1_lines: usize,
+    // The VRC2a ignores the lowest bit of the CHR banks
+    chr_shift: bool,
+    prg_banks: [usize; 2],
+    // Whether 0x8000 is fixed to the second last bank and 0xC000 is switchable (VRC4 only)
+    prg_swap: bool,
+    mirroring: u8,
+    chr_banks: [usize; 8],
+    // The VRC2's 1 bit latch at 0x6000, used for the EEPROM on some boards
+    microwire_latch: u8,
+    irq: VrcIrq,
+}
+
+impl Vrc4 {
+    /// Create a new VRC2/VRC4 given the iNES mapper number and the NES 2.0 submapper
+    pub fn new(mapper: u32, submapper: u8) -> Vrc4 {
+        // Chip's A0 and A1, and whether it is a VRC2
+        let (a0_lines, a1_lines, vrc2) = match (mapper, submapper) {
+            // VRC4a
+            (21, 1) => (line(1), line(2), false),
+            // VRC4c
+            (21, 2) => (line(6), line(7), false),
+            (21, _) => (line(1) | line(6), line(2) | line(7), false),
+            // VRC2a
+            (22, _) => (line(1), line(0), true),
+            // VRC4f
+            (23, 1) => (line(0), line(1), false),
+            // VRC4e
+            (23, 2) => (line(2), line(3), false),
+            // VRC2b
+            (23, 3) => (line(0), line(1), true),
+            (23, _) => (line(0) | line(2), line(1) | line(3), false),
+            // VRC4b
+            (25, 1) => (line(1), line(0), false),
+            // VRC4d
+            (25, 2) => (line(3), line(2), false),
+            // VRC2c
+            (25, 3) => (line(1), line(0), true),
+            (_, _) => (line(1) | line(3), line(0) | line(2), false),
+        };
+        Vrc4 {
+            mapper,
+            vrc2,
+            a0_lines,
+            a1_lines,
+            chr_shift: mapper == 22,
+            prg_banks: [0; 2],
+            prg_swap: false,
+            mirroring: 0,
+            chr_banks: [0; 8],
+            microwire_latch: 0,
+            irq: VrcIrq::default(),
+        }
+    }
+    // Get the register being addressed, as 0x8000, 0x8001, 0x8002, ..., 0xF003
+    fn register(&self, cpu_addr: usize) -> usize {
+        let a0 = (cpu_addr & self.a0_lines) != 0;
+        let a1 = (cpu_addr & self.a1_lines) != 0;
+        (cpu_addr & 0xF000) | a0 as usize | ((a1 as usize) << 1)
+    }
+    // Get the 8KB PRG bank for an address in 0x8000..0x10000
+    fn prg_bank(&self, cpu_addr: usize, mem: &CartridgeMemory) -> usize {
+        let num_banks = num_banks(0x2000, &mem.prg_rom);
+        match ((cpu_addr - 0x8000) / 0x2000, self.prg_swap) {
+            (0, false) | (2, true) => self.prg_banks[0],
+            (1, _) => self.prg_banks[1],
+            (3, _) => num_banks - 1,
+            (_, _) => num_banks - 2,
+        }
+    }
+    fn chr_addr(&self, ppu_addr: usize) -> usize {
+        let bank = self.chr_banks[(ppu_addr / 0x400) % 8];
+        let bank = if self.chr_shift { bank >> 1 } else { bank };
+        bank_addr(0x400, bank, ppu_addr)
+    }
+    // Whether 0x6000..0x7000 is the microwire latch rather than PRG RAM
+    fn has_microwire_latch(&self, mem: &CartridgeMemory) -> bool {
+        self.vrc2 && mem.prg_ram.is_empty()
+    }
+}
+
+#[typetag::serde]
+impl Mapper for Vrc4 {
+    fn mapper_num(&self) -> u32 {
+        self.mapper
+    }
+    fn read_cpu(&self, cpu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        match cpu_addr {
+            // The upper bits are open bus
+            0x6000..0x7000 if self.has_microwire_latch(mem) => 0x60 | self.microwire_latch,
+            0x6000..0x8000 => mem.read_prg_ram(cpu_addr - 0x6000),
+            0x8000..0x10000 => {
+                mem.read_prg_rom(bank_addr(0x2000, self.prg_bank(cpu_addr, mem), cpu_addr))
+            }
+            _ => 0,
+        }
+    }
+    fn write_cpu(&mut self, cpu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        if cpu_addr < 0x8000 {
+            if (0x6000..0x7000).contains(&cpu_addr) && self.has_microwire_latch(mem) {
+                self.microwire_latch = value & 0x01;
+            } else if cpu_addr >= 0x6000 {
+                mem.write_prg_ram(cpu_addr - 0x6000, value);
+            }
+            return;
+        }
+        let v = value as usize;
+        match self.register(cpu_addr) {
+            0x8000..0x8004 => self.prg_banks[0] = v & 0x1F,
+            // The VRC2 only has mirroring control, and only 2 of the VRC4's mirroring modes
+            0x9000..0x9004 if self.vrc2 => self.mirroring = value & 0x01,
+            0x9000 | 0x9001 => self.mirroring = value & 0x03,
+            0x9002 | 0x9003 => self.prg_swap = (value & 0x02) != 0,
+            0xA000..0xA004 => self.prg_banks[1] = v & 0x1F,
+            reg @ 0xB000..0xF000 => {
+                // Each bank is written in two halves, low 4 bits first
+                let bank = 2 * ((reg - 0xB000) >> 12) + ((reg & 0x02) >> 1);
+                self.chr_banks[bank] = if (reg & 0x01) == 0 {
+                    (self.chr_banks[bank] & !0x0F) | (v & 0x0F)
+                } else {
+                    (self.chr_banks[bank] & 0x0F) | ((v & 0x1F) << 4)
+                };
+            }
+            // The VRC2 has no IRQ
+            0xF000..0xF004 if self.vrc2 => {}
+            0xF000 => self.irq.set_latch_nibble(false, value),
+            0xF001 => self.irq.set_latch_nibble(true, value),
+            0xF002 => self.irq.write_control(value),
+            0xF003 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+    fn read_ppu_debug(&self, ppu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        mem.read_chr(self.chr_addr(ppu_addr))
+    }
+    fn write_ppu(&mut self, ppu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        mem.write_chr(self.chr_addr(ppu_addr), value);
+    }
+    fn nametable_arrangement(&self, _mem: &CartridgeMemory) -> NametableArrangement {
+        NametableArrangement::Custom
+    }
+    fn transform_nametable_addr(&self, addr: usize) -> usize {
+        let nametable = ((addr - 0x2000) / 0x400) % 4;
+        let page = match self.mirroring {
+            // Vertical mirroring
+            0 => nametable % 2,
+            // Horizontal mirroring
+            1 => nametable / 2,
+            // One screen
+            2 => 0,
+            _ => 1,
+        };
+        page * 0x400 + addr % 0x400
+    }
+    fn advance_cpu_cycles(&mut self, cycles: u32) {
+        (0..cycles).for_each(|_| self.irq.clock());
+    }
+    fn irq(&mut self) -> bool {
+        self.irq.irq()
+    }
+}
+
+impl Debug for Vrc4 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} mapper={} prg_banks={:X?} prg_swap={} chr_banks={:X?} mirroring={}",
+            self, self.mapper, self.prg_banks, self.prg_swap, self.chr_banks, self.mirroring
+        )
+    }
+}
+impl Display for Vrc4 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", if self.vrc2 { "VRC2" } else { "VRC4" })
+    }
+}
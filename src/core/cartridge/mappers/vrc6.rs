@@ -0,0 +1,214 @@
+use std::fmt::{Debug, Display};
+
+use crate::core::{
+    cartridge::{
+        mapper::{bank_addr, num_banks},
+        mappers::{vrc6_audio::Vrc6Audio, vrc_irq::VrcIrq},
+    },
+    CartridgeMemory, ExpansionAudio, Mapper, NametableArrangement,
+};
+use serde::{Deserialize, Serialize};
+
+/// VRC6 cartridge mapper (mappers 24 and 26)
+///
+/// Mapper 24 is the VRC6a, and mapper 26 is the VRC6b which has the `A0` and `A1` address lines swapped.
+/// Supports PRG/CHR banking, every PPU banking mode (including nametables in CHR ROM), the IRQ counter and
+/// the expansion audio.
+/// See [the NESDEV wiki](https://www.nesdev.org/wiki/VRC6).
+#[derive(Default, Serialize, Deserialize)]
+pub struct Vrc6 {
+    // Whether this is a VRC6b
+    swap_lines: bool,
+    // 16KB bank at 0x8000 and 8KB bank at 0xC000
+    prg_banks: [usize; 2],
+    // 1KB banks set by 0xD000-0xD003 and 0xE000-0xE003
+    chr_banks: [usize; 8],
+    // 0xB003
+    ppu_mode: u8,
+    irq: VrcIrq,
+    audio: Vrc6Audio,
+}
+
+impl Vrc6 {
+    /// Create a new VRC6, with `swap_lines` being [true] for a VRC6b (mapper 26) and [false] for a VRC6a (mapper 24)
+    pub fn new(swap_lines: bool) -> Vrc6 {
+        Vrc6 {
+            swap_lines,
+            ..Default::default()
+        }
+    }
+    // Get the register being addressed, in the VRC6a's layout
+    fn register(&self, cpu_addr: usize) -> usize {
+        let addr = cpu_addr & 0xF003;
+        if self.swap_lines {
+            (addr & 0xF000) | ((addr & 0x01) << 1) | ((addr & 0x02) >> 1)
+        } else {
+            addr
+        }
+    }
+    fn prg_ram_enabled(&self) -> bool {
+        (self.ppu_mode & 0x80) != 0
+    }
+    fn banking_mode(&self) -> u8 {
+        self.ppu_mode & 0x03
+    }
+    // Whether the nametables are read from CHR ROM instead of VRAM
+    fn chr_nametables(&self) -> bool {
+        (self.ppu_mode & 0x10) != 0
+    }
+    // Get the 1KB bank of a 2KB slot, given the register for the slot and which half is being addressed
+    fn half_bank(&self, bank: usize, upper: bool) -> usize {
+        // When 0xB003 bit 5 is clear, both halves use the register's value as is
+        if (self.ppu_mode & 0x20) != 0 {
+            (bank & !0x01) | upper as usize
+        } else {
+            bank
+        }
+    }
+    // Get the 1KB CHR bank for an address in the pattern tables
+    fn chr_bank(&self, ppu_addr: usize) -> usize {
+        let slot = (ppu_addr / 0x400) % 8;
+        match (self.banking_mode(), slot) {
+            (0, _) => self.chr_banks[slot],
+            (1, _) => self.half_bank(self.chr_banks[slot / 2], slot % 2 == 1),
+            (_, 0..4) => self.chr_banks[slot],
+            (_, _) => self.half_bank(self.chr_banks[4 + (slot - 4) / 2], slot % 2 == 1),
+        }
+    }
+    // Get the CHR bank register used for each nametable
+    fn nametable_registers(&self) -> [usize; 4] {
+        let mirroring = (self.ppu_mode >> 2) & 0x03;
+        match self.banking_mode() {
+            0 => match mirroring {
+                0 => [6, 7, 6, 7],
+                1 => [6, 6, 7, 7],
+                2 => [6; 4],
+                _ => [7; 4],
+            },
+            1 => [4, 5, 6, 7],
+            _ => {
+                if (mirroring & 0x01) == 0 {
+                    [6, 7, 6, 7]
+                } else {
+                    [6, 6, 7, 7]
+                }
+            }
+        }
+    }
+    // Get the page of VRAM a nametable uses when the nametables are not in CHR ROM
+    fn vram_page(&self, nametable: usize) -> usize {
+        let reg = self.nametable_registers()[nametable % 4];
+        if self.banking_mode() == 0 {
+            // Regular mirroring, R6 is the first page and R7 is the second
+            reg - 6
+        } else {
+            self.chr_banks[reg] & 0x01
+        }
+    }
+}
+
+#[typetag::serde]
+impl Mapper for Vrc6 {
+    fn mapper_num(&self) -> u32 {
+        if self.swap_lines {
+            26
+        } else {
+            24
+        }
+    }
+    fn read_cpu(&self, cpu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        match cpu_addr {
+            0x6000..0x8000 if self.prg_ram_enabled() => mem.read_prg_ram(cpu_addr - 0x6000),
+            0x8000..0xC000 => mem.read_prg_rom(bank_addr(0x4000, self.prg_banks[0], cpu_addr)),
+            0xC000..0xE000 => mem.read_prg_rom(bank_addr(0x2000, self.prg_banks[1], cpu_addr)),
+            0xE000..0x10000 => mem.read_prg_rom(bank_addr(
+                0x2000,
+                num_banks(0x2000, &mem.prg_rom) - 1,
+                cpu_addr,
+            )),
+            _ => 0,
+        }
+    }
+    fn write_cpu(&mut self, cpu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        if cpu_addr < 0x8000 {
+            if (0x6000..0x8000).contains(&cpu_addr) && self.prg_ram_enabled() {
+                mem.write_prg_ram(cpu_addr - 0x6000, value);
+            }
+            return;
+        }
+        let v = value as usize;
+        match self.register(cpu_addr) {
+            0x8000..0x8004 => self.prg_banks[0] = v & 0x0F,
+            0xB003 => self.ppu_mode = value,
+            addr @ 0x9000..0xB003 => self.audio.write(addr, value),
+            0xC000..0xC004 => self.prg_banks[1] = v & 0x1F,
+            addr @ 0xD000..0xD004 => self.chr_banks[addr & 0x03] = v,
+            addr @ 0xE000..0xE004 => self.chr_banks[4 + (addr & 0x03)] = v,
+            0xF000 => self.irq.set_latch(value),
+            0xF001 => self.irq.write_control(value),
+            0xF002 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+    fn read_ppu_debug(&self, ppu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        mem.read_chr(bank_addr(0x400, self.chr_bank(ppu_addr), ppu_addr))
+    }
+    fn write_ppu(&mut self, ppu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        mem.write_chr(bank_addr(0x400, self.chr_bank(ppu_addr), ppu_addr), value);
+    }
+    fn nametable_arrangement(&self, _mem: &CartridgeMemory) -> NametableArrangement {
+        NametableArrangement::Custom
+    }
+    fn transform_nametable_addr(&self, addr: usize) -> usize {
+        self.vram_page((addr - 0x2000) / 0x400) * 0x400 + addr % 0x400
+    }
+    fn read_nametable(&mut self, ppu_addr: usize, mem: &CartridgeMemory) -> Option<u8> {
+        if !self.chr_nametables() {
+            return None;
+        }
+        let reg = self.nametable_registers()[((ppu_addr - 0x2000) / 0x400) % 4];
+        Some(mem.read_chr(bank_addr(0x400, self.chr_banks[reg], ppu_addr)))
+    }
+    fn write_nametable(
+        &mut self,
+        _ppu_addr: usize,
+        _mem: &mut CartridgeMemory,
+        _value: u8,
+    ) -> bool {
+        // CHR ROM can't be written to
+        self.chr_nametables()
+    }
+    fn advance_cpu_cycles(&mut self, cycles: u32) {
+        (0..cycles).for_each(|_| self.irq.clock());
+    }
+    fn irq(&mut self) -> bool {
+        self.irq.irq()
+    }
+    fn expansion_audio(&self) -> Option<ExpansionAudio> {
+        Some(ExpansionAudio::Vrc6)
+    }
+    fn clock_expansion_audio(&mut self) {
+        self.audio.clock();
+    }
+    fn expansion_audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+}
+
+impl Debug for Vrc6 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "VRC6 mapper={} prg_banks={:X?} chr_banks={:X?} ppu_mode={:02X}",
+            self.mapper_num(),
+            self.prg_banks,
+            self.chr_banks,
+            self.ppu_mode
+        )
+    }
+}
+impl Display for Vrc6 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "VRC6")
+    }
+}
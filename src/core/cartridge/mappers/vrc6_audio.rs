@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+
+/// Output of each step of the chip's output, relative to the APU's output
+const VOLUME: f32 = 0.00752;
+
+/// One of the VRC6's pulse channels
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Vrc6Pulse {
+    volume: u8,
+    duty: u8,
+    // Output the volume regardless of the duty cycle
+    ignore_duty: bool,
+    period: u16,
+    enabled: bool,
+    timer: u16,
+    // Counts down from 15 to 0
+    step: u8,
+}
+
+impl Vrc6Pulse {
+    fn write(&mut self, reg: usize, value: u8) {
+        match reg {
+            0 => {
+                self.ignore_duty = (value & 0x80) != 0;
+                self.duty = (value >> 4) & 0x07;
+                self.volume = value & 0x0F;
+            }
+            1 => self.period = (self.period & 0x0F00) | value as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | ((value as u16 & 0x0F) << 8);
+                self.enabled = (value & 0x80) != 0;
+                if !self.enabled {
+                    self.step = 15;
+                }
+            }
+        }
+    }
+    fn clock(&mut self, shift: u32) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer == 0 {
+            self.timer = self.period >> shift;
+            self.step = if self.step == 0 { 15 } else { self.step - 1 };
+        } else {
+            self.timer -= 1;
+        }
+    }
+    fn output(&self) -> u8 {
+        if self.enabled && (self.ignore_duty || self.step <= self.duty) {
+            self.volume
+        } else {
+            0
+        }
+    }
+}
+
+/// The VRC6's sawtooth channel
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Vrc6Saw {
+    rate: u8,
+    period: u16,
+    enabled: bool,
+    timer: u16,
+    step: u8,
+    accumulator: u8,
+}
+
+impl Vrc6Saw {
+    fn write(&mut self, reg: usize, value: u8) {
+        match reg {
+            0 => self.rate = value & 0x3F,
+            1 => self.period = (self.period & 0x0F00) | value as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | ((value as u16 & 0x0F) << 8);
+                self.enabled = (value & 0x80) != 0;
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+        }
+    }
+    fn clock(&mut self, shift: u32) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer == 0 {
+            self.timer = self.period >> shift;
+            self.step += 1;
+            // The rate is added every other step, and the accumulator is reset after the 7th addition
+            if self.step == 14 {
+                self.step = 0;
+                self.accumulator = 0;
+            } else if self.step.is_multiple_of(2) {
+                self.accumulator = self.accumulator.wrapping_add(self.rate);
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+    fn output(&self) -> u8 {
+        // Only the top 5 bits are output
+        self.accumulator >> 3
+    }
+}
+
+/// The expansion audio of the VRC6.
+///
+/// Two pulse channels with 8 duty cycles and 4 bit volume, and a sawtooth channel.
+/// See [the NESDEV wiki](https://www.nesdev.org/wiki/VRC6_audio).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Vrc6Audio {
+    pulses: [Vrc6Pulse; 2],
+    saw: Vrc6Saw,
+    halt: bool,
+    // How many bits the channels' periods are shifted right by
+    frequency_shift: u32,
+}
+
+impl Vrc6Audio {
+    /// Write to one of the audio registers (`0x9000..0x9004`, `0xA000..0xA003` and `0xB000..0xB003`).
+    ///
+    /// `addr` must already have the mapper's address lines applied.
+    pub fn write(&mut self, addr: usize, value: u8) {
+        match addr {
+            0x9003 => {
+                self.halt = (value & 0x01) != 0;
+                self.frequency_shift = if (value & 0x04) != 0 {
+                    8
+                } else if (value & 0x02) != 0 {
+                    4
+                } else {
+                    0
+                };
+            }
+            0x9000..0x9003 => self.pulses[0].write(addr & 0x03, value),
+            0xA000..0xA003 => self.pulses[1].write(addr & 0x03, value),
+            0xB000..0xB003 => self.saw.write(addr & 0x03, value),
+            _ => {}
+        }
+    }
+    /// Advance the audio by one CPU cycle
+    pub fn clock(&mut self) {
+        if self.halt {
+            return;
+        }
+        let shift = self.frequency_shift;
+        self.pulses.iter_mut().for_each(|p| p.clock(shift));
+        self.saw.clock(shift);
+    }
+    /// The current output, relative to the APU's output
+    pub fn output(&self) -> f32 {
+        let total = self.pulses.iter().map(|p| p.output()).sum::<u8>() + self.saw.output();
+        total as f32 * VOLUME
+    }
+}
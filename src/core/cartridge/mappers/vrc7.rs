@@ -0,0 +1,158 @@
+use std::fmt::{Debug, Display};
+
+use crate::core::{
+    cartridge::{
+        mapper::{bank_addr, num_banks},
+        mappers::{vrc7_audio::Vrc7Audio, vrc_irq::VrcIrq},
+    },
+    CartridgeMemory, ExpansionAudio, Mapper, NametableArrangement,
+};
+use serde::{Deserialize, Serialize};
+
+/// VRC7 cartridge mapper (mapper 85)
+///
+/// The VRC7a (Lagrange Point) uses `A4` to select between registers at the same address, and the VRC7b uses `A3`.
+/// The NES 2.0 submapper chooses between them, and both are decoded when there is no submapper.
+/// Supports PRG/CHR banking, mirroring, PRG RAM, the IRQ counter and the FM synthesizer.
+/// See [the NESDEV wiki](https://www.nesdev.org/wiki/VRC7).
+#[derive(Serialize, Deserialize)]
+pub struct Vrc7 {
+    // Mask of the CPU address lines used to select the second register at an address
+    register_lines: usize,
+    // 8KB banks at 0x8000, 0xA000 and 0xC000
+    prg_banks: [usize; 3],
+    chr_banks: [usize; 8],
+    // 0xE000
+    control: u8,
+    irq: VrcIrq,
+    audio: Vrc7Audio,
+}
+
+impl Vrc7 {
+    /// Create a new VRC7 given the NES 2.0 submapper
+    pub fn new(submapper: u8) -> Vrc7 {
+        Vrc7 {
+            register_lines: match submapper {
+                // VRC7b
+                1 => 0x08,
+                // VRC7a
+                2 => 0x10,
+                _ => 0x18,
+            },
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            control: 0,
+            irq: VrcIrq::default(),
+            audio: Vrc7Audio::default(),
+        }
+    }
+    fn prg_ram_enabled(&self) -> bool {
+        (self.control & 0x80) != 0
+    }
+    fn chr_addr(&self, ppu_addr: usize) -> usize {
+        bank_addr(0x400, self.chr_banks[(ppu_addr / 0x400) % 8], ppu_addr)
+    }
+}
+
+#[typetag::serde]
+impl Mapper for Vrc7 {
+    fn mapper_num(&self) -> u32 {
+        85
+    }
+    fn read_cpu(&self, cpu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        match cpu_addr {
+            0x6000..0x8000 if self.prg_ram_enabled() => mem.read_prg_ram(cpu_addr - 0x6000),
+            0x8000..0xE000 => {
+                let bank = self.prg_banks[(cpu_addr - 0x8000) / 0x2000];
+                mem.read_prg_rom(bank_addr(0x2000, bank, cpu_addr))
+            }
+            0xE000..0x10000 => mem.read_prg_rom(bank_addr(
+                0x2000,
+                num_banks(0x2000, &mem.prg_rom) - 1,
+                cpu_addr,
+            )),
+            _ => 0,
+        }
+    }
+    fn write_cpu(&mut self, cpu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        if cpu_addr < 0x8000 {
+            if (0x6000..0x8000).contains(&cpu_addr) && self.prg_ram_enabled() {
+                mem.write_prg_ram(cpu_addr - 0x6000, value);
+            }
+            return;
+        }
+        let v = value as usize;
+        let second = (cpu_addr & self.register_lines) != 0;
+        match (cpu_addr & 0xF000, second) {
+            (0x8000, false) => self.prg_banks[0] = v & 0x3F,
+            (0x8000, true) => self.prg_banks[1] = v & 0x3F,
+            (0x9000, false) => self.prg_banks[2] = v & 0x3F,
+            // Only the VRC7a has audio, at 0x9010 and 0x9030
+            (0x9000, true) if (cpu_addr & 0x20) != 0 => self.audio.write(value),
+            (0x9000, true) => self.audio.select_register(value),
+            (addr @ 0xA000..0xE000, _) => {
+                self.chr_banks[2 * ((addr - 0xA000) >> 12) + second as usize] = v
+            }
+            (0xE000, false) => {
+                self.control = value;
+                self.audio.set_muted((value & 0x40) != 0);
+            }
+            (0xE000, true) => self.irq.set_latch(value),
+            (0xF000, false) => self.irq.write_control(value),
+            (0xF000, true) => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+    fn read_ppu_debug(&self, ppu_addr: usize, mem: &CartridgeMemory) -> u8 {
+        mem.read_chr(self.chr_addr(ppu_addr))
+    }
+    fn write_ppu(&mut self, ppu_addr: usize, mem: &mut CartridgeMemory, value: u8) {
+        mem.write_chr(self.chr_addr(ppu_addr), value);
+    }
+    fn nametable_arrangement(&self, _mem: &CartridgeMemory) -> NametableArrangement {
+        NametableArrangement::Custom
+    }
+    fn transform_nametable_addr(&self, addr: usize) -> usize {
+        let nametable = ((addr - 0x2000) / 0x400) % 4;
+        let page = match self.control & 0x03 {
+            // Vertical mirroring
+            0 => nametable % 2,
+            // Horizontal mirroring
+            1 => nametable / 2,
+            // One screen
+            2 => 0,
+            _ => 1,
+        };
+        page * 0x400 + addr % 0x400
+    }
+    fn advance_cpu_cycles(&mut self, cycles: u32) {
+        (0..cycles).for_each(|_| self.irq.clock());
+    }
+    fn irq(&mut self) -> bool {
+        self.irq.irq()
+    }
+    fn expansion_audio(&self) -> Option<ExpansionAudio> {
+        Some(ExpansionAudio::Vrc7)
+    }
+    fn clock_expansion_audio(&mut self) {
+        self.audio.clock();
+    }
+    fn expansion_audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+}
+
+impl Debug for Vrc7 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "VRC7 prg_banks={:X?} chr_banks={:X?} control={:02X}",
+            self.prg_banks, self.chr_banks, self.control
+        )
+    }
+}
+impl Display for Vrc7 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "VRC7")
+    }
+}
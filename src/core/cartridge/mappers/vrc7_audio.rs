@@ -0,0 +1,403 @@
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+
+/// Number of CPU cycles between each sample of the synthesizer.
+///
+/// The OPLL produces a sample every 72 clocks of its 3.58MHz clock, which is twice the CPU's.
+const CYCLES_PER_SAMPLE: u32 = 36;
+/// Output of a channel playing at full volume, relative to the APU's output
+const CHANNEL_VOLUME: f32 = 0.15;
+/// Maximum absolute output of an operator
+const MAX_OUTPUT: f32 = 4096.0;
+/// Number of channels on the VRC7 (the OPLL it is based on has 9)
+const NUM_CHANNELS: usize = 6;
+/// Maximum attenuation of an envelope, which is silent
+const MAX_ATTENUATION: u32 = 0x3FF;
+/// The VRC7's built in instruments, instrument 0 is the custom instrument set by registers `0x00-0x07`
+const INSTRUMENTS: [[u8; 8]; 15] = [
+    [0x03, 0x21, 0x05, 0x06, 0xE8, 0x81, 0x42, 0x27],
+    [0x13, 0x41, 0x14, 0x0D, 0xD8, 0xF6, 0x23, 0x12],
+    [0x11, 0x11, 0x08, 0x08, 0xFA, 0xB2, 0x20, 0x12],
+    [0x31, 0x61, 0x0C, 0x07, 0xA8, 0x64, 0x61, 0x27],
+    [0x32, 0x21, 0x1E, 0x06, 0xE1, 0x76, 0x01, 0x28],
+    [0x02, 0x01, 0x06, 0x00, 0xA3, 0xE2, 0xF4, 0xF4],
+    [0x21, 0x61, 0x1D, 0x07, 0x82, 0x81, 0x11, 0x07],
+    [0x23, 0x21, 0x22, 0x17, 0xA2, 0x72, 0x01, 0x17],
+    [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01],
+    [0xB5, 0x01, 0x0F, 0x0F, 0xA8, 0xA5, 0x51, 0x02],
+    [0x17, 0xC1, 0x24, 0x07, 0xF8, 0xF8, 0x22, 0x12],
+    [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16],
+    [0x01, 0x02, 0xD3, 0x05, 0xC9, 0x95, 0x03, 0x02],
+    [0x61, 0x63, 0x0C, 0x00, 0x94, 0xC0, 0x33, 0xF6],
+    [0x21, 0x72, 0x0D, 0x00, 0xC1, 0xD5, 0x56, 0x06],
+];
+/// Frequency multipliers, doubled so that multiplier 0 (which is 1/2) is an integer
+const MULTIPLIERS: [u32; 16] = [1, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 20, 24, 24, 30, 30];
+/// Key scale attenuation for the top 4 bits of the frequency, at the highest block
+const KEY_SCALE_LEVELS: [u32; 16] = [
+    0, 24, 32, 37, 40, 43, 45, 47, 48, 50, 51, 52, 53, 54, 55, 56,
+];
+/// Vibrato offset for each step of the vibrato LFO, in 1/128ths of the frequency
+const VIBRATO: [i32; 8] = [0, 1, 2, 1, 0, -1, -2, -1];
+/// Which of the 8 steps of an envelope's cycle increment the envelope, for the low 2 bits of the rate
+const ENVELOPE_STEPS: [[u32; 8]; 4] = [
+    [0, 1, 0, 1, 0, 1, 0, 1],
+    [0, 1, 0, 1, 1, 1, 0, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1],
+    [0, 1, 1, 1, 1, 1, 1, 1],
+];
+/// Which of the 8 steps double the increment for rates of 48 and above
+const FAST_ENVELOPE_STEPS: [[u32; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 0],
+    [0, 0, 0, 1, 0, 0, 0, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1],
+];
+
+/// The log-sin and exponent tables of the OPLL
+struct Tables {
+    /// `-log2(sin(x))` for the first quarter of a sine wave, in 1/256ths
+    log_sin: [u32; 256],
+    /// `2^x - 1` for `x` in `0..1`, in 1/1024ths
+    exp: [u32; 256],
+}
+
+fn tables() -> &'static Tables {
+    static TABLES: OnceLock<Tables> = OnceLock::new();
+    TABLES.get_or_init(|| Tables {
+        log_sin: core::array::from_fn(|i| {
+            let x = ((i as f64 + 0.5) * std::f64::consts::PI / 512.0).sin();
+            (-x.log2() * 256.0).round() as u32
+        }),
+        exp: core::array::from_fn(|i| (((i as f64 / 256.0).exp2() - 1.0) * 1024.0).round() as u32),
+    })
+}
+
+/// Compute the output of an operator given its phase (10 bits) and attenuation (in the log domain)
+fn operator_output(phase: i32, attenuation: u32, rectified: bool) -> i32 {
+    let phase = (phase & 0x3FF) as usize;
+    let negative = (phase & 0x200) != 0;
+    if negative && rectified {
+        return 0;
+    }
+    let tables = tables();
+    // Mirror the quarter wave
+    let index = if (phase & 0x100) != 0 {
+        !phase & 0xFF
+    } else {
+        phase & 0xFF
+    };
+    let attenuation = tables.log_sin[index] + (attenuation << 2);
+    let value =
+        (((tables.exp[!attenuation as usize & 0xFF] | 0x400) << 1) >> (attenuation >> 8)) as i32;
+    if negative {
+        -value
+    } else {
+        value
+    }
+}
+
+/// The settings of one operator, decoded from an instrument
+struct OperatorPatch {
+    tremolo: bool,
+    vibrato: bool,
+    sustained: bool,
+    key_scale_rate: bool,
+    multiplier: usize,
+    key_scale_level: u32,
+    rectified: bool,
+    attack: u32,
+    decay: u32,
+    sustain_level: u32,
+    release: u32,
+}
+
+impl OperatorPatch {
+    fn new(instrument: &[u8; 8], op: usize) -> OperatorPatch {
+        let flags = instrument[op];
+        OperatorPatch {
+            tremolo: (flags & 0x80) != 0,
+            vibrato: (flags & 0x40) != 0,
+            sustained: (flags & 0x20) != 0,
+            key_scale_rate: (flags & 0x10) != 0,
+            multiplier: (flags & 0x0F) as usize,
+            key_scale_level: (instrument[2 + op] >> 6) as u32,
+            rectified: (instrument[3] & (0x08 << op)) != 0,
+            attack: (instrument[4 + op] >> 4) as u32,
+            decay: (instrument[4 + op] & 0x0F) as u32,
+            sustain_level: (instrument[6 + op] >> 4) as u32,
+            release: (instrument[6 + op] & 0x0F) as u32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+enum EnvelopeState {
+    Attack,
+    Decay,
+    Sustain,
+    #[default]
+    Release,
+}
+
+/// One of the 2 operators (modulator and carrier) in a channel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Operator {
+    // 19 bit phase, the top 10 bits are used to index the sine wave
+    phase: u32,
+    envelope: u32,
+    state: EnvelopeState,
+}
+
+impl Default for Operator {
+    fn default() -> Self {
+        Operator {
+            phase: 0,
+            envelope: MAX_ATTENUATION,
+            state: EnvelopeState::Release,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Channel {
+    // 9 bit frequency number and 3 bit block (octave)
+    fnum: u32,
+    block: u32,
+    key_on: bool,
+    sustain: bool,
+    instrument: usize,
+    volume: u32,
+    operators: [Operator; 2],
+    // Last 2 outputs of the modulator, used for feedback
+    feedback: [i32; 2],
+}
+
+impl Channel {
+    fn set_key_on(&mut self, key_on: bool) {
+        if key_on && !self.key_on {
+            self.operators.iter_mut().for_each(|op| {
+                op.phase = 0;
+                op.state = EnvelopeState::Attack;
+            });
+        } else if !key_on && self.key_on {
+            self.operators
+                .iter_mut()
+                .for_each(|op| op.state = EnvelopeState::Release);
+        }
+        self.key_on = key_on;
+    }
+    // The key scale rate, before being shifted by the operator's key scale rate flag
+    fn key_scale(&self) -> u32 {
+        (self.block << 1) | (self.fnum >> 8)
+    }
+    // Attenuation from the key scale level
+    fn key_scale_attenuation(&self, key_scale_level: u32) -> u32 {
+        if key_scale_level == 0 {
+            return 0;
+        }
+        let level =
+            KEY_SCALE_LEVELS[(self.fnum >> 5) as usize] as i32 - 8 * (7 - self.block as i32);
+        (level.max(0) as u32) << key_scale_level
+    }
+}
+
+/// The expansion audio of the VRC7.
+///
+/// A 6 channel FM synthesizer derived from the Yamaha YM2413 (OPLL), with 15 built in instruments and 1 custom
+/// instrument.
+/// Each channel has a modulator and a carrier operator, with their own phase and envelope generators.
+/// See [the NESDEV wiki](https://www.nesdev.org/wiki/VRC7_audio).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Vrc7Audio {
+    selected_register: u8,
+    custom_instrument: [u8; 8],
+    channels: [Channel; NUM_CHANNELS],
+    // Counters for the envelopes and the tremolo and vibrato LFOs, incremented every sample
+    envelope_counter: u32,
+    lfo_counter: u32,
+    cycles: u32,
+    output: i32,
+    muted: bool,
+}
+
+impl Vrc7Audio {
+    /// Select the register to write to (`0x9010`)
+    pub fn select_register(&mut self, value: u8) {
+        self.selected_register = value;
+    }
+    /// Write to the selected register (`0x9030`)
+    pub fn write(&mut self, value: u8) {
+        let reg = self.selected_register as usize;
+        let channel = reg & 0x0F;
+        match reg {
+            0x00..0x08 => self.custom_instrument[reg] = value,
+            0x10..0x16 => {
+                let c = &mut self.channels[channel];
+                c.fnum = (c.fnum & 0x100) | value as u32;
+            }
+            0x20..0x26 => {
+                let c = &mut self.channels[channel];
+                c.fnum = (c.fnum & 0xFF) | ((value as u32 & 0x01) << 8);
+                c.block = (value as u32 >> 1) & 0x07;
+                c.sustain = (value & 0x20) != 0;
+                c.set_key_on((value & 0x10) != 0);
+            }
+            0x30..0x36 => {
+                let c = &mut self.channels[channel];
+                c.instrument = (value >> 4) as usize;
+                c.volume = (value & 0x0F) as u32;
+            }
+            _ => {}
+        }
+    }
+    /// Silence the audio, set by bit 6 of `0xE000`
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+    fn instrument(&self, instrument: usize) -> [u8; 8] {
+        if instrument == 0 {
+            self.custom_instrument
+        } else {
+            INSTRUMENTS[instrument - 1]
+        }
+    }
+    // Get the envelope rate (0-63) of an operator
+    fn envelope_rate(channel: &Channel, state: EnvelopeState, patch: &OperatorPatch) -> u32 {
+        let rate = match state {
+            EnvelopeState::Attack => patch.attack,
+            EnvelopeState::Decay => patch.decay,
+            EnvelopeState::Sustain if patch.sustained => 0,
+            EnvelopeState::Sustain => patch.release,
+            EnvelopeState::Release if channel.sustain => 5,
+            EnvelopeState::Release if patch.sustained => patch.release,
+            EnvelopeState::Release => 7,
+        };
+        if rate == 0 {
+            return 0;
+        }
+        let key_scale = channel.key_scale() >> if patch.key_scale_rate { 0 } else { 2 };
+        (rate * 4 + key_scale).min(63)
+    }
+    // Advance the envelope of an operator by one sample
+    fn clock_envelope(&self, channel: &Channel, op: &mut Operator, patch: &OperatorPatch) {
+        let rate = Self::envelope_rate(channel, op.state, patch);
+        let shift = 11u32.saturating_sub(rate >> 2);
+        if rate != 0 && (self.envelope_counter & ((1 << shift) - 1)) == 0 {
+            let step = ((self.envelope_counter >> shift) & 0x07) as usize;
+            let increment = if rate >= 60 {
+                8
+            } else if rate >= 48 {
+                (1 + FAST_ENVELOPE_STEPS[(rate & 0x03) as usize][step]) << ((rate >> 2) - 12)
+            } else {
+                ENVELOPE_STEPS[(rate & 0x03) as usize][step]
+            };
+            if op.state == EnvelopeState::Attack {
+                op.envelope = if rate >= 60 {
+                    0
+                } else {
+                    let envelope = op.envelope as i32;
+                    (envelope + (((-envelope - 1) * increment as i32) >> 4)).max(0) as u32
+                };
+            } else {
+                op.envelope = (op.envelope + increment).min(MAX_ATTENUATION);
+            }
+        }
+        match op.state {
+            EnvelopeState::Attack if op.envelope == 0 => op.state = EnvelopeState::Decay,
+            EnvelopeState::Decay if op.envelope >= patch.sustain_level << 5 => {
+                op.state = EnvelopeState::Sustain
+            }
+            _ => {}
+        }
+    }
+    // Advance the phase of an operator by one sample
+    fn clock_phase(&self, channel: &Channel, op: &mut Operator, patch: &OperatorPatch) {
+        let mut fnum = channel.fnum as i32;
+        if patch.vibrato {
+            fnum += ((fnum >> 6) * VIBRATO[((self.lfo_counter >> 10) & 0x07) as usize]) >> 1;
+        }
+        let increment = (((fnum as u32) << channel.block) * MULTIPLIERS[patch.multiplier]) >> 1;
+        op.phase = (op.phase + increment) & 0x7FFFF;
+    }
+    // Current attenuation of the tremolo LFO, which is a triangle wave of 26 steps
+    fn tremolo(&self) -> u32 {
+        let step = (self.lfo_counter >> 9) % 26;
+        (if step < 13 { step } else { 25 - step }) << 2
+    }
+    // Generate the next sample
+    fn sample(&mut self) -> i32 {
+        let tremolo = self.tremolo();
+        let mut channels = std::mem::take(&mut self.channels);
+        let output = channels
+            .iter_mut()
+            .map(|c| {
+                let instrument = self.instrument(c.instrument);
+                let patches = [
+                    OperatorPatch::new(&instrument, 0),
+                    OperatorPatch::new(&instrument, 1),
+                ];
+                let mut operators = std::mem::take(&mut c.operators);
+                operators
+                    .iter_mut()
+                    .zip(patches.iter())
+                    .for_each(|(op, p)| {
+                        self.clock_envelope(c, op, p);
+                        self.clock_phase(c, op, p);
+                    });
+                // Total attenuation of each operator
+                let attenuation: [u32; 2] = core::array::from_fn(|i| {
+                    let level = if i == 0 {
+                        (instrument[2] as u32 & 0x3F) << 3
+                    } else {
+                        c.volume << 5
+                    };
+                    let tremolo = if patches[i].tremolo { tremolo } else { 0 };
+                    (operators[i].envelope
+                        + level
+                        + c.key_scale_attenuation(patches[i].key_scale_level)
+                        + tremolo)
+                        .min(MAX_ATTENUATION)
+                });
+                let feedback = match instrument[3] & 0x07 {
+                    0 => 0,
+                    fb => (c.feedback[0] + c.feedback[1]) >> (9 - fb),
+                };
+                let modulator = operator_output(
+                    (operators[0].phase >> 9) as i32 + feedback,
+                    attenuation[0],
+                    patches[0].rectified,
+                );
+                c.feedback = [c.feedback[1], modulator];
+                let carrier = operator_output(
+                    (operators[1].phase >> 9) as i32 + modulator,
+                    attenuation[1],
+                    patches[1].rectified,
+                );
+                c.operators = operators;
+                carrier
+            })
+            .sum();
+        self.channels = channels;
+        self.envelope_counter = self.envelope_counter.wrapping_add(1);
+        self.lfo_counter = self.lfo_counter.wrapping_add(1);
+        output
+    }
+    /// Advance the audio by one CPU cycle
+    pub fn clock(&mut self) {
+        self.cycles += 1;
+        if self.cycles >= CYCLES_PER_SAMPLE {
+            self.cycles = 0;
+            self.output = self.sample();
+        }
+    }
+    /// The current output, relative to the APU's output
+    pub fn output(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            self.output as f32 / MAX_OUTPUT * CHANNEL_VOLUME
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+
+/// Number of PPU dots in a scanline, used by the prescaler in scanline mode
+const DOTS_PER_SCANLINE: i32 = 341;
+
+/// The IRQ counter shared by Konami's VRC mappers (VRC4, VRC6 and VRC7).
+///
+/// An 8 bit counter that counts up and triggers an IRQ when it overflows, clocked either every CPU cycle or every
+/// scanline (approximated with a prescaler that counts 3 PPU dots per CPU cycle).
+/// See [the NESDEV wiki](https://www.nesdev.org/wiki/VRC_IRQ).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VrcIrq {
+    latch: u8,
+    counter: u8,
+    prescaler: i32,
+    enabled: bool,
+    enable_after_ack: bool,
+    cycle_mode: bool,
+    pending: bool,
+}
+
+impl VrcIrq {
+    /// Set the value the counter is reloaded with
+    pub fn set_latch(&mut self, value: u8) {
+        self.latch = value;
+    }
+    /// Set either the low or high 4 bits of the latch, for mappers that write it one nibble at a time
+    pub fn set_latch_nibble(&mut self, high: bool, value: u8) {
+        self.latch = if high {
+            (self.latch & 0x0F) | ((value & 0x0F) << 4)
+        } else {
+            (self.latch & 0xF0) | (value & 0x0F)
+        };
+    }
+    /// Write to the IRQ control register
+    pub fn write_control(&mut self, value: u8) {
+        self.enable_after_ack = (value & 0x01) != 0;
+        self.enabled = (value & 0x02) != 0;
+        self.cycle_mode = (value & 0x04) != 0;
+        if self.enabled {
+            self.counter = self.latch;
+            self.prescaler = DOTS_PER_SCANLINE;
+        }
+        self.pending = false;
+    }
+    /// Acknowledge the IRQ
+    pub fn acknowledge(&mut self) {
+        self.pending = false;
+        self.enabled = self.enable_after_ack;
+    }
+    /// Advance the counter by one CPU cycle
+    pub fn clock(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        if self.cycle_mode {
+            self.clock_counter();
+        } else {
+            self.prescaler -= 3;
+            if self.prescaler <= 0 {
+                self.prescaler += DOTS_PER_SCANLINE;
+                self.clock_counter();
+            }
+        }
+    }
+    fn clock_counter(&mut self) {
+        if self.counter == 0xFF {
+            self.counter = self.latch;
+            self.pending = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+    /// [true] if the counter is triggering an IRQ
+    pub fn irq(&self) -> bool {
+        self.pending
+    }
+}
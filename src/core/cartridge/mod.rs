@@ -1,10 +1,16 @@
 // mod cartridge;
 // pub use cartridge::{Cartridge, CartridgeMemory, NametableArrangement};
 mod mapper;
-pub use mapper::Mapper;
+pub use mapper::{Mapper, PpuFetch};
 pub mod mappers;
 
-use crate::core::cartridge::mapper::get_mapper;
+use crate::core::{
+    cartridge::{
+        mapper::get_mapper,
+        mappers::{Fds, NsfMapper},
+    },
+    Nsf,
+};
 use log::*;
 use serde::{Deserialize, Serialize};
 use std::{
@@ -125,7 +131,6 @@ impl Cartridge {
         }
         let prg_rom_size = 0x4000 * bytes[4] as usize;
         let chr_rom_size = 0x2000 * bytes[5] as usize;
-        let prg_ram_size = max(bytes[8] as usize * 0x2000, 0x2000);
         let mut chr_ram_size = if chr_rom_size == 0 { 0x2000 } else { 0x0 };
         debug!("Cartridge header: {:X?}", &bytes[0..16]);
         let has_battery_ram = (bytes[6] & 0x02) != 0;
@@ -156,6 +161,22 @@ impl Cartridge {
             debug!("Archaic iNES probably detected");
             1
         };
+        let mut prg_ram_size = if file_type == 0 {
+            // Volatile and battery backed PRG RAM, each given as a shift count
+            [bytes[10] & 0x0F, bytes[10] >> 4]
+                .iter()
+                .map(|shift| if *shift == 0 { 0 } else { 64 << shift })
+                .sum()
+        } else {
+            max(bytes[8] as usize * 0x2000, 0x2000)
+        };
+        if file_type == 0 && bytes[11] != 0 {
+            // Volatile and battery backed CHR RAM, given the same way as PRG RAM
+            chr_ram_size = [bytes[11] & 0x0F, bytes[11] >> 4]
+                .iter()
+                .map(|shift| if *shift == 0 { 0 } else { 64 << shift })
+                .sum();
+        }
         debug!(
             "Detected as {}, ignoring.",
             if bytes[9] & 0x01 != 0 { "PAL" } else { "NTSC" }
@@ -164,8 +185,31 @@ impl Cartridge {
             "{:X} bytes PRG ROM, {:X} bytes CHR ROM, {:X} bytes PRG RAM, {:X} bytes CHR RAM",
             prg_rom_size, chr_rom_size, prg_ram_size, chr_ram_size
         );
-        // Todo
-        let mapper_id = (bytes[6] >> 4) + if file_type != 1 { bytes[7] & 0xF0 } else { 0 };
+        let mapper_id = (bytes[6] >> 4) as usize
+            + if file_type != 1 {
+                (bytes[7] & 0xF0) as usize
+            } else {
+                0
+            }
+            + if file_type == 0 {
+                (bytes[8] as usize & 0x0F) << 8
+            } else {
+                0
+            };
+        // Only NES 2.0 has submappers, 0 means the mapper should guess which variant to use
+        let submapper = if file_type == 0 { bytes[8] >> 4 } else { 0 };
+        // UNROM 512 always has 32KB of CHR RAM, but iNES can't specify that
+        if mapper_id == 30 && file_type != 0 && chr_rom_size == 0 {
+            chr_ram_size = 0x8000;
+        }
+        // UNROM 512 has no PRG RAM, and the battery flag means the PRG ROM is saved to flash instead
+        if mapper_id == 30 {
+            prg_ram_size = 0;
+        }
+        // TQROM has 8KB of CHR RAM along with its CHR ROM
+        if mapper_id == 119 && chr_ram_size == 0 {
+            chr_ram_size = 0x2000;
+        }
         let nametable_arrangement = if (bytes[6] & 0x01) == 0 {
             NametableArrangement::Vertical
         } else {
@@ -184,13 +228,9 @@ impl Cartridge {
             }
         );
         debug!(
-            "Cartridge is using {} mapper (0x{:X})",
-            mapper_id, mapper_id
+            "Cartridge is using {} mapper (0x{:X}), submapper {}",
+            mapper_id, mapper_id, submapper
         );
-        let mapper = match get_mapper(mapper_id as usize) {
-            Some(s) => s,
-            None => return Err(format!("Unsupported mapper number: {}", mapper_id)),
-        };
         let mut start = 16 + if has_trainer { 512 } else { 0 };
         let mut end = start + prg_rom_size;
         let prg_rom = bytes[start..end].to_vec();
@@ -198,24 +238,93 @@ impl Cartridge {
         end += chr_rom_size;
         debug!("Reading CHR ROM at {:#X}", start);
         let chr_rom = bytes[start..end].to_vec();
-        // Load PRG RAM from savedata if we have some
-        let prg_ram = match savedata {
+        let mut memory = CartridgeMemory {
+            prg_rom,
+            chr_rom,
+            prg_ram: vec![0; prg_ram_size],
+            chr_ram: vec![0; chr_ram_size],
+            nametable_arrangement,
+        };
+        let mut mapper = match get_mapper(mapper_id, submapper, bytes[6], &memory) {
+            Some(s) => s,
+            None => return Err(format!("Unsupported mapper number: {}", mapper_id)),
+        };
+        // NES 2.0 may include any battery backed RAM inside the mapper in the battery backed PRG RAM size,
+        // in which case it won't be a multiple of 8KB
+        let battery_ram_size = mapper.battery_ram(&memory).len();
+        if file_type == 0 && (64 << (bytes[10] >> 4)) % 0x2000 == battery_ram_size {
+            prg_ram_size -= battery_ram_size;
+        }
+        // Load PRG RAM from savedata if we have some, followed by the mapper's battery backed memory
+        memory.prg_ram = match savedata {
             Some(data) => {
-                assert_eq!(data.len(), prg_ram_size);
-                data
+                if data.len() != prg_ram_size + battery_ram_size {
+                    return Err(format!(
+                        "Savedata is {:X} bytes, but the cartridge has {:X} bytes of PRG RAM and {:X} bytes of mapper memory",
+                        data.len(),
+                        prg_ram_size,
+                        battery_ram_size
+                    ));
+                }
+                mapper.load_battery_ram(&mut memory, &data[prg_ram_size..]);
+                data[..prg_ram_size].to_vec()
             }
             None => vec![0; prg_ram_size],
         };
         Ok(Cartridge {
+            memory,
+            mapper,
+            has_battery_ram,
+        })
+    }
+    /// Create a new cartridge containing an NSF music file.
+    ///
+    /// The cartridge uses the NSF bankswitching mapper, and has 8KB of PRG RAM and CHR RAM.
+    /// Use [NsfPlayer][crate::core::NsfPlayer] to actually play the music.
+    pub fn from_nsf(nsf: &Nsf) -> Cartridge {
+        let (prg_rom, banks) = nsf.prg_rom_and_banks();
+        debug!(
+            "NSF loaded at {:#X}, {:X} bytes PRG ROM, banks {:X?}",
+            nsf.load_addr,
+            prg_rom.len(),
+            banks
+        );
+        Cartridge {
             memory: CartridgeMemory {
                 prg_rom,
-                chr_rom,
-                prg_ram,
-                chr_ram: vec![0; chr_ram_size],
-                nametable_arrangement,
+                chr_rom: Vec::new(),
+                prg_ram: vec![0; 0x2000],
+                chr_ram: vec![0; 0x2000],
+                nametable_arrangement: NametableArrangement::Vertical,
             },
-            mapper,
-            has_battery_ram,
+            mapper: Box::new(NsfMapper::new(banks)),
+            has_battery_ram: false,
+        }
+    }
+    /// Create a new Famicom Disk System RAM adapter, with a disk inserted.
+    ///
+    /// * `disk` The contents of the .fds file, with or without the fwNES header
+    /// * `bios` The contents of the FDS BIOS (disksys.rom), which must be 8KB
+    /// * `patch` An IPS patch of the changes previously written to the disk, as created by [Mapper::disk_patch].
+    ///
+    /// Returns a [Result] with either the [Cartridge] if successful or a [String] detailing the error if not.
+    pub fn from_fds(disk: &[u8], bios: &[u8], patch: Option<&[u8]>) -> Result<Cartridge, String> {
+        if bios.len() != 0x2000 {
+            return Err(format!(
+                "FDS BIOS must be 8KB, but is {:X} bytes",
+                bios.len()
+            ));
+        }
+        Ok(Cartridge {
+            memory: CartridgeMemory {
+                prg_rom: bios.to_vec(),
+                chr_rom: Vec::new(),
+                prg_ram: vec![0; 0x8000],
+                chr_ram: vec![0; 0x2000],
+                nametable_arrangement: NametableArrangement::Horizontal,
+            },
+            mapper: Box::new(Fds::new(disk, patch)?),
+            has_battery_ram: false,
         })
     }
     /// Read a byte from the cartridge's memory given an address in CPU memory space
@@ -268,6 +377,23 @@ impl Cartridge {
             NametableArrangement::Custom => self.mapper.transform_nametable_addr(addr),
         }
     }
+    /// Read a byte of nametable data given an address in PPU memory space.
+    ///
+    /// Reads from `vram` (the PPU's nametable RAM) unless the mapper provides the nametable itself.
+    pub fn read_nametable(&mut self, addr: usize, vram: &[u8]) -> u8 {
+        match self.mapper.read_nametable(addr, &self.memory) {
+            Some(value) => value,
+            None => vram[self.transform_nametable_addr(addr)],
+        }
+    }
+    /// Write a byte of nametable data given an address in PPU memory space.
+    ///
+    /// Writes to `vram` (the PPU's nametable RAM) unless the mapper handles the write itself.
+    pub fn write_nametable(&mut self, addr: usize, value: u8, vram: &mut [u8]) {
+        if !self.mapper.write_nametable(addr, &mut self.memory, value) {
+            vram[self.transform_nametable_addr(addr)] = value;
+        }
+    }
     /// [true] if the cartridge has battery backed RAM (i.e. save data), [false] otherwise
     pub fn has_battery_backed_ram(&self) -> bool {
         self.has_battery_ram
@@ -276,6 +402,16 @@ impl Cartridge {
     pub fn nametable_arrangement(&self) -> NametableArrangement {
         self.mapper.nametable_arrangement(&self.memory)
     }
+    /// Eject the current disk side and insert the next one, if the cartridge has disks.
+    ///
+    /// Goes through each side in turn, returning to the first side after the last one.
+    pub fn switch_disk_side(&mut self) {
+        let sides = self.mapper.disk_sides();
+        if sides > 0 {
+            let next = self.mapper.disk_side().map_or(0, |s| (s + 1) % sides);
+            self.mapper.insert_disk(Some(next));
+        }
+    }
     /// Advance the cartridge by a certain number of CPU cycles
     pub fn advance_cpu_cycles(&mut self, cycles: u32) {
         self.mapper.advance_cpu_cycles(cycles);
@@ -0,0 +1,78 @@
+//! Creating and applying IPS patches.
+//!
+//! Used to store the changes made to a Famicom Disk System disk separately from the original image.
+//! See [the format](https://zerosoft.zophar.net/ips.php).
+
+const HEADER: &[u8] = b"PATCH";
+const FOOTER: &[u8] = b"EOF";
+/// The largest record an IPS patch can hold
+const MAX_RECORD_LEN: usize = 0xFFFF;
+
+/// Create an IPS patch that transforms `original` into `modified`.
+///
+/// ```
+/// use yane::core::ips;
+/// let original = [0, 1, 2, 3, 4];
+/// let modified = [0, 1, 5, 3, 4, 6];
+/// let patch = ips::create(&original, &modified);
+/// assert_eq!(ips::apply(&original, &patch).unwrap(), modified);
+/// ```
+pub fn create(original: &[u8], modified: &[u8]) -> Vec<u8> {
+    let mut patch = HEADER.to_vec();
+    let mut i = 0;
+    while i < modified.len() {
+        if original.get(i) == Some(&modified[i]) {
+            i += 1;
+            continue;
+        }
+        // An offset of "EOF" would be read as the end of the patch, so start the record a byte earlier
+        let start = if i == 0x454F46 { i - 1 } else { i };
+        // Find the end of the changed bytes
+        while i < modified.len()
+            && i - start < MAX_RECORD_LEN
+            && original.get(i) != Some(&modified[i])
+        {
+            i += 1;
+        }
+        patch.extend_from_slice(&(start as u32).to_be_bytes()[1..]);
+        patch.extend_from_slice(&((i - start) as u16).to_be_bytes());
+        patch.extend_from_slice(&modified[start..i]);
+    }
+    patch.extend_from_slice(FOOTER);
+    patch
+}
+
+/// Apply an IPS patch to `original`, returning the patched data.
+pub fn apply(original: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if !patch.starts_with(HEADER) {
+        return Err("Patch is not an IPS patch".to_string());
+    }
+    let mut data = original.to_vec();
+    let mut i = HEADER.len();
+    let truncated = || "IPS patch is truncated".to_string();
+    loop {
+        let offset = patch.get(i..i + 3).ok_or_else(truncated)?;
+        if offset == FOOTER {
+            break;
+        }
+        let offset = u32::from_be_bytes([0, offset[0], offset[1], offset[2]]) as usize;
+        let size = patch.get(i + 3..i + 5).ok_or_else(truncated)?;
+        let size = u16::from_be_bytes([size[0], size[1]]) as usize;
+        i += 5;
+        // A size of 0 means the record is run length encoded
+        let bytes = if size == 0 {
+            let rle = patch.get(i..i + 3).ok_or_else(truncated)?;
+            i += 3;
+            vec![rle[2]; u16::from_be_bytes([rle[0], rle[1]]) as usize]
+        } else {
+            let bytes = patch.get(i..i + size).ok_or_else(truncated)?.to_vec();
+            i += size;
+            bytes
+        };
+        if data.len() < offset + bytes.len() {
+            data.resize(offset + bytes.len(), 0);
+        }
+        data[offset..offset + bytes.len()].copy_from_slice(&bytes);
+    }
+    Ok(data)
+}
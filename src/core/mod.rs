@@ -3,7 +3,8 @@
 //! A library for emulating the behaviour of the Nintendo Entertainment System.
 //! Contains the entire state of the machine, and updates it accordingly as the NES is advanced.
 //! The visual output can be accessed through the [Ppu::rgb_output] or [Ppu::rgb_output_buf],
-//! and the audio output can be accessed through [Apu::sample_queue] as a queue of samples.
+//! and the audio output can be accessed through [Apu::sample_queue] as a queue of samples
+//! (or read into a buffer at a given sample rate with [Apu::read_samples]).
 //! Input is updated though [Nes::set_controller_state].
 //!
 //! [Nes] and all of its fields can be serialized with the [serde] library,
@@ -44,7 +45,7 @@ pub use nes::{Nes, NesState};
 mod cpu;
 pub use cpu::Cpu;
 mod apu;
-pub use apu::Apu;
+pub use apu::{Apu, AudioChannel, BlipBuf, ExpansionAudio, FilterChain};
 mod status_register;
 pub use status_register::StatusRegister;
 mod cartridge;
@@ -55,7 +56,14 @@ pub use ppu::Ppu;
 mod controller;
 pub use controller::Controller;
 mod settings;
-pub use settings::Settings;
+pub use settings::{AudioOverflow, ChannelMix, Settings};
+mod palette;
+pub use palette::{Palette, DEFAULT_PALETTE};
+mod ntsc;
+pub use ntsc::{NtscFilter, NtscSettings, NTSC_OUTPUT_WIDTH};
+mod nsf;
+pub use nsf::{Nsf, NsfPlayer};
+pub mod ips;
 
 /// The debug palette, used instead of the palette ram if [Settings::use_debug_palette] is [true].
 pub const DEBUG_PALETTE: [u8; 32] = [
@@ -73,6 +81,9 @@ pub const RESET_IRQ_ADDR: usize = 0xFFFC;
 pub const NMI_IRQ_ADDR: usize = 0xFFFA;
 const PALETTE_DATA: &[u8; 1536] = include_bytes!("../2C02G_wiki.pal");
 /// Map of the console's Hue/Value output to RGB values
+///
+/// These are the colours of [DEFAULT_PALETTE] without any emphasis.
+/// Use [Palette] to use a different palette, or to get the emphasized colours.
 pub const HV_TO_RGB: &[[u8; 3]] =
     // This is unsafe but it should only be evaluated at compile time
     unsafe { core::slice::from_raw_parts(PALETTE_DATA.as_ptr() as *const [u8; 3], 3 * 64) };
@@ -45,6 +45,21 @@ impl Debug for NesState {
 
 const NUMBER_STORED_STATES: usize = 200;
 
+// Keeps track of where the current instruction is, so that the PPU can be caught up to the CPU before the CPU
+// accesses one of its registers.
+// Instructions are executed all at once, but on hardware each memory access happens on its own cycle.
+#[derive(Default)]
+struct PpuCatchUp {
+    // The settings the PPU is being advanced with, or None when not in Nes::advance_instruction
+    settings: Option<Settings>,
+    // The cycle of the current instruction the next memory access happens on
+    access_cycle: u32,
+    // The number of dots the PPU has already been advanced by during the current instruction
+    ppu_dots: u32,
+    // Whether the PPU has started VBlank while being caught up
+    vblank_started: bool,
+}
+
 /// The NES.
 ///
 /// The entire NES console.
@@ -72,6 +87,8 @@ pub struct Nes {
     /// The last 200 instructions executed by the NES. Stored for debugging purposes.
     #[serde(skip)]
     pub previous_states: VecDeque<NesState>,
+    #[serde(skip)]
+    ppu_catch_up: PpuCatchUp,
 }
 
 impl Default for Nes {
@@ -104,6 +121,7 @@ impl Nes {
             cached_controllers: [Controller::new(); 2],
             controller_bits: [0; 2],
             previous_states: VecDeque::with_capacity(NUMBER_STORED_STATES),
+            ppu_catch_up: PpuCatchUp::default(),
         }
     }
     /// Initialize the NES with a given cartridge inserted.
@@ -126,6 +144,7 @@ impl Nes {
             cached_controllers: [Controller::new(); 2],
             controller_bits: [0; 2],
             previous_states: VecDeque::with_capacity(NUMBER_STORED_STATES),
+            ppu_catch_up: PpuCatchUp::default(),
         };
         // During startup, the pushes are interpreted as pulls
         // So instead of the stake pointer going 0xFF -> 0x01, it should go to 0xFD
@@ -163,6 +182,7 @@ impl Nes {
     /// if the cartridge has battery backed ram,
     /// since "savedata" on the NES is just the cartridge RAM.
     /// Provided here as a convenience method.
+    /// Some mappers have battery backed RAM of their own, use [Nes::savedata_with_mapper_ram] to include it.
     pub fn savedata(&self) -> Option<&[u8]> {
         if self.cartridge.has_battery_backed_ram() {
             Some(&self.cartridge.memory.prg_ram)
@@ -171,6 +191,19 @@ impl Nes {
         }
     }
 
+    /// Get the savedata of the game in the NES, including any battery backed RAM inside the mapper, if there is any
+    ///
+    /// The same as [Nes::savedata], with the mapper's RAM ([Mapper::battery_ram][crate::core::Mapper::battery_ram])
+    /// added after the PRG RAM.
+    /// This is what should be given to [Cartridge::from_ines][crate::core::Cartridge::from_ines] to load the game again.
+    pub fn savedata_with_mapper_ram(&self) -> Option<Vec<u8>> {
+        self.savedata().map(|prg_ram| {
+            let mut data = prg_ram.to_vec();
+            data.extend_from_slice(self.cartridge.mapper.battery_ram(&self.cartridge.memory));
+            data
+        })
+    }
+
     fn read_controller_bit(&mut self, num: usize) -> u8 {
         let pressed = match self.controller_bits[num] {
             0 => self.cached_controllers[num].a,
@@ -205,15 +238,20 @@ impl Nes {
     /// let reset_low = nes.read_byte(0xFFFE);
     /// ```
     pub fn read_byte(&mut self, addr: usize) -> u8 {
-        match addr {
+        let value = match addr {
             0..0x2000 => self.mem[addr % 0x0800],
-            0x2000..0x4000 => self.ppu.read_byte(addr, &mut self.cartridge),
+            0x2000..0x4000 => {
+                self.catch_up_ppu();
+                self.ppu.read_byte(addr, &mut self.cartridge)
+            }
             0x4016 => self.read_controller_bit(0),
             0x4017 => self.read_controller_bit(1),
             0x4000..0x4020 => self.apu.read_byte(addr),
             0x4020..0x10000 => self.cartridge.read_cpu(addr),
             _ => panic!("Invalid read address provided: {:#X}", addr),
-        }
+        };
+        self.ppu_catch_up.access_cycle += 1;
+        value
     }
     /// Write a byte using CPU memory
     ///
@@ -229,7 +267,10 @@ impl Nes {
     pub fn write_byte(&mut self, addr: usize, value: u8) {
         match addr {
             0..0x2000 => self.mem[addr % 0x0800] = value,
-            0x2000..0x4000 => self.ppu.write_byte(addr, value, &mut self.cartridge),
+            0x2000..0x4000 => {
+                self.catch_up_ppu();
+                self.ppu.write_byte(addr, value, &mut self.cartridge)
+            }
             0x4014 => {
                 // Set PPU DMA register
                 self.ppu.oam_dma = Some(value);
@@ -248,6 +289,24 @@ impl Nes {
             0x4020..0x10000 => self.cartridge.write_cpu(addr, value),
             _ => panic!("Invalid write address provided: {:#X}", addr),
         };
+        self.ppu_catch_up.access_cycle += 1;
+    }
+    // Advance the PPU to the cycle of the current instruction the CPU is accessing memory on.
+    // Does nothing outside of Nes::advance_instruction.
+    fn catch_up_ppu(&mut self) {
+        self.catch_up_ppu_to(3 * self.ppu_catch_up.access_cycle);
+    }
+    fn catch_up_ppu_to(&mut self, target: u32) {
+        let catch_up = &mut self.ppu_catch_up;
+        if let Some(settings) = catch_up.settings {
+            if target > catch_up.ppu_dots {
+                let dots = target - catch_up.ppu_dots;
+                catch_up.ppu_dots = target;
+                if self.ppu.advance_dots(dots, &mut self.cartridge, &settings) {
+                    self.ppu_catch_up.vblank_started = true;
+                }
+            }
+        }
     }
     /// Update a controller's state in the NES.
     ///
@@ -362,6 +421,20 @@ impl Nes {
                 Ok(($bytes, $cycles))
             }};
         }
+        /*
+         * Macro to create a block that reads a byte from memory, calls a CPU function on it and writes the result back
+         */
+        macro_rules! rmw_func {
+            ($func: ident, $addr: ident, $bytes: expr, $cycles: expr) => {{
+                let addr = self.$addr(operands);
+                let v = self.read_byte(addr);
+                // The unmodified value is written back while the result is calculated
+                self.write_byte(addr, v);
+                let value = self.cpu.$func(v);
+                self.write_byte(addr, value);
+                Ok(($bytes, $cycles))
+            }};
+        }
         // Macro to set or unset a CPU flag
         macro_rules! flag_func {
             ($flag: ident, $val: expr) => {{
@@ -430,10 +503,10 @@ impl Nes {
             AND_IND_Y => cpu_func!(and, read_indirect_indexed, pc_ind, 2, 5, 6),
             // ASL
             ASL_A => cpu_write_func!(asl, read_a, write_a, 1, 2),
-            ASL_ZP => cpu_write_func!(asl, read_zp, write_zp, 2, 5),
-            ASL_ZP_X => cpu_write_func!(asl, read_zp_x, write_zp_x, 2, 6),
-            ASL_ABS => cpu_write_func!(asl, read_abs, write_abs, 3, 6),
-            ASL_ABS_X => cpu_write_func!(asl, read_abs_x, write_abs_x, 3, 7),
+            ASL_ZP => rmw_func!(asl, zp_addr, 2, 5),
+            ASL_ZP_X => rmw_func!(asl, zp_x_addr, 2, 6),
+            ASL_ABS => rmw_func!(asl, abs_addr, 3, 6),
+            ASL_ABS_X => rmw_func!(asl, abs_x_addr, 3, 7),
             // Various branching functions
             BCS => Ok((2, self.cpu.branch_if(self.cpu.s_r.c, operands[0]))),
             BCC => Ok((2, self.cpu.branch_if(!self.cpu.s_r.c, operands[0]))),
@@ -480,10 +553,10 @@ impl Nes {
             CPY_ZP => cpu_func!(cpy, read_zp, 2, 3),
             CPY_ABS => cpu_func!(cpy, read_abs, 3, 4),
             // DEC
-            DEC_ZP => cpu_write_func!(dec, read_zp, write_zp, 2, 5),
-            DEC_ZP_X => cpu_write_func!(dec, read_zp_x, write_zp_x, 2, 6),
-            DEC_ABS => cpu_write_func!(dec, read_abs, write_abs, 3, 6),
-            DEC_ABS_X => cpu_write_func!(dec, read_abs_x, write_abs_x, 3, 7),
+            DEC_ZP => rmw_func!(dec, zp_addr, 2, 5),
+            DEC_ZP_X => rmw_func!(dec, zp_x_addr, 2, 6),
+            DEC_ABS => rmw_func!(dec, abs_addr, 3, 6),
+            DEC_ABS_X => rmw_func!(dec, abs_x_addr, 3, 7),
             DEX => {
                 self.cpu.x = self.cpu.dec(self.cpu.x);
                 Ok((1, 2))
@@ -502,10 +575,10 @@ impl Nes {
             EOR_IND_X => cpu_func!(eor, read_indexed_indirect, 2, 6),
             EOR_IND_Y => cpu_func!(eor, read_indirect_indexed, pc_ind, 2, 5, 6),
             // INC
-            INC_ZP => cpu_write_func!(inc, read_zp, write_zp, 2, 5),
-            INC_ZP_X => cpu_write_func!(inc, read_zp_x, write_zp_x, 2, 6),
-            INC_ABS => cpu_write_func!(inc, read_abs, write_abs, 3, 6),
-            INC_ABS_X => cpu_write_func!(inc, read_abs_x, write_abs_x, 3, 7),
+            INC_ZP => rmw_func!(inc, zp_addr, 2, 5),
+            INC_ZP_X => rmw_func!(inc, zp_x_addr, 2, 6),
+            INC_ABS => rmw_func!(inc, abs_addr, 3, 6),
+            INC_ABS_X => rmw_func!(inc, abs_x_addr, 3, 7),
             INX => {
                 self.cpu.x = self.cpu.inc(self.cpu.x);
                 Ok((1, 2))
@@ -536,10 +609,10 @@ impl Nes {
             }
             // LSR
             LSR_A => cpu_write_func!(lsr, read_a, write_a, 1, 2),
-            LSR_ZP => cpu_write_func!(lsr, read_zp, write_zp, 2, 5),
-            LSR_ZP_X => cpu_write_func!(lsr, read_zp_x, write_zp_x, 2, 6),
-            LSR_ABS => cpu_write_func!(lsr, read_abs, write_abs, 3, 6),
-            LSR_ABS_X => cpu_write_func!(lsr, read_abs_x, write_abs_x, 3, 7),
+            LSR_ZP => rmw_func!(lsr, zp_addr, 2, 5),
+            LSR_ZP_X => rmw_func!(lsr, zp_x_addr, 2, 6),
+            LSR_ABS => rmw_func!(lsr, abs_addr, 3, 6),
+            LSR_ABS_X => rmw_func!(lsr, abs_x_addr, 3, 7),
             NOP => Ok((1, 2)),
             // ORA
             ORA_I => cpu_func!(ora, read_immediate, 2, 2),
@@ -574,15 +647,15 @@ impl Nes {
             }
             // ROL
             ROL_A => cpu_write_func!(rol, read_a, write_a, 1, 2),
-            ROL_ZP => cpu_write_func!(rol, read_zp, write_zp, 2, 5),
-            ROL_ZP_X => cpu_write_func!(rol, read_zp_x, write_zp_x, 2, 6),
-            ROL_ABS => cpu_write_func!(rol, read_abs, write_abs, 3, 6),
-            ROL_ABS_X => cpu_write_func!(rol, read_abs_x, write_abs_x, 3, 7),
+            ROL_ZP => rmw_func!(rol, zp_addr, 2, 5),
+            ROL_ZP_X => rmw_func!(rol, zp_x_addr, 2, 6),
+            ROL_ABS => rmw_func!(rol, abs_addr, 3, 6),
+            ROL_ABS_X => rmw_func!(rol, abs_x_addr, 3, 7),
             ROR_A => cpu_write_func!(ror, read_a, write_a, 1, 2),
-            ROR_ZP => cpu_write_func!(ror, read_zp, write_zp, 2, 5),
-            ROR_ZP_X => cpu_write_func!(ror, read_zp_x, write_zp_x, 2, 6),
-            ROR_ABS => cpu_write_func!(ror, read_abs, write_abs, 3, 6),
-            ROR_ABS_X => cpu_write_func!(ror, read_abs_x, write_abs_x, 3, 7),
+            ROR_ZP => rmw_func!(ror, zp_addr, 2, 5),
+            ROR_ZP_X => rmw_func!(ror, zp_x_addr, 2, 6),
+            ROR_ABS => rmw_func!(ror, abs_addr, 3, 6),
+            ROR_ABS_X => rmw_func!(ror, abs_x_addr, 3, 7),
             RTI => {
                 let v = self.pull_from_stack();
                 self.cpu.s_r.from_byte(v);
@@ -667,71 +740,71 @@ impl Nes {
             unofficial::SAX_IND_X => {
                 store_func!(self.cpu.a & self.cpu.x, write_indexed_indirect, 2, 6)
             }
-            unofficial::DCP_ZP => cpu_write_func!(dcp, read_zp, write_zp, 2, 5),
-            unofficial::DCP_ZP_X => cpu_write_func!(dcp, read_zp_x, write_zp_x, 2, 6),
-            unofficial::DCP_ABS => cpu_write_func!(dcp, read_abs, write_abs, 3, 6),
-            unofficial::DCP_ABS_X => cpu_write_func!(dcp, read_abs_x, write_abs_x, 3, 7),
-            unofficial::DCP_ABS_Y => cpu_write_func!(dcp, read_abs_y, write_abs_y, 3, 7),
+            unofficial::DCP_ZP => rmw_func!(dcp, zp_addr, 2, 5),
+            unofficial::DCP_ZP_X => rmw_func!(dcp, zp_x_addr, 2, 6),
+            unofficial::DCP_ABS => rmw_func!(dcp, abs_addr, 3, 6),
+            unofficial::DCP_ABS_X => rmw_func!(dcp, abs_x_addr, 3, 7),
+            unofficial::DCP_ABS_Y => rmw_func!(dcp, abs_y_addr, 3, 7),
             unofficial::DCP_IND_X => {
-                cpu_write_func!(dcp, read_indexed_indirect, write_indexed_indirect, 2, 8)
+                rmw_func!(dcp, indexed_indirect_addr, 2, 8)
             }
             unofficial::DCP_IND_Y => {
-                cpu_write_func!(dcp, read_indirect_indexed, write_indirect_indexed, 2, 8)
+                rmw_func!(dcp, indirect_indexed_addr, 2, 8)
             }
-            unofficial::ISC_ZP => cpu_write_func!(isc, read_zp, write_zp, 2, 5),
-            unofficial::ISC_ZP_X => cpu_write_func!(isc, read_zp_x, write_zp_x, 2, 6),
-            unofficial::ISC_ABS => cpu_write_func!(isc, read_abs, write_abs, 3, 6),
-            unofficial::ISC_ABS_X => cpu_write_func!(isc, read_abs_x, write_abs_x, 3, 7),
-            unofficial::ISC_ABS_Y => cpu_write_func!(isc, read_abs_y, write_abs_y, 3, 7),
+            unofficial::ISC_ZP => rmw_func!(isc, zp_addr, 2, 5),
+            unofficial::ISC_ZP_X => rmw_func!(isc, zp_x_addr, 2, 6),
+            unofficial::ISC_ABS => rmw_func!(isc, abs_addr, 3, 6),
+            unofficial::ISC_ABS_X => rmw_func!(isc, abs_x_addr, 3, 7),
+            unofficial::ISC_ABS_Y => rmw_func!(isc, abs_y_addr, 3, 7),
             unofficial::ISC_IND_X => {
-                cpu_write_func!(isc, read_indexed_indirect, write_indexed_indirect, 2, 8)
+                rmw_func!(isc, indexed_indirect_addr, 2, 8)
             }
             unofficial::ISC_IND_Y => {
-                cpu_write_func!(isc, read_indirect_indexed, write_indirect_indexed, 2, 8)
+                rmw_func!(isc, indirect_indexed_addr, 2, 8)
             }
-            unofficial::RLA_ZP => cpu_write_func!(rla, read_zp, write_zp, 2, 5),
-            unofficial::RLA_ZP_X => cpu_write_func!(rla, read_zp_x, write_zp_x, 2, 6),
-            unofficial::RLA_ABS => cpu_write_func!(rla, read_abs, write_abs, 3, 6),
-            unofficial::RLA_ABS_X => cpu_write_func!(rla, read_abs_x, write_abs_x, 3, 7),
-            unofficial::RLA_ABS_Y => cpu_write_func!(rla, read_abs_y, write_abs_y, 3, 7),
+            unofficial::RLA_ZP => rmw_func!(rla, zp_addr, 2, 5),
+            unofficial::RLA_ZP_X => rmw_func!(rla, zp_x_addr, 2, 6),
+            unofficial::RLA_ABS => rmw_func!(rla, abs_addr, 3, 6),
+            unofficial::RLA_ABS_X => rmw_func!(rla, abs_x_addr, 3, 7),
+            unofficial::RLA_ABS_Y => rmw_func!(rla, abs_y_addr, 3, 7),
             unofficial::RLA_IND_X => {
-                cpu_write_func!(rla, read_indexed_indirect, write_indexed_indirect, 2, 8)
+                rmw_func!(rla, indexed_indirect_addr, 2, 8)
             }
             unofficial::RLA_IND_Y => {
-                cpu_write_func!(rla, read_indirect_indexed, write_indirect_indexed, 2, 8)
+                rmw_func!(rla, indirect_indexed_addr, 2, 8)
             }
-            unofficial::RRA_ZP => cpu_write_func!(rra, read_zp, write_zp, 2, 5),
-            unofficial::RRA_ZP_X => cpu_write_func!(rra, read_zp_x, write_zp_x, 2, 6),
-            unofficial::RRA_ABS => cpu_write_func!(rra, read_abs, write_abs, 3, 6),
-            unofficial::RRA_ABS_X => cpu_write_func!(rra, read_abs_x, write_abs_x, 3, 7),
-            unofficial::RRA_ABS_Y => cpu_write_func!(rra, read_abs_y, write_abs_y, 3, 7),
+            unofficial::RRA_ZP => rmw_func!(rra, zp_addr, 2, 5),
+            unofficial::RRA_ZP_X => rmw_func!(rra, zp_x_addr, 2, 6),
+            unofficial::RRA_ABS => rmw_func!(rra, abs_addr, 3, 6),
+            unofficial::RRA_ABS_X => rmw_func!(rra, abs_x_addr, 3, 7),
+            unofficial::RRA_ABS_Y => rmw_func!(rra, abs_y_addr, 3, 7),
             unofficial::RRA_IND_X => {
-                cpu_write_func!(rra, read_indexed_indirect, write_indexed_indirect, 2, 8)
+                rmw_func!(rra, indexed_indirect_addr, 2, 8)
             }
             unofficial::RRA_IND_Y => {
-                cpu_write_func!(rra, read_indirect_indexed, write_indirect_indexed, 2, 8)
+                rmw_func!(rra, indirect_indexed_addr, 2, 8)
             }
-            unofficial::SLO_ZP => cpu_write_func!(slo, read_zp, write_zp, 2, 5),
-            unofficial::SLO_ZP_X => cpu_write_func!(slo, read_zp_x, write_zp_x, 2, 6),
-            unofficial::SLO_ABS => cpu_write_func!(slo, read_abs, write_abs, 3, 6),
-            unofficial::SLO_ABS_X => cpu_write_func!(slo, read_abs_x, write_abs_x, 3, 7),
-            unofficial::SLO_ABS_Y => cpu_write_func!(slo, read_abs_y, write_abs_y, 3, 7),
+            unofficial::SLO_ZP => rmw_func!(slo, zp_addr, 2, 5),
+            unofficial::SLO_ZP_X => rmw_func!(slo, zp_x_addr, 2, 6),
+            unofficial::SLO_ABS => rmw_func!(slo, abs_addr, 3, 6),
+            unofficial::SLO_ABS_X => rmw_func!(slo, abs_x_addr, 3, 7),
+            unofficial::SLO_ABS_Y => rmw_func!(slo, abs_y_addr, 3, 7),
             unofficial::SLO_IND_X => {
-                cpu_write_func!(slo, read_indexed_indirect, write_indexed_indirect, 2, 8)
+                rmw_func!(slo, indexed_indirect_addr, 2, 8)
             }
             unofficial::SLO_IND_Y => {
-                cpu_write_func!(slo, read_indirect_indexed, write_indirect_indexed, 2, 8)
+                rmw_func!(slo, indirect_indexed_addr, 2, 8)
             }
-            unofficial::SRE_ZP => cpu_write_func!(sre, read_zp, write_zp, 2, 5),
-            unofficial::SRE_ZP_X => cpu_write_func!(sre, read_zp_x, write_zp_x, 2, 6),
-            unofficial::SRE_ABS => cpu_write_func!(sre, read_abs, write_abs, 3, 6),
-            unofficial::SRE_ABS_X => cpu_write_func!(sre, read_abs_x, write_abs_x, 3, 7),
-            unofficial::SRE_ABS_Y => cpu_write_func!(sre, read_abs_y, write_abs_y, 3, 7),
+            unofficial::SRE_ZP => rmw_func!(sre, zp_addr, 2, 5),
+            unofficial::SRE_ZP_X => rmw_func!(sre, zp_x_addr, 2, 6),
+            unofficial::SRE_ABS => rmw_func!(sre, abs_addr, 3, 6),
+            unofficial::SRE_ABS_X => rmw_func!(sre, abs_x_addr, 3, 7),
+            unofficial::SRE_ABS_Y => rmw_func!(sre, abs_y_addr, 3, 7),
             unofficial::SRE_IND_X => {
-                cpu_write_func!(sre, read_indexed_indirect, write_indexed_indirect, 2, 8)
+                rmw_func!(sre, indexed_indirect_addr, 2, 8)
             }
             unofficial::SRE_IND_Y => {
-                cpu_write_func!(sre, read_indirect_indexed, write_indirect_indexed, 2, 8)
+                rmw_func!(sre, indirect_indexed_addr, 2, 8)
             }
             unofficial::SBC => cpu_func!(sbc, read_immediate, 2, 2),
             _ if unofficial::NOPS.contains(opcode) => Ok((1, 2)),
@@ -760,7 +833,17 @@ impl Nes {
     /// Updates the PPU, APU, and cartridge accordingly.
     pub fn advance_instruction(&mut self, settings: &Settings) -> Result<u32, String> {
         // Advance the CPU by 1 instruction
-        let mut c = self.step()?;
+        // The instruction's opcode and operands are read on the first 3 cycles, and the PPU is caught up whenever
+        // the CPU accesses it after that
+        self.ppu_catch_up.settings = Some(*settings);
+        self.ppu_catch_up.access_cycle = 0;
+        let step = self.step();
+        if let Ok(c) = step {
+            // Interrupts are polled during the second last cycle of the instruction
+            self.catch_up_ppu_to(3 * (c - 2) + 1);
+        }
+        self.ppu_catch_up.settings = None;
+        let mut c = step?;
         // If we are in VBlank
         if self.ppu.in_vblank() {
             // Check for OAM DMA
@@ -773,13 +856,23 @@ impl Nes {
             self.interrupt_to_addr(CARTRIDGE_IRQ_ADDR);
             c += 7;
         }
-        self.apu.advance_cpu_cycles(c, &mut self.cartridge);
+        self.apu
+            .advance_cpu_cycles(c, &mut self.cartridge, settings);
         self.cartridge.advance_cpu_cycles(c);
-        if self.ppu.advance_dots(3 * c, &mut self.cartridge, settings) && self.ppu.get_nmi_enabled()
-        {
+        // Advance the PPU by the rest of the instruction
+        // The PPU can be a few dots ahead if an instruction accessed it more times than it has cycles, in which case
+        // the difference is carried over to the next instruction
+        let dots = (3 * c).saturating_sub(self.ppu_catch_up.ppu_dots);
+        self.ppu_catch_up.ppu_dots -= 3 * c - dots;
+        // NMIs are polled at the same time, so VBlank starting after that is handled after the next instruction
+        let vblank_started = std::mem::take(&mut self.ppu_catch_up.vblank_started);
+        self.ppu_catch_up.vblank_started =
+            self.ppu.advance_dots(dots, &mut self.cartridge, settings);
+        if vblank_started && self.ppu.get_nmi_enabled() {
             self.on_nmi();
             c += 7;
-            self.apu.advance_cpu_cycles(7, &mut self.cartridge);
+            self.apu
+                .advance_cpu_cycles(7, &mut self.cartridge, settings);
             self.cartridge.advance_cpu_cycles(7);
             self.ppu.advance_dots(21, &mut self.cartridge, settings);
         }
@@ -891,6 +984,14 @@ impl Nes {
     fn write_zp_offset(&mut self, addr: u8, offset: u8, value: u8) {
         self.write_byte(addr.wrapping_add(offset) as usize, value)
     }
+    // Get the address used by zero page addressing
+    fn zp_addr(&self, addr: &[u8]) -> usize {
+        addr[0] as usize
+    }
+    // Get the address used by zero page addressing with X register offset
+    fn zp_x_addr(&self, addr: &[u8]) -> usize {
+        addr[0].wrapping_add(self.cpu.x) as usize
+    }
     // Absolute addressing
     fn get_absolute_addr_offset(addr: &[u8], offset: u8) -> usize {
         (addr[0] as u16 + ((addr[1] as u16) << 8)).wrapping_add(offset as u16) as usize
@@ -920,6 +1021,10 @@ impl Nes {
     pub fn write_abs(&mut self, addr: &[u8], value: u8) {
         self.write_byte(Nes::get_absolute_addr(addr), value)
     }
+    // Get the address used by absolute addressing
+    fn abs_addr(&self, addr: &[u8]) -> usize {
+        Nes::get_absolute_addr(addr)
+    }
     // Check whether an absolute address with offset (i.e. $1234, X) would cross a page
     fn addr_offset_is_page_cross(addr: &[u8], offset: u8) -> bool {
         addr[0] as u16 + offset as u16 > 0xFF
@@ -936,12 +1041,26 @@ impl Nes {
         self.read_byte(Nes::get_absolute_addr_offset(addr, offset))
     }
     fn write_abs_offset(&mut self, addr: &[u8], offset: u8, value: u8) {
+        let addr = self.abs_offset_addr(addr, offset);
+        self.write_byte(addr, value)
+    }
+    // Get the address used by absolute addressing with an offset when writing.
+    // Writes always do a dummy read first, even if no page is crossed.
+    fn abs_offset_addr(&mut self, addr: &[u8], offset: u8) -> usize {
         // Do a dummy read
         self.read_byte(
             (Nes::get_absolute_addr(addr) & 0xFF00)
                 | (Nes::get_absolute_addr_offset(addr, offset) & 0x00FF),
         );
-        self.write_byte(Nes::get_absolute_addr_offset(addr, offset), value)
+        Nes::get_absolute_addr_offset(addr, offset)
+    }
+    // Get the address used by absolute addressing with X register offset when writing
+    fn abs_x_addr(&mut self, addr: &[u8]) -> usize {
+        self.abs_offset_addr(addr, self.cpu.x)
+    }
+    // Get the address used by absolute addressing with Y register offset when writing
+    fn abs_y_addr(&mut self, addr: &[u8]) -> usize {
+        self.abs_offset_addr(addr, self.cpu.y)
     }
     /// Read a byte from memory using absolute addressing with X register offset.
     /// ```
@@ -984,12 +1103,8 @@ impl Nes {
     /// nes.read_indexed_indirect(&[0x12]);
     /// ```
     pub fn read_indexed_indirect(&mut self, addr: &[u8]) -> u8 {
-        let first_addr = addr[0].wrapping_add(self.cpu.x);
-        let second_addr = [
-            self.read_byte(first_addr as usize),
-            self.read_byte(first_addr.wrapping_add(1) as usize),
-        ];
-        self.read_abs(&second_addr)
+        let addr = self.indexed_indirect_addr(addr);
+        self.read_byte(addr)
     }
     /// Write a single byte using indexed indirect addressing.
     /// ```
@@ -997,20 +1112,40 @@ impl Nes {
     /// nes.write_indexed_indirect(&[0x12], 0x01);
     /// ```
     pub fn write_indexed_indirect(&mut self, addr: &[u8], value: u8) {
+        let addr = self.indexed_indirect_addr(addr);
+        self.write_byte(addr, value);
+    }
+    // Transform an address into an indexed indirect address
+    // Adds the X register to the address and then reads the pointer at that zero page address
+    fn indexed_indirect_addr(&mut self, addr: &[u8]) -> usize {
         let first_addr = addr[0].wrapping_add(self.cpu.x);
-        let second_addr = [
+        Nes::get_absolute_addr(&[
             self.read_byte(first_addr as usize),
             self.read_byte(first_addr.wrapping_add(1) as usize),
-        ];
-        self.write_abs(&second_addr, value);
+        ])
     }
     // Transform an address into an indirect indexed address
     // Reads the byte at the address and then uses that byte as a pointer with the Y register for the address of the actual value
-    fn indirect_indexed_addr(&mut self, addr: &[u8]) -> usize {
+    // Also returns the address without the carry from adding the Y register, which is read from first on hardware
+    fn indirect_indexed_addrs(&mut self, addr: &[u8]) -> (usize, usize) {
+        // The operand is a single byte, so the pointer is read on the third and fourth cycles rather than after the 3
+        // bytes Nes::step reads
+        self.ppu_catch_up.access_cycle = 2;
         let first_addr = addr[0];
-        (self.read_byte(first_addr as usize) as u16
-            + ((self.read_byte(first_addr.wrapping_add(1) as usize) as u16) << 8))
-            .wrapping_add(self.cpu.y as u16) as usize
+        let pointer = self.read_byte(first_addr as usize) as u16
+            + ((self.read_byte(first_addr.wrapping_add(1) as usize) as u16) << 8);
+        let addr = pointer.wrapping_add(self.cpu.y as u16);
+        (
+            addr as usize,
+            ((pointer & 0xFF00) | (addr & 0x00FF)) as usize,
+        )
+    }
+    // Get the address used by indirect indexed addressing when writing.
+    // Like absolute offset addressing, writes always do a dummy read first.
+    fn indirect_indexed_addr(&mut self, addr: &[u8]) -> usize {
+        let (addr, dummy_addr) = self.indirect_indexed_addrs(addr);
+        self.read_byte(dummy_addr);
+        addr
     }
     /// Read a single byte from memory using indirect indexed addressing.
     ///
@@ -1021,7 +1156,11 @@ impl Nes {
     /// nes.read_indirect_indexed(&[0x18]);
     /// ```
     pub fn read_indirect_indexed(&mut self, addr: &[u8]) -> u8 {
-        let addr = self.indirect_indexed_addr(addr);
+        let (addr, dummy_addr) = self.indirect_indexed_addrs(addr);
+        if addr != dummy_addr {
+            // Do a dummy read
+            self.read_byte(dummy_addr);
+        }
         self.read_byte(addr)
     }
     /// Write a single byte to memory using indirect indexed addressing.
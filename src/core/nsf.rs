@@ -0,0 +1,381 @@
+use log::*;
+
+use crate::core::{Apu, Cartridge, Nes, Settings, CPU_CLOCK_SPEED};
+
+/// The play speed used if the file doesn't specify one, in microseconds (~60.1 Hz)
+const DEFAULT_PLAY_SPEED: u16 = 16_639;
+/// The address INIT and PLAY return to.
+/// Nothing is ever mapped here in an NSF, so it can't be reached any other way.
+const RETURN_ADDR: u16 = 0x4100;
+/// The maximum number of cycles to wait for INIT to return
+const MAX_INIT_CYCLES: u32 = CPU_CLOCK_SPEED;
+
+/// A parsed NSF or NSFe music file.
+///
+/// Contains the music's 6502 code and data, as well as the information needed to play it.
+/// Use [Cartridge::from_nsf] to load it into an [Nes], or [NsfPlayer] to play it.
+/// See [the NESDEV wiki](https://www.nesdev.org/wiki/NSF) for the format.
+#[derive(Debug, Clone)]
+pub struct Nsf {
+    /// Name of the game or album
+    pub name: String,
+    /// Name of the artist
+    pub artist: String,
+    /// Copyright holder
+    pub copyright: String,
+    /// Number of songs (tracks) in the file
+    pub num_songs: u8,
+    /// The song to play first, starting at 0
+    pub starting_song: u8,
+    /// The address the data is loaded at
+    pub load_addr: u16,
+    /// The address of the routine that initialises a song
+    pub init_addr: u16,
+    /// The address of the routine that is called at the play rate
+    pub play_addr: u16,
+    /// The time between calls of the play routine on NTSC, in microseconds
+    pub play_speed: u16,
+    /// The initial values of the bankswitching registers, if the file uses bankswitching
+    pub bankswitch: Option<[u8; 8]>,
+    /// Flags of which expansion audio chips the music uses
+    pub expansion_chips: u8,
+    /// Names of each track, if the file has any (NSFe only)
+    pub track_names: Vec<String>,
+    /// Length of each track in milliseconds, if the file has them (NSFe only)
+    pub track_lengths: Vec<Option<u32>>,
+    /// The program data
+    pub data: Vec<u8>,
+}
+
+impl Nsf {
+    /// Parse an NSF (.nsf) or NSFe (.nsfe) file.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Nsf, String> {
+        if bytes.starts_with(b"NESM\x1A") {
+            Nsf::from_nsf(bytes)
+        } else if bytes.starts_with(b"NSFE") {
+            Nsf::from_nsfe(bytes)
+        } else {
+            Err("File is not an NSF or NSFe file".to_string())
+        }
+    }
+    fn from_nsf(bytes: &[u8]) -> Result<Nsf, String> {
+        if bytes.len() < 0x80 {
+            return Err(format!("NSF header is too short ({} bytes)", bytes.len()));
+        }
+        let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+        let string_at = |i: usize| read_string(&bytes[i..i + 32]);
+        debug!("NSF version {}", bytes[5]);
+        let bankswitch: [u8; 8] = bytes[0x70..0x78].try_into().unwrap();
+        Ok(Nsf {
+            name: string_at(0x0E),
+            artist: string_at(0x2E),
+            copyright: string_at(0x4E),
+            num_songs: bytes[0x06],
+            starting_song: bytes[0x07].saturating_sub(1),
+            load_addr: u16_at(0x08),
+            init_addr: u16_at(0x0A),
+            play_addr: u16_at(0x0C),
+            play_speed: u16_at(0x6E),
+            bankswitch: if bankswitch.iter().any(|b| *b != 0) {
+                Some(bankswitch)
+            } else {
+                None
+            },
+            expansion_chips: bytes[0x7B],
+            track_names: Vec::new(),
+            track_lengths: Vec::new(),
+            data: bytes[0x80..].to_vec(),
+        })
+    }
+    fn from_nsfe(bytes: &[u8]) -> Result<Nsf, String> {
+        let mut nsf = Nsf {
+            name: String::new(),
+            artist: String::new(),
+            copyright: String::new(),
+            num_songs: 1,
+            starting_song: 0,
+            load_addr: 0,
+            init_addr: 0,
+            play_addr: 0,
+            play_speed: DEFAULT_PLAY_SPEED,
+            bankswitch: None,
+            expansion_chips: 0,
+            track_names: Vec::new(),
+            track_lengths: Vec::new(),
+            data: Vec::new(),
+        };
+        let mut has_info = false;
+        let mut has_data = false;
+        let mut i = 4;
+        while i + 8 <= bytes.len() {
+            let len = u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap()) as usize;
+            let id = &bytes[i + 4..i + 8];
+            let chunk = bytes
+                .get(i + 8..i + 8 + len)
+                .ok_or_else(|| format!("NSFe chunk {:?} is truncated", read_string(id)))?;
+            i += 8 + len;
+            match id {
+                b"INFO" => {
+                    if chunk.len() < 8 {
+                        return Err("NSFe INFO chunk is too short".to_string());
+                    }
+                    let u16_at = |i: usize| u16::from_le_bytes([chunk[i], chunk[i + 1]]);
+                    nsf.load_addr = u16_at(0);
+                    nsf.init_addr = u16_at(2);
+                    nsf.play_addr = u16_at(4);
+                    nsf.expansion_chips = chunk[7];
+                    nsf.num_songs = chunk.get(8).copied().unwrap_or(1);
+                    nsf.starting_song = chunk.get(9).copied().unwrap_or(0);
+                    has_info = true;
+                }
+                b"DATA" => {
+                    nsf.data = chunk.to_vec();
+                    has_data = true;
+                }
+                b"BANK" => {
+                    let mut banks = [0; 8];
+                    chunk
+                        .iter()
+                        .take(8)
+                        .enumerate()
+                        .for_each(|(i, b)| banks[i] = *b);
+                    nsf.bankswitch = Some(banks);
+                }
+                b"RATE" => {
+                    if chunk.len() >= 2 {
+                        nsf.play_speed = u16::from_le_bytes([chunk[0], chunk[1]]);
+                    }
+                }
+                b"auth" => {
+                    let mut strings = chunk.split(|b| *b == 0).map(read_string);
+                    nsf.name = strings.next().unwrap_or_default();
+                    nsf.artist = strings.next().unwrap_or_default();
+                    nsf.copyright = strings.next().unwrap_or_default();
+                }
+                b"tlbl" => {
+                    nsf.track_names = chunk
+                        .split(|b| *b == 0)
+                        .take(nsf.num_songs as usize)
+                        .map(read_string)
+                        .collect();
+                }
+                b"time" => {
+                    nsf.track_lengths = chunk
+                        .chunks_exact(4)
+                        .map(|t| {
+                            let ms = i32::from_le_bytes(t.try_into().unwrap());
+                            if ms < 0 {
+                                None
+                            } else {
+                                Some(ms as u32)
+                            }
+                        })
+                        .collect();
+                }
+                b"NEND" => break,
+                _ => {
+                    // Chunks starting with an uppercase letter are required to play the file
+                    if id[0].is_ascii_uppercase() {
+                        return Err(format!("Unsupported NSFe chunk {:?}", read_string(id)));
+                    }
+                    debug!("Skipping NSFe chunk {:?}", read_string(id));
+                }
+            }
+        }
+        if !has_info || !has_data {
+            return Err("NSFe file is missing its INFO or DATA chunk".to_string());
+        }
+        Ok(nsf)
+    }
+    /// Get the name of a track, or "Track N" if the file doesn't name it
+    pub fn track_name(&self, song: u8) -> String {
+        match self.track_names.get(song as usize) {
+            Some(n) if !n.is_empty() => n.clone(),
+            _ => format!("Track {}", song as u32 + 1),
+        }
+    }
+    /// Get the length of a track in milliseconds, if the file specifies it
+    pub fn track_length(&self, song: u8) -> Option<u32> {
+        self.track_lengths.get(song as usize).copied().flatten()
+    }
+    /// The number of CPU cycles between calls of the play routine
+    pub fn cycles_per_play(&self) -> f64 {
+        let speed = if self.play_speed == 0 {
+            DEFAULT_PLAY_SPEED
+        } else {
+            self.play_speed
+        };
+        speed as f64 * CPU_CLOCK_SPEED as f64 / 1_000_000.0
+    }
+    /// Get the contents of PRG ROM and the initial banks for the NSF mapper.
+    pub fn prg_rom_and_banks(&self) -> (Vec<u8>, [u8; 8]) {
+        match self.bankswitch {
+            Some(banks) => {
+                // Data is padded so that the load address is at the right place in its 4KB bank
+                let padding = (self.load_addr & 0x0FFF) as usize;
+                let mut rom = vec![0; padding];
+                rom.extend_from_slice(&self.data);
+                rom.resize(rom.len().div_ceil(0x1000) * 0x1000, 0);
+                (rom, banks)
+            }
+            None => {
+                // Data is loaded as is into 0x8000..0x10000
+                let mut rom = vec![0; 0x8000];
+                let start = (self.load_addr as usize).saturating_sub(0x8000);
+                let len = self.data.len().min(0x8000 - start);
+                rom[start..start + len].copy_from_slice(&self.data[..len]);
+                (rom, [0, 1, 2, 3, 4, 5, 6, 7])
+            }
+        }
+    }
+}
+
+// Read a null terminated string
+fn read_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+}
+
+/// Plays an [Nsf] on an [Nes].
+///
+/// Acts as the NSF driver: calls the file's INIT routine when a song is started, and then calls its
+/// PLAY routine at the file's play rate.
+/// The audio output can be read from the NES's APU as usual.
+/// ```
+/// use yane::core::{Nsf, NsfPlayer, Settings};
+/// # let mut bytes = b"NESM\x1A\x01\x01\x01\x00\x80\x00\x80\x00\x80".to_vec();
+/// # bytes.resize(0x80, 0);
+/// # bytes.push(0x60);
+/// let nsf = Nsf::from_bytes(&bytes).unwrap();
+/// let settings = Settings::default();
+/// let mut player = NsfPlayer::new(nsf);
+/// player.start_song(0, &settings).unwrap();
+/// player.advance_frame(&settings).unwrap();
+/// let samples = player.nes.apu.sample_queue();
+/// ```
+pub struct NsfPlayer {
+    /// The NES the NSF is being played on
+    pub nes: Nes,
+    /// The NSF being played
+    pub nsf: Nsf,
+    song: u8,
+    // Whether the CPU is currently in INIT or PLAY
+    in_routine: bool,
+    // Number of cycles left before PLAY should be called again
+    cycles_until_play: f64,
+    // Number of cycles since the song was started
+    song_cycles: u64,
+}
+
+impl NsfPlayer {
+    /// Create a new player, with the NSF loaded into an NES.
+    ///
+    /// Call [NsfPlayer::start_song] to start playing.
+    pub fn new(nsf: Nsf) -> NsfPlayer {
+        if nsf.expansion_chips != 0 {
+            warn!(
+                "NSF uses expansion audio ({:#04X}), which is not supported",
+                nsf.expansion_chips
+            );
+        }
+        NsfPlayer {
+            nes: Nes::with_cartridge(Cartridge::from_nsf(&nsf)),
+            nsf,
+            song: 0,
+            in_routine: false,
+            cycles_until_play: 0.0,
+            song_cycles: 0,
+        }
+    }
+    /// The song currently being played, starting at 0
+    pub fn song(&self) -> u8 {
+        self.song
+    }
+    /// The time the current song has been playing for, in milliseconds
+    pub fn song_time(&self) -> u64 {
+        self.song_cycles * 1000 / CPU_CLOCK_SPEED as u64
+    }
+    /// Start playing a song, starting at 0.
+    ///
+    /// Resets the NES's memory and APU and runs the INIT routine.
+    pub fn start_song(&mut self, song: u8, settings: &Settings) -> Result<(), String> {
+        let song = song.min(self.nsf.num_songs.saturating_sub(1));
+        info!("Starting song {} ({})", song, self.nsf.track_name(song));
+        self.song = song;
+        self.song_cycles = 0;
+        self.cycles_until_play = 0.0;
+        // Clear memory
+        self.nes.mem.fill(0);
+        self.nes.cartridge.memory.prg_ram.fill(0);
+        // Reset the APU
+        self.nes.apu = Apu::new();
+        (0x4000..0x4014).for_each(|addr| self.nes.write_byte(addr, 0));
+        self.nes.write_byte(0x4015, 0x00);
+        self.nes.write_byte(0x4015, 0x0F);
+        self.nes.write_byte(0x4017, 0x40);
+        // Reset banks
+        let (_, banks) = self.nsf.prg_rom_and_banks();
+        banks
+            .iter()
+            .enumerate()
+            .for_each(|(i, b)| self.nes.write_byte(0x5FF8 + i, *b));
+        // Call INIT with the song in A and NTSC in X
+        self.nes.cpu.a = song;
+        self.nes.cpu.x = 0;
+        self.nes.cpu.s_p = 0xFD;
+        self.call(self.nsf.init_addr);
+        let mut cycles = 0;
+        while self.in_routine {
+            cycles += self.advance_instruction(settings)?;
+            if cycles > MAX_INIT_CYCLES {
+                warn!("INIT did not return after {} cycles", cycles);
+                self.in_routine = false;
+            }
+        }
+        Ok(())
+    }
+    /// Advance until the next call of the PLAY routine.
+    ///
+    /// Calls PLAY, and then waits for the rest of the play period.
+    /// Returns the number of CPU cycles elapsed.
+    pub fn advance_frame(&mut self, settings: &Settings) -> Result<u32, String> {
+        self.cycles_until_play += self.nsf.cycles_per_play();
+        // If PLAY took more than a whole period last time, let it finish before calling it again
+        if !self.in_routine {
+            self.call(self.nsf.play_addr);
+        }
+        let mut cycles = 0;
+        while self.in_routine && (cycles as f64) < self.cycles_until_play {
+            cycles += self.advance_instruction(settings)?;
+        }
+        // Idle until the next call of PLAY
+        let idle = (self.cycles_until_play - cycles as f64).max(0.0) as u32;
+        self.nes
+            .apu
+            .advance_cpu_cycles(idle, &mut self.nes.cartridge, settings);
+        self.nes.cartridge.advance_cpu_cycles(idle);
+        cycles += idle;
+        self.cycles_until_play -= cycles as f64;
+        self.song_cycles += cycles as u64;
+        Ok(cycles)
+    }
+    // Advance the NES by one instruction, checking if the current routine has returned
+    fn advance_instruction(&mut self, settings: &Settings) -> Result<u32, String> {
+        let cycles = self.nes.advance_instruction(settings)?;
+        if self.nes.cpu.p_c == RETURN_ADDR {
+            self.in_routine = false;
+        }
+        Ok(cycles)
+    }
+    // Start a routine, with it set to return to RETURN_ADDR
+    fn call(&mut self, addr: u16) {
+        // RTS adds 1 to the address on the stack
+        let [low, high] = (RETURN_ADDR - 1).to_le_bytes();
+        [high, low].iter().for_each(|v| {
+            self.nes.write_byte(0x100 + self.nes.cpu.s_p as usize, *v);
+            self.nes.cpu.s_p = self.nes.cpu.s_p.wrapping_sub(1);
+        });
+        self.nes.cpu.p_c = addr;
+        self.in_routine = true;
+    }
+}
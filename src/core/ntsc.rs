@@ -0,0 +1,222 @@
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+use super::Ppu;
+
+/// The number of composite signal samples the PPU outputs per pixel
+const SAMPLES_PER_PIXEL: usize = 8;
+/// The number of samples in one cycle of the colour subcarrier
+const SAMPLES_PER_CYCLE: usize = 12;
+/// The number of samples per pixel of the filtered output
+const SAMPLES_PER_OUTPUT_PIXEL: usize = 4;
+/// The width of the NTSC filter's output, in pixels.
+pub const NTSC_OUTPUT_WIDTH: usize = 256 * SAMPLES_PER_PIXEL / SAMPLES_PER_OUTPUT_PIXEL;
+/// Signal levels, relative to sync, of the low and high parts of the wave for each value (luminance).
+const SIGNAL_LOW: [f32; 4] = [0.228, 0.312, 0.552, 0.880];
+const SIGNAL_HIGH: [f32; 4] = [0.616, 0.840, 1.100, 1.100];
+const BLACK: f32 = 0.312;
+const WHITE: f32 = 1.100;
+/// How much the signal is attenuated when emphasis is active
+const EMPHASIS_ATTENUATION: f32 = 0.746;
+/// The phase shift (in samples) of the colour burst, used to decode the hue correctly
+const HUE_OFFSET: f32 = 3.9;
+/// Gain applied to the demodulated chroma, to get roughly the same saturation as the default palette
+const CHROMA_GAIN: f32 = 1.4;
+/// The number of samples the phase of the colour subcarrier moves by between scanlines (341 dots * 8 samples)
+const SCANLINE_PHASE_SHIFT: usize = 341 * SAMPLES_PER_PIXEL % SAMPLES_PER_CYCLE;
+/// The number of samples per master clock cycle, used to convert the phase given by [Ppu::frame_phase]
+const SAMPLES_PER_MASTER_CLOCK: usize = 2;
+
+/// Settings used by the [NtscFilter].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct NtscSettings {
+    /// Sharpening (or blurring if negative) applied to the decoded image, between -1 and 1
+    pub sharpness: f32,
+    /// How much luma bleeds into the chroma (colour artifacts around edges of detailed areas), between 0 and 1
+    pub artifacts: f32,
+    /// How much chroma bleeds into the luma (colour fringes and dot crawl), between 0 and 1
+    pub fringing: f32,
+}
+
+impl NtscSettings {
+    /// Settings for a composite video signal, with full artifacts and fringing
+    pub fn composite() -> NtscSettings {
+        NtscSettings {
+            sharpness: 0.0,
+            artifacts: 1.0,
+            fringing: 1.0,
+        }
+    }
+    /// Settings for an S-Video signal, where luma and chroma are separated
+    pub fn svideo() -> NtscSettings {
+        NtscSettings {
+            sharpness: 0.2,
+            artifacts: 0.0,
+            fringing: 0.0,
+        }
+    }
+}
+
+impl Default for NtscSettings {
+    fn default() -> Self {
+        NtscSettings::composite()
+    }
+}
+
+/// A filter that simulates the NES's NTSC composite video output.
+///
+/// Rather than looking each colour up in a palette, this generates the composite waveform the PPU
+/// would output for each pixel and decodes it the way a television would.
+/// Each pixel is 8 samples of the signal, and the filter outputs one RGB value every 4 samples, so the
+/// output is [NTSC_OUTPUT_WIDTH] pixels wide.
+/// See [the NESDEV wiki](https://www.nesdev.org/wiki/NTSC_video).
+/// ```
+/// use yane::core::{Nes, NtscFilter, NTSC_OUTPUT_WIDTH};
+/// let nes = Nes::new();
+/// let mut filter = NtscFilter::default();
+/// let mut buf = vec![[[0; 3]; NTSC_OUTPUT_WIDTH]; 240];
+/// filter.apply(&nes.ppu, buf.as_mut_slice().try_into().unwrap());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct NtscFilter {
+    /// The settings of the filter
+    pub settings: NtscSettings,
+    // Signal, luma only signal and decoded YIQ buffers, reused every scanline
+    signal: Vec<f32>,
+    luma: Vec<f32>,
+    yiq: Vec<(f32, f32, f32)>,
+}
+
+impl NtscFilter {
+    /// Create a new filter with the settings given
+    pub fn new(settings: NtscSettings) -> NtscFilter {
+        NtscFilter {
+            settings,
+            ..Default::default()
+        }
+    }
+    /// Filter the PPU's current output into the buffer given.
+    ///
+    /// The phase of the colour subcarrier is taken from the PPU's current frame (see [Ppu::frame_phase]),
+    /// which changes every frame and causes the dot crawl effect.
+    pub fn apply(&mut self, ppu: &Ppu, buf: &mut [[[u8; 3]; NTSC_OUTPUT_WIDTH]; 240]) {
+        let frame_phase = ppu.frame_phase() as usize * SAMPLES_PER_MASTER_CLOCK;
+        ppu.color_output()
+            .iter()
+            .zip(buf.iter_mut())
+            .enumerate()
+            .for_each(|(y, (color_row, out_row))| {
+                let phase = (frame_phase + y * SCANLINE_PHASE_SHIFT) % SAMPLES_PER_CYCLE;
+                self.encode_scanline(color_row, phase);
+                self.decode_scanline(out_row, phase);
+            });
+    }
+    /// Generate the composite signal for a scanline of 9 bit colours, as well as the signal's luma without any chroma
+    fn encode_scanline(&mut self, color_row: &[usize; 256], phase: usize) {
+        self.signal.clear();
+        self.luma.clear();
+        color_row.iter().enumerate().for_each(|(x, color)| {
+            let hv = color & 0x3F;
+            let emphasis = (color >> 6) & 0x07;
+            (0..SAMPLES_PER_PIXEL).for_each(|i| {
+                let sample_phase = phase + x * SAMPLES_PER_PIXEL + i;
+                let (signal, luma) = NtscFilter::sample(hv, emphasis, sample_phase);
+                self.signal.push(signal);
+                self.luma.push(luma);
+            });
+        });
+    }
+    /// Get the normalised signal level and the luma (the average level) of a colour at a given phase
+    fn sample(hv: usize, emphasis: usize, phase: usize) -> (f32, f32) {
+        let color = hv & 0x0F;
+        // Colours $xE and $xF are always black
+        let value = if color > 0x0D { 1 } else { (hv >> 4) & 0x03 };
+        let (low, high) = match color {
+            // Colour $x0 is a constant high level
+            0x00 => (SIGNAL_HIGH[value], SIGNAL_HIGH[value]),
+            // Colours $xD-$xF are a constant low level
+            0x0D..=0x0F => (SIGNAL_LOW[value], SIGNAL_LOW[value]),
+            _ => (SIGNAL_LOW[value], SIGNAL_HIGH[value]),
+        };
+        let in_color_phase = |c: usize| (c + phase) % SAMPLES_PER_CYCLE < SAMPLES_PER_CYCLE / 2;
+        let mut signal = if in_color_phase(color) { high } else { low };
+        let mut luma = (low + high) / 2.0;
+        // Emphasis attenuates the signal during the phases of the emphasized colours
+        // Colours $xE and $xF are unaffected
+        if color < 0x0E
+            && [0, 4, 8]
+                .iter()
+                .enumerate()
+                .any(|(bit, c)| emphasis & (1 << bit) != 0 && in_color_phase(*c))
+        {
+            signal *= EMPHASIS_ATTENUATION;
+            luma *= EMPHASIS_ATTENUATION;
+        }
+        (
+            (signal - BLACK) / (WHITE - BLACK),
+            (luma - BLACK) / (WHITE - BLACK),
+        )
+    }
+    /// Decode a scanline of the composite signal into RGB values
+    fn decode_scanline(&mut self, out_row: &mut [[u8; 3]; NTSC_OUTPUT_WIDTH], phase: usize) {
+        let len = self.signal.len() as isize;
+        // Samples outside of the scanline are black
+        let get = |s: &[f32], i: isize| {
+            if i < 0 || i >= len {
+                0.0
+            } else {
+                s[i as usize]
+            }
+        };
+        let mut yiq = std::mem::take(&mut self.yiq);
+        yiq.clear();
+        yiq.extend((0..NTSC_OUTPUT_WIDTH).map(|x| {
+            let center = (x * SAMPLES_PER_OUTPUT_PIXEL + SAMPLES_PER_OUTPUT_PIXEL / 2) as isize;
+            let half = SAMPLES_PER_CYCLE as isize / 2;
+            // Luma, averaged over a whole cycle of the subcarrier to remove the chroma
+            let mut y = 0.0;
+            // Chroma, demodulated over a whole cycle
+            let mut i = 0.0;
+            let mut q = 0.0;
+            // Chroma caused only by changes in luma (i.e. artifacts)
+            let mut artifact_i = 0.0;
+            let mut artifact_q = 0.0;
+            (center - half..center + half).for_each(|s| {
+                let signal = get(&self.signal, s);
+                let luma = get(&self.luma, s);
+                let angle = PI * ((s + phase as isize) as f32 + HUE_OFFSET) / 6.0;
+                y += signal;
+                i += signal * angle.cos();
+                q += signal * angle.sin();
+                artifact_i += luma * angle.cos();
+                artifact_q += luma * angle.sin();
+            });
+            let n = SAMPLES_PER_CYCLE as f32;
+            y /= n;
+            // Luma over a single pixel, which still contains some of the chroma
+            let narrow_half = SAMPLES_PER_PIXEL as isize / 2;
+            let narrow_y = (center - narrow_half..center + narrow_half)
+                .map(|s| get(&self.signal, s))
+                .sum::<f32>()
+                / SAMPLES_PER_PIXEL as f32;
+            let y = y + self.settings.fringing * (narrow_y - y);
+            let artifacts = 1.0 - self.settings.artifacts;
+            let i = CHROMA_GAIN * (i - artifacts * artifact_i) / n;
+            let q = CHROMA_GAIN * (q - artifacts * artifact_q) / n;
+            (y, i, q)
+        }));
+        out_row.iter_mut().enumerate().for_each(|(x, pixel)| {
+            let (y, i, q) = yiq[x];
+            // Sharpen the luma
+            let neighbours = (yiq[x.saturating_sub(1)].0 + yiq[(x + 1).min(yiq.len() - 1)].0) / 2.0;
+            let y = y + self.settings.sharpness * (y - neighbours);
+            let rgb = [
+                y + 0.946882 * i + 0.623557 * q,
+                y - 0.274788 * i - 0.635691 * q,
+                y - 1.108545 * i + 1.709007 * q,
+            ];
+            *pixel = rgb.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+        });
+        self.yiq = yiq;
+    }
+}
@@ -0,0 +1,100 @@
+use std::fmt::Debug;
+
+/// The number of colours the PPU can output (64 hue/value colours for each of the 8 emphasis combinations)
+pub const NUM_COLORS: usize = 512;
+/// How much a colour channel is attenuated when a different colour is emphasized.
+/// Only used when generating the emphasis colours for 64 colour palettes.
+const EMPHASIS_ATTENUATION: f32 = 0.816328;
+const PALETTE_DATA: &[u8; 3 * NUM_COLORS] = include_bytes!("../2C02G_wiki.pal");
+
+/// A palette, mapping the PPU's output to RGB values.
+///
+/// Contains an RGB value for each of the PPU's 512 possible colours.
+/// Colours are indexed the same way they are in a 512 colour
+/// [NES palette file \(.PAL\)](https://www.nesdev.org/wiki/PPU_palettes#Palettes),
+/// i.e. `64 * emphasis + hue_value`, where `emphasis` is the 3 emphasis bits of PPUMASK.
+/// ```
+/// use yane::core::Palette;
+/// // The palette yane uses by default
+/// let palette = Palette::default();
+/// // Load a palette from a .pal file
+/// let pal_file = [0; 64 * 3];
+/// let palette = Palette::from_pal_bytes(&pal_file).unwrap();
+/// ```
+#[derive(Clone, PartialEq)]
+pub struct Palette {
+    /// The RGB value of each colour
+    pub colors: [[u8; 3]; NUM_COLORS],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        DEFAULT_PALETTE.clone()
+    }
+}
+
+impl Debug for Palette {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Palette {:X?}", &self.colors[0..64])
+    }
+}
+
+/// The palette used by default, generated from the
+/// [NESDEV wiki's 2C02G palette](https://www.nesdev.org/wiki/PPU_palettes#2C02).
+pub const DEFAULT_PALETTE: Palette = {
+    let mut colors = [[0; 3]; NUM_COLORS];
+    let mut i = 0;
+    while i < NUM_COLORS {
+        colors[i] = [
+            PALETTE_DATA[3 * i],
+            PALETTE_DATA[3 * i + 1],
+            PALETTE_DATA[3 * i + 2],
+        ];
+        i += 1;
+    }
+    Palette { colors }
+};
+
+impl Palette {
+    /// Create a palette from the contents of a .pal file.
+    ///
+    /// Accepts either a 64 colour (192 byte) palette or a 512 colour (1536 byte) palette
+    /// that includes every emphasis combination.
+    /// When given a 64 colour palette, the emphasis colours are generated by attenuating the
+    /// colour channels that are not emphasized.
+    pub fn from_pal_bytes(bytes: &[u8]) -> Result<Palette, String> {
+        let base: Vec<[u8; 3]> = bytes.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect();
+        match bytes.len() {
+            // 64 entry palette
+            192 => Ok(Palette {
+                colors: core::array::from_fn(|i| {
+                    let emphasis = i / 64;
+                    let color = base[i % 64];
+                    // Emphasis bits are red, green and blue
+                    // Emphasizing a colour attenuates the other two
+                    core::array::from_fn(|channel| {
+                        let attenuated = (0..3)
+                            .any(|bit| bit != channel && emphasis & (1 << bit) != 0);
+                        if attenuated {
+                            (color[channel] as f32 * EMPHASIS_ATTENUATION).floor() as u8
+                        } else {
+                            color[channel]
+                        }
+                    })
+                }),
+            }),
+            // 512 entry palette
+            1536 => Ok(Palette {
+                colors: core::array::from_fn(|i| base[i]),
+            }),
+            len => Err(format!(
+                "Invalid palette size {} bytes, expected either 192 (64 colours) or 1536 (512 colours)",
+                len
+            )),
+        }
+    }
+    /// Get the RGB value of a colour given the hue/value byte and the 3 emphasis bits.
+    pub fn rgb(&self, hv: usize, emphasis: usize) -> [u8; 3] {
+        self.colors[64 * (emphasis & 0x07) + (hv & 0x3F)]
+    }
+}
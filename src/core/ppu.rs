@@ -2,7 +2,7 @@ use std::{cmp::min, collections::VecDeque};
 
 use crate::core::Settings;
 
-use super::{Cartridge, DEBUG_PALETTE, HV_TO_RGB};
+use super::{Cartridge, Palette, PpuFetch, DEBUG_PALETTE, DEFAULT_PALETTE};
 use log::*;
 use serde::{Deserialize, Serialize};
 use serde_big_array::BigArray;
@@ -16,6 +16,9 @@ const SCANLINES_PER_FRAME: u32 = 262;
 const PRERENDER_SCANLINE: u32 = SCANLINES_PER_FRAME - 1;
 /// Number of render scanlines (scanlines during rendering)
 const RENDER_SCANLINES: u32 = 240;
+// The number of master clock cycles per dot, and per cycle of the NTSC colour subcarrier
+const MASTER_CLOCKS_PER_DOT: u32 = 4;
+const MASTER_CLOCKS_PER_SUBCARRIER_CYCLE: u32 = 6;
 /// Visible dots per scanline
 const RENDER_DOTS: u32 = 256;
 const DOTS_PER_OPEN_BUS_DECAY: u32 = 1_789_000 / 3;
@@ -29,8 +32,8 @@ fn zeros() -> Box<[[usize; 256]; 240]> {
 ///
 /// Responsible for computing the picture output of the console.
 /// The PPU provides the video output as either the raw hue/value byte per pixel computed
-/// by the NES through [Ppu::hv_output], or an easier-to-use RGB value per pixel through
-/// [Ppu::rgb_output] or [Ppu::rgb_output_buf].
+/// by the NES through [Ppu::hv_output] (or [Ppu::color_output] to include emphasis), or an
+/// easier-to-use RGB value per pixel through [Ppu::rgb_output] or [Ppu::rgb_output_buf].
 pub struct Ppu {
     /// The Object Access Memory, or OAM
     #[serde(with = "BigArray")]
@@ -71,9 +74,12 @@ pub struct Ppu {
     // None means no sprite on that pixel
     #[serde(with = "BigArray")]
     scanline_sprites: [Option<(usize, usize)>; 256],
-    // Screen buffer, storing 1 byte HV values per pixel
+    // Screen buffer, storing the HV value per pixel
     #[serde(skip, default = "zeros")]
     output: Box<[[usize; 256]; 240]>,
+    // Screen buffer, storing 9 bit colour values (6 bit HV value and 3 emphasis bits) per pixel
+    #[serde(skip, default = "zeros")]
+    colors: Box<[[usize; 256]; 240]>,
     // Open bus output
     open_bus: u8,
     // Cycles since open bus was written
@@ -84,6 +90,14 @@ pub struct Ppu {
     // and the 8bit shift register for the attribute data.
     // First entry is the tile data (index of the pixel in the palette), second is the palette index
     tile_buffer: VecDeque<(usize, usize)>,
+    // Whether the current frame is odd, in which case it is a dot shorter on hardware if rendering is enabled
+    // Not stored in savestates, since the PPU is serialized by position and older savestates would no longer load.
+    // Loading a savestate restarts the dot crawl pattern from an even frame, which isn't noticeable.
+    #[serde(skip)]
+    odd_frame: bool,
+    // Phase of the colour subcarrier at the start of the current frame, in master clock cycles
+    #[serde(skip)]
+    frame_phase: u32,
 }
 
 impl Default for Ppu {
@@ -116,10 +130,13 @@ impl Ppu {
             x: 0,
             scanline_sprites: [None; 256],
             output: Box::new([[0; 256]; 240]),
+            colors: Box::new([[0; 256]; 240]),
             open_bus: 0,
             open_bus_dots: 0,
             status_dots: 0,
             tile_buffer: VecDeque::from([(0, 0); 16]),
+            odd_frame: false,
+            frame_phase: 0,
         }
     }
     /// Read a byte from the PPU register given an address in CPU space.
@@ -158,6 +175,9 @@ impl Ppu {
     pub fn write_byte(&mut self, addr: usize, value: u8, cartridge: &mut Cartridge) {
         self.open_bus = value;
         self.open_bus_dots = 0;
+        cartridge
+            .mapper
+            .write_ppu_register(0x2000 + addr % 8, value);
         match addr % 8 {
             // PPUCTRL
             0 => {
@@ -227,6 +247,31 @@ impl Ppu {
         self.oam_addr = self.oam_addr.wrapping_add(1);
     }
 
+    /// Get the indices of every sprite in OAM that is in range of the scanline given.
+    ///
+    /// This is every sprite the PPU finds when evaluating sprites on `scanline`, including those past
+    /// the 8th that are dropped when [Settings::scanline_sprite_limit] is on.
+    /// Note that sprites evaluated on a scanline are drawn on the next scanline.
+    pub fn sprites_on_scanline(&self, scanline: u32) -> Vec<usize> {
+        let sprite_height = if self.is_8x16_sprites() { 16 } else { 8 };
+        self.oam
+            .chunks(4)
+            .enumerate()
+            .filter(|(_i, obj)| {
+                (obj[0] as u32) <= scanline && obj[0] as u32 + sprite_height > scanline
+            })
+            .map(|(i, _obj)| i)
+            .collect()
+    }
+    /// Get the number of sprites in range of each visible scanline.
+    ///
+    /// Any scanline with more than 8 sprites will trigger the sprite overflow behaviour,
+    /// and will drop sprites if [Settings::scanline_sprite_limit] is on.
+    /// See [Ppu::sprites_on_scanline].
+    pub fn sprite_count_per_scanline(&self) -> [usize; 240] {
+        core::array::from_fn(|i| self.sprites_on_scanline(i as u32).len())
+    }
+
     // Refresh scanline_sprites by fetching the first 8 sprites on the scanline given
     // May set the overflow flag.
     // Note that this is done at the end of the scanline, so these sprites will show up on the next scanline (and thus will appear at Y + 1)
@@ -239,17 +284,10 @@ impl Ppu {
         // Refresh scanline sprites
         self.scanline_sprites = [None; 256];
         if scanline < RENDER_SCANLINES || scanline == PRERENDER_SCANLINE {
+            cartridge.mapper.set_ppu_fetch(PpuFetch::Sprites);
             let sprite_height = if self.is_8x16_sprites() { 16 } else { 8 };
             // Get the 8 objs on the scanline (actually on the next scanline, since sprites will be draw on the next one)
-            let objs: Vec<usize> = self
-                .oam
-                .chunks(4)
-                .enumerate()
-                .filter(|(_i, obj)| {
-                    (obj[0] as u32) <= scanline && obj[0] as u32 + sprite_height > scanline
-                })
-                .map(|(i, _obj)| i)
-                .collect();
+            let objs: Vec<usize> = self.sprites_on_scanline(scanline);
             // Check for sprite overflow
             if objs.len() > 8 {
                 // Check in an incorrectly implemented fashion
@@ -354,6 +392,7 @@ impl Ppu {
             self.status_dots = self.status_dots.saturating_add(1);
             self.dot = if self.dot.0 == DOTS_PER_SCANLINE - 1 {
                 if self.dot.1 == SCANLINES_PER_FRAME - 1 {
+                    self.start_frame();
                     (0, 0)
                 } else {
                     (0, self.dot.1 + 1)
@@ -382,11 +421,19 @@ impl Ppu {
                 }
                 // IF we are in the visible picture
                 if self.dot.1 < RENDER_SCANLINES || self.dot.1 == PRERENDER_SCANLINE {
-                    // Fetch sprites to render at dot 263
-                    if self.dot.0 == 264 {
+                    // Fetch sprites at dot 261, where the first sprite pattern fetch is on hardware
+                    if self.dot.0 == 261 {
                         // Refresh scanline sprites
                         self.refresh_scanline_sprites(self.dot.1, cartridge, settings);
                     }
+                    // On hardware the pattern address is on the bus a few dots before the tile is loaded here, and the MMC3 uses it to
+                    // clock its IRQ counter
+                    if (self.dot.0 < 256 && self.dot.0 % 8 == 5) || [325, 333].contains(&self.dot.0)
+                    {
+                        cartridge
+                            .mapper
+                            .set_addr_value(self.nametable_tile_addr() as u32);
+                    }
                     // Check if we should fetch a tile
                     if self.dot.0 < 256 && self.dot.0 % 8 == 7 {
                         self.read_tile_to_buffer(cartridge);
@@ -395,6 +442,13 @@ impl Ppu {
                         // Fetch tiles for next line
                         self.read_tile_to_buffer(cartridge);
                         self.coarse_x_inc();
+                    } else if [337, 339].contains(&self.dot.0) {
+                        // Unused nametable fetches, which some mappers use to detect the end of the scanline
+                        cartridge.mapper.set_ppu_fetch(PpuFetch::Dummy);
+                        cartridge.read_nametable(
+                            0x2000 + (self.v as usize & 0x0FFF),
+                            &self.nametable_ram,
+                        );
                     }
                 }
                 if self.dot.0 == 256 && !self.can_access_vram() {
@@ -417,13 +471,37 @@ impl Ppu {
         });
         to_return
     }
+    /// Get the phase of the NTSC colour subcarrier at the start of the current frame.
+    ///
+    /// Given in master clock cycles, between 0 and 5, where one cycle of the subcarrier is 6 master clock cycles
+    /// and each dot is 4.
+    /// The phase changes every frame, causing the dot crawl seen on composite video.
+    pub fn frame_phase(&self) -> u32 {
+        self.frame_phase
+    }
+    // Update the frame parity and the subcarrier phase at the end of a frame
+    fn start_frame(&mut self) {
+        let mut dots = DOTS_PER_SCANLINE * SCANLINES_PER_FRAME;
+        // On hardware, odd frames skip the last dot of the pre-render scanline when rendering is enabled.
+        // The dot isn't skipped here, but the phase follows hardware so that the dot crawl pattern is right.
+        if self.odd_frame
+            && (self.is_background_rendering_enabled() || self.is_sprite_rendering_enabled())
+        {
+            dots -= 1;
+        }
+        self.frame_phase =
+            (self.frame_phase + dots * MASTER_CLOCKS_PER_DOT) % MASTER_CLOCKS_PER_SUBCARRIER_CYCLE;
+        self.odd_frame = !self.odd_frame;
+    }
     /// Get the output of the PPU as RGB triplets, in a new array
     ///
     /// Get the output of the PPU (i.e. the pixels on the screen) as RGB values.
     /// This will allocate a new buffer every call - it is recomended to allocate a screen
     /// buffer once and call [Ppu::rgb_output_buf] instead.
     pub fn rgb_output(&self) -> [[[u8; 3]; 256]; 240] {
-        core::array::from_fn(|y| core::array::from_fn(|x| self.get_rgb(self.output[y][x])))
+        core::array::from_fn(|y| {
+            core::array::from_fn(|x| self.get_rgb(self.colors[y][x], &DEFAULT_PALETTE))
+        })
     }
     /// Copy the current output of the PPU as RGB values into the given buffer
     ///
@@ -431,45 +509,74 @@ impl Ppu {
     /// This will overwrite any data in `buf`.
     /// If you want to create a new array instead, use [Ppu::rgb_output].
     pub fn rgb_output_buf(&self, buf: &mut [[[u8; 3]; 256]; 240]) {
+        self.rgb_output_buf_with_palette(buf, &DEFAULT_PALETTE);
+    }
+    /// Copy the current output of the PPU as RGB values into the given buffer, using a custom palette
+    ///
+    /// Same as [Ppu::rgb_output_buf], but uses `palette` to convert the PPU's output to RGB values
+    /// instead of the default palette.
+    pub fn rgb_output_buf_with_palette(&self, buf: &mut [[[u8; 3]; 256]; 240], palette: &Palette) {
         buf.iter_mut().enumerate().for_each(|(y, row)| {
             row.iter_mut()
                 .enumerate()
-                .for_each(|(x, pixel)| *pixel = self.get_rgb(self.output[y][x]))
+                .for_each(|(x, pixel)| *pixel = self.get_rgb(self.colors[y][x], palette))
         });
     }
+    /// Encode the current output of the PPU as a PNG image, using the palette given.
+    ///
+    /// Returns the contents of the PNG file.
+    /// ```
+    /// use yane::core::{Nes, Palette};
+    /// let nes = Nes::new();
+    /// let png = nes.ppu.rgb_png(&Palette::default()).unwrap();
+    /// // std::fs::write("screenshot.png", png);
+    /// ```
+    pub fn rgb_png(&self, palette: &Palette) -> Result<Vec<u8>, String> {
+        let mut buf = [[[0; 3]; 256]; 240];
+        self.rgb_output_buf_with_palette(&mut buf, palette);
+        encode_png(buf.as_flattened().as_flattened(), png::ColorType::Rgb, None)
+    }
+    /// Encode the current output of the PPU as an indexed PNG image.
+    ///
+    /// Each pixel's index is its raw hue/value byte (see [Ppu::hv_output]), and the PNG's palette
+    /// contains the 64 colours of `palette` without emphasis.
+    /// Since the index is the hue/value byte, emphasis is not included in the image.
+    /// Returns the contents of the PNG file.
+    pub fn hv_png(&self, palette: &Palette) -> Result<Vec<u8>, String> {
+        let data: Vec<u8> = self
+            .hv_output()
+            .as_flattened()
+            .iter()
+            .map(|hv| *hv as u8)
+            .collect();
+        encode_png(
+            &data,
+            png::ColorType::Indexed,
+            Some(palette.colors[0..64].as_flattened()),
+        )
+    }
     /// Get the current output of the PPU as hue-value bytes
     ///
     /// The NES's video output is a single value for each pixel, representing a
     /// hue/value combination. This output can be paired with an
     /// [NES palette file \(.PAL\)](https://www.nesdev.org/wiki/PPU_palettes#Palettes) to generate
     /// an RGB value for each pixel. [Ppu::rgb_output] will do this automatically.
+    /// Does not include the emphasis bits, use [Ppu::color_output] to get them as well.
     pub fn hv_output(&self) -> &[[usize; 256]; 240] {
         &self.output
     }
-    /// Transform an HV value into an RGB value
-    fn get_rgb(&self, hv_byte: usize) -> [u8; 3] {
-        // Check for greyscale
-        let i = if self.is_greyscale_mode_on() {
-            hv_byte & 0x30
-        } else {
-            hv_byte
-        };
-        // Get RGB
-        let v = HV_TO_RGB[i & 0x3F];
-        // Check for red/green/blue emphasis
-        if !(self.is_red_tint_on() || self.is_green_tint_on() || self.is_blue_tint_on()) {
-            v
-        } else {
-            const M: f32 = 0.5;
-            let should_dim = [
-                self.is_green_tint_on() || self.is_blue_tint_on(),
-                self.is_red_tint_on() || self.is_blue_tint_on(),
-                self.is_red_tint_on() || self.is_green_tint_on(),
-            ];
-            core::array::from_fn(|i| {
-                (v[i] as f32 * if should_dim[i] { M } else { 1.0 }).floor() as u8
-            })
-        }
+    /// Get the current output of the PPU as 9 bit colour values
+    ///
+    /// Each value contains the hue/value of the pixel in the lower 6 bits, and the
+    /// 3 emphasis bits of PPUMASK at the time the pixel was drawn in the upper 3 bits.
+    /// Greyscale has already been applied.
+    /// This can be used to index directly into a 512 colour palette, such as [Palette::colors].
+    pub fn color_output(&self) -> &[[usize; 256]; 240] {
+        &self.colors
+    }
+    /// Transform a 9 bit colour value into an RGB value
+    fn get_rgb(&self, color: usize, palette: &Palette) -> [u8; 3] {
+        palette.colors[color & 0x1FF]
     }
     /// Compute the output at the current dot, and set it in [Ppu::output]
     fn set_output(&mut self, settings: &Settings) {
@@ -530,8 +637,17 @@ impl Ppu {
                 }
             }
             // Set output to background/sprite or palette 0
-            self.output[self.dot.1 as usize][self.dot.0 as usize] =
-                output.unwrap_or(self.palette_ram[0] as usize);
+            let hv = output.unwrap_or(self.palette_ram[0] as usize) & 0x3F;
+            // Check for greyscale
+            let hv = if self.is_greyscale_mode_on() {
+                hv & 0x30
+            } else {
+                hv
+            };
+            let (x, y) = (self.dot.0 as usize, self.dot.1 as usize);
+            self.output[y][x] = hv;
+            // Store emphasis bits along with the colour
+            self.colors[y][x] = ((self.mask as usize & 0xE0) << 1) | hv;
         }
         // Shift tile and attribute registers
         if self.dot.0 < 337 {
@@ -541,14 +657,16 @@ impl Ppu {
     }
 
     fn read_tile_to_buffer(&mut self, cartridge: &mut Cartridge) {
+        cartridge.mapper.set_ppu_fetch(PpuFetch::Background);
         // Get nametable
-        let nt_addr = cartridge.transform_nametable_addr(0x2000 + (self.v as usize & 0x0FFF));
-        let nt_num = self.nametable_ram[nt_addr] as usize;
+        let nt_num = cartridge
+            .read_nametable(0x2000 + (self.v as usize & 0x0FFF), &self.nametable_ram)
+            as usize;
         // Get palette index
-        let palette_byte_addr = cartridge.transform_nametable_addr(
+        let palette_byte = cartridge.read_nametable(
             (0x23C0 + (self.v & 0xC00) + ((self.v >> 4) & 0x38) + ((self.v >> 2) & 0x07)) as usize,
+            &self.nametable_ram,
         );
-        let palette_byte = self.nametable_ram[palette_byte_addr];
         let palette_shift = ((self.v & 0x40) >> 4) + (self.v & 0x02);
         let palette_index = ((palette_byte >> palette_shift) as usize) & 0x03;
         // Get high/low byte of tile
@@ -614,10 +732,11 @@ impl Ppu {
     /// Increments `PPUADDR` by 1 or by 32 depending on `PPUSTATUS`
     fn write_vram(&mut self, value: u8, cartridge: &mut Cartridge) {
         let addr = self.v & 0x3FFF;
+        cartridge.mapper.set_ppu_fetch(PpuFetch::Cpu);
         if addr < 0x2000 {
             cartridge.write_ppu(addr as usize, value);
         } else if addr < 0x3000 {
-            self.nametable_ram[cartridge.transform_nametable_addr(addr as usize)] = value;
+            cartridge.write_nametable(addr as usize, value, &mut self.nametable_ram);
         } else if addr >= 0x3F00 {
             let palette_index = Ppu::get_palette_index(addr as u16);
             self.palette_ram[palette_index] = value;
@@ -633,6 +752,7 @@ impl Ppu {
     /// Read a single byte from VRAM using the v register
     fn read_vram(&mut self, cartridge: &mut Cartridge) -> u8 {
         let addr = self.v & 0x3FFF;
+        cartridge.mapper.set_ppu_fetch(PpuFetch::Cpu);
         if self.can_access_vram() {
             self.inc_addr(cartridge);
         } else {
@@ -648,14 +768,16 @@ impl Ppu {
         if addr < 0x3F00 {
             // Update buffer to nametable value and return old buffer
             let b = self.data;
-            self.data = self.nametable_ram[cartridge.transform_nametable_addr(addr as usize)];
+            self.data =
+                cartridge.read_nametable(0x2000 + (addr as usize & 0x0FFF), &self.nametable_ram);
             return b;
         }
         // Palette ram updates the buffer but also returns the current value
         let palette_index = Ppu::get_palette_index(addr as u16);
         let b = (self.open_bus & 0xC0) | (self.palette_ram[palette_index] & 0x3F);
         // Read the mirrored nametable byte into memory
-        self.data = self.nametable_ram[cartridge.transform_nametable_addr(addr as usize)];
+        self.data =
+            cartridge.read_nametable(0x2000 + (addr as usize & 0x0FFF), &self.nametable_ram);
         b
     }
 
@@ -786,3 +908,23 @@ impl Ppu {
         self.dot.1
     }
 }
+
+/// Encode a 256x240 image as a PNG file
+fn encode_png(
+    data: &[u8],
+    color_type: png::ColorType,
+    palette: Option<&[u8]>,
+) -> Result<Vec<u8>, String> {
+    let mut png_data = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png_data, 256, 240);
+    encoder.set_color(color_type);
+    encoder.set_depth(png::BitDepth::Eight);
+    if let Some(p) = palette {
+        encoder.set_palette(p);
+    }
+    encoder
+        .write_header()
+        .and_then(|mut w| w.write_image_data(data))
+        .map_err(|e| format!("Unable to encode PNG: {}", e))?;
+    Ok(png_data)
+}
@@ -1,6 +1,8 @@
+use crate::core::{AudioChannel, ExpansionAudio};
+
 /// Settings for how to run the emulator.
 ///
-/// Contain fields that change the visual output of the PPU.
+/// Contain fields that change the visual output of the PPU and the audio output of the APU.
 /// Some fields can also change the behaviour of some games (by interfering with the
 /// sprite 0 hit or sprite overflow flags).
 #[derive(Copy, Clone)]
@@ -13,6 +15,64 @@ pub struct Settings {
     pub scanline_sprite_limit: bool,
     /// Whether to always draw sprites on top of the background
     pub always_sprites_on_top: bool,
+    /// Volume, mute and solo settings of each of the APU's channels, indexed by [AudioChannel].
+    /// Applied before the channels are mixed together.
+    #[cfg_attr(feature = "wasm-bindgen", wasm_bindgen(skip))]
+    pub channel_mix: [ChannelMix; 5],
+    /// Volume of each expansion audio chip relative to its normal level, indexed by [ExpansionAudio].
+    /// Expansion audio is muted if any of the APU's channels are soloed.
+    #[cfg_attr(feature = "wasm-bindgen", wasm_bindgen(skip))]
+    pub expansion_volumes: [f32; 6],
+    /// Whether the APU should also store each channel's output separately.
+    /// The samples are available via [Apu::channel_sample_queues][crate::core::Apu::channel_sample_queues].
+    pub channel_sample_queues: bool,
+    /// Whether to apply the filters the NES applies to its audio output (see [FilterChain][crate::core::FilterChain]).
+    pub audio_filters: bool,
+    /// The sample rate the APU should output audio at.
+    /// If set, [Apu::sample_queue][crate::core::Apu::sample_queue] will contain band-limited samples at this rate
+    /// rather than one sample every CPU cycle.
+    pub output_sample_rate: Option<u32>,
+    /// The maximum number of samples the APU will hold before they are read.
+    pub audio_queue_capacity: usize,
+    /// What the APU should do when more than [Settings::audio_queue_capacity] samples are queued.
+    pub audio_overflow: AudioOverflow,
+}
+
+/// What to do with new audio samples when the APU's sample queue is full.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "wasm-bindgen", wasm_bindgen::prelude::wasm_bindgen)]
+pub enum AudioOverflow {
+    /// Discard the new samples, keeping the samples already queued
+    #[default]
+    DropNewest,
+    /// Discard the oldest queued samples to make room for the new ones.
+    /// Keeps the latency down when the caller is reading samples slower than they are generated.
+    DropOldest,
+    /// Never discard samples, the queue grows until it is read
+    Unbounded,
+}
+
+/// Mixer settings for a single audio channel.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "wasm-bindgen", wasm_bindgen::prelude::wasm_bindgen)]
+pub struct ChannelMix {
+    /// Gain applied to the channel, 1.0 being the channel's normal volume
+    pub volume: f32,
+    /// Whether the channel is muted
+    pub muted: bool,
+    /// Whether the channel is soloed.
+    /// If any channel is soloed, all channels that are not soloed are muted.
+    pub solo: bool,
+}
+
+impl Default for ChannelMix {
+    fn default() -> Self {
+        ChannelMix {
+            volume: 1.0,
+            muted: false,
+            solo: false,
+        }
+    }
 }
 
 impl Default for Settings {
@@ -21,6 +81,47 @@ impl Default for Settings {
             use_debug_palette: false,
             scanline_sprite_limit: true,
             always_sprites_on_top: false,
+            channel_mix: [ChannelMix::default(); 5],
+            expansion_volumes: [1.0; 6],
+            channel_sample_queues: false,
+            audio_filters: false,
+            output_sample_rate: None,
+            audio_queue_capacity: 2usize.pow(16),
+            audio_overflow: AudioOverflow::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Get the gain that should be applied to each audio channel, taking mute and solo into account.
+    pub fn channel_gains(&self) -> [f32; 5] {
+        let any_solo = self.channel_mix.iter().any(|m| m.solo);
+        self.channel_mix.map(|m| {
+            if m.muted || (any_solo && !m.solo) {
+                0.0
+            } else {
+                m.volume
+            }
+        })
+    }
+    /// Get the mixer settings of a single audio channel
+    pub fn mix(&self, channel: AudioChannel) -> &ChannelMix {
+        &self.channel_mix[channel as usize]
+    }
+    /// Get the mixer settings of a single audio channel mutably
+    pub fn mix_mut(&mut self, channel: AudioChannel) -> &mut ChannelMix {
+        &mut self.channel_mix[channel as usize]
+    }
+    /// Get the gain that should be applied to an expansion audio chip, taking solo into account.
+    pub fn expansion_gain(&self, chip: ExpansionAudio) -> f32 {
+        if self.channel_mix.iter().any(|m| m.solo) {
+            0.0
+        } else {
+            self.expansion_volumes[chip as usize]
         }
     }
+    /// Get the volume of an expansion audio chip mutably
+    pub fn expansion_volume_mut(&mut self, chip: ExpansionAudio) -> &mut f32 {
+        &mut self.expansion_volumes[chip as usize]
+    }
 }
@@ -10,15 +10,21 @@ use std::io::ErrorKind;
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
 use std::thread::sleep;
-use std::{fs::File, io::Write, path::PathBuf};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
 use std::{
     path::Path,
     time::{Duration, Instant},
 };
-use wavers::{write, Samples};
 use yane::{
-    app::{Audio, Config, DebugWindow, Input, KeyMap, Window},
-    core::{Cartridge, Nes, CPU_CLOCK_SPEED},
+    app::{
+        utils::load_palette, Audio, Config, DebugWindow, Input, KeyMap, Pacing, Recorder,
+        WavWriter, Window, RECORDING_SAMPLE_RATE,
+    },
+    core::{Cartridge, Nes, Nsf, NsfPlayer, Settings, CPU_CLOCK_SPEED},
 };
 
 const SETTINGS_FILENAME: &str = "settings.yaml";
@@ -76,6 +82,10 @@ struct CommonArgs {
     /// Directory to save logs to
     #[arg(long, default_value = get_file_in_config_dir("logs").into_os_string(), value_name = "DIRECTORY")]
     log_dir: PathBuf,
+    /// The .pal file to use for the emulator's colours, either 64 or 512 (emphasis-aware) colours.
+    /// Overrides the palette file set in the configuration file.
+    #[arg(long, value_name = "FILE")]
+    palette: Option<PathBuf>,
 }
 #[derive(Subcommand)]
 #[command(styles=get_cli_styles())]
@@ -95,6 +105,42 @@ enum Command {
         /// Will default to using the NES file name and location.
         #[arg(short, long)]
         savedata_file: Option<String>,
+        /// Save a screenshot to a PNG file after running for a number of frames, without opening a window
+        #[arg(long, value_name = "FILE")]
+        screenshot: Option<PathBuf>,
+        /// The number of frames to run before taking the screenshot
+        #[arg(long, default_value_t = 60, requires = "screenshot")]
+        frames: u32,
+        #[command(flatten)]
+        args: CommonArgs,
+    },
+    /// Play an NSF (.nsf) or NSFe (.nsfe) music file
+    Nsf {
+        /// The NSF or NSFe file to play
+        nsf_file: String,
+        /// The track to play first, starting at 1.
+        /// Defaults to the file's starting track.
+        #[arg(short = 'n', long)]
+        track: Option<u8>,
+        /// Render the track to a WAV file instead of playing it, without opening a window
+        #[arg(long, value_name = "FILE")]
+        wav: Option<PathBuf>,
+        /// The length of the WAV file to render, in seconds.
+        /// Defaults to the track's length if the file has one, and 150 seconds otherwise.
+        #[arg(long)]
+        seconds: Option<f32>,
+        #[command(flatten)]
+        args: CommonArgs,
+    },
+    /// Load and run a Famicom Disk System (.fds) disk image.
+    ///
+    /// Changes made to the disk are saved beside it as an IPS patch (.ips), which is applied when the disk is loaded.
+    Fds {
+        /// The .fds file to run, with or without the fwNES header
+        fds_file: String,
+        /// The FDS BIOS file
+        #[arg(short, long, default_value = get_file_in_config_dir("disksys.rom").into_os_string(), value_name = "FILE")]
+        bios: PathBuf,
         #[command(flatten)]
         args: CommonArgs,
     },
@@ -322,6 +368,8 @@ pub fn run() {
             Some(Command::Ines {
                 nes_file,
                 savedata_file,
+                screenshot,
+                frames,
                 args,
             }) => {
                 initialise_logger(args.tail, &args.log_dir);
@@ -359,8 +407,78 @@ pub fn run() {
                     Cartridge::from_ines(data.as_slice(), savedata)
                         .expect("Unable to initialise emulator: "),
                 );
+                if let Some(path) = screenshot {
+                    if let Err(e) = render_screenshot(nes, path, *frames, args) {
+                        println!("Error while taking screenshot: {}", e);
+                        std::process::exit(1);
+                    }
+                    std::process::exit(0);
+                }
                 (nes, savedata_path, game_name, args)
             }
+            Some(Command::Nsf {
+                nsf_file,
+                track,
+                wav,
+                seconds,
+                args,
+            }) => {
+                initialise_logger(args.tail, &args.log_dir);
+                let nsf = match std::fs::read(nsf_file).map_err(|e| e.to_string()) {
+                    Ok(data) => Nsf::from_bytes(&data),
+                    Err(e) => Err(e),
+                };
+                let nsf = match nsf {
+                    Ok(nsf) => nsf,
+                    Err(e) => {
+                        println!("Unable to read the NSF file '{}': {}", nsf_file, e);
+                        std::process::exit(1);
+                    }
+                };
+                let track = match track {
+                    Some(t) => t.saturating_sub(1),
+                    None => nsf.starting_song,
+                };
+                let result = match wav {
+                    Some(path) => render_nsf(nsf, track, path, *seconds),
+                    None => play_nsf(nsf, track, args),
+                };
+                if let Err(e) = result {
+                    println!("Error while playing NSF: {}", e);
+                    std::process::exit(1);
+                }
+                std::process::exit(0);
+            }
+            Some(Command::Fds {
+                fds_file,
+                bios,
+                args,
+            }) => {
+                initialise_logger(args.tail, &args.log_dir);
+                let read = |path: &Path| match std::fs::read(path) {
+                    Ok(data) => data,
+                    Err(e) => {
+                        println!("Unable to read the file {:?}: {}", path, e);
+                        std::process::exit(1);
+                    }
+                };
+                let disk = read(Path::new(fds_file));
+                let bios_data = read(bios);
+                // The changes previously made to the disk
+                let mut patch_path = PathBuf::from(fds_file);
+                patch_path.set_extension("ips");
+                let patch = std::fs::read(&patch_path).ok();
+                let game_name = get_filename(fds_file).ok();
+                let nes = match Cartridge::from_fds(&disk, &bios_data, patch.as_deref()) {
+                    Ok(c) => Nes::with_cartridge(c),
+                    Err(e) => {
+                        println!("Unable to load the disk: {}", e);
+                        std::process::exit(1);
+                    }
+                };
+                let patch_path = patch_path.into_os_string().into_string().ok();
+                (nes, patch_path, game_name, args)
+            }
             Some(Command::Savestate {
                 savestate_file,
                 args,
@@ -418,6 +536,7 @@ pub fn run() {
         // Load key map
         let key_map = read_config_file(&args.keymap_file, KeyMap::default());
         config.key_map = key_map;
+        load_palette_from_args(&mut config, args);
         // Initialise yane SDL componentes
         let mut window = Window::from_sdl_video(&mut sdl_video);
         match get_window_icon() {
@@ -445,10 +564,17 @@ pub fn run() {
         // Setup savestate and savedata repositories
         config.savestate_dir = try_create_dir(&config.savestate_dir);
         debug!("Savestates will be saved in {:?}", config.savestate_dir);
+        config.screenshot_dir = try_create_dir(&config.screenshot_dir);
+        debug!("Screenshots will be saved in {:?}", config.screenshot_dir);
+        config.recording_dir = try_create_dir(&config.recording_dir);
+        debug!("Recordings will be saved in {:?}", config.recording_dir);
+        let mut recorder: Option<Recorder> = None;
 
         let mut last_window_render = Instant::now();
         // Various constants for keeping emulator time in check with real time
         const WINDOW_REFRESH_RATE: Duration = Duration::from_millis(1000 / 60);
+        // Maximum number of frames to emulate before handling input and rendering again
+        const MAX_FRAMES_PER_UPDATE: u32 = 4;
         // Used for logging information every 100 frames
         let mut last_hundred_frames = Instant::now();
         let mut frame_cycles = 0;
@@ -456,7 +582,8 @@ pub fn run() {
         let mut actual_frame_count = 0;
         let mut frame_wait_time = Duration::ZERO;
         let mut delta = Instant::now();
-        loop {
+        let mut pacing = None;
+        'running: loop {
             // Update IMGUI/Window input
             let mut should_exit = false;
             // event_pump.poll_iter().any(|e| );
@@ -477,10 +604,27 @@ pub fn run() {
             if should_exit {
                 break;
             }
+            // Turn vsync on or off if the pacing has changed
+            if pacing != Some(config.pacing) {
+                debug!("Using {:?} pacing", config.pacing);
+                pacing = Some(config.pacing);
+                window.set_vsync(&sdl_video, config.pacing == Pacing::Vsync);
+                delta = Instant::now();
+            }
 
             // Render debug window
-            if Instant::now().duration_since(last_window_render) >= WINDOW_REFRESH_RATE {
-                last_window_render += WINDOW_REFRESH_RATE;
+            let should_render = match config.pacing {
+                // Rendering will wait for the next vsync
+                Pacing::Vsync => true,
+                Pacing::Audio => {
+                    Instant::now().duration_since(last_window_render) >= WINDOW_REFRESH_RATE
+                }
+            };
+            if should_render {
+                match config.pacing {
+                    Pacing::Vsync => last_window_render = Instant::now(),
+                    Pacing::Audio => last_window_render += WINDOW_REFRESH_RATE,
+                }
                 // Render debug window
                 if let Some(d) = debug_window.as_mut() {
                     d.render(&mut nes, &event_pump, &mut config)
@@ -508,61 +652,85 @@ pub fn run() {
             }
             // Update window
             input.update(&mut nes, &event_pump, &mut config);
-            // Update audio
-            audio.update(&mut nes, &config);
+            // Start or stop recording
+            if config.recording && recorder.is_none() {
+                match Recorder::start(&config) {
+                    Ok(r) => recorder = Some(r),
+                    Err(e) => {
+                        error!("Unable to start recording: {}", e);
+                        config.recording = false;
+                    }
+                }
+            } else if !config.recording {
+                if let Some(Err(e)) = recorder.take().map(|r| r.finish()) {
+                    error!("Unable to save recording: {}", e);
+                }
+            }
             // Update CPU
             if config.paused {
                 delta = Instant::now();
+                // Output any audio generated while paused (i.e. by stepping through the debug window)
+                output_samples(&mut nes, &mut audio, &mut recorder, &mut config);
+                if config.pacing == Pacing::Audio {
+                    // Avoid spinning while waiting for input
+                    sleep(Duration::from_millis(1));
+                }
             } else {
-                // Advance 1 frame
-                let cycles_to_wait = match nes.advance_frame(&config.emu_settings) {
-                    Ok(c) => c,
-                    Err(e) => {
-                        error!("Error encountered while advancing emulator: {:X?}", e);
-                        break;
+                // Advance frames until the audio queue is full enough, or until we have caught up with real time
+                let mut frames = 0;
+                while frames < MAX_FRAMES_PER_UPDATE
+                    && match config.pacing {
+                        Pacing::Audio => audio.needs_samples(),
+                        Pacing::Vsync => Instant::now() >= delta,
                     }
-                };
-                frame_cycles += cycles_to_wait;
-                // Debug log FPS info
-                emu_frame_count += 1;
-
-                // Calculate how much time has passed in the emulation
-                let emu_elapsed = Duration::from_nanos(
-                    cycles_to_wait as u64 * 1_000_000_000 / CPU_CLOCK_SPEED as u64,
-                )
-                .div_f64(config.speed as f64);
-                // Calculate how much time has actually passed
-                let actual_elapsed = Instant::now().duration_since(delta);
-                // Wait for the difference
-                let wait_duration = emu_elapsed.saturating_sub(actual_elapsed);
-                // If we are going too fast, slow down
-                // Check if we want to slow down first, since sleep is costly even if wait_duration is 0
-                if wait_duration != Duration::ZERO {
+                {
+                    let cycles = match nes.advance_frame(&config.emu_settings) {
+                        Ok(c) => c,
+                        Err(e) => {
+                            error!("Error encountered while advancing emulator: {:X?}", e);
+                            break 'running;
+                        }
+                    };
+                    frames += 1;
+                    frame_cycles += cycles;
+                    if let Some(r) = recorder.as_mut() {
+                        if let Err(e) = r.add_frame(&nes, &config.palette) {
+                            error!("Error while recording video, stopping recording: {}", e);
+                            config.recording = false;
+                        }
+                    }
+                    output_samples(&mut nes, &mut audio, &mut recorder, &mut config);
+                    // Debug log FPS info
+                    emu_frame_count += 1;
+                    // Advance real time by amount of emulator time that has passed
+                    delta += Duration::from_nanos(
+                        cycles as u64 * 1_000_000_000 / CPU_CLOCK_SPEED as u64,
+                    )
+                    .div_f64(config.speed as f64);
+                }
+                if frames == 0 && config.pacing == Pacing::Audio {
+                    // The audio queue is full enough, wait for it to drain
+                    let wait_duration = Duration::from_millis(1);
                     frame_wait_time += wait_duration;
                     sleep(wait_duration);
-                } else if Instant::now().duration_since(delta) > Duration::from_millis(500) {
+                }
+                if Instant::now().saturating_duration_since(delta) > Duration::from_millis(500) {
                     // If we have fallen way behind (by messing with the speed in settings)
                     delta = Instant::now();
                 }
-                // Advance real time by amount of emulator time that will have passed
-                // Since sleep may overshoot, this will let us catch up next frame/scanline
-                delta += emu_elapsed;
             }
         }
-        // Save audio recording
-        let data = audio.all_samples.into_boxed_slice();
-        if !data.is_empty() {
-            let samples = Samples::new(data);
-            write(
-                Path::new(format!("./{}.wav", config.record_audio_filename).as_str()),
-                &samples,
-                1_789_000,
-                1,
-            )
-            .unwrap();
+        // Finish recording
+        if let Some(Err(e)) = recorder.map(|r| r.finish()) {
+            error!("Unable to save recording: {}", e);
         }
         // Save game if we want to
-        match nes.savedata() {
+        // Disk changes are saved as a patch
+        let savedata = match nes.cartridge.mapper.disk_patch() {
+            Some(patch) => Some(patch),
+            None => nes.savedata_with_mapper_ram(),
+        };
+        match savedata {
             Some(data) => match savedata_path {
                 Some(p) => {
                     info!("Writing savedata to to {:#?}", &p);
@@ -574,3 +742,157 @@ pub fn run() {
         }
     }
 }
+
+/// Move the audio samples generated by the NES to the audio queue, and to the recording if there is one
+fn output_samples(
+    nes: &mut Nes,
+    audio: &mut Audio,
+    recorder: &mut Option<Recorder>,
+    config: &mut Config,
+) {
+    let samples = nes.apu.sample_queue();
+    audio.queue_samples(&samples, config);
+    if let Some(r) = recorder.as_mut() {
+        let sample_rate = config
+            .emu_settings
+            .output_sample_rate
+            .unwrap_or(CPU_CLOCK_SPEED);
+        if let Err(e) = r.add_samples(&samples, sample_rate) {
+            error!("Error while recording audio, stopping recording: {}", e);
+            config.recording = false;
+        }
+    }
+}
+
+// Print the information about the current track, and show it in the window's title
+fn show_track_info(player: &NsfPlayer, window: Option<&mut Window>) {
+    let nsf = &player.nsf;
+    let info = format!(
+        "{}/{}: {} - {} - {}",
+        player.song() as u32 + 1,
+        nsf.num_songs,
+        nsf.track_name(player.song()),
+        nsf.name,
+        nsf.artist
+    );
+    println!("{}", info);
+    if let Some(w) = window {
+        if let Err(e) = w.sdl_window().set_title(&format!("Y.A.N.E. - {}", info)) {
+            error!("Unable to set window title: {}", e);
+        }
+    }
+}
+
+// Load the palette given in the arguments, or the one in the config file if there isn't one
+fn load_palette_from_args(config: &mut Config, args: &CommonArgs) {
+    if args.palette.is_some() {
+        config.palette_file = args.palette.clone();
+    }
+    if let Some(palette) = config.palette_file.as_deref().and_then(load_palette) {
+        config.palette = palette;
+    }
+}
+
+/// Run the NES for a number of frames and save a screenshot of the last one to a PNG file, without opening a window
+fn render_screenshot(
+    mut nes: Nes,
+    path: &Path,
+    frames: u32,
+    args: &CommonArgs,
+) -> Result<(), String> {
+    let mut config = read_config_file(&args.config_file, Config::default());
+    load_palette_from_args(&mut config, args);
+    for _ in 0..frames {
+        nes.advance_frame(&config.emu_settings)?;
+    }
+    let data = nes.ppu.rgb_png(&config.palette)?;
+    std::fs::write(path, data).map_err(|e| format!("Unable to write {:?}: {}", path, e))?;
+    println!("Saved a screenshot after {} frames to {:?}", frames, path);
+    Ok(())
+}
+
+/// Render a track of an NSF to a WAV file, without opening a window
+fn render_nsf(nsf: Nsf, track: u8, path: &Path, seconds: Option<f32>) -> Result<(), String> {
+    let seconds = seconds
+        .or(nsf.track_length(track).map(|ms| ms as f32 / 1000.0))
+        .unwrap_or(150.0);
+    let settings = Settings {
+        output_sample_rate: Some(RECORDING_SAMPLE_RATE),
+        audio_filters: true,
+        ..Settings::default()
+    };
+    let mut player = NsfPlayer::new(nsf);
+    player.start_song(track, &settings)?;
+    show_track_info(&player, None);
+    let file = File::create(path).map_err(|e| format!("Unable to create {:?}: {}", path, e))?;
+    let mut wav = WavWriter::new(BufWriter::new(file), RECORDING_SAMPLE_RATE)?;
+    let total_cycles = (seconds as f64 * CPU_CLOCK_SPEED as f64) as u64;
+    let mut cycles = 0;
+    while cycles < total_cycles {
+        cycles += player.advance_frame(&settings)? as u64;
+        wav.write_samples(&player.nes.apu.sample_queue())?;
+    }
+    wav.finish()?;
+    println!("Rendered {} seconds to {:?}", seconds, path);
+    Ok(())
+}
+
+/// Play an NSF in a window, with keys to change the track
+fn play_nsf(nsf: Nsf, track: u8, args: &CommonArgs) -> Result<(), String> {
+    let sdl = sdl2::init()?;
+    let mut sdl_video = sdl.video()?;
+    let sdl_audio = sdl.audio()?;
+    let mut event_pump = sdl.event_pump()?;
+    let mut config = read_config_file(&args.config_file, Config::default());
+    config.key_map = read_config_file(&args.keymap_file, KeyMap::default());
+    if args.muted {
+        config.volume = 0.0;
+    }
+    config.paused = args.paused;
+    let mut window = Window::from_sdl_video(&mut sdl_video);
+    let mut input = Input::new();
+    let mut audio = Audio::from_sdl_audio(&sdl_audio);
+    let mut player = NsfPlayer::new(nsf);
+    player.start_song(track, &config.emu_settings)?;
+    show_track_info(&player, Some(&mut window));
+    const WINDOW_REFRESH_RATE: Duration = Duration::from_millis(1000 / 60);
+    let mut last_window_render = Instant::now();
+    loop {
+        if event_pump.poll_iter().any(|e| match e {
+            Event::Window { win_event, .. } => win_event == WindowEvent::Close,
+            Event::Quit { .. } => true,
+            _ => false,
+        }) {
+            break;
+        }
+        // Change track
+        let mut change = input.update_nsf(&event_pump, &mut config);
+        // Move to the next track once the current one has finished
+        if let Some(length) = player.nsf.track_length(player.song()) {
+            if player.song_time() >= length as u64 {
+                change = 1;
+            }
+        }
+        if change != 0 {
+            let num_songs = player.nsf.num_songs.max(1) as i32;
+            let song = (player.song() as i32 + change).rem_euclid(num_songs) as u8;
+            player.start_song(song, &config.emu_settings)?;
+            show_track_info(&player, Some(&mut window));
+        }
+        // Play
+        if config.paused {
+            sleep(Duration::from_millis(1));
+        } else if audio.needs_samples() {
+            player.advance_frame(&config.emu_settings)?;
+            let samples = player.nes.apu.sample_queue();
+            audio.queue_samples(&samples, &config);
+        } else {
+            sleep(Duration::from_millis(1));
+        }
+        if Instant::now().duration_since(last_window_render) >= WINDOW_REFRESH_RATE {
+            last_window_render = Instant::now();
+            window.render(&player.nes, &config);
+        }
+    }
+    Ok(())
+}
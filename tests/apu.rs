@@ -0,0 +1,164 @@
+use yane::core::{AudioChannel, AudioOverflow, ExpansionAudio, Nes, Settings, CPU_CLOCK_SPEED};
+
+// Create an NES with both pulse channels playing a constant tone
+fn nes_playing_pulses() -> Nes {
+    let mut nes = Nes::new();
+    // Enable both pulse channels
+    nes.write_byte(0x4015, 0x03);
+    [0x4000, 0x4004].iter().for_each(|addr| {
+        // 50% duty, halt length counter, constant volume 15
+        nes.write_byte(*addr, 0xBF);
+        // Disable sweep
+        nes.write_byte(addr + 1, 0x08);
+        nes.write_byte(addr + 2, 0xFD);
+        nes.write_byte(addr + 3, 0x00);
+    });
+    nes
+}
+
+#[test]
+fn test_muted_channels_are_silent() {
+    let mut nes = nes_playing_pulses();
+    let mut settings = Settings::default();
+    settings.channel_mix.iter_mut().for_each(|m| m.muted = true);
+    (0..60).for_each(|_| {
+        nes.advance_frame(&settings).unwrap();
+        assert!(nes.apu.sample_queue().iter().all(|s| *s == 0.0));
+    });
+}
+#[test]
+fn test_solo_matches_channel_queue() {
+    let mut nes = nes_playing_pulses();
+    let mut settings = Settings {
+        channel_sample_queues: true,
+        ..Default::default()
+    };
+    settings.mix_mut(AudioChannel::Pulse1).solo = true;
+    (0..60).for_each(|_| {
+        nes.advance_frame(&settings).unwrap();
+        let samples = nes.apu.sample_queue();
+        let channels = nes.apu.channel_sample_queues();
+        assert_eq!(samples, channels[AudioChannel::Pulse1 as usize]);
+        // Other channels are muted by the solo
+        assert!(channels[AudioChannel::Pulse2 as usize]
+            .iter()
+            .all(|s| *s == 0.0));
+    });
+}
+#[test]
+fn test_mixer_gain_clips() {
+    let mut nes = nes_playing_pulses();
+    // Load the DMC's output level with its maximum value
+    nes.write_byte(0x4011, 0x7F);
+    let mut settings = Settings::default();
+    nes.advance_frame(&settings).unwrap();
+    let max_at_unity = nes
+        .apu
+        .sample_queue()
+        .iter()
+        .fold(0.0_f32, |a, b| a.max(*b));
+    // Turning every channel up past its normal range clips rather than muting
+    settings.channel_mix.iter_mut().for_each(|m| m.volume = 2.0);
+    nes.advance_frame(&settings).unwrap();
+    let samples = nes.apu.sample_queue();
+    assert!(samples.iter().all(|s| (0.0..=1.0).contains(s)));
+    assert!(samples.contains(&1.0));
+    assert!(max_at_unity < 1.0);
+}
+#[test]
+fn test_output_sample_rate() {
+    let mut nes = nes_playing_pulses();
+    let settings = Settings {
+        output_sample_rate: Some(44_100),
+        ..Default::default()
+    };
+    let mut cycles = 0;
+    let mut num_samples = 0;
+    (0..60).for_each(|_| {
+        cycles += nes.advance_frame(&settings).unwrap();
+        num_samples += nes.apu.sample_queue().len();
+    });
+    let expected = cycles as f64 * 44_100.0 / CPU_CLOCK_SPEED as f64;
+    assert!((num_samples as f64 - expected).abs() <= 1.0);
+}
+#[test]
+fn test_audio_filters_remove_dc() {
+    let mut nes = nes_playing_pulses();
+    let settings = Settings {
+        output_sample_rate: Some(44_100),
+        audio_filters: true,
+        ..Default::default()
+    };
+    let mut samples = Vec::new();
+    (0..120).for_each(|_| {
+        nes.advance_frame(&settings).unwrap();
+        samples.extend(nes.apu.sample_queue());
+    });
+    // Skip the first second while the filters settle
+    let tail = &samples[44_100..];
+    let mean = tail.iter().sum::<f32>() / tail.len() as f32;
+    assert!(mean.abs() < 0.01);
+}
+#[test]
+fn test_read_samples_into_buffer() {
+    let mut nes = nes_playing_pulses();
+    let settings = Settings {
+        output_sample_rate: Some(48_000),
+        ..Default::default()
+    };
+    nes.advance_frame(&settings).unwrap();
+    let available = nes.apu.samples_available();
+    let mut buffer = [0.0; 100];
+    assert_eq!(nes.apu.read_samples(&mut buffer), 100);
+    assert!(buffer.iter().any(|s| *s != 0.0));
+    assert_eq!(nes.apu.samples_available(), available - 100);
+    let mut buffer = vec![0.0; available];
+    assert_eq!(nes.apu.read_samples(&mut buffer), available - 100);
+    assert_eq!(nes.apu.samples_available(), 0);
+}
+#[test]
+fn test_audio_overflow_policies() {
+    let run = |overflow: AudioOverflow| {
+        let mut nes = nes_playing_pulses();
+        let settings = Settings {
+            output_sample_rate: Some(44_100),
+            audio_queue_capacity: 1000,
+            audio_overflow: overflow,
+            ..Default::default()
+        };
+        // Generate far more samples than the capacity without reading any
+        let generated: usize = (0..10)
+            .map(|_| {
+                let before = nes.apu.samples_available() + nes.apu.dropped_samples() as usize;
+                nes.advance_frame(&settings).unwrap();
+                nes.apu.samples_available() + nes.apu.dropped_samples() as usize - before
+            })
+            .sum();
+        (nes, generated)
+    };
+    let (mut nes, generated) = run(AudioOverflow::Unbounded);
+    assert_eq!(nes.apu.dropped_samples(), 0);
+    let all = nes.apu.sample_queue();
+    assert_eq!(all.len(), generated);
+
+    let (mut nes, _) = run(AudioOverflow::DropNewest);
+    assert_eq!(nes.apu.samples_available(), 1000);
+    assert_eq!(nes.apu.dropped_samples() as usize, generated - 1000);
+    assert_eq!(nes.apu.sample_queue(), all[..1000]);
+
+    let (mut nes, _) = run(AudioOverflow::DropOldest);
+    assert_eq!(nes.apu.samples_available(), 1000);
+    assert_eq!(nes.apu.sample_queue(), all[all.len() - 1000..]);
+}
+#[test]
+fn test_expansion_gain() {
+    let mut settings = Settings::default();
+    assert_eq!(settings.expansion_gain(ExpansionAudio::Vrc6), 1.0);
+    // Each chip has its own volume
+    *settings.expansion_volume_mut(ExpansionAudio::Vrc6) = 0.5;
+    assert_eq!(settings.expansion_gain(ExpansionAudio::Vrc6), 0.5);
+    assert_eq!(settings.expansion_gain(ExpansionAudio::Fds), 1.0);
+    // Expansion audio is muted when an APU channel is soloed
+    settings.mix_mut(AudioChannel::Pulse1).solo = true;
+    assert_eq!(settings.expansion_gain(ExpansionAudio::Fds), 0.0);
+}
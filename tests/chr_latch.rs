@@ -0,0 +1,79 @@
+use yane::core::{Cartridge, Nes};
+
+// A cartridge using the mapper given, with 128KB of PRG ROM, where the first byte of each 8KB bank is its number,
+// and 128KB of CHR ROM, where the first byte of each 4KB bank is its number.
+fn chr_latch_nes(mapper: u8) -> Nes {
+    let mut rom = b"NES\x1A".to_vec();
+    rom.extend_from_slice(&[8, 16, (mapper << 4) | 0x02, mapper & 0xF0]);
+    rom.resize(16, 0);
+    (0..16).for_each(|i| {
+        let mut bank = vec![0; 0x2000];
+        bank[0] = i;
+        rom.extend_from_slice(&bank);
+    });
+    (0..32).for_each(|i| {
+        let mut bank = vec![0; 0x1000];
+        bank[0] = i;
+        rom.extend_from_slice(&bank);
+    });
+    Nes::with_cartridge(Cartridge::from_ines(&rom, None).unwrap())
+}
+
+// Set the CHR banks to 1-4
+fn set_chr_banks(nes: &mut Nes) {
+    (0..4).for_each(|i| nes.write_byte(0xB000 + 0x1000 * i, i as u8 + 1));
+}
+
+#[test]
+fn test_mmc2() {
+    let mut nes = chr_latch_nes(9);
+    nes.write_byte(0xA000, 0x03);
+    assert_eq!(nes.read_byte(0x8000), 3);
+    assert_eq!(nes.read_byte(0xA000), 13);
+    assert_eq!(nes.read_byte(0xC000), 14);
+    assert_eq!(nes.read_byte(0xE000), 15);
+    set_chr_banks(&mut nes);
+    assert_eq!(nes.cartridge.read_ppu(0x0000), 1);
+    assert_eq!(nes.cartridge.read_ppu(0x1000), 3);
+    // The latch changes after the fetch
+    assert_eq!(nes.cartridge.read_ppu(0x0FE8), 0);
+    assert_eq!(nes.cartridge.read_ppu(0x0000), 2);
+    // Only the first row of the tile sets the first latch
+    nes.cartridge.read_ppu(0x0FD9);
+    assert_eq!(nes.cartridge.read_ppu(0x0000), 2);
+    nes.cartridge.read_ppu(0x0FD8);
+    assert_eq!(nes.cartridge.read_ppu(0x0000), 1);
+    // Any row sets the second
+    nes.cartridge.read_ppu(0x1FEF);
+    assert_eq!(nes.cartridge.read_ppu(0x1000), 4);
+    nes.cartridge.read_ppu(0x1FDA);
+    assert_eq!(nes.cartridge.read_ppu(0x1000), 3);
+}
+#[test]
+fn test_mmc4() {
+    let mut nes = chr_latch_nes(10);
+    assert_eq!(nes.cartridge.mapper.mapper_num(), 10);
+    // 16KB PRG banks
+    nes.write_byte(0xA000, 0x03);
+    assert_eq!(nes.read_byte(0x8000), 6);
+    assert_eq!(nes.read_byte(0xA000), 7);
+    assert_eq!(nes.read_byte(0xC000), 14);
+    assert_eq!(nes.read_byte(0xE000), 15);
+    set_chr_banks(&mut nes);
+    assert_eq!(nes.cartridge.read_ppu(0x0000), 1);
+    // Any row sets both latches
+    nes.cartridge.read_ppu(0x0FEA);
+    assert_eq!(nes.cartridge.read_ppu(0x0000), 2);
+    nes.cartridge.read_ppu(0x1FEF);
+    assert_eq!(nes.cartridge.read_ppu(0x1000), 4);
+    let savestate = nes.to_savestate().unwrap();
+    let mut nes = Nes::from_savestate(&savestate).unwrap();
+    assert_eq!(nes.cartridge.read_ppu(0x0000), 2);
+    assert_eq!(nes.read_byte(0x8000), 6);
+    // Battery backed PRG RAM
+    nes.write_byte(0x6123, 0x45);
+    assert_eq!(nes.read_byte(0x6123), 0x45);
+    let savedata = nes.savedata().unwrap();
+    assert_eq!(savedata.len(), 0x2000);
+    assert_eq!(savedata[0x123], 0x45);
+}
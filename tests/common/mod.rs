@@ -69,3 +69,48 @@ macro_rules! assert_background_snapshot {
         insta::assert_debug_snapshot!($name, $nes.ppu.nametable_ram.as_slice());
     };
 }
+
+// Read a byte from PPU memory through PPUDATA
+#[macro_export]
+macro_rules! read_ppu {
+    ($nes: expr, $addr: expr) => {{
+        let addr: u16 = $addr;
+        // Reset the address latch
+        $nes.read_byte(0x2002);
+        $nes.write_byte(0x2006, (addr >> 8) as u8);
+        $nes.write_byte(0x2006, addr as u8);
+        // Reads are buffered
+        $nes.read_byte(0x2007);
+        $nes.read_byte(0x2007)
+    }};
+}
+
+// Write a byte to PPU memory through PPUDATA
+#[macro_export]
+macro_rules! write_ppu {
+    ($nes: expr, $addr: expr, $value: expr) => {{
+        let addr: u16 = $addr;
+        $nes.read_byte(0x2002);
+        $nes.write_byte(0x2006, (addr >> 8) as u8);
+        $nes.write_byte(0x2006, addr as u8);
+        $nes.write_byte(0x2007, $value);
+    }};
+}
+
+// Clock the cartridge's expansion audio for a number of samples, returning its output after each one.
+// Each sample is a CPU cycle, unless the number of CPU cycles per sample is given.
+#[macro_export]
+macro_rules! audio_samples {
+    ($nes: expr, $samples: expr, $cycles_per_sample: expr) => {{
+        let samples: Vec<f32> = (0..$samples)
+            .map(|_| {
+                (0..$cycles_per_sample).for_each(|_| $nes.cartridge.mapper.clock_expansion_audio());
+                $nes.cartridge.mapper.expansion_audio_output()
+            })
+            .collect();
+        samples
+    }};
+    ($nes: expr, $samples: expr) => {
+        audio_samples!($nes, $samples, 1)
+    };
+}
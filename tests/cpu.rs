@@ -6,7 +6,7 @@ use std::{
 };
 
 use assert_hex::assert_eq_hex;
-use yane::core::{Cartridge, Nes};
+use yane::core::{Cartridge, Nes, Settings};
 
 // Runs the NES test CPU file and checks the state of the NES after each execution
 #[test]
@@ -87,3 +87,96 @@ fn test_official() {
 fn test_branch() {
     rom_test!("./test_roms/cpu_branch.nes");
 }
+#[test]
+fn test_dummy_writes_ppumem() {
+    rom_test!("./test_roms/cpu_dummy_writes_ppumem.nes");
+}
+
+// Run an instruction from RAM, starting the given number of dots before the PPU sets the VBlank flag
+fn run_before_vblank(instruction: &[u8], dots: u32, setup: fn(&mut Nes)) -> Nes {
+    let mut nes = Nes::new();
+    setup(&mut nes);
+    nes.mem[0x0200..(0x0200 + instruction.len())].copy_from_slice(instruction);
+    nes.cpu.p_c = 0x0200;
+    // Run the PPU for a bit, since a new one acts as if PPUSTATUS has just been read, and clear the VBlank flag it
+    // starts with
+    nes.ppu
+        .advance_dots(10, &mut nes.cartridge, &Settings::default());
+    nes.ppu.status &= 0x7F;
+    // The flag is set on the second dot of scanline 241
+    nes.ppu.dot = if dots <= 1 {
+        (1 - dots, 241)
+    } else {
+        (342 - dots, 240)
+    };
+    nes.advance_instruction(&Settings::default()).unwrap();
+    nes
+}
+
+// Get the numbers of dots before VBlank starts that the instruction can start on for `check` to be true after it
+fn vblank_dots(instruction: &[u8], setup: fn(&mut Nes), check: fn(&Nes) -> bool) -> Vec<u32> {
+    (1..24)
+        .filter(|dots| check(&run_before_vblank(instruction, *dots, setup)))
+        .collect()
+}
+
+#[test]
+fn test_ppu_register_access_cycle() {
+    let negative = |nes: &Nes| nes.cpu.a & 0x80 != 0;
+    let carry = |nes: &Nes| nes.cpu.s_r.c;
+    // PPUSTATUS is read on the fourth cycle of LDA $2002, which is 9 dots after the instruction starts
+    assert_eq!(
+        vblank_dots(&[0xAD, 0x02, 0x20], |_| {}, negative),
+        (1..=9).collect::<Vec<u32>>()
+    );
+    // And on the fifth cycle of LDA $1FF2,X if X causes a page cross
+    assert_eq!(
+        vblank_dots(&[0xBD, 0xF2, 0x1F], |nes| nes.cpu.x = 0x10, negative),
+        (1..=12).collect::<Vec<u32>>()
+    );
+    // LDA ($10),Y reads on the fifth cycle, or the sixth if a page is crossed
+    assert_eq!(
+        vblank_dots(
+            &[0xB1, 0x10],
+            |nes| {
+                nes.mem[0x10..0x12].copy_from_slice(&[0x00, 0x20]);
+                nes.cpu.y = 0x02;
+            },
+            negative
+        ),
+        (1..=12).collect::<Vec<u32>>()
+    );
+    assert_eq!(
+        vblank_dots(
+            &[0xB1, 0x10],
+            |nes| {
+                nes.mem[0x10..0x12].copy_from_slice(&[0xF2, 0x1F]);
+                nes.cpu.y = 0x10;
+            },
+            negative
+        ),
+        (1..=15).collect::<Vec<u32>>()
+    );
+    // ASL $2002 reads on the fourth cycle, and the flag ends up in the carry
+    assert_eq!(
+        vblank_dots(&[0x0E, 0x02, 0x20], |_| {}, carry),
+        (1..=9).collect::<Vec<u32>>()
+    );
+    // ASL $2002,X always does a dummy read on the fourth cycle, which clears the flag before it is read on the fifth
+    assert_eq!(
+        vblank_dots(&[0x1E, 0x02, 0x20], |_| {}, carry),
+        (10..=12).collect::<Vec<u32>>()
+    );
+}
+#[test]
+fn test_nmi_poll_cycle() {
+    // Whether an NMI is taken right after LDA $0300
+    let nmi_taken = |nes: &Nes| nes.cpu.p_c != 0x0203;
+    let enable_nmi = |nes: &mut Nes| nes.write_byte(0x2000, 0x80);
+    // Interrupts are polled during the second last cycle, so VBlank has to start in the first 7 dots of the
+    // instruction for the NMI to happen right after it
+    assert_eq!(
+        vblank_dots(&[0xAD, 0x00, 0x03], enable_nmi, nmi_taken),
+        (1..=7).collect::<Vec<u32>>()
+    );
+}
@@ -0,0 +1,247 @@
+mod common;
+use yane::core::{Cartridge, NametableArrangement, Nes};
+
+// A cartridge using the mapper given, with 256KB of PRG ROM and `chr_banks` 8KB banks of CHR ROM (or CHR RAM if 0).
+// The first byte of each 16KB PRG bank is the bank number and the rest is 0xFF, so that writes to `0x8001`
+// don't have bus conflicts.
+// The first byte of each 4KB of CHR ROM is its number.
+fn discrete_nes(mapper: u8, submapper: Option<u8>, chr_banks: u8) -> Nes {
+    let mut rom = b"NES\x1A".to_vec();
+    rom.extend_from_slice(&[16, chr_banks, mapper << 4, mapper & 0xF0]);
+    if let Some(submapper) = submapper {
+        rom[7] |= 0x08;
+        rom.extend_from_slice(&[submapper << 4, 0, 0x07]);
+    }
+    rom.resize(16, 0);
+    (0..16).for_each(|i| {
+        let mut bank = vec![0xFF; 0x4000];
+        bank[0] = i;
+        rom.extend_from_slice(&bank);
+    });
+    (0..(2 * chr_banks)).for_each(|i| {
+        let mut bank = vec![0; 0x1000];
+        bank[0] = i;
+        rom.extend_from_slice(&bank);
+    });
+    Nes::with_cartridge(Cartridge::from_ines(&rom, None).unwrap())
+}
+
+// Save and load the NES as a savestate, checking that the mapper is the same afterwards
+fn round_trip(nes: &Nes) -> Nes {
+    let savestate = nes.to_savestate().unwrap();
+    let loaded = Nes::from_savestate(&savestate).unwrap();
+    assert_eq!(
+        format!("{:?}", loaded.cartridge),
+        format!("{:?}", nes.cartridge)
+    );
+    assert_eq!(
+        loaded.cartridge.mapper.mapper_num(),
+        nes.cartridge.mapper.mapper_num()
+    );
+    loaded
+}
+
+#[test]
+fn test_gxrom() {
+    let mut nes = discrete_nes(66, None, 4);
+    nes.write_byte(0x8001, 0x21);
+    assert_eq!(nes.read_byte(0x8000), 4);
+    assert_eq!(read_ppu!(nes, 0x0000), 2);
+    let mut nes = round_trip(&nes);
+    assert_eq!(nes.read_byte(0x8000), 4);
+    assert_eq!(read_ppu!(nes, 0x1000), 3);
+    // Bus conflict with the 4 at 0x8000
+    nes.write_byte(0x8000, 0x13);
+    assert_eq!(nes.read_byte(0x8000), 0);
+    assert_eq!(read_ppu!(nes, 0x0000), 0);
+}
+#[test]
+fn test_color_dreams() {
+    let mut nes = discrete_nes(11, None, 16);
+    nes.write_byte(0x8001, 0x52);
+    assert_eq!(nes.read_byte(0x8000), 4);
+    assert_eq!(read_ppu!(nes, 0x0000), 10);
+    let mut nes = round_trip(&nes);
+    assert_eq!(nes.read_byte(0xC000), 5);
+    assert_eq!(read_ppu!(nes, 0x1000), 11);
+    // Bus conflict with the 4 at 0x8000
+    nes.write_byte(0x8000, 0x13);
+    assert_eq!(nes.read_byte(0x8000), 0);
+    assert_eq!(read_ppu!(nes, 0x0000), 0);
+}
+#[test]
+fn test_bnrom() {
+    [Some(2), None].into_iter().for_each(|submapper| {
+        let mut nes = discrete_nes(34, submapper, 0);
+        nes.write_byte(0x8001, 0x03);
+        assert_eq!(nes.read_byte(0x8000), 6);
+        assert_eq!(nes.read_byte(0xC000), 7);
+        let mut nes = round_trip(&nes);
+        assert_eq!(nes.read_byte(0x8000), 6);
+        // Bus conflict with the 6 at 0x8000
+        nes.write_byte(0x8000, 0x05);
+        assert_eq!(nes.read_byte(0x8000), 8);
+        // CHR RAM
+        write_ppu!(nes, 0x1234, 0x56);
+        assert_eq!(read_ppu!(nes, 0x1234), 0x56);
+    });
+}
+#[test]
+fn test_nina001() {
+    [Some(1), None].into_iter().for_each(|submapper| {
+        let mut nes = discrete_nes(34, submapper, 4);
+        nes.write_byte(0x7FFD, 0x01);
+        nes.write_byte(0x7FFE, 0x02);
+        nes.write_byte(0x7FFF, 0x05);
+        assert_eq!(nes.read_byte(0x8000), 2);
+        assert_eq!(read_ppu!(nes, 0x0000), 2);
+        assert_eq!(read_ppu!(nes, 0x1000), 5);
+        let mut nes = round_trip(&nes);
+        assert_eq!(nes.read_byte(0x8000), 2);
+        assert_eq!(read_ppu!(nes, 0x1000), 5);
+        // The registers are in PRG RAM
+        assert_eq!(nes.read_byte(0x7FFE), 0x02);
+        nes.write_byte(0x6000, 0x12);
+        assert_eq!(nes.read_byte(0x6000), 0x12);
+        // No bus conflicts or registers at 0x8000
+        nes.write_byte(0x8000, 0x00);
+        assert_eq!(nes.read_byte(0x8000), 2);
+    });
+}
+#[test]
+fn test_camerica() {
+    let mut nes = discrete_nes(71, None, 0);
+    nes.write_byte(0xC000, 0x03);
+    assert_eq!(nes.read_byte(0x8000), 3);
+    assert_eq!(nes.read_byte(0xC000), 15);
+    assert_eq!(
+        nes.cartridge.nametable_arrangement(),
+        NametableArrangement::Vertical
+    );
+    // 0x8000 only selects the mirroring on the BF9097
+    nes.write_byte(0x8000, 0x10);
+    assert_eq!(
+        nes.cartridge.nametable_arrangement(),
+        NametableArrangement::Vertical
+    );
+    nes.write_byte(0x9000, 0x10);
+    assert_eq!(
+        nes.cartridge.nametable_arrangement(),
+        NametableArrangement::Custom
+    );
+    write_ppu!(nes, 0x2000, 0x42);
+    let mut nes = round_trip(&nes);
+    assert_eq!(read_ppu!(nes, 0x2C00), 0x42);
+    assert_eq!(nes.read_byte(0x8000), 3);
+    nes.write_byte(0x9000, 0x00);
+    assert_eq!(read_ppu!(nes, 0x2C00), 0x00);
+    // Fire Hawk
+    let mut nes = discrete_nes(71, Some(1), 0);
+    nes.write_byte(0x8000, 0x10);
+    write_ppu!(nes, 0x2000, 0x42);
+    nes.write_byte(0x8000, 0x00);
+    assert_eq!(read_ppu!(nes, 0x2000), 0x00);
+    nes.write_byte(0x8000, 0x10);
+    assert_eq!(read_ppu!(nes, 0x2800), 0x42);
+}
+#[test]
+fn test_nina03_06() {
+    let mut nes = discrete_nes(79, None, 8);
+    nes.write_byte(0x4100, 0x0A);
+    assert_eq!(nes.read_byte(0x8000), 2);
+    assert_eq!(read_ppu!(nes, 0x0000), 4);
+    let mut nes = round_trip(&nes);
+    assert_eq!(nes.read_byte(0x8000), 2);
+    // Registers are mirrored when A8 is set
+    nes.write_byte(0x5F00, 0x03);
+    assert_eq!(nes.read_byte(0x8000), 0);
+    assert_eq!(read_ppu!(nes, 0x0000), 6);
+    nes.write_byte(0x4200, 0x00);
+    assert_eq!(read_ppu!(nes, 0x0000), 6);
+    // Multicart
+    let mut nes = discrete_nes(113, None, 16);
+    assert_eq!(
+        nes.cartridge.nametable_arrangement(),
+        NametableArrangement::Vertical
+    );
+    nes.write_byte(0x4100, 0x80 | 0x40 | (2 << 3) | 0x01);
+    assert_eq!(nes.read_byte(0x8000), 4);
+    assert_eq!(read_ppu!(nes, 0x0000), 18);
+    let mut nes = round_trip(&nes);
+    assert_eq!(read_ppu!(nes, 0x0000), 18);
+    assert_eq!(
+        nes.cartridge.nametable_arrangement(),
+        NametableArrangement::Horizontal
+    );
+}
+#[test]
+fn test_jaleco_jf11() {
+    let mut nes = discrete_nes(140, None, 16);
+    nes.write_byte(0x6000, 0x23);
+    assert_eq!(nes.read_byte(0x8000), 4);
+    assert_eq!(read_ppu!(nes, 0x0000), 6);
+    let mut nes = round_trip(&nes);
+    assert_eq!(nes.read_byte(0xC000), 5);
+    assert_eq!(read_ppu!(nes, 0x1000), 7);
+    // No registers or bus conflicts at 0x8000
+    nes.write_byte(0x8000, 0x00);
+    assert_eq!(nes.read_byte(0x8000), 4);
+}
+#[test]
+fn test_jaleco_jf05() {
+    let mut nes = discrete_nes(87, None, 4);
+    // The bits are swapped
+    nes.write_byte(0x6000, 0x01);
+    assert_eq!(read_ppu!(nes, 0x0000), 4);
+    let mut nes = round_trip(&nes);
+    assert_eq!(read_ppu!(nes, 0x0000), 4);
+    nes.write_byte(0x6000, 0x02);
+    assert_eq!(read_ppu!(nes, 0x0000), 2);
+    assert_eq!(nes.read_byte(0x8000), 0);
+    assert_eq!(nes.read_byte(0xC000), 1);
+}
+#[test]
+fn test_uxrom_bus_conflicts() {
+    [(Some(2), 1), (Some(1), 3), (None, 3)]
+        .into_iter()
+        .for_each(|(submapper, bank)| {
+            let mut nes = discrete_nes(2, submapper, 0);
+            nes.write_byte(0x8001, 0x05);
+            assert_eq!(nes.read_byte(0x8000), 5);
+            // Conflicts with the 5 at 0x8000
+            nes.write_byte(0x8000, 0x03);
+            assert_eq!(nes.read_byte(0x8000), bank);
+            let mut nes = round_trip(&nes);
+            nes.write_byte(0x8001, 0x04);
+            assert_eq!(nes.read_byte(0x8000), 4);
+        });
+}
+#[test]
+fn test_cnrom_bus_conflicts() {
+    [(Some(2), 4), (Some(1), 6), (None, 6)]
+        .into_iter()
+        .for_each(|(submapper, chr)| {
+            let mut nes = discrete_nes(3, submapper, 4);
+            assert_eq!(nes.read_byte(0x8000), 2);
+            // Conflicts with the 2 at 0x8000
+            nes.write_byte(0x8000, 0x03);
+            assert_eq!(read_ppu!(nes, 0x0000), chr);
+            nes.write_byte(0x8001, 0x01);
+            let mut nes = round_trip(&nes);
+            assert_eq!(read_ppu!(nes, 0x0000), 2);
+        });
+}
+#[test]
+fn test_axrom_bus_conflicts() {
+    [(Some(2), 0), (Some(1), 4), (None, 4)]
+        .into_iter()
+        .for_each(|(submapper, bank)| {
+            let mut nes = discrete_nes(7, submapper, 0);
+            // Conflicts with the 0 at 0x8000
+            nes.write_byte(0x8000, 0x12);
+            assert_eq!(nes.read_byte(0x8000), bank);
+            nes.write_byte(0x8001, 0x13);
+            let mut nes = round_trip(&nes);
+            assert_eq!(nes.read_byte(0x8000), 6);
+        });
+}
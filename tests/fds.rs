@@ -0,0 +1,137 @@
+use yane::core::{ips, mappers::FDS_SIDE_SIZE, Cartridge, Nes, Settings};
+
+// A disk side containing a single 4 byte file
+fn disk_side(file: [u8; 4]) -> Vec<u8> {
+    let mut side = vec![0x01];
+    side.extend_from_slice(b"*NINTENDO-HVC*");
+    side.resize(56, 0);
+    // File amount
+    side.extend_from_slice(&[0x02, 0x01]);
+    // File header
+    side.extend_from_slice(&[0x03, 0x00, 0x00]);
+    side.extend_from_slice(b"TESTFILE");
+    side.extend_from_slice(&[0x00, 0x60, 0x04, 0x00, 0x00]);
+    // File data
+    side.push(0x04);
+    side.extend_from_slice(&file);
+    side.resize(FDS_SIDE_SIZE, 0);
+    side
+}
+
+// A BIOS that loops forever
+fn bios() -> Vec<u8> {
+    let mut bios = vec![0; 0x2000];
+    // JMP $E000
+    bios[0..3].copy_from_slice(&[0x4C, 0x00, 0xE0]);
+    // Reset vector
+    bios[0x1FFC..0x1FFE].copy_from_slice(&[0x00, 0xE0]);
+    bios
+}
+
+fn two_sided_disk() -> Vec<u8> {
+    let mut disk = b"FDS\x1A\x02".to_vec();
+    disk.resize(16, 0);
+    disk.extend_from_slice(&disk_side([1, 2, 3, 4]));
+    disk.extend_from_slice(&disk_side([5, 6, 7, 8]));
+    disk
+}
+
+#[test]
+fn test_ips_round_trip() {
+    let original: Vec<u8> = (0..=255).cycle().take(0x2000).collect();
+    let mut modified = original.clone();
+    modified[0x10..0x20].fill(0xAA);
+    modified[0x1FFF] = 0;
+    modified.extend_from_slice(&[1, 2, 3]);
+    let patch = ips::create(&original, &modified);
+    assert_eq!(ips::apply(&original, &patch).unwrap(), modified);
+    // Identical data gives an empty patch
+    assert_eq!(ips::create(&original, &original), b"PATCHEOF");
+    assert!(ips::apply(&original, b"PATCH\x00\x00").is_err());
+    assert!(ips::apply(&original, b"NOT A PATCH").is_err());
+}
+#[test]
+fn test_fds_loading() {
+    assert!(Cartridge::from_fds(&two_sided_disk(), &[0; 0x1000], None).is_err());
+    assert!(Cartridge::from_fds(&[0; 100], &bios(), None).is_err());
+    let cartridge = Cartridge::from_fds(&two_sided_disk(), &bios(), None).unwrap();
+    assert_eq!(cartridge.mapper.disk_sides(), 2);
+    assert_eq!(cartridge.mapper.disk_side(), Some(0));
+    // Nothing has been written to the disk yet
+    let patch = cartridge.mapper.disk_patch().unwrap();
+    assert_eq!(
+        ips::apply(&two_sided_disk(), &patch).unwrap(),
+        two_sided_disk()
+    );
+    // The BIOS is mapped at $E000
+    let nes = Nes::with_cartridge(cartridge);
+    assert_eq!(nes.cpu.p_c, 0xE000);
+    // Changes from a previous session are kept in the new patch, which still applies to the unpatched disk
+    let mut changed = two_sided_disk();
+    changed[16 + FDS_SIDE_SIZE + 75] = 0xAA;
+    let patch = ips::create(&two_sided_disk(), &changed);
+    let cartridge = Cartridge::from_fds(&two_sided_disk(), &bios(), Some(&patch)).unwrap();
+    let patch = cartridge.mapper.disk_patch().unwrap();
+    assert_eq!(ips::apply(&two_sided_disk(), &patch).unwrap(), changed);
+}
+#[test]
+fn test_fds_switch_disk_side() {
+    let mut nes =
+        Nes::with_cartridge(Cartridge::from_fds(&two_sided_disk(), &bios(), None).unwrap());
+    let settings = Settings::default();
+    nes.cartridge.switch_disk_side();
+    // The disk is ejected for a while before the new side is inserted
+    assert_eq!(nes.cartridge.mapper.disk_side(), None);
+    (0..120).for_each(|_| {
+        nes.advance_frame(&settings).unwrap();
+    });
+    assert_eq!(nes.cartridge.mapper.disk_side(), Some(1));
+    nes.cartridge.switch_disk_side();
+    (0..120).for_each(|_| {
+        nes.advance_frame(&settings).unwrap();
+    });
+    assert_eq!(nes.cartridge.mapper.disk_side(), Some(0));
+}
+#[test]
+fn test_fds_timer_irq() {
+    let mut nes =
+        Nes::with_cartridge(Cartridge::from_fds(&two_sided_disk(), &bios(), None).unwrap());
+    let settings = Settings::default();
+    // Enable disk registers
+    nes.write_byte(0x4023, 0x01);
+    nes.write_byte(0x4020, 0x00);
+    nes.write_byte(0x4021, 0x10);
+    assert!(!nes.cartridge.mapper.irq());
+    // Enable the timer IRQ
+    nes.write_byte(0x4022, 0x02);
+    nes.advance_frame(&settings).unwrap();
+    assert!(nes.cartridge.mapper.irq());
+    // Reading the status acknowledges the IRQ
+    nes.read_byte(0x4030);
+    assert!(!nes.cartridge.mapper.irq());
+}
+#[test]
+fn test_fds_audio() {
+    let mut nes =
+        Nes::with_cartridge(Cartridge::from_fds(&two_sided_disk(), &bios(), None).unwrap());
+    let settings = Settings::default();
+    // Enable sound registers
+    nes.write_byte(0x4023, 0x03);
+    // Write a square wave to the wave table
+    nes.write_byte(0x4089, 0x80);
+    (0..64).for_each(|i| nes.write_byte(0x4040 + i, if i < 32 { 0x3F } else { 0x00 }));
+    assert_eq!(nes.read_byte(0x4040) & 0x3F, 0x3F);
+    // Full master volume, and a constant gain of 32
+    nes.write_byte(0x4089, 0x00);
+    nes.write_byte(0x4080, 0xA0);
+    nes.write_byte(0x4082, 0x00);
+    nes.write_byte(0x4083, 0x01);
+    nes.advance_frame(&settings).unwrap();
+    let samples = nes.apu.sample_queue();
+    assert!(samples.iter().any(|s| *s > 0.1));
+    // Setting the gain to 0 silences it
+    nes.write_byte(0x4080, 0x80);
+    nes.advance_frame(&settings).unwrap();
+    nes.advance_frame(&settings).unwrap();
+    assert!(nes.apu.sample_queue().iter().all(|s| s.abs() < 0.01));
+}
@@ -0,0 +1,167 @@
+mod common;
+use yane::core::{Cartridge, ExpansionAudio, Nes};
+
+// A mapper 69 cartridge with 128KB of PRG ROM, where each 8KB bank starts with its bank number,
+// and 128KB of CHR ROM, where each 1KB bank starts with its bank number.
+// Uses an NES 2.0 header with 8KB of PRG RAM if `submapper` is given.
+fn fme7_nes(submapper: Option<u8>) -> Nes {
+    let mut rom = b"NES\x1A".to_vec();
+    rom.extend_from_slice(&[8, 16, 0x50, 0x40]);
+    if let Some(submapper) = submapper {
+        rom[7] |= 0x08;
+        rom.extend_from_slice(&[submapper << 4, 0, 0x07]);
+    }
+    rom.resize(16, 0);
+    (0..16).for_each(|i| {
+        let mut bank = vec![0; 0x2000];
+        bank[0] = i;
+        rom.extend_from_slice(&bank);
+    });
+    let last_bank = rom.len() - 0x2000;
+    // JMP $E010
+    rom[last_bank + 0x10..last_bank + 0x13].copy_from_slice(&[0x4C, 0x10, 0xE0]);
+    // Reset vector
+    rom[last_bank + 0x1FFC..last_bank + 0x1FFE].copy_from_slice(&[0x10, 0xE0]);
+    (0..128).for_each(|i| {
+        let mut bank = vec![0; 0x400];
+        bank[0] = i;
+        rom.extend_from_slice(&bank);
+    });
+    Nes::with_cartridge(Cartridge::from_ines(&rom, None).unwrap())
+}
+
+fn write_command(nes: &mut Nes, command: u8, value: u8) {
+    nes.write_byte(0x8000, command);
+    nes.write_byte(0xA000, value);
+}
+
+fn write_audio(nes: &mut Nes, reg: u8, value: u8) {
+    nes.write_byte(0xC000, reg);
+    nes.write_byte(0xE000, value);
+}
+
+#[test]
+fn test_fme7_banking() {
+    let mut nes = fme7_nes(Some(1));
+    write_command(&mut nes, 0x9, 3);
+    write_command(&mut nes, 0xA, 4);
+    write_command(&mut nes, 0xB, 5);
+    assert_eq!(nes.read_byte(0x8000), 3);
+    assert_eq!(nes.read_byte(0xA000), 4);
+    assert_eq!(nes.read_byte(0xC000), 5);
+    assert_eq!(nes.read_byte(0xE000), 15);
+    (0..8).for_each(|i| write_command(&mut nes, i, 20 + i));
+    (0..8).for_each(|i| assert_eq!(read_ppu!(nes, 0x400 * i), 20 + i as u8));
+}
+#[test]
+fn test_fme7_prg_ram() {
+    let mut nes = fme7_nes(Some(1));
+    // ROM at 0x6000
+    write_command(&mut nes, 0x8, 7);
+    assert_eq!(nes.read_byte(0x6000), 7);
+    // Disabled RAM
+    write_command(&mut nes, 0x8, 0x40);
+    nes.write_byte(0x6000, 0x12);
+    assert_eq!(nes.read_byte(0x6000), 0);
+    // Enabled RAM
+    write_command(&mut nes, 0x8, 0xC0);
+    nes.write_byte(0x6000, 0x12);
+    assert_eq!(nes.read_byte(0x6000), 0x12);
+}
+#[test]
+fn test_fme7_mirroring() {
+    let mut nes = fme7_nes(Some(1));
+    // Horizontal mirroring
+    write_command(&mut nes, 0xC, 1);
+    nes.read_byte(0x2002);
+    nes.write_byte(0x2006, 0x20);
+    nes.write_byte(0x2006, 0x00);
+    nes.write_byte(0x2007, 0x42);
+    assert_eq!(read_ppu!(nes, 0x2400), 0x42);
+    assert_eq!(read_ppu!(nes, 0x2800), 0x00);
+    // One screen, upper bank
+    write_command(&mut nes, 0xC, 3);
+    assert_eq!(read_ppu!(nes, 0x2000), 0x00);
+    write_command(&mut nes, 0xC, 2);
+    assert_eq!(read_ppu!(nes, 0x2C00), 0x42);
+}
+#[test]
+fn test_fme7_irq() {
+    let mut nes = fme7_nes(Some(1));
+    write_command(&mut nes, 0xE, 0x10);
+    write_command(&mut nes, 0xF, 0x00);
+    // Counter enabled but IRQ disabled
+    write_command(&mut nes, 0xD, 0x80);
+    nes.cartridge.mapper.advance_cpu_cycles(0x20);
+    assert!(!nes.cartridge.mapper.irq());
+    write_command(&mut nes, 0xE, 0x10);
+    write_command(&mut nes, 0xF, 0x00);
+    write_command(&mut nes, 0xD, 0x81);
+    nes.cartridge.mapper.advance_cpu_cycles(0x10);
+    assert!(!nes.cartridge.mapper.irq());
+    nes.cartridge.mapper.advance_cpu_cycles(1);
+    assert!(nes.cartridge.mapper.irq());
+    // Acknowledged by writing to the control register
+    write_command(&mut nes, 0xD, 0x00);
+    assert!(!nes.cartridge.mapper.irq());
+}
+#[test]
+fn test_fme7_variants() {
+    [(Some(1), None), (Some(2), None)]
+        .into_iter()
+        .chain([(Some(3), Some(ExpansionAudio::Sunsoft5B))])
+        .chain([(None, Some(ExpansionAudio::Sunsoft5B))])
+        .for_each(|(submapper, audio)| {
+            let nes = fme7_nes(submapper);
+            assert_eq!(nes.cartridge.mapper.expansion_audio(), audio);
+        });
+    // The FME-7 has no audio
+    let mut nes = fme7_nes(Some(1));
+    write_audio(&mut nes, 0x07, 0x3E);
+    write_audio(&mut nes, 0x08, 0x0F);
+    assert!(audio_samples!(nes, 1000).iter().all(|s| *s == 0.0));
+}
+#[test]
+fn test_sunsoft5b_tone() {
+    let mut nes = fme7_nes(Some(3));
+    // Channel A with a period of 0x100, so it toggles every 0x1000 CPU cycles
+    write_audio(&mut nes, 0x00, 0x00);
+    write_audio(&mut nes, 0x01, 0x01);
+    // Enable the tone for channel A only
+    write_audio(&mut nes, 0x07, 0x3E);
+    write_audio(&mut nes, 0x08, 0x0F);
+    let samples = audio_samples!(nes, 0x10000);
+    let max = samples.iter().fold(0.0f32, |a, b| a.max(*b));
+    assert!(max > 0.1);
+    let toggles = samples.windows(2).filter(|w| w[0] != w[1]).count();
+    assert_eq!(toggles, 16);
+    // Each volume step is 3dB
+    write_audio(&mut nes, 0x08, 0x0D);
+    let quiet = audio_samples!(nes, 0x2000)
+        .iter()
+        .fold(0.0f32, |a, b| a.max(*b));
+    assert!((quiet / max - 0.5).abs() < 0.02, "{} {}", max, quiet);
+    // Silent at volume 0
+    write_audio(&mut nes, 0x08, 0x00);
+    assert!(audio_samples!(nes, 0x2000).iter().all(|s| *s == 0.0));
+}
+#[test]
+fn test_sunsoft5b_envelope() {
+    let mut nes = fme7_nes(None);
+    // Channel A constantly high, using the envelope
+    write_audio(&mut nes, 0x07, 0x3F);
+    write_audio(&mut nes, 0x08, 0x10);
+    write_audio(&mut nes, 0x0B, 0x10);
+    write_audio(&mut nes, 0x0C, 0x00);
+    // Decay once then stay silent
+    write_audio(&mut nes, 0x0D, 0x00);
+    let samples = audio_samples!(nes, 0x4000);
+    assert!(samples[0] > 0.1);
+    assert!(samples.windows(2).all(|w| w[1] <= w[0]));
+    assert_eq!(*samples.last().unwrap(), 0.0);
+    // Attack and hold
+    write_audio(&mut nes, 0x0D, 0x0D);
+    let samples = audio_samples!(nes, 0x4000);
+    assert!(samples.windows(2).all(|w| w[1] >= w[0]));
+    assert!(*samples.last().unwrap() > 0.1);
+}
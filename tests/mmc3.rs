@@ -13,3 +13,141 @@ fn test_details() {
 fn test_a12_clocking() {
     rom_test!("./test_roms/mmc3_a12_clocking.nes");
 }
+#[test]
+fn test_scanline_timing() {
+    rom_test!("./test_roms/mmc3_scanline_timing.nes", 600);
+}
+
+// A cartridge using the MMC3 mapper given, with 128KB of PRG ROM and 64KB of CHR ROM, where the first byte of each
+// 1KB CHR bank is its number.
+// The PRG RAM is battery backed, and if `submapper` is given, the file is NES 2.0 with 1KB of PRG RAM.
+fn mmc3_nes(mapper: u8, submapper: Option<u8>) -> Nes {
+    let mut rom = b"NES\x1A".to_vec();
+    rom.extend_from_slice(&[8, 8, (mapper << 4) | 0x02, mapper & 0xF0]);
+    if let Some(submapper) = submapper {
+        rom[7] |= 0x08;
+        rom.extend_from_slice(&[submapper << 4, 0, 0x40]);
+    }
+    rom.resize(16 + 8 * 0x4000, 0);
+    (0..64).for_each(|i| {
+        let mut bank = vec![0; 0x400];
+        bank[0] = i;
+        rom.extend_from_slice(&bank);
+    });
+    Nes::with_cartridge(Cartridge::from_ines(&rom, None).unwrap())
+}
+
+fn set_bank(nes: &mut Nes, bank: u8, value: u8) {
+    nes.write_byte(0x8000, bank);
+    nes.write_byte(0x8001, value);
+}
+
+// Clock the IRQ counter by toggling A12, and return whether an IRQ is generated
+fn clock_irq(nes: &mut Nes) -> bool {
+    nes.cartridge.mapper.set_addr_value(0x0000);
+    nes.cartridge.mapper.set_addr_value(0x1000);
+    nes.cartridge.mapper.advance_cpu_cycles(2);
+    nes.cartridge.mapper.irq()
+}
+
+#[test]
+fn test_mmc6_prg_ram() {
+    let mut nes = mmc3_nes(4, Some(1));
+    // Disabled, which gives open bus
+    nes.write_byte(0xA001, 0xF0);
+    nes.write_byte(0x7000, 0x12);
+    assert_eq!(nes.read_byte(0x7000), 0x70);
+    // Enable reading and writing to the lower half
+    nes.write_byte(0x8000, 0x20);
+    nes.write_byte(0xA001, 0x30);
+    nes.write_byte(0x7000, 0x12);
+    nes.write_byte(0x7200, 0x34);
+    assert_eq!(nes.read_byte(0x7000), 0x12);
+    // The other half reads as 0 when one half can be read
+    assert_eq!(nes.read_byte(0x7200), 0x00);
+    // Mirrored every 1KB
+    assert_eq!(nes.read_byte(0x7C00), 0x12);
+    // Lower half read only, upper half read and write
+    nes.write_byte(0xA001, 0xE0);
+    nes.write_byte(0x7000, 0x56);
+    nes.write_byte(0x7200, 0x78);
+    assert_eq!(nes.read_byte(0x7000), 0x12);
+    assert_eq!(nes.read_byte(0x7200), 0x78);
+    assert_eq!(nes.read_byte(0x6000), 0x60);
+    // Writing needs reading to be enabled
+    nes.write_byte(0xA001, 0x50);
+    nes.write_byte(0x7000, 0x56);
+    nes.write_byte(0x7200, 0x56);
+    assert_eq!(nes.read_byte(0x7000), 0x70);
+    nes.write_byte(0xA001, 0xA0);
+    assert_eq!(nes.read_byte(0x7000), 0x12);
+    assert_eq!(nes.read_byte(0x7200), 0x78);
+    let savedata = nes.savedata().unwrap();
+    assert_eq!(savedata.len(), 0x400);
+    assert_eq!(savedata[0x200], 0x78);
+    let savestate = nes.to_savestate().unwrap();
+    let mut nes = Nes::from_savestate(&savestate).unwrap();
+    assert_eq!(nes.read_byte(0x7000), 0x12);
+}
+#[test]
+fn test_tqrom() {
+    let mut nes = mmc3_nes(119, None);
+    assert_eq!(nes.cartridge.mapper.mapper_num(), 119);
+    set_bank(&mut nes, 2, 0x05);
+    assert_eq!(read_ppu!(nes, 0x1000), 5);
+    write_ppu!(nes, 0x1000, 0x42);
+    assert_eq!(read_ppu!(nes, 0x1000), 5);
+    // CHR RAM
+    set_bank(&mut nes, 2, 0x43);
+    write_ppu!(nes, 0x1000, 0x42);
+    assert_eq!(read_ppu!(nes, 0x1000), 0x42);
+    set_bank(&mut nes, 0, 0x42);
+    assert_eq!(read_ppu!(nes, 0x0400), 0x42);
+    assert_eq!(read_ppu!(nes, 0x0000), 0x00);
+}
+#[test]
+fn test_txsrom() {
+    let mut nes = mmc3_nes(118, None);
+    assert_eq!(nes.cartridge.mapper.mapper_num(), 118);
+    // Ignores 0xA000
+    nes.write_byte(0xA000, 0x01);
+    set_bank(&mut nes, 0, 0x82);
+    set_bank(&mut nes, 1, 0x04);
+    assert_eq!(read_ppu!(nes, 0x0400), 3);
+    write_ppu!(nes, 0x2000, 0x42);
+    assert_eq!(read_ppu!(nes, 0x2400), 0x42);
+    assert_eq!(read_ppu!(nes, 0x2800), 0x00);
+    // 1KB banks in the lower pattern table control each nametable
+    nes.write_byte(0x8000, 0x80);
+    (2..6)
+        .zip([0x00, 0x80, 0x80, 0x00])
+        .for_each(|(bank, value)| {
+            nes.write_byte(0x8000, 0x80 | bank);
+            nes.write_byte(0x8001, value);
+        });
+    assert_eq!(read_ppu!(nes, 0x2000), 0x00);
+    assert_eq!(read_ppu!(nes, 0x2400), 0x42);
+    assert_eq!(read_ppu!(nes, 0x2800), 0x42);
+    assert_eq!(read_ppu!(nes, 0x2C00), 0x00);
+}
+#[test]
+fn test_mmc3_irq_reload_to_zero() {
+    // New behaviour, where reloading to 0 generates an IRQ every time
+    let mut nes = mmc3_nes(4, Some(0));
+    nes.write_byte(0xC000, 0x00);
+    nes.write_byte(0xC001, 0x00);
+    nes.write_byte(0xE001, 0x00);
+    assert!(clock_irq(&mut nes));
+    assert!(clock_irq(&mut nes));
+    // Old behaviour, where it only generates an IRQ after writing to 0xC001
+    let mut nes = mmc3_nes(4, Some(4));
+    nes.write_byte(0xC000, 0x00);
+    nes.write_byte(0xC001, 0x00);
+    nes.write_byte(0xE001, 0x00);
+    assert!(clock_irq(&mut nes));
+    assert!(!clock_irq(&mut nes));
+    nes.write_byte(0xC000, 0x01);
+    nes.write_byte(0xC001, 0x00);
+    assert!(!clock_irq(&mut nes));
+    assert!(clock_irq(&mut nes));
+}
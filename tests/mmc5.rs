@@ -0,0 +1,167 @@
+mod common;
+use yane::core::{AudioChannel, Cartridge, ExpansionAudio, Nes, Settings};
+
+// An MMC5 cartridge with 128KB of PRG ROM, where each 8KB bank starts with its bank number.
+// The last bank contains an infinite loop at 0xE000.
+fn mmc5_nes() -> Nes {
+    let mut rom = b"NES\x1A".to_vec();
+    rom.extend_from_slice(&[8, 0, 0x50, 0x00, 1]);
+    rom.resize(16, 0);
+    (0..16).for_each(|i| {
+        let mut bank = vec![0; 0x2000];
+        bank[0] = i;
+        rom.extend_from_slice(&bank);
+    });
+    let last_bank = rom.len() - 0x2000;
+    // JMP $E000
+    rom[last_bank..last_bank + 3].copy_from_slice(&[0x4C, 0x00, 0xE0]);
+    // Reset vector
+    rom[last_bank + 0x1FFC..last_bank + 0x1FFE].copy_from_slice(&[0x00, 0xE0]);
+    Nes::with_cartridge(Cartridge::from_ines(&rom, None).unwrap())
+}
+
+#[test]
+fn test_mmc5_prg_modes() {
+    let mut nes = mmc5_nes();
+    // Starts in mode 3 with the last bank at 0xE000
+    assert_eq!(nes.read_byte(0xE000), 0x4C);
+    nes.write_byte(0x5117, 0x83);
+    // 8KB banks
+    (0..3).for_each(|i| nes.write_byte(0x5114 + i, 0x81 + i as u8));
+    assert_eq!(nes.read_byte(0x8000), 1);
+    assert_eq!(nes.read_byte(0xA000), 2);
+    assert_eq!(nes.read_byte(0xC000), 3);
+    assert_eq!(nes.read_byte(0xE000), 3);
+    // 16KB + 8KB + 8KB
+    nes.write_byte(0x5100, 2);
+    nes.write_byte(0x5115, 0x85);
+    assert_eq!(nes.read_byte(0x8000), 4);
+    assert_eq!(nes.read_byte(0xA000), 5);
+    assert_eq!(nes.read_byte(0xC000), 3);
+    // 16KB + 16KB
+    nes.write_byte(0x5100, 1);
+    assert_eq!(nes.read_byte(0xC000), 2);
+    assert_eq!(nes.read_byte(0xE000), 3);
+    // 32KB
+    nes.write_byte(0x5100, 0);
+    nes.write_byte(0x5117, 0x8E);
+    assert_eq!(nes.read_byte(0x8000), 12);
+    assert_eq!(nes.read_byte(0xC000), 14);
+}
+#[test]
+fn test_mmc5_prg_ram() {
+    let mut nes = mmc5_nes();
+    // RAM is protected by default
+    nes.write_byte(0x6000, 0x12);
+    assert_eq!(nes.read_byte(0x6000), 0);
+    nes.write_byte(0x5102, 0x02);
+    nes.write_byte(0x5103, 0x01);
+    nes.write_byte(0x6000, 0x12);
+    assert_eq!(nes.read_byte(0x6000), 0x12);
+    // RAM can be mapped into 0x8000..0xE000
+    nes.write_byte(0x5114, 0x00);
+    assert_eq!(nes.read_byte(0x8000), 0x12);
+}
+#[test]
+fn test_mmc5_multiplier_and_exram() {
+    let mut nes = mmc5_nes();
+    nes.write_byte(0x5205, 200);
+    nes.write_byte(0x5206, 150);
+    assert_eq!(nes.read_byte(0x5205), (30000 & 0xFF) as u8);
+    assert_eq!(nes.read_byte(0x5206), (30000 >> 8) as u8);
+    // ExRAM as CPU RAM
+    nes.write_byte(0x5104, 0x02);
+    nes.write_byte(0x5C10, 0x34);
+    assert_eq!(nes.read_byte(0x5C10), 0x34);
+    // Read only
+    nes.write_byte(0x5104, 0x03);
+    nes.write_byte(0x5C10, 0x56);
+    assert_eq!(nes.read_byte(0x5C10), 0x34);
+}
+#[test]
+fn test_mmc5_fill_mode() {
+    let mut nes = mmc5_nes();
+    // Top left nametable in fill mode, bottom right uses the second page of VRAM
+    nes.write_byte(0x5105, 0b01_00_00_11);
+    nes.write_byte(0x5106, 0x42);
+    nes.write_byte(0x5107, 0x02);
+    assert_eq!(read_ppu!(nes, 0x2000), 0x42);
+    assert_eq!(read_ppu!(nes, 0x23C0), 0xAA);
+    write_ppu!(nes, 0x2C00, 0x77);
+    assert_eq!(nes.ppu.nametable_ram[0x400], 0x77);
+    assert_eq!(read_ppu!(nes, 0x2C00), 0x77);
+}
+#[test]
+fn test_mmc5_scanline_irq() {
+    let mut nes = mmc5_nes();
+    let settings = Settings::default();
+    nes.write_byte(0x5203, 100);
+    nes.write_byte(0x5204, 0x80);
+    // Rendering is disabled so there is no IRQ
+    nes.advance_frame(&settings).unwrap();
+    assert!(!nes.cartridge.mapper.irq());
+    // Enable rendering
+    nes.write_byte(0x2001, 0x18);
+    nes.advance_frame(&settings).unwrap();
+    // Acknowledge the IRQ from the last frame
+    nes.read_byte(0x5204);
+    while !nes.cartridge.mapper.irq() {
+        nes.advance_instruction(&settings).unwrap();
+    }
+    assert_eq!(nes.ppu.scanline(), 100);
+    // The PPU is rendering
+    let status = nes.read_byte(0x5204);
+    assert_eq!(status, 0xC0);
+    // Reading the status acknowledges the IRQ
+    assert!(!nes.cartridge.mapper.irq());
+    // The PPU stops rendering during VBlank
+    nes.advance_frame(&settings).unwrap();
+    (0..100).for_each(|_| {
+        nes.advance_instruction(&settings).unwrap();
+    });
+    assert_eq!(nes.read_byte(0x5204) & 0x40, 0);
+}
+#[test]
+fn test_mmc5_audio() {
+    let mut nes = mmc5_nes();
+    let settings = Settings::default();
+    nes.advance_frame(&settings).unwrap();
+    assert!(nes.apu.sample_queue().iter().all(|s| *s == 0.0));
+    // Play a tone on the first pulse channel
+    nes.write_byte(0x5015, 0x01);
+    nes.write_byte(0x5000, 0xBF);
+    nes.write_byte(0x5002, 0xFD);
+    nes.write_byte(0x5003, 0x00);
+    assert_eq!(nes.read_byte(0x5015), 0x01);
+    nes.advance_frame(&settings).unwrap();
+    assert!(nes.apu.sample_queue().iter().any(|s| *s > 0.0));
+    // Raw PCM
+    nes.write_byte(0x5015, 0x00);
+    nes.write_byte(0x5011, 0x80);
+    nes.advance_frame(&settings).unwrap();
+    assert!(nes.apu.sample_queue().iter().all(|s| *s > 0.1));
+}
+#[test]
+fn test_mmc5_audio_volume() {
+    let mut nes = mmc5_nes();
+    assert_eq!(
+        nes.cartridge.mapper.expansion_audio(),
+        Some(ExpansionAudio::Mmc5)
+    );
+    nes.write_byte(0x5011, 0x80);
+    let mut settings = Settings::default();
+    nes.advance_frame(&settings).unwrap();
+    let level = *nes.apu.sample_queue().last().unwrap();
+    // Expansion audio is scaled by its volume
+    *settings.expansion_volume_mut(ExpansionAudio::Mmc5) = 0.5;
+    nes.advance_frame(&settings).unwrap();
+    assert!(nes
+        .apu
+        .sample_queue()
+        .iter()
+        .all(|s| (s - level / 2.0).abs() < 1e-6));
+    // And muted when an APU channel is soloed
+    settings.mix_mut(AudioChannel::Pulse1).solo = true;
+    nes.advance_frame(&settings).unwrap();
+    assert!(nes.apu.sample_queue().iter().all(|s| *s == 0.0));
+}
@@ -0,0 +1,196 @@
+mod common;
+use yane::core::{Cartridge, ExpansionAudio, Nes};
+
+// A Namco 163 cartridge with battery backed RAM, 128KB of PRG ROM, where each 8KB bank starts with its bank number,
+// and 128KB of CHR ROM, where each 1KB bank starts with its bank number.
+fn namco163_rom() -> Vec<u8> {
+    let mut rom = b"NES\x1A".to_vec();
+    rom.extend_from_slice(&[8, 16, 0x32, 0x10]);
+    rom.resize(16, 0);
+    (0..16).for_each(|i| {
+        let mut bank = vec![0; 0x2000];
+        bank[0] = i;
+        rom.extend_from_slice(&bank);
+    });
+    let last_bank = rom.len() - 0x2000;
+    // JMP $E010
+    rom[last_bank + 0x10..last_bank + 0x13].copy_from_slice(&[0x4C, 0x10, 0xE0]);
+    // Reset vector
+    rom[last_bank + 0x1FFC..last_bank + 0x1FFE].copy_from_slice(&[0x10, 0xE0]);
+    (0..128).for_each(|i| {
+        let mut bank = vec![0; 0x400];
+        bank[0] = i;
+        rom.extend_from_slice(&bank);
+    });
+    rom
+}
+
+fn namco163_nes() -> Nes {
+    Nes::with_cartridge(Cartridge::from_ines(&namco163_rom(), None).unwrap())
+}
+
+// Write to the sound RAM, starting at `addr`
+fn write_sound_ram(nes: &mut Nes, addr: u8, values: &[u8]) {
+    nes.write_byte(0xF800, 0x80 | addr);
+    values.iter().for_each(|v| nes.write_byte(0x4800, *v));
+}
+
+// Play a square wave of 16 samples on channel 7, using `num_channels` channels
+fn play_square(nes: &mut Nes, num_channels: u8) {
+    // 8 samples of 0xF followed by 8 samples of 0x0
+    write_sound_ram(nes, 0x00, &[0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0]);
+    // Frequency 0x10000 so that each update advances by one sample, length 16 and volume 15
+    write_sound_ram(
+        nes,
+        0x78,
+        &[
+            0x00,
+            0x00,
+            0x00,
+            0x00,
+            0xF1,
+            0x00,
+            0x00,
+            0x0F | ((num_channels - 1) << 4),
+        ],
+    );
+}
+
+#[test]
+fn test_namco163_banking() {
+    let mut nes = namco163_nes();
+    nes.write_byte(0xE000, 3);
+    nes.write_byte(0xE800, 4);
+    nes.write_byte(0xF000, 5);
+    assert_eq!(nes.read_byte(0x8000), 3);
+    assert_eq!(nes.read_byte(0xA000), 4);
+    assert_eq!(nes.read_byte(0xC000), 5);
+    assert_eq!(nes.read_byte(0xE000), 15);
+    (0..8).for_each(|i| nes.write_byte(0x8000 + 0x800 * i, 20 + i as u8));
+    (0..8).for_each(|i| assert_eq!(read_ppu!(nes, 0x400 * i), 20 + i as u8));
+}
+#[test]
+fn test_namco163_nametables() {
+    let mut nes = namco163_nes();
+    // Vertical mirroring using nametable RAM, with a CHR ROM bank in the last nametable
+    (0..4)
+        .zip([0xE0, 0xE1, 0xE0, 0x07])
+        .for_each(|(i, bank)| nes.write_byte(0xC000 + 0x800 * i, bank));
+    write_ppu!(nes, 0x2001, 0x42);
+    assert_eq!(read_ppu!(nes, 0x2801), 0x42);
+    assert_eq!(read_ppu!(nes, 0x2401), 0x00);
+    assert_eq!(read_ppu!(nes, 0x2C00), 0x07);
+    // Nametable RAM in the pattern tables
+    nes.write_byte(0x8000, 0xE0);
+    nes.write_byte(0xA000, 0xE0);
+    assert_eq!(read_ppu!(nes, 0x0001), 0x42);
+    assert_eq!(read_ppu!(nes, 0x1001), 0x42);
+    write_ppu!(nes, 0x0002, 0x43);
+    assert_eq!(read_ppu!(nes, 0x2002), 0x43);
+    // Disabled in the upper pattern table
+    nes.write_byte(0xE800, 0x80);
+    assert_eq!(read_ppu!(nes, 0x0001), 0x42);
+    assert_eq!(read_ppu!(nes, 0x1000), 0x60);
+}
+#[test]
+fn test_namco163_prg_ram_write_protect() {
+    let mut nes = namco163_nes();
+    nes.write_byte(0x6000, 0x12);
+    assert_eq!(nes.read_byte(0x6000), 0x00);
+    nes.write_byte(0xF800, 0x40);
+    nes.write_byte(0x6000, 0x12);
+    assert_eq!(nes.read_byte(0x6000), 0x12);
+    // Protect 0x6800-0x6FFF
+    nes.write_byte(0xF800, 0x42);
+    nes.write_byte(0x6000, 0x34);
+    nes.write_byte(0x6800, 0x34);
+    assert_eq!(nes.read_byte(0x6000), 0x34);
+    assert_eq!(nes.read_byte(0x6800), 0x00);
+}
+#[test]
+fn test_namco163_irq() {
+    let mut nes = namco163_nes();
+    nes.write_byte(0x5000, 0xF0);
+    nes.write_byte(0x5800, 0xFF);
+    nes.cartridge.mapper.advance_cpu_cycles(0x0E);
+    assert!(!nes.cartridge.mapper.irq());
+    nes.cartridge.mapper.advance_cpu_cycles(1);
+    assert!(nes.cartridge.mapper.irq());
+    // Stops counting at 0x7FFF
+    nes.cartridge.mapper.advance_cpu_cycles(10);
+    assert_eq!(nes.read_byte(0x5000), 0xFF);
+    assert_eq!(nes.read_byte(0x5800), 0xFF);
+    // Acknowledged by writing to the counter
+    nes.write_byte(0x5800, 0x80);
+    assert!(!nes.cartridge.mapper.irq());
+    // Disabled
+    nes.write_byte(0x5000, 0xFF);
+    nes.write_byte(0x5800, 0x7F);
+    assert!(!nes.cartridge.mapper.irq());
+}
+#[test]
+fn test_namco163_audio() {
+    let mut nes = namco163_nes();
+    assert_eq!(
+        nes.cartridge.mapper.expansion_audio(),
+        Some(ExpansionAudio::Namco163)
+    );
+    play_square(&mut nes, 1);
+    // Channel 7 is updated every 15 CPU cycles, so the wave has a period of 16 * 15 cycles
+    let samples = audio_samples!(nes, 16 * 15 * 10);
+    let max = samples.iter().fold(0.0f32, |a, b| a.max(*b));
+    let min = samples.iter().fold(0.0f32, |a, b| a.min(*b));
+    assert!(max > 0.0 && min < 0.0);
+    let crossings = samples
+        .windows(2)
+        .filter(|w| (w[0] < 0.0) != (w[1] < 0.0))
+        .count();
+    assert!((19..=20).contains(&crossings), "{} crossings", crossings);
+    // With 2 channels, channel 7 is updated half as often and only heard half of the time
+    play_square(&mut nes, 2);
+    let samples = audio_samples!(nes, 16 * 30 * 10);
+    assert_eq!(samples.iter().fold(0.0f32, |a, b| a.max(*b)), max / 2.0);
+    let crossings = samples
+        .windows(2)
+        .filter(|w| (w[0] < 0.0) != (w[1] < 0.0))
+        .count();
+    assert!((19..=20).contains(&crossings), "{} crossings", crossings);
+    // Disabled by 0xE000
+    nes.write_byte(0xE000, 0x40);
+    assert!(audio_samples!(nes, 1000).iter().all(|s| *s == 0.0));
+}
+#[test]
+fn test_namco163_sound_ram_savedata() {
+    let mut nes = namco163_nes();
+    nes.write_byte(0xF800, 0x40);
+    nes.write_byte(0x6000, 0x12);
+    write_sound_ram(&mut nes, 0x10, &[0x34, 0x56]);
+    // Auto increment when reading
+    nes.write_byte(0xF800, 0x90);
+    assert_eq!(nes.read_byte(0x4800), 0x34);
+    assert_eq!(nes.read_byte(0x4800), 0x56);
+    let savedata = nes.savedata_with_mapper_ram().unwrap();
+    assert_eq!(savedata.len(), 0x2080);
+    assert_eq!(savedata[0], 0x12);
+    assert_eq!(savedata[0x2010..0x2012], [0x34, 0x56]);
+    // Reload the savedata
+    let mut nes =
+        Nes::with_cartridge(Cartridge::from_ines(&namco163_rom(), Some(savedata)).unwrap());
+    assert_eq!(nes.read_byte(0x6000), 0x12);
+    nes.write_byte(0xF800, 0x10);
+    assert_eq!(nes.read_byte(0x4800), 0x34);
+    // Savedata with the wrong size
+    assert!(Cartridge::from_ines(&namco163_rom(), Some(vec![0; 0x2000])).is_err());
+}
+#[test]
+fn test_namco163_nes2_sound_ram_only() {
+    // NES 2.0 header with only the 128 bytes of sound RAM battery backed
+    let mut rom = namco163_rom();
+    rom[7] |= 0x08;
+    rom[10] = 0x10;
+    let mut nes = Nes::with_cartridge(Cartridge::from_ines(&rom, None).unwrap());
+    write_sound_ram(&mut nes, 0x00, &[0x78]);
+    let savedata = nes.savedata_with_mapper_ram().unwrap();
+    assert_eq!(savedata.len(), 0x80);
+    assert_eq!(savedata[0], 0x78);
+}
@@ -0,0 +1,156 @@
+use yane::core::{Cartridge, Nes, Nsf, NsfPlayer, Settings};
+
+// INIT stores the song number in $00 and starts a tone on pulse 1
+const INIT: &[u8] = &[
+    0x85, 0x00, // STA $00
+    0xA9, 0xBF, 0x8D, 0x00, 0x40, // LDA #$BF, STA $4000
+    0xA9, 0x08, 0x8D, 0x01, 0x40, // LDA #$08, STA $4001
+    0xA9, 0xFD, 0x8D, 0x02, 0x40, // LDA #$FD, STA $4002
+    0xA9, 0x00, 0x8D, 0x03, 0x40, // LDA #$00, STA $4003
+    0x60, // RTS
+];
+// PLAY increments $01
+const PLAY: &[u8] = &[0xE6, 0x01, 0x60];
+const PLAY_ADDR: u16 = 0x8020;
+
+// Program data loaded at $8000
+fn program() -> Vec<u8> {
+    let mut data = INIT.to_vec();
+    data.resize((PLAY_ADDR - 0x8000) as usize, 0);
+    data.extend_from_slice(PLAY);
+    data
+}
+
+fn nsf_bytes(bankswitch: [u8; 8], data: &[u8]) -> Vec<u8> {
+    let mut bytes = b"NESM\x1A\x01".to_vec();
+    // 3 songs, starting at song 2
+    bytes.extend_from_slice(&[3, 2]);
+    [0x8000u16, 0x8000, PLAY_ADDR]
+        .iter()
+        .for_each(|a| bytes.extend_from_slice(&a.to_le_bytes()));
+    [&b"Test Game"[..], b"Test Artist", b"2025 Test"]
+        .iter()
+        .for_each(|s| {
+            let mut field = s.to_vec();
+            field.resize(32, 0);
+            bytes.extend_from_slice(&field);
+        });
+    bytes.extend_from_slice(&16639u16.to_le_bytes());
+    bytes.extend_from_slice(&bankswitch);
+    bytes.resize(0x80, 0);
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+fn nsfe_chunk(bytes: &mut Vec<u8>, id: &[u8], data: &[u8]) {
+    bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(id);
+    bytes.extend_from_slice(data);
+}
+
+#[test]
+fn test_nsf_header() {
+    let nsf = Nsf::from_bytes(&nsf_bytes([0; 8], &program())).unwrap();
+    assert_eq!(nsf.name, "Test Game");
+    assert_eq!(nsf.artist, "Test Artist");
+    assert_eq!(nsf.copyright, "2025 Test");
+    assert_eq!(nsf.num_songs, 3);
+    assert_eq!(nsf.starting_song, 1);
+    assert_eq!(nsf.play_addr, PLAY_ADDR);
+    assert_eq!(nsf.bankswitch, None);
+    assert_eq!(nsf.track_name(0), "Track 1");
+    assert!(Nsf::from_bytes(b"NES\x1A").is_err());
+}
+#[test]
+fn test_nsf_init_and_play() {
+    let nsf = Nsf::from_bytes(&nsf_bytes([0; 8], &program())).unwrap();
+    let cycles_per_play = nsf.cycles_per_play();
+    let settings = Settings::default();
+    let mut player = NsfPlayer::new(nsf);
+    player.start_song(2, &settings).unwrap();
+    assert_eq!(player.nes.mem[0], 2);
+    assert_eq!(player.nes.mem[1], 0);
+    let mut cycles = 0;
+    (0..60).for_each(|_| cycles += player.advance_frame(&settings).unwrap());
+    assert_eq!(player.nes.mem[1], 60);
+    assert!((cycles as f64 - 60.0 * cycles_per_play).abs() <= 1.0);
+    assert!(player.nes.apu.sample_queue().iter().any(|s| *s != 0.0));
+    // Starting a new song resets memory
+    player.start_song(0, &settings).unwrap();
+    assert_eq!(player.nes.mem[0], 0);
+    assert_eq!(player.nes.mem[1], 0);
+}
+#[test]
+fn test_nsf_bankswitching() {
+    // 3 4KB banks, the first containing the program
+    let mut data = program();
+    data.resize(0x3000, 0);
+    data[0x1000] = 0x11;
+    data[0x2000] = 0x22;
+    let nsf = Nsf::from_bytes(&nsf_bytes([0, 2, 0, 0, 0, 0, 0, 0], &data)).unwrap();
+    let settings = Settings::default();
+    let mut player = NsfPlayer::new(nsf);
+    player.start_song(0, &settings).unwrap();
+    assert_eq!(player.nes.read_byte(0x9000), 0x22);
+    player.nes.write_byte(0x5FF9, 1);
+    assert_eq!(player.nes.read_byte(0x9000), 0x11);
+    // Banks are reset when a song is started
+    player.start_song(0, &settings).unwrap();
+    assert_eq!(player.nes.read_byte(0x9000), 0x22);
+}
+#[test]
+fn test_nsfe() {
+    let mut bytes = b"NSFE".to_vec();
+    let mut info = Vec::new();
+    [0x8000u16, 0x8000, PLAY_ADDR]
+        .iter()
+        .for_each(|a| info.extend_from_slice(&a.to_le_bytes()));
+    info.extend_from_slice(&[0, 0, 2, 1]);
+    nsfe_chunk(&mut bytes, b"INFO", &info);
+    nsfe_chunk(&mut bytes, b"DATA", &program());
+    nsfe_chunk(&mut bytes, b"auth", b"Game\0Artist\0Copyright\0Ripper\0");
+    nsfe_chunk(&mut bytes, b"tlbl", b"Title Theme\0Ending\0");
+    let mut time = 90_000i32.to_le_bytes().to_vec();
+    time.extend_from_slice(&(-1i32).to_le_bytes());
+    nsfe_chunk(&mut bytes, b"time", &time);
+    // Optional chunks are skipped
+    nsfe_chunk(&mut bytes, b"text", b"Some text\0");
+    nsfe_chunk(&mut bytes, b"NEND", &[]);
+    let nsf = Nsf::from_bytes(&bytes).unwrap();
+    assert_eq!(nsf.name, "Game");
+    assert_eq!(nsf.artist, "Artist");
+    assert_eq!(nsf.num_songs, 2);
+    assert_eq!(nsf.starting_song, 1);
+    assert_eq!(nsf.track_name(0), "Title Theme");
+    assert_eq!(nsf.track_name(1), "Ending");
+    assert_eq!(nsf.track_length(0), Some(90_000));
+    assert_eq!(nsf.track_length(1), None);
+    let settings = Settings::default();
+    let mut player = NsfPlayer::new(nsf);
+    player.start_song(1, &settings).unwrap();
+    player.advance_frame(&settings).unwrap();
+    assert_eq!(player.nes.mem[0], 1);
+    assert_eq!(player.nes.mem[1], 1);
+}
+#[test]
+fn test_nsfe_unknown_required_chunk() {
+    let mut bytes = b"NSFE".to_vec();
+    nsfe_chunk(&mut bytes, b"INFO", &[0; 10]);
+    nsfe_chunk(&mut bytes, b"XTRA", &[0; 4]);
+    assert!(Nsf::from_bytes(&bytes).is_err());
+}
+#[test]
+fn test_mapper_31_last_bank() {
+    // 48KB of PRG ROM, which isn't a power of 2, where the first byte of each 4KB bank is its number
+    let mut rom = b"NES\x1A\x03\x00\xF0\x10".to_vec();
+    rom.resize(16, 0);
+    (0..12).for_each(|i| {
+        let mut bank = vec![0; 0x1000];
+        bank[0] = i;
+        rom.extend_from_slice(&bank);
+    });
+    let mut nes = Nes::with_cartridge(Cartridge::from_ines(&rom, None).unwrap());
+    assert_eq!(nes.read_byte(0xF000), 11);
+    nes.write_byte(0x5FFF, 0x04);
+    assert_eq!(nes.read_byte(0xF000), 4);
+}
@@ -1,4 +1,4 @@
-use yane::core::{Cartridge, Nes};
+use yane::core::{Cartridge, Nes, Settings};
 mod common;
 
 #[test]
@@ -33,3 +33,25 @@ fn test_spr_overflow() {
 fn test_spr_overflow_details() {
     rom_test!("./test_roms/spr_overflow_details.nes");
 }
+#[test]
+fn test_frame_phase() {
+    let phases = |mask: u8| {
+        let mut nes = Nes::new();
+        let settings = Settings::default();
+        nes.write_byte(0x2001, mask);
+        (0..6)
+            .map(|_| {
+                nes.advance_frame(&settings).unwrap();
+                nes.ppu.frame_phase()
+            })
+            .collect::<Vec<u32>>()
+    };
+    // The colour subcarrier's phase repeats every 3 frames with rendering disabled
+    let off = phases(0x00);
+    assert_eq!(off[0..3], off[3..6]);
+    assert!(off[0] != off[1] && off[1] != off[2] && off[0] != off[2]);
+    // And every 2 frames with rendering enabled, since odd frames are a dot shorter
+    let on = phases(0x08);
+    assert_eq!(on[0..2], on[2..4]);
+    assert_ne!(on[0], on[1]);
+}
@@ -0,0 +1,23 @@
+use yane::core::{Nes, Settings};
+
+// Savestates made by an older version of the emulator, with NROM, MMC1, MMC3 and MMC2 cartridges
+const SAVESTATES: [(&[u8], u32); 4] = [
+    (include_bytes!("./savestates/nrom.state"), 0),
+    (include_bytes!("./savestates/sxrom.state"), 1),
+    (include_bytes!("./savestates/mmc3.state"), 4),
+    (include_bytes!("./savestates/pxrom.state"), 9),
+];
+
+#[test]
+fn test_older_savestates_load() {
+    let settings = Settings::default();
+    SAVESTATES.iter().for_each(|(savestate, mapper)| {
+        let mut nes = Nes::from_savestate(savestate).unwrap();
+        assert_eq!(nes.cartridge.mapper.mapper_num(), *mapper);
+        nes.advance_frame(&settings).unwrap();
+        // And can be saved and loaded again
+        let savestate = nes.to_savestate().unwrap();
+        let mut loaded = Nes::from_savestate(&savestate).unwrap();
+        loaded.advance_frame(&settings).unwrap();
+    });
+}
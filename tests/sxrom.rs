@@ -0,0 +1,182 @@
+mod common;
+use yane::core::{Cartridge, Nes};
+
+// An MMC1 cartridge with `prg_banks` 16KB banks of PRG ROM, where the first byte of each bank is the bank number,
+// and `chr_banks` 8KB banks of CHR ROM (or 8KB of CHR RAM if 0), where the first byte of each 4KB is its number.
+// The PRG RAM is battery backed, and if `ram_shift` is given, the file is NES 2.0 with that battery backed PRG RAM
+// shift count.
+fn sxrom_nes(mapper: u8, prg_banks: u8, chr_banks: u8, ram_shift: Option<u8>) -> Nes {
+    let mut rom = b"NES\x1A".to_vec();
+    rom.extend_from_slice(&[prg_banks, chr_banks, (mapper << 4) | 0x02, mapper & 0xF0]);
+    if let Some(ram_shift) = ram_shift {
+        rom[7] |= 0x08;
+        rom.extend_from_slice(&[0, 0, ram_shift << 4]);
+    }
+    rom.resize(16, 0);
+    (0..prg_banks).for_each(|i| {
+        let mut bank = vec![0; 0x4000];
+        bank[0] = i;
+        rom.extend_from_slice(&bank);
+    });
+    (0..(2 * chr_banks)).for_each(|i| {
+        let mut bank = vec![0; 0x1000];
+        bank[0] = i;
+        rom.extend_from_slice(&bank);
+    });
+    Nes::with_cartridge(Cartridge::from_ines(&rom, None).unwrap())
+}
+
+// Write a value to an MMC1 register through the serial port, one bit at a time
+fn write_register(nes: &mut Nes, addr: usize, value: u8) {
+    (0..5).for_each(|i| {
+        nes.write_byte(addr, value >> i);
+        // Writes in the same instruction are ignored
+        nes.cartridge.mapper.advance_cpu_cycles(2);
+    });
+}
+
+#[test]
+fn test_sxrom_banking() {
+    let mut nes = sxrom_nes(1, 8, 4, None);
+    // 32KB PRG mode and 8KB CHR mode
+    write_register(&mut nes, 0x8000, 0x00);
+    write_register(&mut nes, 0xE000, 0x05);
+    assert_eq!(nes.read_byte(0x8000), 4);
+    assert_eq!(nes.read_byte(0xC000), 5);
+    write_register(&mut nes, 0xA000, 0x05);
+    assert_eq!(read_ppu!(nes, 0x0000), 4);
+    assert_eq!(read_ppu!(nes, 0x1000), 5);
+    // Fix the first bank, 4KB CHR mode
+    write_register(&mut nes, 0x8000, 0x18);
+    assert_eq!(nes.read_byte(0x8000), 0);
+    assert_eq!(nes.read_byte(0xC000), 5);
+    write_register(&mut nes, 0xC000, 0x02);
+    assert_eq!(read_ppu!(nes, 0x0000), 5);
+    assert_eq!(read_ppu!(nes, 0x1000), 2);
+    // Fix the last bank
+    write_register(&mut nes, 0x8000, 0x0C);
+    assert_eq!(nes.read_byte(0x8000), 5);
+    assert_eq!(nes.read_byte(0xC000), 7);
+    // Savestate
+    let savestate = nes.to_savestate().unwrap();
+    let mut nes = Nes::from_savestate(&savestate).unwrap();
+    assert_eq!(nes.cartridge.mapper.mapper_num(), 1);
+    assert_eq!(nes.read_byte(0x8000), 5);
+}
+#[test]
+fn test_sxrom_consecutive_writes() {
+    let mut nes = sxrom_nes(1, 16, 0, None);
+    write_register(&mut nes, 0x8000, 0x0C);
+    // Both writes of a read-modify-write instruction, where the second is ignored
+    nes.write_byte(0xE000, 0x01);
+    nes.write_byte(0xE000, 0x00);
+    nes.cartridge.mapper.advance_cpu_cycles(6);
+    (1..5).for_each(|_| {
+        nes.write_byte(0xE000, 0x00);
+        nes.cartridge.mapper.advance_cpu_cycles(2);
+    });
+    assert_eq!(nes.read_byte(0x8000), 1);
+    // The reset write is also ignored
+    nes.write_byte(0xE000, 0x00);
+    nes.write_byte(0xE000, 0x80);
+    nes.cartridge.mapper.advance_cpu_cycles(6);
+    (1..5).for_each(|_| {
+        nes.write_byte(0xE000, 0x01);
+        nes.cartridge.mapper.advance_cpu_cycles(2);
+    });
+    assert_eq!(nes.read_byte(0x8000), 14);
+}
+#[test]
+fn test_surom() {
+    let mut nes = sxrom_nes(1, 32, 0, None);
+    write_register(&mut nes, 0x8000, 0x0C);
+    write_register(&mut nes, 0xE000, 0x03);
+    assert_eq!(nes.read_byte(0x8000), 3);
+    assert_eq!(nes.read_byte(0xC000), 15);
+    // CHR bit 4 selects the upper 256KB, including the fixed bank
+    write_register(&mut nes, 0xA000, 0x10);
+    assert_eq!(nes.read_byte(0x8000), 19);
+    assert_eq!(nes.read_byte(0xC000), 31);
+}
+#[test]
+fn test_sorom() {
+    // 16KB of PRG RAM
+    let mut nes = sxrom_nes(1, 16, 0, Some(0x08));
+    nes.write_byte(0x6000, 0x12);
+    write_register(&mut nes, 0xA000, 0x08);
+    assert_eq!(nes.read_byte(0x6000), 0x00);
+    nes.write_byte(0x6000, 0x34);
+    write_register(&mut nes, 0xA000, 0x00);
+    assert_eq!(nes.read_byte(0x6000), 0x12);
+    let savedata = nes.savedata().unwrap();
+    assert_eq!(savedata.len(), 0x4000);
+    assert_eq!(savedata[0x2000], 0x34);
+    // Detected using the PRG RAM size in iNES 1.0
+    let mut rom = b"NES\x1A".to_vec();
+    rom.extend_from_slice(&[16, 0, 0x12, 0x00, 2]);
+    rom.resize(16 + 16 * 0x4000, 0);
+    let mut nes = Nes::with_cartridge(Cartridge::from_ines(&rom, None).unwrap());
+    nes.write_byte(0x6000, 0x12);
+    write_register(&mut nes, 0xA000, 0x08);
+    assert_eq!(nes.read_byte(0x6000), 0x00);
+}
+#[test]
+fn test_sxrom_board() {
+    // 512KB of PRG ROM and 32KB of PRG RAM
+    let mut nes = sxrom_nes(1, 32, 0, Some(0x09));
+    write_register(&mut nes, 0x8000, 0x0C);
+    (0..4).for_each(|i| {
+        write_register(&mut nes, 0xA000, 0x10 | (i << 2));
+        nes.write_byte(0x6000, i + 1);
+    });
+    (0..4).for_each(|i| {
+        write_register(&mut nes, 0xA000, i << 2);
+        assert_eq!(nes.read_byte(0x6000), i + 1);
+    });
+    assert_eq!(nes.read_byte(0xC000), 15);
+    write_register(&mut nes, 0xA000, 0x10);
+    assert_eq!(nes.read_byte(0xC000), 31);
+}
+#[test]
+fn test_snrom_wram_disable() {
+    let mut nes = sxrom_nes(1, 16, 0, None);
+    nes.write_byte(0x6000, 0x12);
+    write_register(&mut nes, 0xA000, 0x10);
+    assert_eq!(nes.read_byte(0x6000), 0x00);
+    nes.write_byte(0x6000, 0x34);
+    write_register(&mut nes, 0xA000, 0x00);
+    assert_eq!(nes.read_byte(0x6000), 0x12);
+    // Doesn't disable PRG RAM on boards with CHR ROM
+    let mut nes = sxrom_nes(1, 16, 4, None);
+    nes.write_byte(0x6000, 0x12);
+    write_register(&mut nes, 0xA000, 0x10);
+    assert_eq!(nes.read_byte(0x6000), 0x12);
+}
+#[test]
+fn test_mmc1_prg_ram_disable() {
+    // MMC1B
+    let mut nes = sxrom_nes(1, 16, 4, None);
+    nes.write_byte(0x6000, 0x12);
+    write_register(&mut nes, 0xE000, 0x10);
+    assert_eq!(nes.read_byte(0x6000), 0x00);
+    nes.write_byte(0x6000, 0x34);
+    write_register(&mut nes, 0xE000, 0x00);
+    assert_eq!(nes.read_byte(0x6000), 0x12);
+    // MMC1A is always enabled
+    let mut nes = sxrom_nes(155, 16, 4, None);
+    nes.write_byte(0x6000, 0x12);
+    write_register(&mut nes, 0xE000, 0x10);
+    assert_eq!(nes.read_byte(0x6000), 0x12);
+    let savestate = nes.to_savestate().unwrap();
+    let nes = Nes::from_savestate(&savestate).unwrap();
+    assert_eq!(nes.cartridge.mapper.mapper_num(), 155);
+    // So is NES 2.0 mapper 1 submapper 3
+    let mut rom = b"NES\x1A".to_vec();
+    rom.extend_from_slice(&[16, 4, 0x12, 0x08, 0x30, 0, 0x70]);
+    rom.resize(16 + 16 * 0x4000 + 4 * 0x2000, 0);
+    let mut nes = Nes::with_cartridge(Cartridge::from_ines(&rom, None).unwrap());
+    nes.write_byte(0x6000, 0x12);
+    write_register(&mut nes, 0xE000, 0x10);
+    assert_eq!(nes.read_byte(0x6000), 0x12);
+    assert_eq!(nes.cartridge.mapper.mapper_num(), 1);
+}
@@ -0,0 +1,143 @@
+mod common;
+use yane::core::{Cartridge, Nes};
+
+// An UNROM 512 cartridge with 512KB of PRG ROM, where the first byte of each 16KB bank is the bank number and
+// the rest is 0xFF, and 32KB of CHR RAM.
+// `flags` is added to byte 6 of the header.
+fn unrom512_rom(flags: u8) -> Vec<u8> {
+    let mut rom = b"NES\x1A".to_vec();
+    rom.extend_from_slice(&[32, 0, 0xE0 | flags, 0x10]);
+    rom.resize(16, 0);
+    (0..32).for_each(|i| {
+        let mut bank = vec![0xFF; 0x4000];
+        bank[0] = i;
+        rom.extend_from_slice(&bank);
+    });
+    rom
+}
+
+fn unrom512_nes(flags: u8) -> Nes {
+    Nes::with_cartridge(Cartridge::from_ines(&unrom512_rom(flags), None).unwrap())
+}
+
+// Write the unlock sequence and then a command to the flash, leaving `bank` selected
+fn flash_command(nes: &mut Nes, command: u8, bank: u8) {
+    nes.write_byte(0xC000, 0x01);
+    nes.write_byte(0x9555, 0xAA);
+    nes.write_byte(0xC000, 0x00);
+    nes.write_byte(0xAAAA, 0x55);
+    nes.write_byte(0xC000, 0x01);
+    nes.write_byte(0x9555, command);
+    nes.write_byte(0xC000, bank);
+}
+
+fn program_byte(nes: &mut Nes, bank: u8, addr: usize, value: u8) {
+    flash_command(nes, 0xA0, bank);
+    nes.write_byte(addr, value);
+}
+
+#[test]
+fn test_unrom512_banking() {
+    let mut nes = unrom512_nes(0x00);
+    nes.write_byte(0x8001, 0x05 | (2 << 5));
+    assert_eq!(nes.read_byte(0x8000), 5);
+    assert_eq!(nes.read_byte(0xC000), 31);
+    write_ppu!(nes, 0x0000, 0x11);
+    nes.write_byte(0x8001, 0x05);
+    assert_eq!(read_ppu!(nes, 0x0000), 0x00);
+    nes.write_byte(0x8001, 0x05 | (2 << 5));
+    assert_eq!(read_ppu!(nes, 0x0000), 0x11);
+    // Bus conflict with the 5 at 0x8000
+    nes.write_byte(0x8000, 0x03);
+    assert_eq!(nes.read_byte(0x8000), 1);
+    // Not flashable, so there is no savedata
+    assert!(nes.savedata_with_mapper_ram().is_none());
+}
+#[test]
+fn test_unrom512_mirroring() {
+    // Horizontal
+    let mut nes = unrom512_nes(0x00);
+    write_ppu!(nes, 0x2000, 0x42);
+    assert_eq!(read_ppu!(nes, 0x2400), 0x42);
+    assert_eq!(read_ppu!(nes, 0x2800), 0x00);
+    // Vertical
+    let mut nes = unrom512_nes(0x01);
+    write_ppu!(nes, 0x2000, 0x42);
+    assert_eq!(read_ppu!(nes, 0x2400), 0x00);
+    assert_eq!(read_ppu!(nes, 0x2800), 0x42);
+    // One screen, selected by the register
+    let mut nes = unrom512_nes(0x08);
+    write_ppu!(nes, 0x2000, 0x42);
+    assert_eq!(read_ppu!(nes, 0x2C00), 0x42);
+    nes.write_byte(0x8001, 0x80);
+    assert_eq!(read_ppu!(nes, 0x2C00), 0x00);
+    // Four screen, using the last 8KB of CHR RAM
+    let mut nes = unrom512_nes(0x09);
+    (0..4).for_each(|i| write_ppu!(nes, 0x2000 + 0x400 * i, i as u8 + 1));
+    (0..4).for_each(|i| assert_eq!(read_ppu!(nes, 0x2000 + 0x400 * i), i as u8 + 1));
+    nes.write_byte(0x8001, 0x60);
+    assert_eq!(read_ppu!(nes, 0x0400), 2);
+}
+#[test]
+fn test_unrom512_flash_software_id() {
+    let mut nes = unrom512_nes(0x02);
+    flash_command(&mut nes, 0x90, 0x00);
+    assert_eq!(nes.read_byte(0x8000), 0xBF);
+    assert_eq!(nes.read_byte(0x8001), 0xB7);
+    nes.write_byte(0x8000, 0xF0);
+    assert_eq!(nes.read_byte(0x8000), 0);
+    assert_eq!(nes.read_byte(0x8001), 0xFF);
+}
+#[test]
+fn test_unrom512_flash_program_and_erase() {
+    let mut nes = unrom512_nes(0x02);
+    // Writes to 0x8000-0xBFFF don't change the bank
+    nes.write_byte(0xC000, 0x03);
+    nes.write_byte(0x8000, 0x07);
+    assert_eq!(nes.read_byte(0x8000), 3);
+    program_byte(&mut nes, 0x03, 0x8010, 0x12);
+    assert_eq!(nes.read_byte(0x8010), 0x12);
+    // Only clears bits
+    program_byte(&mut nes, 0x03, 0x8010, 0x30);
+    assert_eq!(nes.read_byte(0x8010), 0x10);
+    // Program the fixed bank, through 0x8000-0xBFFF since 0xC000-0xFFFF is the register
+    program_byte(&mut nes, 31, 0xBFF0, 0x34);
+    assert_eq!(nes.read_byte(0xFFF0), 0x34);
+    nes.write_byte(0xC000, 0x03);
+    // Erase the sector at 0x8000-0x8FFF in bank 3
+    flash_command(&mut nes, 0x80, 0x01);
+    nes.write_byte(0x9555, 0xAA);
+    nes.write_byte(0xC000, 0x00);
+    nes.write_byte(0xAAAA, 0x55);
+    nes.write_byte(0xC000, 0x03);
+    nes.write_byte(0x8000, 0x30);
+    assert_eq!(nes.read_byte(0x8000), 0xFF);
+    assert_eq!(nes.read_byte(0x8010), 0xFF);
+    assert_eq!(nes.read_byte(0xFFF0), 0x34);
+    nes.write_byte(0xC000, 0x04);
+    assert_eq!(nes.read_byte(0x8000), 4);
+    // Erase the whole chip
+    flash_command(&mut nes, 0x80, 0x01);
+    nes.write_byte(0x9555, 0xAA);
+    nes.write_byte(0xC000, 0x00);
+    nes.write_byte(0xAAAA, 0x55);
+    nes.write_byte(0xC000, 0x01);
+    nes.write_byte(0x9555, 0x10);
+    nes.write_byte(0xC000, 0x04);
+    assert_eq!(nes.read_byte(0x8000), 0xFF);
+    assert_eq!(nes.read_byte(0xFFF0), 0xFF);
+}
+#[test]
+fn test_unrom512_flash_savedata() {
+    let mut nes = unrom512_nes(0x02);
+    program_byte(&mut nes, 0x05, 0x8123, 0x45);
+    let savedata = nes.savedata_with_mapper_ram().unwrap();
+    assert_eq!(savedata.len(), 0x80000);
+    assert_eq!(savedata[5 * 0x4000 + 0x123], 0x45);
+    // Reload the savedata
+    let mut nes =
+        Nes::with_cartridge(Cartridge::from_ines(&unrom512_rom(0x02), Some(savedata)).unwrap());
+    nes.write_byte(0xC000, 0x05);
+    assert_eq!(nes.read_byte(0x8123), 0x45);
+    assert_eq!(nes.read_byte(0x8000), 5);
+}
@@ -0,0 +1,156 @@
+mod common;
+use yane::core::{Cartridge, Nes};
+
+// A VRC2/VRC4 cartridge with 128KB of PRG ROM, where each 8KB bank starts with its bank number,
+// and 256KB of CHR ROM, where each 1KB bank starts with its bank number.
+// Uses an NES 2.0 header if `submapper` is given, with `prg_ram_shift` giving the size of PRG RAM.
+fn vrc4_nes(mapper: u8, submapper: Option<u8>, prg_ram_shift: u8) -> Nes {
+    let mut rom = b"NES\x1A".to_vec();
+    rom.extend_from_slice(&[8, 32, (mapper & 0x0F) << 4, mapper & 0xF0]);
+    if let Some(submapper) = submapper {
+        rom[7] |= 0x08;
+        rom.extend_from_slice(&[submapper << 4, 0, prg_ram_shift]);
+    }
+    rom.resize(16, 0);
+    (0..16).for_each(|i| {
+        let mut bank = vec![0; 0x2000];
+        bank[0] = i;
+        rom.extend_from_slice(&bank);
+    });
+    let last_bank = rom.len() - 0x2000;
+    // JMP $E010
+    rom[last_bank + 0x10..last_bank + 0x13].copy_from_slice(&[0x4C, 0x10, 0xE0]);
+    // Reset vector
+    rom[last_bank + 0x1FFC..last_bank + 0x1FFE].copy_from_slice(&[0x10, 0xE0]);
+    (0..256).for_each(|i| {
+        let mut bank = vec![0; 0x400];
+        bank[0] = i as u8;
+        rom.extend_from_slice(&bank);
+    });
+    Nes::with_cartridge(Cartridge::from_ines(&rom, None).unwrap())
+}
+
+#[test]
+fn test_vrc4_submapper_wiring() {
+    // Mapper, submapper, and the CPU address lines of the chip's A0 and A1
+    [
+        (21, 1, 0x02, 0x04),
+        (21, 2, 0x40, 0x80),
+        (22, 0, 0x02, 0x01),
+        (23, 1, 0x01, 0x02),
+        (23, 2, 0x04, 0x08),
+        (23, 3, 0x01, 0x02),
+        (25, 1, 0x02, 0x01),
+        (25, 2, 0x08, 0x04),
+        (25, 3, 0x02, 0x01),
+    ]
+    .into_iter()
+    .for_each(|(mapper, submapper, a0, a1)| {
+        let mut nes = vrc4_nes(mapper, Some(submapper), 7);
+        assert_eq!(nes.cartridge.mapper.mapper_num(), mapper as u32);
+        // Second CHR bank is 0x13
+        nes.write_byte(0xB000 | a1, 0x06);
+        nes.write_byte(0xB000 | a1 | a0, 0x02);
+        let expected = if mapper == 22 { 0x13 } else { 0x26 };
+        assert_eq!(read_ppu!(nes, 0x0400), expected);
+        // A0 alone addresses the upper bits of the first bank instead
+        nes.write_byte(0xB000 | a0, 0x01);
+        assert_eq!(read_ppu!(nes, 0x0400), expected);
+    });
+}
+#[test]
+fn test_vrc4_ines_heuristic() {
+    // Both the VRC4a and VRC4c wirings work without a submapper
+    let mut nes = vrc4_nes(21, None, 0);
+    nes.write_byte(0xB004, 0x05);
+    assert_eq!(read_ppu!(nes, 0x0400), 0x05);
+    nes.write_byte(0xB080, 0x07);
+    assert_eq!(read_ppu!(nes, 0x0400), 0x07);
+    // As well as the VRC4 features
+    nes.write_byte(0x9004, 0x02);
+    assert_eq!(nes.read_byte(0xC000), 0);
+    nes.write_byte(0x9080, 0x00);
+    assert_eq!(nes.read_byte(0xC000), 14);
+}
+#[test]
+fn test_vrc4_prg_banking() {
+    let mut nes = vrc4_nes(23, Some(1), 7);
+    nes.write_byte(0x8000, 3);
+    nes.write_byte(0xA000, 5);
+    assert_eq!(nes.read_byte(0x8000), 3);
+    assert_eq!(nes.read_byte(0xA000), 5);
+    assert_eq!(nes.read_byte(0xC000), 14);
+    assert_eq!(nes.read_byte(0xE000), 15);
+    // Swap mode
+    nes.write_byte(0x9002, 0x02);
+    assert_eq!(nes.read_byte(0x8000), 14);
+    assert_eq!(nes.read_byte(0xA000), 5);
+    assert_eq!(nes.read_byte(0xC000), 3);
+    assert_eq!(nes.read_byte(0xE000), 15);
+    // PRG RAM
+    nes.write_byte(0x6000, 0x12);
+    assert_eq!(nes.read_byte(0x6000), 0x12);
+}
+#[test]
+fn test_vrc2_prg_banking_and_mirroring() {
+    let mut nes = vrc4_nes(23, Some(3), 0);
+    // There is no swap mode, only mirroring
+    nes.write_byte(0x9002, 0x03);
+    nes.write_byte(0x8000, 3);
+    assert_eq!(nes.read_byte(0x8000), 3);
+    assert_eq!(nes.read_byte(0xC000), 14);
+    // Horizontal mirroring
+    nes.read_byte(0x2002);
+    nes.write_byte(0x2006, 0x20);
+    nes.write_byte(0x2006, 0x00);
+    nes.write_byte(0x2007, 0x42);
+    assert_eq!(read_ppu!(nes, 0x2400), 0x42);
+    assert_eq!(read_ppu!(nes, 0x2800), 0x00);
+}
+#[test]
+fn test_vrc2_microwire_latch() {
+    // Without PRG RAM, 0x6000 is a 1 bit latch
+    let mut nes = vrc4_nes(23, Some(3), 0);
+    nes.write_byte(0x6000, 0xFF);
+    assert_eq!(nes.read_byte(0x6000) & 0x01, 0x01);
+    assert_eq!(nes.read_byte(0x6FFF) & 0x01, 0x01);
+    nes.write_byte(0x6000, 0xFE);
+    assert_eq!(nes.read_byte(0x6000) & 0x01, 0x00);
+    // With PRG RAM, it is just RAM
+    let mut nes = vrc4_nes(23, Some(3), 7);
+    nes.write_byte(0x6000, 0xFE);
+    assert_eq!(nes.read_byte(0x6000), 0xFE);
+}
+#[test]
+fn test_vrc4_irq() {
+    let mut nes = vrc4_nes(25, Some(1), 7);
+    // Latch is written in 2 halves, 0xF0
+    nes.write_byte(0xF000, 0x00);
+    nes.write_byte(0xF002, 0x0F);
+    // Cycle mode
+    nes.write_byte(0xF001, 0x06);
+    nes.cartridge.mapper.advance_cpu_cycles(15);
+    assert!(!nes.cartridge.mapper.irq());
+    nes.cartridge.mapper.advance_cpu_cycles(1);
+    assert!(nes.cartridge.mapper.irq());
+    nes.write_byte(0xF003, 0x00);
+    assert!(!nes.cartridge.mapper.irq());
+    // The VRC2 has no IRQ
+    let mut nes = vrc4_nes(25, Some(3), 7);
+    nes.write_byte(0xF000, 0x0F);
+    nes.write_byte(0xF002, 0x0F);
+    nes.write_byte(0xF001, 0x06);
+    nes.cartridge.mapper.advance_cpu_cycles(1000);
+    assert!(!nes.cartridge.mapper.irq());
+}
+#[test]
+fn test_vrc4_one_screen_mirroring() {
+    let mut nes = vrc4_nes(21, Some(1), 7);
+    nes.write_byte(0x9000, 0x03);
+    nes.read_byte(0x2002);
+    nes.write_byte(0x2006, 0x20);
+    nes.write_byte(0x2006, 0x00);
+    nes.write_byte(0x2007, 0x42);
+    assert_eq!(nes.ppu.nametable_ram[0x400], 0x42);
+    assert_eq!(read_ppu!(nes, 0x2C00), 0x42);
+}
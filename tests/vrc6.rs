@@ -0,0 +1,167 @@
+mod common;
+use yane::core::{Cartridge, ExpansionAudio, Nes, Settings};
+
+// A VRC6 cartridge with 256KB of PRG ROM, where each 8KB bank starts with its bank number,
+// and 128KB of CHR ROM, where each 1KB bank starts with its bank number.
+// The last bank contains an infinite loop at 0xE010.
+fn vrc6_nes(mapper: u8) -> Nes {
+    let mut rom = b"NES\x1A".to_vec();
+    rom.extend_from_slice(&[16, 16, (mapper & 0x0F) << 4, mapper & 0xF0]);
+    rom.resize(16, 0);
+    (0..32).for_each(|i| {
+        let mut bank = vec![0; 0x2000];
+        bank[0] = i;
+        rom.extend_from_slice(&bank);
+    });
+    let last_bank = rom.len() - 0x2000;
+    // JMP $E010
+    rom[last_bank + 0x10..last_bank + 0x13].copy_from_slice(&[0x4C, 0x10, 0xE0]);
+    // Reset vector
+    rom[last_bank + 0x1FFC..last_bank + 0x1FFE].copy_from_slice(&[0x10, 0xE0]);
+    (0..128).for_each(|i| {
+        let mut bank = vec![0; 0x400];
+        bank[0] = i;
+        rom.extend_from_slice(&bank);
+    });
+    Nes::with_cartridge(Cartridge::from_ines(&rom, None).unwrap())
+}
+
+#[test]
+fn test_vrc6_prg_banking() {
+    [24, 26].into_iter().for_each(|mapper| {
+        let mut nes = vrc6_nes(mapper);
+        assert_eq!(nes.cartridge.mapper.mapper_num(), mapper as u32);
+        nes.write_byte(0x8000, 3);
+        assert_eq!(nes.read_byte(0x8000), 6);
+        assert_eq!(nes.read_byte(0xA000), 7);
+        nes.write_byte(0xC000, 9);
+        assert_eq!(nes.read_byte(0xC000), 9);
+        // Last bank is fixed
+        assert_eq!(nes.read_byte(0xE000), 31);
+        // PRG RAM is disabled by default
+        nes.write_byte(0x6000, 0x12);
+        assert_eq!(nes.read_byte(0x6000), 0);
+        nes.write_byte(0xB003, 0x80);
+        nes.write_byte(0x6000, 0x12);
+        assert_eq!(nes.read_byte(0x6000), 0x12);
+    });
+}
+#[test]
+fn test_vrc6_chr_banking() {
+    let mut nes = vrc6_nes(24);
+    (0..4).for_each(|i| {
+        nes.write_byte(0xD000 + i, 10 + i as u8);
+        nes.write_byte(0xE000 + i, 20 + i as u8);
+    });
+    (0..4).for_each(|i| {
+        assert_eq!(read_ppu!(nes, 0x400 * i), 10 + i as u8);
+        assert_eq!(read_ppu!(nes, 0x1000 + 0x400 * i), 20 + i as u8);
+    });
+    // 2KB banks, with CHR A10 taken from the address
+    nes.write_byte(0xB003, 0x21);
+    assert_eq!(read_ppu!(nes, 0x0000), 10);
+    assert_eq!(read_ppu!(nes, 0x0400), 11);
+    assert_eq!(read_ppu!(nes, 0x1800), 12);
+    assert_eq!(read_ppu!(nes, 0x1C00), 13);
+    // With CHR A10 taken from the register
+    nes.write_byte(0xB003, 0x01);
+    assert_eq!(read_ppu!(nes, 0x0400), 10);
+    // Mixed 1KB and 2KB banks
+    nes.write_byte(0xB003, 0x22);
+    assert_eq!(read_ppu!(nes, 0x0C00), 13);
+    assert_eq!(read_ppu!(nes, 0x1400), 21);
+}
+#[test]
+fn test_vrc6b_swapped_lines() {
+    let mut a = vrc6_nes(24);
+    let mut b = vrc6_nes(26);
+    a.write_byte(0xD001, 5);
+    b.write_byte(0xD001, 5);
+    assert_eq!(read_ppu!(a, 0x0400), 5);
+    assert_eq!(read_ppu!(b, 0x0800), 5);
+    // 0xB003 is at the same address on both
+    a.write_byte(0xB003, 0x80);
+    b.write_byte(0xB003, 0x80);
+    b.write_byte(0x6000, 0x34);
+    assert_eq!(b.read_byte(0x6000), 0x34);
+}
+#[test]
+fn test_vrc6_nametables() {
+    let mut nes = vrc6_nes(24);
+    // Horizontal mirroring
+    nes.write_byte(0xB003, 0x04);
+    write_ppu!(nes, 0x2000, 0x42);
+    assert_eq!(read_ppu!(nes, 0x2400), 0x42);
+    assert_ne!(read_ppu!(nes, 0x2800), 0x42);
+    // One screen
+    nes.write_byte(0xB003, 0x08);
+    assert_eq!(read_ppu!(nes, 0x2C00), 0x42);
+    // Nametables in CHR ROM, using R6 and R7 with vertical mirroring
+    nes.write_byte(0xE002, 50);
+    nes.write_byte(0xE003, 51);
+    nes.write_byte(0xB003, 0x10);
+    assert_eq!(read_ppu!(nes, 0x2000), 50);
+    assert_eq!(read_ppu!(nes, 0x2400), 51);
+    assert_eq!(read_ppu!(nes, 0x2800), 50);
+    // Which can't be written to
+    write_ppu!(nes, 0x2000, 0x99);
+    assert_eq!(read_ppu!(nes, 0x2000), 50);
+}
+#[test]
+fn test_vrc6_irq() {
+    let mut nes = vrc6_nes(24);
+    // CPU cycle mode
+    nes.write_byte(0xF000, 0xF0);
+    nes.write_byte(0xF001, 0x06);
+    nes.cartridge.mapper.advance_cpu_cycles(15);
+    assert!(!nes.cartridge.mapper.irq());
+    nes.cartridge.mapper.advance_cpu_cycles(1);
+    assert!(nes.cartridge.mapper.irq());
+    // Acknowledging the IRQ disables it since bit 0 of the control was clear
+    nes.write_byte(0xF002, 0);
+    assert!(!nes.cartridge.mapper.irq());
+    nes.cartridge.mapper.advance_cpu_cycles(1000);
+    assert!(!nes.cartridge.mapper.irq());
+    // Scanline mode, clocked every 113 2/3 CPU cycles
+    nes.write_byte(0xF000, 0xFE);
+    nes.write_byte(0xF001, 0x03);
+    nes.cartridge.mapper.advance_cpu_cycles(227);
+    assert!(!nes.cartridge.mapper.irq());
+    nes.cartridge.mapper.advance_cpu_cycles(1);
+    assert!(nes.cartridge.mapper.irq());
+    // Stays enabled after being acknowledged
+    nes.write_byte(0xF002, 0);
+    assert!(!nes.cartridge.mapper.irq());
+    nes.cartridge.mapper.advance_cpu_cycles(228);
+    assert!(nes.cartridge.mapper.irq());
+}
+#[test]
+fn test_vrc6_audio() {
+    let mut nes = vrc6_nes(24);
+    let settings = Settings::default();
+    assert_eq!(
+        nes.cartridge.mapper.expansion_audio(),
+        Some(ExpansionAudio::Vrc6)
+    );
+    nes.advance_frame(&settings).unwrap();
+    assert!(nes.apu.sample_queue().iter().all(|s| *s == 0.0));
+    // Pulse ignoring its duty cycle outputs a constant level
+    nes.write_byte(0x9000, 0x8F);
+    nes.write_byte(0x9002, 0x80);
+    nes.advance_frame(&settings).unwrap();
+    assert!(nes.apu.sample_queue().iter().all(|s| *s > 0.0));
+    // Sawtooth
+    nes.write_byte(0x9002, 0x00);
+    nes.write_byte(0xB000, 0x20);
+    nes.write_byte(0xB001, 0x40);
+    nes.write_byte(0xB002, 0x80);
+    nes.advance_frame(&settings).unwrap();
+    let samples = nes.apu.sample_queue();
+    assert!(samples.iter().any(|s| *s > 0.0));
+    assert!(samples.contains(&0.0));
+    // Halting stops all channels
+    nes.write_byte(0x9003, 0x01);
+    nes.advance_frame(&settings).unwrap();
+    let samples = nes.apu.sample_queue();
+    assert!(samples.iter().all(|s| *s == samples[0]));
+}
@@ -0,0 +1,336 @@
+mod common;
+use yane::core::{Cartridge, ExpansionAudio, Nes, Settings};
+
+// The synthesizer makes a sample every 36 CPU cycles
+const CYCLES_PER_SAMPLE: usize = 36;
+
+// A VRC7 cartridge with 128KB of PRG ROM, where each 8KB bank starts with its bank number,
+// and 128KB of CHR ROM, where each 1KB bank starts with its bank number.
+// Uses an NES 2.0 header if `submapper` is given.
+fn vrc7_nes(submapper: Option<u8>) -> Nes {
+    let mut rom = b"NES\x1A".to_vec();
+    rom.extend_from_slice(&[8, 16, 0x50, 0x50]);
+    if let Some(submapper) = submapper {
+        rom[7] |= 0x08;
+        rom.extend_from_slice(&[submapper << 4, 0, 0x07]);
+    }
+    rom.resize(16, 0);
+    (0..16).for_each(|i| {
+        let mut bank = vec![0; 0x2000];
+        bank[0] = i;
+        rom.extend_from_slice(&bank);
+    });
+    let last_bank = rom.len() - 0x2000;
+    // JMP $E010
+    rom[last_bank + 0x10..last_bank + 0x13].copy_from_slice(&[0x4C, 0x10, 0xE0]);
+    // Reset vector
+    rom[last_bank + 0x1FFC..last_bank + 0x1FFE].copy_from_slice(&[0x10, 0xE0]);
+    (0..128).for_each(|i| {
+        let mut bank = vec![0; 0x400];
+        bank[0] = i;
+        rom.extend_from_slice(&bank);
+    });
+    Nes::with_cartridge(Cartridge::from_ines(&rom, None).unwrap())
+}
+
+fn write_audio(nes: &mut Nes, reg: u8, value: u8) {
+    nes.write_byte(0x9010, reg);
+    nes.write_byte(0x9030, value);
+}
+
+// Set the custom instrument to a pure sine wave, which is released quickly
+fn set_sine_instrument(nes: &mut Nes) {
+    [0x21, 0x21, 0x3F, 0x00, 0xF0, 0xF0, 0x00, 0x0F]
+        .into_iter()
+        .enumerate()
+        .for_each(|(i, v)| write_audio(nes, i as u8, v));
+}
+
+#[test]
+fn test_vrc7_banking() {
+    [Some(2), None].into_iter().for_each(|submapper| {
+        let mut nes = vrc7_nes(submapper);
+        nes.write_byte(0x8000, 3);
+        nes.write_byte(0x8010, 4);
+        nes.write_byte(0x9000, 5);
+        assert_eq!(nes.read_byte(0x8000), 3);
+        assert_eq!(nes.read_byte(0xA000), 4);
+        assert_eq!(nes.read_byte(0xC000), 5);
+        assert_eq!(nes.read_byte(0xE000), 15);
+        (0..8).for_each(|i| {
+            nes.write_byte(0xA000 + 0x1000 * (i / 2) + 0x10 * (i % 2), 20 + i as u8);
+        });
+        (0..8).for_each(|i| assert_eq!(read_ppu!(nes, 0x400 * i), 20 + i as u8));
+    });
+    // The VRC7b uses A3
+    let mut nes = vrc7_nes(Some(1));
+    nes.write_byte(0x8008, 6);
+    assert_eq!(nes.read_byte(0xA000), 6);
+}
+#[test]
+fn test_vrc7_prg_ram_and_mirroring() {
+    let mut nes = vrc7_nes(Some(2));
+    nes.write_byte(0x6000, 0x12);
+    assert_eq!(nes.read_byte(0x6000), 0);
+    // Enable PRG RAM and use horizontal mirroring
+    nes.write_byte(0xE000, 0x81);
+    nes.write_byte(0x6000, 0x12);
+    assert_eq!(nes.read_byte(0x6000), 0x12);
+    nes.read_byte(0x2002);
+    nes.write_byte(0x2006, 0x20);
+    nes.write_byte(0x2006, 0x00);
+    nes.write_byte(0x2007, 0x42);
+    assert_eq!(read_ppu!(nes, 0x2400), 0x42);
+    assert_eq!(read_ppu!(nes, 0x2800), 0x00);
+}
+#[test]
+fn test_vrc7_irq() {
+    let mut nes = vrc7_nes(Some(2));
+    nes.write_byte(0xE010, 0xF0);
+    nes.write_byte(0xF000, 0x07);
+    nes.cartridge.mapper.advance_cpu_cycles(15);
+    assert!(!nes.cartridge.mapper.irq());
+    nes.cartridge.mapper.advance_cpu_cycles(1);
+    assert!(nes.cartridge.mapper.irq());
+    nes.write_byte(0xF010, 0x00);
+    assert!(!nes.cartridge.mapper.irq());
+}
+#[test]
+fn test_vrc7_audio_frequency() {
+    let mut nes = vrc7_nes(Some(2));
+    assert_eq!(
+        nes.cartridge.mapper.expansion_audio(),
+        Some(ExpansionAudio::Vrc7)
+    );
+    set_sine_instrument(&mut nes);
+    // A440 is frequency 0x122 in block 4
+    write_audio(&mut nes, 0x30, 0x00);
+    write_audio(&mut nes, 0x10, 0x22);
+    write_audio(&mut nes, 0x20, 0x19);
+    // One second at the synthesizer's sample rate
+    let samples = audio_samples!(nes, 49716, CYCLES_PER_SAMPLE);
+    let max = samples.iter().fold(0.0f32, |a, b| a.max(*b));
+    assert!(max > 0.1);
+    let crossings = samples
+        .windows(2)
+        .filter(|w| (w[0] < 0.0) != (w[1] < 0.0))
+        .count();
+    assert!(
+        (crossings as i32 - 880).abs() < 10,
+        "{} crossings",
+        crossings
+    );
+}
+#[test]
+fn test_vrc7_audio_envelope() {
+    let mut nes = vrc7_nes(Some(2));
+    set_sine_instrument(&mut nes);
+    write_audio(&mut nes, 0x30, 0x00);
+    write_audio(&mut nes, 0x10, 0x22);
+    write_audio(&mut nes, 0x20, 0x19);
+    let loud = audio_samples!(nes, 1000, CYCLES_PER_SAMPLE)
+        .iter()
+        .fold(0.0f32, |a, b| a.max(b.abs()));
+    // Lower volume
+    write_audio(&mut nes, 0x30, 0x04);
+    let quiet = audio_samples!(nes, 1000, CYCLES_PER_SAMPLE)
+        .iter()
+        .fold(0.0f32, |a, b| a.max(b.abs()));
+    // 4 steps of 3dB is a quarter of the amplitude
+    assert!((quiet / loud - 0.25).abs() < 0.02, "{} {}", loud, quiet);
+    // Key off and the carrier is released
+    write_audio(&mut nes, 0x20, 0x09);
+    audio_samples!(nes, 200, CYCLES_PER_SAMPLE);
+    assert!(audio_samples!(nes, 1000, CYCLES_PER_SAMPLE)
+        .iter()
+        .all(|s| *s == 0.0));
+}
+#[test]
+fn test_vrc7_builtin_instruments() {
+    let mut nes = vrc7_nes(Some(2));
+    let settings = Settings::default();
+    (1..16).for_each(|instrument| {
+        write_audio(&mut nes, 0x30, instrument << 4);
+        write_audio(&mut nes, 0x10, 0x22);
+        write_audio(&mut nes, 0x20, 0x19);
+        assert!(
+            audio_samples!(nes, 2000, CYCLES_PER_SAMPLE)
+                .iter()
+                .any(|s| *s != 0.0),
+            "Instrument {} is silent",
+            instrument
+        );
+        write_audio(&mut nes, 0x20, 0x00);
+    });
+    // Mixed with the APU
+    write_audio(&mut nes, 0x20, 0x19);
+    nes.advance_frame(&settings).unwrap();
+    assert!(nes.apu.sample_queue().iter().any(|s| *s != 0.0));
+    // Silenced by 0xE000
+    nes.write_byte(0xE000, 0x40);
+    nes.advance_frame(&settings).unwrap();
+    assert!(nes.apu.sample_queue().iter().all(|s| *s == 0.0));
+}
+
+// Settings of the custom instrument and channel 0 for comparing against the reference
+struct Patch {
+    modulator_level: u8,
+    feedback: u8,
+    carrier_key_scale_level: u8,
+    carrier_decay: u8,
+    carrier_sustain_level: u8,
+    volume: u8,
+    block: u8,
+}
+
+// Play fnum 0x100 on channel 0 with the patch given, where both operators attack instantly and hold their envelopes.
+// Returns the raw output (in the synthesizer's units) of the first `samples` samples.
+fn patch_samples(patch: &Patch, samples: usize) -> Vec<f32> {
+    let mut nes = vrc7_nes(Some(2));
+    [
+        0x21,
+        0x21,
+        patch.modulator_level,
+        (patch.carrier_key_scale_level << 6) | patch.feedback,
+        0xF0,
+        0xF0 | patch.carrier_decay,
+        0x0F,
+        (patch.carrier_sustain_level << 4) | 0x0F,
+    ]
+    .into_iter()
+    .enumerate()
+    .for_each(|(i, v)| write_audio(&mut nes, i as u8, v));
+    write_audio(&mut nes, 0x30, patch.volume);
+    write_audio(&mut nes, 0x10, 0x00);
+    write_audio(&mut nes, 0x20, 0x11 | (patch.block << 1));
+    audio_samples!(nes, samples, CYCLES_PER_SAMPLE)
+        .into_iter()
+        .map(|s| s * 4096.0 / 0.15)
+        .collect()
+}
+
+// Reference output of an operator from the YM2413's documented behaviour rather than its log-sin and exponent tables.
+// The phase is in 1/1024ths of a cycle, and the operator's attenuation is in dB.
+fn reference_operator(phase: f64, attenuation: f64) -> f64 {
+    4096.0
+        * ((phase.floor() + 0.5) * std::f64::consts::PI / 512.0).sin()
+        * 10f64.powf(-attenuation / 20.0)
+}
+
+// Reference output of a channel, with the modulator's output added to the carrier's phase.
+// The total level is in steps of 0.75dB, and feedback adds the last 2 outputs of the modulator, shifted right by
+// 9 minus the feedback value, to its own phase.
+fn reference_samples(
+    patch: &Patch,
+    carrier_attenuation: impl Fn(usize) -> f64,
+    samples: usize,
+) -> Vec<f64> {
+    // Each sample advances the phase by fnum * 2^block / 2^9 of a 1/1024th cycle
+    let step = (1 << patch.block) as f64 / 2.0;
+    let mut feedback = [0.0, 0.0];
+    (1..=samples)
+        .map(|n| {
+            let phase = step * n as f64;
+            let offset = if patch.feedback == 0 {
+                0.0
+            } else {
+                ((feedback[0] + feedback[1]) / (1 << (9 - patch.feedback)) as f64).floor()
+            };
+            let modulator = reference_operator(phase + offset, 0.75 * patch.modulator_level as f64);
+            feedback = [feedback[1], modulator];
+            // The operators output whole numbers
+            reference_operator(phase + modulator.trunc(), carrier_attenuation(n))
+        })
+        .collect()
+}
+
+fn assert_matches_reference(output: &[f32], reference: &[f64]) {
+    output
+        .iter()
+        .zip(reference.iter())
+        .enumerate()
+        .for_each(|(i, (o, r))| {
+            assert!(
+                (*o as f64 - r).abs() < 64.0,
+                "Sample {}: {} but the reference is {}",
+                i + 1,
+                o,
+                r
+            )
+        });
+}
+
+#[test]
+fn test_vrc7_audio_modulation_reference() {
+    let patch = Patch {
+        modulator_level: 40,
+        feedback: 0,
+        carrier_key_scale_level: 0,
+        carrier_decay: 0,
+        carrier_sustain_level: 0,
+        volume: 0,
+        block: 1,
+    };
+    assert_matches_reference(
+        &patch_samples(&patch, 1024),
+        &reference_samples(&patch, |_| 0.0, 1024),
+    );
+    // Feedback changes the modulator's phase
+    let patch = Patch {
+        feedback: 6,
+        ..patch
+    };
+    let output = patch_samples(&patch, 1024);
+    assert_matches_reference(&output, &reference_samples(&patch, |_| 0.0, 1024));
+    let without_feedback = reference_samples(
+        &Patch {
+            feedback: 0,
+            ..patch
+        },
+        |_| 0.0,
+        1024,
+    );
+    assert!(output
+        .iter()
+        .zip(without_feedback.iter())
+        .any(|(o, r)| (*o as f64 - r).abs() > 256.0));
+}
+#[test]
+fn test_vrc7_audio_key_scale_level_reference() {
+    // The key scale level at block 7 with fnum 0x100 is 36dB, which is scaled down to 1.5dB, 3dB and 6dB per octave
+    [(1, 9.0), (2, 18.0), (3, 36.0)]
+        .into_iter()
+        .for_each(|(key_scale_level, attenuation)| {
+            let patch = Patch {
+                modulator_level: 63,
+                feedback: 0,
+                carrier_key_scale_level: key_scale_level,
+                carrier_decay: 0,
+                carrier_sustain_level: 0,
+                volume: 0,
+                block: 7,
+            };
+            assert_matches_reference(
+                &patch_samples(&patch, 256),
+                &reference_samples(&patch, |_| attenuation, 256),
+            );
+        });
+}
+#[test]
+fn test_vrc7_audio_envelope_reference() {
+    // The carrier attacks instantly, and then decays by 0.75dB a sample (0 to 96dB in about 2.5ms) until it reaches
+    // its sustain level of 12dB, on top of the 6dB from the volume
+    let patch = Patch {
+        modulator_level: 40,
+        feedback: 0,
+        carrier_key_scale_level: 0,
+        carrier_decay: 15,
+        carrier_sustain_level: 4,
+        volume: 2,
+        block: 7,
+    };
+    assert_matches_reference(
+        &patch_samples(&patch, 1024),
+        &reference_samples(&patch, |n| 6.0 + 0.75 * (n - 1).min(16) as f64, 1024),
+    );
+}